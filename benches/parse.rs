@@ -2,7 +2,6 @@
 //!
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use log::debug;
 
 use dices_rs::dice::parse::parse_with_bonus;
 use dices_rs::dice::{Dice, DiceSet};
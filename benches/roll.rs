@@ -0,0 +1,47 @@
+//! Benchmark the ways of rolling a dice: uniform draw from `thread_rng` or `OsRng` and the old
+//! biased-coin loop, a simulation as done by `prob` and parsing then rolling a large set.
+//!
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rand::thread_rng;
+
+use dices_rs::dice::internal::{internal_roll_with, legacy_roll_with, Roller, RollerKind};
+use dices_rs::dice::simulate::simulate_with;
+use dices_rs::dice::{DiceSet, Rollable};
+
+const SIDES: usize = 100;
+
+fn fast(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    c.bench_function("fast_d100", |b| {
+        b.iter(|| internal_roll_with(&mut rng, SIDES))
+    });
+}
+
+fn legacy(c: &mut Criterion) {
+    let mut rng = thread_rng();
+    c.bench_function("legacy_d100", |b| {
+        b.iter(|| legacy_roll_with(&mut rng, SIDES))
+    });
+}
+
+fn crypto(c: &mut Criterion) {
+    RollerKind::Crypto.scope(|rng| c.bench_function("crypto_d100", |b| b.iter(|| rng.roll(SIDES))));
+}
+
+fn simulate(c: &mut Criterion) {
+    let mut rng = Roller::default();
+    c.bench_function("simulate_1000_d20", |b| {
+        b.iter(|| simulate_with("d20 + 5", 1000, &mut rng))
+    });
+}
+
+fn large(c: &mut Criterion) {
+    let mut rng = Roller::default();
+    c.bench_function("parse_roll_255d100", |b| {
+        b.iter(|| DiceSet::parse("255D100 +10").unwrap().roll_with(&mut rng))
+    });
+}
+
+criterion_group!(benches, fast, legacy, crypto, simulate, large);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+//! Skeleton for a chat bot: each line on stdin is `<user>: <command>` and every roll is
+//! answered to the user on stdout, in Markdown as most chat systems display it.
+//!
+//! echo "alice: dice 3D6" | cargo run --example bot
+//!
+
+use std::io::{self, BufRead};
+
+use dices_rs::compiler::{Action, Compiler};
+use dices_rs::engine::Engine;
+
+fn main() -> anyhow::Result<()> {
+    let e = Engine::try_new()?;
+    let cc = Compiler::new(&e.cmds);
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let Some((user, cmd)) = line.split_once(':') else {
+            continue;
+        };
+
+        let answer = match cc.compile(cmd.trim()) {
+            Ok(Action::Execute(cmd, input, _)) => match cmd.execute_with(&input, &e.config) {
+                Ok(res) => res.format_markdown(),
+                Err(err) => err.to_string(),
+            },
+            Ok(_) => "sorry, I only roll dices".to_string(),
+            Err(err) => err.to_string(),
+        };
+        println!("@{}: {answer}", user.trim());
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! Writing a custom dice: a Fudge/FATE dice giving -1, 0 or +1, mixed with regular ones
+//! and combinators.
+//!
+//! cargo run --example custom
+//!
+
+use dices_rs::dice::combinators::{Best, Repeat};
+use dices_rs::dice::internal::Roller;
+use dices_rs::dice::result::Res;
+use dices_rs::dice::{Dice, DiceSet, Rollable};
+use rand::Rng;
+
+/// One Fudge dice
+///
+struct Fudge;
+
+impl Rollable for Fudge {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        let v = rng.gen_range(-1..=1);
+        Res {
+            sum: v,
+            bonus: v,
+            ..Default::default()
+        }
+    }
+}
+
+fn main() {
+    // 4dF the usual way
+    //
+    let r = Repeat(4, Fudge).roll();
+    println!("4dF = {}", r.sum);
+
+    // Best of two 4dF
+    //
+    let r = Best(Repeat(4, Fudge), Repeat(4, Fudge)).roll();
+    println!("best of 2x 4dF = {}", r.sum);
+
+    // Custom dices can live in a `DiceSet` alongside regular ones
+    //
+    let mut ds = DiceSet::from(Dice::Regular(6));
+    ds.add_boxed(Box::new(Repeat(4, Fudge)));
+    println!("D6 + 4dF = {}", ds.roll());
+}
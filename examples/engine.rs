@@ -0,0 +1,40 @@
+//! Embedding the engine: load the builtin commands and aliases, then run commands one line at
+//! a time without the interactive shell.
+//!
+//! cargo run --example engine
+//!
+
+use dices_rs::config::Config;
+use dices_rs::engine::{Engine, Output};
+
+fn main() -> anyhow::Result<()> {
+    let cfg = Config {
+        default_dice: "D20".to_string(),
+        ..Default::default()
+    };
+
+    // Builtin commands plus the aliases shipped with the tests
+    //
+    let mut e = Engine::try_new()?
+        .with_config(cfg)
+        .with(Some("testdata/aliases".into()));
+
+    for line in [
+        "dice 3D6",
+        "doom",
+        "set str 2",
+        "roll d8 +$str",
+        "open D6",
+        "dice",
+        "again 2",
+        "vars",
+    ] {
+        match e.eval(line)? {
+            Output::Roll(res) => println!("{line:>14} -> {res}"),
+            Output::Rolls(all) => all.iter().for_each(|res| println!("{line:>14} -> {res}")),
+            Output::Listing(text) => println!("{line:>14} -> {text}"),
+            Output::Chained(_) | Output::Empty | Output::Exit => (),
+        }
+    }
+    Ok(())
+}
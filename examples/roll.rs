@@ -0,0 +1,47 @@
+//! Using the library to roll dices, from the one-shot API down to expressions.
+//!
+//! cargo run --example roll
+//!
+
+use dices_rs::dice::expr::parse_expr;
+use dices_rs::dice::internal::Roller;
+use dices_rs::dice::rules::{Advantage, Rules};
+use dices_rs::dice::{DiceSet, Rollable};
+use rand::{rngs::StdRng, SeedableRng};
+
+fn main() -> anyhow::Result<()> {
+    // One-shot API
+    //
+    let r = dices_rs::roll("3D6 +2")?;
+    println!("3D6 +2 = {r}");
+
+    // Same thing, always giving the same result
+    //
+    let r = dices_rs::roll_seeded("3D6 +2", 42)?;
+    println!("3D6 +2 (seeded) = {r}");
+
+    // Parse once, roll many times
+    //
+    let ds = DiceSet::parse("2D10")?;
+    for _ in 0..3 {
+        println!("2D10 = {}", ds.roll().sum);
+    }
+
+    // With house rules
+    //
+    let rules = Rules {
+        advantage: Advantage::Adv,
+        ..Default::default()
+    };
+    let r = rules.roll_with(
+        &DiceSet::parse("D20")?,
+        &mut Roller::new(StdRng::seed_from_u64(1)),
+    );
+    println!("D20 with advantage = {r}");
+
+    // Full expressions
+    //
+    let (_, e) = parse_expr("(2D6 + 3) * 2").map_err(|e| anyhow::anyhow!("{e}"))?;
+    println!("{} = {}", e.canonicalize(), e.eval(&mut Roller::default())?);
+    Ok(())
+}
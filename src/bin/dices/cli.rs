@@ -1,4 +1,6 @@
-use clap::{crate_authors, crate_description, crate_name, crate_version, Parser};
+use clap::{crate_authors, crate_description, crate_name, crate_version, Parser, Subcommand};
+
+use dices_rs::config::Theme;
 
 /// CLI options
 #[derive(Parser, Debug)]
@@ -9,10 +11,65 @@ pub struct Opts {
     /// Alias file
     #[clap(short = 'A', long)]
     pub alias_file: Option<String>,
+    /// Character file with named rolls and modifiers, can be given more than once
+    #[clap(short = 'C', long)]
+    pub character: Vec<String>,
+    /// System pack whose mechanics become commands, can be given more than once
+    #[clap(long)]
+    pub pack: Vec<String>,
+    /// Configuration file
+    #[clap(short = 'c', long, conflicts_with = "no_config")]
+    pub config: Option<String>,
+    /// Execute commands from this file instead of the interactive shell
+    #[clap(short = 'f', long)]
+    pub file: Option<String>,
+    /// Append every roll to this file, one JSON object per line
+    #[clap(long)]
+    pub log_file: Option<String>,
+    /// Section of the alias file to use
+    #[clap(short = 'P', long)]
+    pub profile: Option<String>,
+    /// Do not use any file from the home directory (config, aliases, history)
+    #[clap(long)]
+    pub no_config: bool,
+    /// History file of the shell
+    #[clap(long, conflicts_with = "no_history")]
+    pub history_file: Option<String>,
+    /// Do not read or save the history of the shell
+    #[clap(long)]
+    pub no_history: bool,
+    /// Plain results, without colors
+    #[clap(long)]
+    pub no_color: bool,
+    /// Colors for a dark or light background, or mono for none
+    #[clap(long)]
+    pub theme: Option<Theme>,
+    /// Only one `total:dices:bonus` line per roll on stdout, no banner and no logging but errors
+    #[clap(long, conflicts_with = "verbose")]
+    pub porcelain: bool,
     /// Verbose mode.
     #[clap(short = 'v', long, action = clap::ArgAction::Count)]
     pub verbose: u8,
     /// Display utility full version.
     #[clap(short = 'V', long)]
     pub version: bool,
+    /// Run something else than the shell
+    #[command(subcommand)]
+    pub cmd: Option<Sub>,
+}
+
+/// Other modes
+#[derive(Subcommand, Debug)]
+pub enum Sub {
+    /// Write a sample configuration and aliases in our directory, saving the existing ones
+    Init,
+    /// Roll over HTTP with `GET /roll?expr=...` or `POST /roll` (needs the `server` feature)
+    Serve {
+        /// Address to listen on
+        #[clap(short, long, default_value = "127.0.0.1")]
+        bind: String,
+        /// Port to listen on
+        #[clap(short, long, default_value_t = 8080)]
+        port: u16,
+    },
 }
@@ -1,47 +1,56 @@
+use std::env::var_os;
+use std::fs::{self, File};
+use std::io::{stderr, stdin, BufReader, IsTerminal};
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use home::home_dir;
-use log::trace;
-use rustyline::{config::BellStyle::Visible, CompletionType::List, Config, EditMode, Editor};
-use stderrlog::LogLevelNum::{Debug, Info, Trace};
+use log::{info, trace};
+use rustyline::{config::BellStyle::Visible, CompletionType, Config, EditMode, Editor};
+use stderrlog::LogLevelNum::{Debug, Error, Info, Trace};
 
-use crate::cli::Opts;
+use crate::cli::{Opts, Sub};
 use crate::version::version;
 
-use dices_rs::engine::Engine;
-use dices_rs::makepath;
+use dices_rs::config;
+use dices_rs::engine::{
+    character::Character,
+    complete::DiceHelper,
+    io::{BufSource, LogSink, Sink, StdoutSink},
+    pack::Pack,
+    rolllog::RollLog,
+    storage::FileStorage,
+    table::Table,
+    Engine,
+};
+use dices_rs::messages::Msg;
+use dices_rs::paths::Paths;
 
 mod cli;
+#[cfg(feature = "server")]
+mod server;
 mod version;
 
-const BASE_DIR: &str = ".config";
-const ALIASES_FILE: &str = "aliases";
-const HISTORY_FILE: &str = "history";
-
 /// Main entry point
 ///
 fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
 
-    let home = home_dir().unwrap();
-    let hist: PathBuf = makepath!(&home, BASE_DIR, "dices", HISTORY_FILE);
-    let def_alias: PathBuf = makepath!(&home, BASE_DIR, "dices", ALIASES_FILE);
-
-    // Add banner
+    // Commands from a file or a pipe are run without readline
     //
-    println!("{}\n", version());
+    let batch = opts.file.is_some() || !stdin().is_terminal();
 
-    // Exit if needed
+    // Show version and exit
     //
     if opts.version {
+        println!("{}\n", version());
         std::process::exit(0);
     }
 
     // Check verbosity
     //
     let lvl = match opts.verbose {
+        _ if opts.porcelain => Error,
         0 => Info,
         1 => Debug,
         _ => Trace,
@@ -52,52 +61,257 @@ fn main() -> Result<()> {
     stderrlog::new()
         .modules(["dices", "dices_rs"])
         .verbosity(lvl)
-        .init()
-        .unwrap();
+        .init()?;
+
+    // No files at all with `--no-config`
+    //
+    let paths = if opts.no_config {
+        Paths::none()
+    } else {
+        Paths::new()
+    };
+
+    // Our files used to be in `~/.config/dices` everywhere
+    //
+    if let Some(old) = Paths::legacy() {
+        if paths.migrate_from(&old)? {
+            info!(
+                "moved {} to {}",
+                old.display(),
+                paths.base().unwrap_or(&old).display()
+            );
+        }
+    }
+
+    // `dices init` writes our sample files again
+    //
+    if let Some(Sub::Init) = opts.cmd {
+        for fname in paths.init(true)? {
+            println!("wrote {}", fname.display());
+        }
+        return Ok(());
+    }
 
     trace!("Load config...");
 
+    // Check whether we supplied a config file on CLI, if not just load our default one
+    //
+    let mut config = match (opts.config, paths.config()) {
+        (Some(fname), _) => config::Config::load(&PathBuf::from(fname))?,
+        (None, Some(fname)) => config::Config::load(&fname)?,
+        (None, None) => config::Config::default(),
+    };
+
+    // Results are logged on stderr, no colors if it is not a terminal or if asked not to
+    //
+    if opts.no_color || var_os("NO_COLOR").is_some() || !stderr().is_terminal() {
+        config.color = false;
+    }
+    if let Some(theme) = opts.theme {
+        config.theme = theme;
+    }
+
+    // Scripts get one line per roll on stdout
+    //
+    let mut sink: Box<dyn Sink> = match opts.porcelain {
+        true => {
+            config.format = config::Format::Porcelain;
+            Box::new(StdoutSink)
+        }
+        false => Box::new(LogSink),
+    };
+
+    // Audit trail of the rolls, the CLI wins over the config
+    //
+    if let Some(fname) = opts.log_file {
+        config.roll_log = Some(PathBuf::from(fname));
+    }
+
+    // Profile from the CLI wins over the config
+    //
+    if let Some(profile) = opts.profile {
+        config.profile = Some(profile);
+    }
+
+    // Characters from the CLI come after those of the config
+    //
+    config
+        .characters
+        .extend(opts.character.into_iter().map(PathBuf::from));
+
+    // Packs from the CLI come after those of the config
+    //
+    config
+        .packs
+        .extend(opts.pack.into_iter().map(PathBuf::from));
+
+    // Random tables from the config or our default directory
+    //
+    if config.table_dir.is_none() {
+        config.table_dir = paths.tables();
+    }
+
+    // History from the CLI wins over the config
+    //
+    if let Some(fname) = opts.history_file {
+        config.history_file = Some(PathBuf::from(fname));
+    }
+    if opts.no_history {
+        config.history = false;
+    }
+
+    // Check whether we supplied an alias file on CLI or in the config, if not just load out
+    // default one
+    //
+    trace!("Check for aliases...");
+    let alias = match opts.alias_file {
+        Some(fname) => Some(PathBuf::from(fname)),
+        _ => config.alias_file.clone().or(paths.aliases()),
+    };
+
+    if let Some(Sub::Serve { bind, port }) = opts.cmd {
+        trace!("Create engine for the server...");
+        return serve(&engine(config, alias)?, &bind, port);
+    }
+
+    if batch {
+        trace!("Create engine for batch...");
+        let mut commands = engine(config, alias)?;
+
+        let failed = match opts.file {
+            Some(fname) => commands.run(
+                &mut BufSource::new(BufReader::new(File::open(fname)?)),
+                sink.as_mut(),
+            )?,
+            None => commands.run(&mut BufSource::new(stdin().lock()), sink.as_mut())?,
+        };
+        return match failed {
+            0 => Ok(()),
+            n => Err(anyhow!("{} command(s) failed", n)),
+        };
+    }
+
+    // Add banner, unless configured away
+    //
+    if !opts.porcelain && config.banner {
+        println!("{}\n", version());
+    }
+
+    // First run of the shell, give new users something to start with
+    //
+    for fname in paths.first_run()? {
+        info!("created {}, have a look", fname.display());
+    }
+
+    let edit_mode = match config.edit_mode {
+        config::EditMode::Emacs => EditMode::Emacs,
+        config::EditMode::Vi => EditMode::Vi,
+    };
+    let completion = match config.completion {
+        config::Completion::Circular => CompletionType::Circular,
+        config::Completion::List => CompletionType::List,
+    };
+
     // Setup readline
     //
     let cfg = Config::builder()
-        .completion_type(List)
+        .completion_type(completion)
         .history_ignore_dups(true)
         .history_ignore_space(true)
+        .max_history_size(config.history_size)
         .bell_style(Visible)
-        .edit_mode(EditMode::Emacs)
+        .edit_mode(edit_mode)
         .build();
-    let mut repl = Editor::<()>::with_config(cfg)?;
+    let mut repl = Editor::<DiceHelper>::with_config(cfg)?;
 
-    // Load history if there is one
+    // Load history if there is one and it is wanted
     //
-    if hist.exists() {
+    let hist = paths.history(&config);
+    if let Some(hist) = hist.as_ref().filter(|h| h.exists()) {
         trace!("Load history from {:?}...", hist);
-        repl.load_history(&hist)?;
+        repl.load_history(hist)?;
     }
 
-    // Check whether we supplied an alias file on CLI, if not just load out default one
-    //
-    trace!("Check for aliases...");
-    let alias = match opts.alias_file {
-        Some(fname) => Some(PathBuf::from(fname)),
-        _ => Some(def_alias),
-    };
-
     // Create a new engine with all builtin commands
     //
     trace!("Create engine...");
-    let mut commands = Engine::new().with(alias);
+    let mut commands = engine(config, alias)?;
+    repl.set_helper(Some(DiceHelper::new(&commands)));
+
+    if !opts.porcelain {
+        let m = commands.config.messages();
+        println!("{}:\n{}\n", m.get(Msg::AvailableCommands), commands.list());
+    }
 
-    println!("Available commands:\n{}\n", commands.list());
+    commands
+        .run(&mut repl, sink.as_mut())
+        .map_err(|e| anyhow!(e.to_string()))?;
 
-    match commands.run(&mut repl) {
-        Ok(_) => match repl.save_history(&hist) {
-            Ok(()) => {
-                trace!("Saved history...");
-                Ok(())
-            }
-            Err(e) => Err(anyhow!("Error: can't save history: {}", e.to_string())),
-        },
-        Err(e) => Err(anyhow!(e.to_string())),
+    // Save history, creating our directory if needed
+    //
+    if let Some(hist) = hist {
+        if let Some(dir) = hist.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        repl.save_history(&hist)
+            .map_err(|e| anyhow!("Error: can't save history: {}", e.to_string()))?;
+        trace!("Saved history...");
     }
+    Ok(())
+}
+
+/// Answer rolls over HTTP
+///
+#[cfg(feature = "server")]
+fn serve(e: &Engine, bind: &str, port: u16) -> Result<()> {
+    server::serve(e, bind, port)
+}
+
+#[cfg(not(feature = "server"))]
+fn serve(_: &Engine, _: &str, _: u16) -> Result<()> {
+    Err(anyhow!("built without the server feature"))
+}
+
+/// Create a new engine with all builtin commands, our aliases and profile, the characters, the
+/// system packs, the random tables, the saved session, the roll database and the roll log if any
+///
+fn engine(config: config::Config, alias: Option<PathBuf>) -> Result<Engine> {
+    let session = config.session_file.clone();
+    let roll_log = config.roll_log.clone();
+    let characters = config.characters.clone();
+    let packs = config.packs.clone();
+    let profile = config.profile.clone();
+    let table_dir = config.table_dir.clone();
+    #[cfg(feature = "sqlite")]
+    let roll_db = config.roll_db.clone();
+    let e = Engine::new().with_config(config).with(alias);
+    let e = match profile {
+        Some(name) => e.with_profile(&name)?,
+        None => e,
+    };
+    let e = characters
+        .iter()
+        .try_fold(e, |e, fname| e.with_character(Character::load(fname)?))?;
+    let e = packs
+        .iter()
+        .try_fold(e, |e, fname| e.with_pack(Pack::load(fname)?))?;
+    let e = match table_dir {
+        Some(dir) if dir.is_dir() => Table::load_dir(dir)?
+            .into_iter()
+            .fold(e, |e, t| e.with_table(t)),
+        _ => e,
+    };
+    #[cfg(feature = "sqlite")]
+    let e = match roll_db {
+        Some(fname) => e.with_roll_db(dices_rs::engine::rolldb::RollDb::open(fname)?),
+        None => e,
+    };
+    let e = match roll_log {
+        Some(fname) => e.with_roll_log(RollLog::open(fname)?),
+        None => e,
+    };
+    Ok(match session {
+        Some(fname) => e.with_storage(Box::new(FileStorage::new(fname)))?,
+        None => e,
+    })
 }
@@ -0,0 +1,157 @@
+//! HTTP API for `dices serve`, a trusted roller for online play tools.
+//!
+//! - `GET /roll?expr=3d6+2`
+//! - `POST /roll` with `{"expr": "3d6+2"}` as body
+//!
+//! Both answer with the JSON `Res` of the roll, or `{"error": "..."}` with a 4xx status.  The
+//! expression goes through the same compiler as `dice <expr>` in the shell, so variables can
+//! be used but no other command.  A `+` in the query string is kept as is, not turned into a
+//! space.  Bodies larger than `MAX_BODY` are refused with a 413.
+
+use std::io::Read;
+
+use anyhow::{anyhow, Result};
+use log::{info, trace};
+use percent_encoding::percent_decode_str;
+use serde::Deserialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Response, Server};
+
+use dices_rs::dice::result::Res;
+use dices_rs::engine::Engine;
+
+/// Largest body accepted, much more than any expression needs
+const MAX_BODY: u64 = 4096;
+
+/// Body of `POST /roll`
+///
+#[derive(Debug, Deserialize)]
+struct RollRequest {
+    expr: String,
+}
+
+/// Answer requests on `bind:port` until killed
+///
+pub(crate) fn serve(e: &Engine, bind: &str, port: u16) -> Result<()> {
+    let server = Server::http((bind, port)).map_err(|e| anyhow!(e.to_string()))?;
+    let json = Header::from_bytes("Content-Type", "application/json")
+        .expect("Content-Type: application/json is a valid header");
+
+    info!("listening on http://{bind}:{port}/roll");
+    for mut req in server.incoming_requests() {
+        let (status, text) = match read_body(req.as_reader()) {
+            Ok(body) => answer(e, req.method(), req.url(), &body),
+            Err(refused) => refused,
+        };
+        trace!("{} {} -> {status}", req.method(), req.url());
+
+        let resp = Response::from_string(text)
+            .with_status_code(status)
+            .with_header(json.clone());
+        req.respond(resp)?;
+    }
+    Ok(())
+}
+
+/// Body of a request, at most `MAX_BODY` bytes of UTF-8
+///
+fn read_body(reader: &mut dyn Read) -> Result<String, (u16, String)> {
+    let mut body = String::new();
+    match reader.take(MAX_BODY + 1).read_to_string(&mut body) {
+        Ok(n) if n as u64 > MAX_BODY => {
+            Err((413, error(format!("body larger than {MAX_BODY} bytes"))))
+        }
+        Ok(_) => Ok(body),
+        Err(err) => Err((400, error(err.to_string()))),
+    }
+}
+
+/// Status and JSON body for one request
+///
+fn answer(e: &Engine, method: &Method, url: &str, body: &str) -> (u16, String) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    if path != "/roll" {
+        return (404, error(format!("{path} not found")));
+    }
+
+    let expr = match method {
+        Method::Get => query
+            .split('&')
+            .find_map(|kv| kv.strip_prefix("expr="))
+            .map(|v| percent_decode_str(v).decode_utf8_lossy().to_string())
+            .ok_or_else(|| "missing expr".to_string()),
+        Method::Post => serde_json::from_str::<RollRequest>(body)
+            .map(|r| r.expr)
+            .map_err(|e| e.to_string()),
+        _ => return (405, error(format!("{method} not allowed"))),
+    };
+
+    match expr.and_then(|expr| roll(e, &expr)) {
+        Ok(res) => (200, json!(res).to_string()),
+        Err(err) => (400, error(err)),
+    }
+}
+
+/// Roll `expr` like `dice <expr>` would
+///
+fn roll(e: &Engine, expr: &str) -> Result<Res, String> {
+    e.roll_line(&format!("dice {}", expr.trim()))
+        .map_err(|e| e.to_string())
+}
+
+/// JSON error message
+///
+fn error<S: Into<String>>(msg: S) -> String {
+    json!({ "error": msg.into() }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(Method::Get, "/roll?expr=3d6+2", "")]
+    #[case(Method::Get, "/roll?foo=1&expr=3d6%20%2B2", "")]
+    #[case(Method::Post, "/roll", r#"{"expr": "3d6+2"}"#)]
+    fn test_answer_roll(#[case] method: Method, #[case] url: &str, #[case] body: &str) {
+        let e = Engine::new();
+        let (status, text) = answer(&e, &method, url, body);
+        assert_eq!(200, status, "{text}");
+
+        let res: Res = serde_json::from_str(&text).unwrap();
+        assert_eq!(2, res.bonus);
+        assert_eq!(3, res.list.len());
+    }
+
+    #[test]
+    fn test_read_body() {
+        let body = r#"{"expr": "3d6+2"}"#;
+        assert_eq!(Ok(body.to_string()), read_body(&mut body.as_bytes()));
+
+        let big = vec![b' '; MAX_BODY as usize + 1];
+        let (status, text) = read_body(&mut big.as_slice()).unwrap_err();
+        assert_eq!(413, status);
+        assert!(text.starts_with(r#"{"error":"#), "{text}");
+        assert!(read_body(&mut [0xff_u8, 0xfe].as_slice()).is_err());
+    }
+
+    #[rstest]
+    #[case(Method::Get, "/roll", "", 400)]
+    #[case(Method::Get, "/roll?expr=3d1", "", 400)]
+    #[case(Method::Post, "/roll", "3d6", 400)]
+    #[case(Method::Get, "/", "", 404)]
+    #[case(Method::Delete, "/roll?expr=3d6", "", 405)]
+    fn test_answer_error(
+        #[case] method: Method,
+        #[case] url: &str,
+        #[case] body: &str,
+        #[case] want: u16,
+    ) {
+        let e = Engine::new();
+        let (status, text) = answer(&e, &method, url, body);
+        assert_eq!(want, status);
+        assert!(text.starts_with(r#"{"error":"#), "{text}");
+    }
+}
@@ -1,35 +1,28 @@
 //! versioning stuff
 
-use clap::{crate_authors, crate_description, crate_version};
+use dices_rs::engine::version::build_info;
 
 /// Binary name, using a different binary name
 pub(crate) const NAME: &str = env!("CARGO_BIN_NAME");
-/// Binary version
-pub(crate) const VERSION: &str = crate_version!();
-/// Authors
-pub(crate) const AUTHORS: &str = crate_authors!();
 
 /// Display our version banner
 ///
 #[inline]
 pub fn version() -> String {
-    format!(
-        "{}/{} by {}\n{}",
-        NAME,
-        VERSION,
-        AUTHORS,
-        crate_description!()
-    )
+    build_info().banner(NAME)
 }
 
 #[cfg(test)]
 mod tests {
+    use clap::{crate_authors, crate_version};
+
     use super::*;
 
     #[test]
     fn test_version() {
         assert!(version().contains(NAME));
-        assert!(version().contains(VERSION));
-        assert!(version().contains(AUTHORS))
+        assert!(version().contains(crate_version!()));
+        assert!(version().contains(crate_authors!()));
+        assert!(version().contains(env!("GIT_HASH")))
     }
 }
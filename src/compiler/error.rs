@@ -0,0 +1,28 @@
+//! Errors returned by the `Compiler`.
+//!
+
+use thiserror::Error;
+
+/// Everything that can go wrong while compiling a line
+///
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum CompilerError {
+    /// No keyword at the start of the line
+    #[error("invalid command")]
+    InvalidCommand,
+    /// Keyword not found in the commands, with the closest one if any
+    #[error("unknown command {0}{}", .1.as_ref().map(|s| format!(", did you mean {s}?")).unwrap_or_default())]
+    UnknownCommand(String, Option<String>),
+    /// Aliases and macros are nested too deep (or loop)
+    #[error("max recursion level reached for {0}")]
+    MaxRecursion(String),
+    /// `$name` used but never `set`
+    #[error("unknown variable ${0}")]
+    UnknownVariable(String),
+    /// `then` without `<op> <N>` before it or nothing after it
+    #[error("invalid condition in {0}, expected <expr> <op> <N> then <expr>")]
+    InvalidCondition(String),
+    /// Command that can not be reduced
+    #[error("impossible command {0}")]
+    Impossible(String),
+}
@@ -4,14 +4,36 @@
 //! and aliases and output our "compiled" code (aka `Action`) and the engine is supposed to
 //! deal with the output.
 //!
+//! A line can hold several commands separated by `;`, and a `\` at the end of a line
+//! continues it on the next one: `split` and `join_continued` turn that into the single
+//! commands given to `compile`, which the engine runs in order.
+//!
+//! A line starting with dices instead of a command (`3d6+2`) is rolled with `dice`.
+//!
+//! A command rolling dices can roll a second time only if the first roll succeeds,
+//! `dice d20+7 >= 16 then 2d6+4`, this is an `Action::Conditional`.
+//!
+//! Comments are the same as in the aliases file, from `#`, `//` or `!` followed by a space to
+//! the end of the line, on a line of their own or after a command (`dice d20 # initiative`).
+//!
 
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 
-use anyhow::{anyhow, bail, Result};
 use log::trace;
 use nom::{character::complete::alphanumeric1, IResult};
 
-use crate::engine::Command;
+use crate::dice::parse::{parse_dice, Defined};
+use crate::dice::simulate::{split_condition, Op};
+use crate::dice::{expr::parse_expr, Dice};
+use crate::engine::{split_note, Command};
+
+use self::error::CompilerError;
+
+pub mod error;
+
+/// Shortcut for our results
+pub type Result<T> = std::result::Result<T, CompilerError>;
 
 /// Action is more or less the result of the compilation done by `Compiler`
 ///
@@ -19,76 +41,243 @@ use crate::engine::Command;
 pub enum Action {
     /// List aliases
     Aliases,
-    /// This is the error
-    Error(String),
-    /// We need to execute a command
-    Execute(Command, String),
+    /// We need to execute a command, with the way it was reached
+    Execute(Command, String, Trace),
+    /// Execute a command with the first arguments, then with the second ones if the total
+    /// passes the condition
+    Conditional(Command, String, Condition, String, Trace),
     /// Get out
     Exit,
     /// List all commands
     List,
     /// List only macros
     Macros,
+    /// Show the last roll, or the last one with a given label
+    Last(String),
+    /// Set (or unset) a variable, `name [value]`
+    Set(String),
+    /// Toggle speech, with optional `on`/`off`
+    Speak(String),
+    /// List all variables
+    Vars,
+    /// Search the roll database
+    Query(String),
+    /// Start a what-if branch
+    Branch,
+    /// Keep the current branch
+    Merge,
+    /// Forget the current branch
+    Discard,
+    /// Show version and build information
+    Version,
+    /// Show the startup banner
+    Banner,
+    /// Show the usage of all commands or a given one
+    Help(String),
+    /// Toggle display of alias expansions, with optional `on`/`off`
+    Trace(String),
+    /// Roll the last dice command again
+    Reroll,
+    /// Roll the last dice command again N times
+    Again(String),
+    /// List characters or switch to another one
+    Char(String),
+    /// List alias profiles or switch to another one
+    Profile(String),
+    /// Roll, create or list usage dice
+    Usage(String),
+    /// List random tables or roll on one
+    Table(String),
+    /// Create, draw from or shuffle the deck of cards
+    Deck(String),
+    /// Chances of a roll, with variables replaced
+    Prob(String),
+    /// Probability table of a roll, with variables replaced
+    Anydice(String),
+    /// Remove aliases and macros
+    Unalias(String),
+    /// Toggle hidden rolls, with optional `on`/`off`
+    Gm(String),
+    /// Show and forget the hidden rolls
+    Reveal,
+    /// Execute a command for a player, `<player> <command>`
+    As(String),
+    /// Number of rolls and average of each player
+    Scores,
+    /// Command registered from Rust with its arguments, variables replaced
+    Custom(String, String),
+    /// Rhai script, as typed
+    Script(String),
+    /// Nothing to do, the line is a comment
+    Comment,
+}
+
+/// Expansion of a line through aliases and macros, starting with the line itself
+///
+/// Displayed as `doom -> dice 2D6`.
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Trace(pub Vec<String>);
+
+impl Trace {
+    /// Has anything been expanded?
+    ///
+    pub fn expanded(&self) -> bool {
+        self.0.len() > 1
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join(" -> "))
+    }
+}
+
+/// Test of the first roll of `dice <expr> <op> <N> then <expr>`
+///
+/// Displayed as `>= 16`.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Condition {
+    pub op: Op,
+    pub value: isize,
+}
+
+impl Condition {
+    /// Does `total` pass?
+    ///
+    pub fn passes(&self, total: isize) -> bool {
+        self.op.matches(total, self.value)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.op, self.value)
+    }
 }
 
 #[derive(Debug)]
-/// Our compiler struct
+/// Our compiler struct, borrowing the commands so it always sees the current ones
 ///
-pub struct Compiler {
+pub struct Compiler<'a> {
     /// List of all available commands
-    cmds: HashMap<String, Command>,
+    cmds: &'a HashMap<String, Command>,
+    /// Dices defined in the configuration, for the conditions of conditional rolls
+    defined: Defined<'a>,
 }
 
-impl Compiler {
+impl<'a> Compiler<'a> {
     /// Max depth we allow for recursion
     ///
     pub const MAX_RECUR: usize = 5;
 
-    /// Instantiate a new compiler with allowed commands
+    /// Instantiate a new compiler with allowed commands.  Names are case-insensitive, they are
+    /// expected in lowercase like `Engine` keeps them.
+    ///
+    pub fn new(cmds: &'a HashMap<String, Command>) -> Self {
+        trace!("create compiler with({} commands)", cmds.len());
+        Self {
+            cmds,
+            defined: Defined::default(),
+        }
+    }
+
+    /// Know the dices of `defined` when parsing the first roll of a conditional one
     ///
-    pub fn new(cmds: &HashMap<String, Command>) -> Self {
-        trace!("create compiler with({:?})", cmds);
-        Self { cmds: cmds.clone() }
+    pub fn with_defined(mut self, defined: Defined<'a>) -> Self {
+        self.defined = defined;
+        self
     }
 
     /// We have the initial analysis of the input, resolve it into something we do know or
     /// something we can execute
     ///
-    pub fn compile(&self, input: &str) -> Action {
+    pub fn compile(&self, input: &str) -> Result<Action> {
+        self.compile_with(input, &HashMap::new())
+    }
+
+    /// Same as `compile()` but replace all `$name` in the arguments of the command to execute
+    /// by the value of the variable
+    ///
+    pub fn compile_with(&self, input: &str, vars: &HashMap<String, i64>) -> Result<Action> {
         trace!("in compile({input})");
 
-        // Go directly into `recurse()`
-        //
-        let (input, cmd) = match self.recurse(input, None) {
-            Ok((input, cmd)) => (input, cmd),
-            Err(_) => return Action::Error("unknown command".to_string()),
+        let input = match strip_comment(input) {
+            "" if !input.trim().is_empty() => return Ok(Action::Comment),
+            input => input,
         };
 
+        // Go directly into `reduce()`
+        //
+        let (input, cmd, trace) = self.reduce(input)?;
+
         trace!("cmd={:?}", cmd);
 
-        match cmd {
+        let action = match cmd {
             Command::Exit => Action::Exit,
             Command::List => Action::List,
             Command::Aliases => Action::Aliases,
             Command::Macros => Action::Macros,
+            Command::Speak => Action::Speak(input),
+            Command::Last => Action::Last(input),
+            Command::Set => Action::Set(input),
+            Command::Vars => Action::Vars,
+            Command::Query => Action::Query(input),
+            Command::Branch => Action::Branch,
+            Command::Merge => Action::Merge,
+            Command::Discard => Action::Discard,
+            Command::Version => Action::Version,
+            Command::Banner => Action::Banner,
+            Command::Help => Action::Help(input),
+            Command::Trace => Action::Trace(input),
+            Command::Reroll => Action::Reroll,
+            Command::Again => Action::Again(input),
+            Command::Char => Action::Char(input),
+            Command::Profile => Action::Profile(input),
+            Command::Usage => Action::Usage(input),
+            Command::Table => Action::Table(input),
+            Command::Deck => Action::Deck(input),
+            Command::Prob => Action::Prob(substitute(&input, vars)?),
+            Command::Anydice => Action::Anydice(substitute(&input, vars)?),
+            Command::Unalias => Action::Unalias(input),
+            Command::Gm => Action::Gm(input),
+            Command::Reveal => Action::Reveal,
+            Command::As => Action::As(input),
+            Command::Scores => Action::Scores,
+            Command::Custom { name } => Action::Custom(name, substitute(&input, vars)?),
+            Command::Script => Action::Script(input),
 
             // At this point these are not possible
             //
-            Command::Macro { .. } => Action::Error("no macro".to_string()),
-            Command::Alias { .. } => Action::Error("no alias".to_string()),
+            Command::Macro { name, .. } | Command::Alias { name, .. } => {
+                return Err(CompilerError::Impossible(name))
+            }
+
+            // Rolling twice, the second time only if the first roll succeeds
+            //
+            Command::Builtin { cmd: ref c, .. }
+                if c.takes_dices() && split_note(&input).0.contains(THEN) =>
+            {
+                let input = substitute(&input, vars)?;
+                let (test, cond, then) = split_conditional(&input, &self.defined)
+                    .ok_or_else(|| CompilerError::InvalidCondition(input.trim().to_string()))?;
+                Action::Conditional(cmd, test.to_string(), cond, then.to_string(), trace)
+            }
 
             // These can be executed directly
             //
-            Command::Builtin { .. } => {
+            Command::Builtin { .. } | Command::Mechanic { .. } => {
                 // Identify and execute each command
                 // Short one may be inserted here directly
                 // otherwise put them in `engine/mod.rs`
                 //
                 trace!("builtin={:?}", cmd);
-                Action::Execute(cmd, input)
+                Action::Execute(cmd, substitute(&input, vars)?, trace)
             }
-            _ => Action::Error("impossible command".to_string()),
-        }
+            _ => return Err(CompilerError::Impossible(input)),
+        };
+        Ok(action)
     }
 
     /// Parse then validate
@@ -103,75 +292,270 @@ impl Compiler {
 
         // Get command name
         //
-        let (input, name) = match parse_keyword(input) {
-            Ok((input, name)) => (input.to_owned(), name.to_owned()),
-            Err(_) => return Err(anyhow!("invalid command")),
+        let (rest, name) = match parse_keyword(input) {
+            Ok((rest, name)) => (rest.to_owned(), name.to_owned()),
+            Err(_) => return self.dice(input).ok_or(CompilerError::InvalidCommand),
         };
 
         trace!("name={name} with input={input}");
 
-        // Validate that a given input does map to a `Command`
+        // Validate that a given input does map to a `Command`, whatever the case; only the
+        // name is lowered, the arguments (dice notation) are kept as typed
         //
-        match self.cmds.get(&name) {
+        match self.cmds.get(&name.to_lowercase()) {
             Some(cmd) => {
                 trace!("parse found {:?}", cmd);
-                Ok((input, cmd.to_owned()))
+                Ok((rest, cmd.to_owned()))
             }
-            None => return Err(anyhow!("unknown command")),
+            None => self.dice(input).ok_or_else(|| {
+                let near = suggest(&name, self.cmds.keys());
+                CompilerError::UnknownCommand(name, near)
+            }),
         }
     }
 
-    /// Try to reduce/compile `Macro` & `Alias` into a `Builtin` or special command
+    /// `dice` with the whole of `input` as arguments if it starts with dices, like `3d6+2` or
+    /// `(d6+1)*2`
     ///
-    /// This is a tail recursive function, might be turned into an iterative one at some point
-    /// Not sure it is worth it.
+    fn dice(&self, input: &str) -> Option<(String, Command)> {
+        let input = input.trim_start();
+        let word = input.split(|c: char| !c.is_alphanumeric()).next();
+        let dices = match word {
+            Some(word) if !word.is_empty() => {
+                let size = word.trim_start_matches(|c: char| c.is_ascii_digit());
+                matches!(parse_dice(size), Ok((_, Dice::Regular(_))))
+            }
+            _ => matches!(parse_expr(input), Ok((_, expr)) if !expr.sizes().is_empty()),
+        };
+        let cmd = self.cmds.get("dice").filter(|_| dices)?;
+        trace!("parse found dices");
+        Some((format!(" {input}"), cmd.to_owned()))
+    }
+
+    /// Reduce/compile `Macro` & `Alias` into a `Builtin` or special command, keeping every
+    /// step in a `Trace`
     ///
-    fn recurse(&self, input: &str, max: Option<usize>) -> Result<(String, Command)> {
-        trace!("in compiler::recurse({max:?})={:?}", input);
+    fn reduce(&self, input: &str) -> Result<(String, Command, Trace)> {
+        trace!("in compiler::reduce({input:?})");
+
+        let mut line = input.to_string();
+        let mut steps = Trace(vec![line.clone()]);
+
+        for depth in 0..Compiler::MAX_RECUR {
+            let (args, command) = self.parse(&line)?;
+            line = match command {
+                // The end, we are at the Builtin level
+                //
+                Command::Builtin { .. } | Command::Mechanic { .. } => {
+                    trace!("reduce=builtin, end");
+                    return Ok((args, command, steps));
+                }
+                // Aliases and macros are replaced, we must not lose any argument so append
+                // the old input
+                //
+                Command::Alias { cmd, .. } | Command::Macro { cmd, .. } => {
+                    trace!("reduce=alias/macro({cmd}) depth={depth}");
+                    cmd + args.as_str()
+                }
+                // These are builtin & special commands
+                //
+                Command::List
+                | Command::Exit
+                | Command::Aliases
+                | Command::Macros
+                | Command::Speak
+                | Command::Last
+                | Command::Set
+                | Command::Vars
+                | Command::Query
+                | Command::Branch
+                | Command::Merge
+                | Command::Discard
+                | Command::Version
+                | Command::Banner
+                | Command::Help
+                | Command::Trace
+                | Command::Reroll
+                | Command::Again
+                | Command::Char
+                | Command::Profile
+                | Command::Usage
+                | Command::Table
+                | Command::Deck
+                | Command::Prob
+                | Command::Anydice
+                | Command::Unalias
+                | Command::Gm
+                | Command::Reveal
+                | Command::As
+                | Command::Scores
+                | Command::Custom { .. }
+                | Command::Script => {
+                    trace!("special, end");
+                    return Ok((args, command, steps));
+                }
+                // Everything else is  an error here
+                //
+                _ => return Err(CompilerError::Impossible(args)),
+            };
+            steps.0.push(line.clone());
+        }
 
-        // Set default recursion max
+        // Error out if too deep recursion
         //
-        let mut max = max.unwrap_or(Compiler::MAX_RECUR);
+        Err(CompilerError::MaxRecursion(line))
+    }
+}
 
-        let (input, command) = self.parse(input)?;
-        let input = match command {
-            // The end, we are at the Builtin level
-            //
-            Command::Builtin { .. } => {
-                trace!("recurse=builtin, end");
-                return Ok((input, command));
-            }
-            // This is an alias
-            //
-            Command::Alias { cmd, .. } => {
-                trace!("recurse=alias({cmd})");
-                cmd + input.as_str()
-            }
-            // XXX Need to recurse now but we must not lose any argument so append old input
-            //
-            Command::Macro { name, cmd } => {
-                trace!("recurse=macro({})", name);
-                cmd + input.as_str()
+/// Separator of the commands of a line
+pub const SEPARATOR: char = ';';
+
+/// Continuation of a line on the next one, at its very end
+pub const CONTINUATION: char = '\\';
+
+/// Start of a comment, followed by a space or the end of the line
+pub const COMMENTS: [&str; 3] = ["#", "//", "!"];
+
+/// `line` without its comment, if any, and the spaces before it
+///
+pub fn strip_comment(line: &str) -> &str {
+    let end = comment_start(line).unwrap_or(line.len());
+    line[..end].trim_end()
+}
+
+/// Text of the comment of `line`, if not empty
+///
+pub fn comment(line: &str) -> Option<&str> {
+    let start = comment_start(line)?;
+    let text = line[start..].trim_start_matches(['#', '/', '!']).trim();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Where the comment of `line` starts, at the beginning or after a space
+///
+fn comment_start(line: &str) -> Option<usize> {
+    line.char_indices()
+        .find(|&(i, _)| {
+            (i == 0 || line[..i].ends_with(char::is_whitespace))
+                && COMMENTS.iter().any(|c| {
+                    line[i..].strip_prefix(c).is_some_and(|rest| {
+                        rest.is_empty() || rest.starts_with(char::is_whitespace)
+                    })
+                })
+        })
+        .map(|(i, _)| i)
+}
+
+/// Join the lines of `text` ending with `\` with the next one
+///
+pub fn join_continued(text: &str) -> String {
+    text.replace("\\\r\n", " ").replace("\\\n", " ")
+}
+
+/// Commands of a line, without the empty ones.  Separators between double quotes do not
+/// count, and the comment of the line stays with the last command, as its annotation.
+///
+pub fn split(line: &str) -> Vec<&str> {
+    let end = comment_start(line).unwrap_or(line.len());
+    let mut quoted = false;
+    let mut start = 0;
+    let mut cmds = vec![];
+    for (i, c) in line[..end].char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            SEPARATOR if !quoted => {
+                cmds.push(&line[start..i]);
+                start = i + 1;
             }
-            // These are builtin & special commands
-            //
-            Command::List | Command::Exit | Command::Aliases | Command::Macros => {
-                trace!("list/exit, end");
-                return Ok((input, command));
+            _ => (),
+        }
+    }
+    cmds.push(&line[start..]);
+    cmds.into_iter()
+        .map(str::trim)
+        .filter(|c| !strip_comment(c).is_empty())
+        .collect()
+}
+
+/// Separator of the two rolls of a conditional
+pub const THEN: &str = " then ";
+
+/// Split `<expr> <op> <N> then <expr>` into the first roll, its condition and the second roll
+///
+fn split_conditional<'i>(
+    input: &'i str,
+    defined: &Defined,
+) -> Option<(&'i str, Condition, &'i str)> {
+    let (test, then) = input.split_once(THEN)?;
+    let (test, op, value) = split_condition(test, defined)?;
+    (!test.trim().is_empty() && !then.trim().is_empty()).then_some((
+        test,
+        Condition { op, value },
+        then,
+    ))
+}
+
+/// Replace every `$name` in `input` by the value of the variable
+///
+pub(crate) fn substitute(input: &str, vars: &HashMap<String, i64>) -> Result<String> {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        match vars.get(name) {
+            Some(v) => out.push_str(&v.to_string()),
+            None => return Err(CompilerError::UnknownVariable(name.to_string())),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Closest name to `name` among `names`, if it is close enough to be a typo: at most one edit
+/// for every three letters
+///
+pub fn suggest<'a, I>(name: &str, names: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let name = name.to_lowercase();
+    let max = (name.chars().count() / 3).max(1);
+    names
+        .into_iter()
+        .map(|n| (distance(&name, n), n))
+        .filter(|(d, _)| *d <= max)
+        .min()
+        .map(|(_, n)| n.clone())
+}
+
+/// Edit distance between `a` and `b` where swapping two letters counts as one edit
+///
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    (0..=a.len()).for_each(|i| d[i][0] = i);
+    (0..=b.len()).for_each(|j| d[0][j] = j);
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
             }
-            // Everything else is  an error here
-            //
-            _ => bail!("impossible in recurse"),
-        };
-        // Error out if too deep recursion
-        //
-        max -= 1;
-        if max == 0 {
-            return Err(anyhow!("max recursion level reached for {}", input));
         }
-        trace!("recurse(input)={input} max={max}");
-        self.recurse(&input, Some(max))
     }
+    d[a.len()][b.len()]
 }
 
 #[cfg(test)]
@@ -187,9 +571,229 @@ mod tests {
     #[case("list", Action::List)]
     #[case("aliases", Action::Aliases)]
     #[case("macros", Action::Macros)]
+    #[case("speak", Action::Speak("".to_string()))]
+    #[case("speak on", Action::Speak(" on".to_string()))]
+    #[case("set str 4", Action::Set(" str 4".to_string()))]
+    #[case("vars", Action::Vars)]
+    #[case("query rolls", Action::Query(" rolls".to_string()))]
+    #[case("branch", Action::Branch)]
+    #[case("merge", Action::Merge)]
+    #[case("discard", Action::Discard)]
+    #[case("version", Action::Version)]
+    #[case("banner", Action::Banner)]
+    #[case("help dice", Action::Help(" dice".to_string()))]
+    #[case("last attack", Action::Last(" attack".to_string()))]
+    #[case("trace off", Action::Trace(" off".to_string()))]
+    #[case("reroll", Action::Reroll)]
+    #[case("again 3", Action::Again(" 3".to_string()))]
+    #[case("char use bob", Action::Char(" use bob".to_string()))]
+    #[case("profile dnd", Action::Profile(" dnd".to_string()))]
+    #[case("usage torch", Action::Usage(" torch".to_string()))]
+    #[case("table encounter", Action::Table(" encounter".to_string()))]
+    #[case("deck draw 3", Action::Deck(" draw 3".to_string()))]
+    #[case("prob d20 >= 15", Action::Prob(" d20 >= 15".to_string()))]
+    #[case("anydice 3d6 --output csv", Action::Anydice(" 3d6 --output csv".to_string()))]
+    #[case("unalias doom", Action::Unalias(" doom".to_string()))]
+    #[case("undef doom", Action::Unalias(" doom".to_string()))]
+    #[case("gm on", Action::Gm(" on".to_string()))]
+    #[case("reveal", Action::Reveal)]
+    #[case("as Alice dice d20+3", Action::As(" Alice dice d20+3".to_string()))]
+    #[case("scores", Action::Scores)]
+    #[case("rhai roll(\"$x\")", Action::Script(" roll(\"$x\")".to_string()))]
+    #[case("LIST", Action::List)]
+    #[case("Speak on", Action::Speak(" on".to_string()))]
+    #[case("# heading for this fight", Action::Comment)]
+    #[case("speak on // loud", Action::Speak(" on".to_string()))]
     fn test_compile(#[case] input: &str, #[case] cmd: Action) {
         let n = Engine::new();
         let cc = Compiler::new(&n.cmds);
-        assert_eq!(cmd, cc.compile(input))
+        assert_eq!(cmd, cc.compile(input).unwrap())
+    }
+
+    #[test]
+    fn test_compile_conditional() {
+        let n = Engine::new();
+        let cc = Compiler::new(&n.cmds);
+        let vars = HashMap::from([("str".to_string(), 3)]);
+
+        let Ok(Action::Conditional(cmd, test, cond, then, _)) =
+            cc.compile_with("dice d20+7 >=16 then 2d6+$str", &vars)
+        else {
+            panic!("not a conditional");
+        };
+        assert!(matches!(cmd, Command::Builtin { .. }));
+        assert_eq!(" d20+7 ", test);
+        assert_eq!(">= 16", cond.to_string());
+        assert!(cond.passes(16) && !cond.passes(15));
+        assert_eq!("2d6+3", then);
+    }
+
+    #[rstest]
+    #[case(" d20 >= 16 then 2d6", Some((" d20 ", Op::Ge, 16, "2d6")))]
+    #[case(" 3d6<10 then d4 \"poison\"", Some((" 3d6", Op::Lt, 10, "d4 \"poison\"")))]
+    #[case(" d20 = -1 then d6", Some((" d20 ", Op::Eq, -1, "d6")))]
+    #[case(" 2d6r<3 >= 7 then 2d6", Some((" 2d6r<3 ", Op::Ge, 7, "2d6")))]
+    #[case(" d20 adv >= 16 then 2d6", Some((" d20 adv ", Op::Ge, 16, "2d6")))]
+    #[case(" d20 then 2d6", None)]
+    #[case(" >= 16 then 2d6", None)]
+    #[case(" d20 >= 16 then ", None)]
+    #[case(" d20 >= x then 2d6", None)]
+    fn test_split_conditional(#[case] input: &str, #[case] want: Option<(&str, Op, isize, &str)>) {
+        let got =
+            split_conditional(input, &Defined::default()).map(|(t, c, e)| (t, c.op, c.value, e));
+        assert_eq!(want, got);
+    }
+
+    #[rstest]
+    #[case("", CompilerError::InvalidCommand)]
+    #[case("foo", CompilerError::UnknownCommand("foo".to_string(), None))]
+    #[case("lsit", CompilerError::UnknownCommand("lsit".to_string(), Some("list".to_string())))]
+    #[case("Dcie 3D6", CompilerError::UnknownCommand("Dcie".to_string(), Some("dice".to_string())))]
+    #[case("dance", CompilerError::UnknownCommand("dance".to_string(), None))]
+    #[case("(2 + 3)", CompilerError::InvalidCommand)]
+    #[case("dice d20 then 2d6", CompilerError::InvalidCondition("d20 then 2d6".to_string()))]
+    fn test_compile_error(#[case] input: &str, #[case] err: CompilerError) {
+        let n = Engine::new();
+        let cc = Compiler::new(&n.cmds);
+        assert_eq!(Err(err), cc.compile(input))
+    }
+
+    #[rstest]
+    #[case("dcie", Some("dice"))]
+    #[case("verison", Some("version"))]
+    #[case("speek", Some("speak"))]
+    #[case("dic", Some("dice"))]
+    #[case("foo", None)]
+    #[case("x", None)]
+    fn test_suggest(#[case] name: &str, #[case] want: Option<&str>) {
+        let n = Engine::new();
+        assert_eq!(want.map(String::from), suggest(name, n.cmds.keys()));
+    }
+
+    #[rstest]
+    #[case("dice d20", &["dice d20"])]
+    #[case("dice d20; dice 2d6+3", &["dice d20", "dice 2d6+3"])]
+    #[case(" set str 2 ;;dice d20 +$str; ", &["set str 2", "dice d20 +$str"])]
+    #[case(" ; ", &[])]
+    #[case("dice d20; dice d6 # initiative", &["dice d20", "dice d6 # initiative"])]
+    #[case("dice d20 # initiative; dice d6", &["dice d20 # initiative; dice d6"])]
+    #[case("# round 2; dice d6", &[])]
+    #[case("rhai \"let d = roll(`d6`); d.sum\"; dice d4", &["rhai \"let d = roll(`d6`); d.sum\"", "dice d4"])]
+    #[case("dice d20 \"a;b\"; dice d6", &["dice d20 \"a;b\"", "dice d6"])]
+    fn test_split(#[case] line: &str, #[case] cmds: &[&str]) {
+        assert_eq!(cmds.to_vec(), split(line));
+    }
+
+    #[rstest]
+    #[case("# heading for this fight", "")]
+    #[case("  // heading", "")]
+    #[case("!", "")]
+    #[case("dice d20 +2 # initiative", "dice d20 +2")]
+    #[case("dice 3d6 !norules ! no rules", "dice 3d6 !norules")]
+    #[case("dice d20 // to hit", "dice d20")]
+    #[case("dice 3d6#1", "dice 3d6#1")]
+    #[case("dice d20", "dice d20")]
+    fn test_strip_comment(#[case] line: &str, #[case] want: &str) {
+        assert_eq!(want, strip_comment(line));
+    }
+
+    #[rstest]
+    #[case("dice 2d6+4 # goblin damage", Some("goblin damage"))]
+    #[case("dice 2d6+4 //  goblin damage ", Some("goblin damage"))]
+    #[case("# heading", Some("heading"))]
+    #[case("dice d20 #", None)]
+    #[case("dice d20 !norules", None)]
+    fn test_comment(#[case] line: &str, #[case] want: Option<&str>) {
+        assert_eq!(want, comment(line));
+    }
+
+    #[rstest]
+    #[case("dice d20", "dice d20")]
+    #[case("dice d20 \\\n+3", "dice d20  +3")]
+    #[case("dice \\\r\nd20\\\n", "dice  d20 ")]
+    #[case("dice d20\\", "dice d20\\")]
+    fn test_join_continued(#[case] text: &str, #[case] line: &str) {
+        assert_eq!(line, join_continued(text));
+    }
+
+    #[test]
+    fn test_unknown_command_message() {
+        let n = Engine::new();
+        let cc = Compiler::new(&n.cmds);
+
+        assert_eq!(
+            "unknown command lsit, did you mean list?",
+            cc.compile("lsit").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "unknown command foo",
+            cc.compile("foo").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_compile_loop() {
+        let n = Engine::new().merge(vec![
+            Command::Macro {
+                name: "foo".to_string(),
+                cmd: "bar".to_string(),
+            },
+            Command::Macro {
+                name: "bar".to_string(),
+                cmd: "foo".to_string(),
+            },
+        ]);
+        let cc = Compiler::new(&n.cmds);
+        assert!(matches!(
+            cc.compile("foo"),
+            Err(CompilerError::MaxRecursion(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("dice d20 +$str", " d20 +4")]
+    #[case("dice 3D6 +$str +$dex_bonus", " 3D6 +4 +-1")]
+    #[case("dice d20", " d20")]
+    #[case("DICE 3d6 +$str", " 3d6 +4")]
+    #[case("3d6+$str", " 3d6+4")]
+    #[case("  d20 adv as init", " d20 adv as init")]
+    #[case("(2d6+3)*2", " (2d6+3)*2")]
+    fn test_compile_with_vars(#[case] input: &str, #[case] want: &str) {
+        let n = Engine::new();
+        let cc = Compiler::new(&n.cmds);
+        let vars = HashMap::from([("str".to_string(), 4), ("dex_bonus".to_string(), -1)]);
+
+        match cc.compile_with(input, &vars).unwrap() {
+            Action::Execute(_, args, _) => assert_eq!(want, args),
+            a => panic!("unexpected {a:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case("dice 3D6", "dice 3D6")]
+    #[case("doom", "doom -> dice 2D6")]
+    #[case("mouv +2", "mouv +2 -> move +7 +2 -> dice 3D6 -9 +7 +2")]
+    #[case("Doom", "Doom -> dice 2D6")]
+    fn test_compile_trace(#[case] input: &str, #[case] want: &str) {
+        let n = Engine::new().with(Some("testdata/aliases".into()));
+        let cc = Compiler::new(&n.cmds);
+
+        match cc.compile(input).unwrap() {
+            Action::Execute(_, _, trace) => {
+                assert_eq!(want, trace.to_string());
+                assert_eq!(input != want, trace.expanded());
+            }
+            a => panic!("unexpected {a:?}"),
+        }
+    }
+
+    #[test]
+    fn test_compile_with_unknown_var() {
+        let n = Engine::new();
+        let cc = Compiler::new(&n.cmds);
+        assert_eq!(
+            Err(CompilerError::UnknownVariable("con".to_string())),
+            cc.compile_with("dice d20 +$con", &HashMap::new())
+        );
     }
 }
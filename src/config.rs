@@ -0,0 +1,596 @@
+//! Module to load the configuration from `$HOME/.config/dices/config.toml`.
+//!
+//! Every setting is optional, anything not specified in the file keeps its default value.
+//!
+//! Public API:
+//!
+//! ```no_run
+//! # use std::path::PathBuf;
+//! use dices_rs::config::Config;
+//! use dices_rs::engine::Engine;
+//!
+//! let cfg = Config::load(&PathBuf::from("/some/location/config.toml")).unwrap();
+//! let e = Engine::new().with_config(cfg);
+//! ```
+//!
+//! File format:
+//! ```text
+//! # Prompt of the REPL, can use {last_roll}, {count} and {time}
+//! prompt = "Roll> "
+//! # Either "text", "debug", "large", "markdown" or "porcelain"
+//! format = "text"
+//! # Colored results, never used when the output is not a terminal
+//! color = true
+//! # Colors for a "dark" or "light" background, or "mono" without colors
+//! theme = "dark"
+//! # Name and version when the shell starts
+//! banner = true
+//! # Either "emacs" or "vi"
+//! edit_mode = "vi"
+//! # Completion of commands: "circular" (cycle in the line) or "list" (all with description)
+//! completion = "list"
+//! # Keep the history of the shell between runs, off for shared computers
+//! history = true
+//! # History file, default is `history` next to this file
+//! history_file = "/home/me/rpg/history"
+//! # Max number of entries kept in history
+//! history_size = 500
+//! # What is rolled when a command is given no argument
+//! default_dice = "D6"
+//! # Location of the aliases file
+//! alias_file = "/home/me/rpg/aliases"
+//! # Section of the aliases file to use
+//! profile = "dnd"
+//! # Keep variables and labelled rolls between runs in this file
+//! session_file = "/home/me/rpg/session.yaml"
+//! # Record every roll in this SQLite database (needs the `sqlite` feature)
+//! roll_db = "/home/me/rpg/rolls.db"
+//! # Append every roll to this file, one JSON object per line
+//! roll_log = "/home/me/rpg/rolls.log"
+//! # Character files, the first one is in use
+//! characters = ["/home/me/rpg/bob.toml", "/home/me/rpg/alice.yaml"]
+//! # System packs, each mechanic becoming a command
+//! packs = ["/home/me/rpg/savage.yaml"]
+//! # Random tables, default is the `tables` directory next to this file
+//! table_dir = "/home/me/rpg/tables"
+//! # Name recorded with each roll, default is $USER or "player"
+//! actor = "Bob"
+//! # Locale used to display numbers and for the language of messages, default is taken from
+//! # the environment
+//! locale = "fr_FR"
+//! # Command used to speak results, text is given on stdin
+//! speak_command = "espeak --stdin"
+//! # Commands run after a natural or a fumble (a botch too)
+//! on_crit = "dice d6"
+//! on_fumble = "table fumble"
+//! # Allowed dice sizes: "any" (2 to 1000), "physical" or { only = [6, 10] }
+//! dice_sizes = "physical"
+//! # Refuse anything left after the dices and options, like "3D6 oops"
+//! strict = true
+//! # How dices are rolled: "fast", "crypto" (system CSPRNG) or "legacy"
+//! roller = "fast"
+//! # Most times an open-ended dice explodes in one roll
+//! max_explosions = 100
+//!
+//! # Unfair dices used as `dW:<name>`, as [face, weight]
+//! [weighted]
+//! loaded = [[1, 1], [2, 1], [3, 1], [4, 1], [5, 1], [6, 2]]
+//!
+//! # Dices showing symbols used as `d<name>`, each face lists its symbols, "" is blank
+//! [symbolic]
+//! boost = ["", "", "success", "success advantage", "advantage advantage", "advantage"]
+//!
+//! # Default house rules, can be overridden for each roll
+//! [rules]
+//! # Either "none", "adv" or "dis"
+//! advantage = "none"
+//! # Dices totalling this or more is a natural, a range is given as "19-20"
+//! crit = 19
+//! # Dices totalling this or less is a fumble, or a range like "1-2"
+//! fumble = 1
+//! # No special results at all
+//! norules = false
+//! # How divisions are rounded: "zero" (like integers), "down", "up" or "near"
+//! rounding = "zero"
+//!
+//! # Replace messages, names are the ones of `messages::Msg` in snake case
+//! [messages]
+//! available_commands = "Commands"
+//! unknown_command = "what is {0}?"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::dice::explode::MAX_EXPLOSIONS;
+use crate::dice::internal::{Roller, RollerKind};
+use crate::dice::parse::Defined;
+use crate::dice::policy::SizePolicy;
+use crate::dice::rules::Rules;
+use crate::dice::symbolic::Symbols;
+use crate::dice::weighted::Faces;
+use crate::locale::NumberFormat;
+use crate::messages::{Messages, Msg};
+
+/// Default prompt
+const PS1: &str = "Dices> ";
+
+/// Default number of history entries
+const HISTORY_SIZE: usize = 100;
+
+/// Default dice for commands without argument
+const DEFAULT_DICE: &str = "D6";
+
+/// How results are displayed
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Human-readable summary
+    #[default]
+    Text,
+    /// Full dump of the result
+    Debug,
+    /// Total in large-print digits
+    Large,
+    /// Markdown for chat rooms
+    Markdown,
+    /// `total:dices:bonus`, for scripts
+    Porcelain,
+}
+
+/// Colors of the results, the prompt and the hints
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// For dark backgrounds
+    #[default]
+    Dark,
+    /// For light backgrounds, gray instead of dimmed text
+    Light,
+    /// Bold, underlined and dimmed text only, no colors
+    Mono,
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            "mono" => Ok(Theme::Mono),
+            _ => Err(format!("unknown theme {s}, use dark, light or mono")),
+        }
+    }
+}
+
+/// How commands are completed in the REPL
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Completion {
+    /// Cycle through candidates in the line, description shown as a hint
+    #[default]
+    Circular,
+    /// List all candidates with their description
+    List,
+}
+
+/// Line editing mode of the REPL
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EditMode {
+    /// Emacs keybindings
+    #[default]
+    Emacs,
+    /// vi keybindings
+    Vi,
+}
+
+/// All the knobs available to users and embedders
+///
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Prompt displayed by the REPL
+    pub prompt: String,
+    /// How results are displayed
+    pub format: Format,
+    /// Colored results
+    pub color: bool,
+    /// Colors used
+    pub theme: Theme,
+    /// Startup banner of the REPL
+    pub banner: bool,
+    /// Line editing mode
+    pub edit_mode: EditMode,
+    /// Completion style
+    pub completion: Completion,
+    /// Keep the history between runs
+    pub history: bool,
+    /// Location of the history file
+    pub history_file: Option<PathBuf>,
+    /// Max number of entries kept in history
+    pub history_size: usize,
+    /// What is rolled when a command is given no argument
+    pub default_dice: String,
+    /// Location of the aliases file
+    pub alias_file: Option<PathBuf>,
+    /// Section of the aliases file in use
+    pub profile: Option<String>,
+    /// Where variables and labelled rolls are kept between runs
+    pub session_file: Option<PathBuf>,
+    /// Database recording every roll (`sqlite` feature)
+    pub roll_db: Option<PathBuf>,
+    /// Audit trail of every roll, in JSONL
+    pub roll_log: Option<PathBuf>,
+    /// Character files loaded at startup
+    pub characters: Vec<PathBuf>,
+    /// System packs loaded at startup
+    pub packs: Vec<PathBuf>,
+    /// Directory of the random tables
+    pub table_dir: Option<PathBuf>,
+    /// Who is rolling, as recorded in the roll database and log
+    pub actor: Option<String>,
+    /// Locale used to display numbers and for messages
+    pub locale: Option<String>,
+    /// Command used to speak results
+    pub speak_command: Option<String>,
+    /// Command run after a natural roll
+    pub on_crit: Option<String>,
+    /// Command run after a fumble or a botch
+    pub on_fumble: Option<String>,
+    /// Allowed dice sizes
+    pub dice_sizes: SizePolicy,
+    /// Refuse trailing garbage after the dices and options
+    pub strict: bool,
+    /// Generator and algorithm used for rolls
+    pub roller: RollerKind,
+    /// Most times an open-ended dice explodes in one roll
+    pub max_explosions: usize,
+    /// Default house rules
+    pub rules: Rules,
+    /// Weighted dices by name
+    pub weighted: BTreeMap<String, Faces>,
+    /// Symbol dices by name
+    pub symbolic: BTreeMap<String, Symbols>,
+    /// Messages replacing the ones of the catalog
+    pub messages: BTreeMap<Msg, String>,
+    /// File this configuration was loaded from, if any
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// Allow for `.unwrap_or_default()` calls.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            prompt: PS1.to_string(),
+            format: Format::default(),
+            color: true,
+            theme: Theme::default(),
+            banner: true,
+            edit_mode: EditMode::default(),
+            completion: Completion::default(),
+            history: true,
+            history_file: None,
+            history_size: HISTORY_SIZE,
+            default_dice: DEFAULT_DICE.to_string(),
+            alias_file: None,
+            profile: None,
+            session_file: None,
+            roll_db: None,
+            roll_log: None,
+            characters: vec![],
+            packs: vec![],
+            table_dir: None,
+            actor: None,
+            locale: None,
+            messages: BTreeMap::new(),
+            speak_command: None,
+            on_crit: None,
+            on_fumble: None,
+            dice_sizes: SizePolicy::default(),
+            strict: true,
+            roller: RollerKind::default(),
+            max_explosions: MAX_EXPLOSIONS,
+            rules: Rules::default(),
+            weighted: BTreeMap::new(),
+            symbolic: BTreeMap::new(),
+            path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from `fname`, a missing file means all defaults.
+    ///
+    pub fn load(fname: &Path) -> Result<Self> {
+        trace!("config::load({:?})", fname);
+
+        if !fname.exists() {
+            trace!("no config file, using defaults");
+            return Ok(Config::default());
+        }
+        let content = fs::read_to_string(fname)?;
+        Ok(Config {
+            path: Some(fname.to_path_buf()),
+            ..Config::parse(&content)?
+        })
+    }
+
+    /// Parse the content of a configuration file
+    ///
+    pub fn parse(content: &str) -> Result<Self> {
+        toml::from_str(content).map_err(|e| anyhow!("invalid configuration: {}", e))
+    }
+
+    /// Call `f` with a generator of the configured kind and limit of explosions
+    ///
+    pub fn scope<T, F: FnOnce(&mut Roller) -> T>(&self, f: F) -> T {
+        let mut rng = Roller::from(self.roller).with_max_explosions(self.max_explosions);
+        f(&mut rng)
+    }
+
+    /// Dices defined here, for the parsers
+    ///
+    pub fn defined(&self) -> Defined<'_> {
+        Defined {
+            weighted: &self.weighted,
+            symbolic: &self.symbolic,
+        }
+    }
+
+    /// Return the messages in the language of the configured locale or the environment, with
+    /// the texts replaced in `[messages]`
+    ///
+    pub fn messages(&self) -> Messages {
+        let m = match self.locale {
+            Some(ref locale) => Messages::new(locale),
+            None => Messages::from_env(),
+        };
+        self.messages
+            .iter()
+            .fold(m, |m, (msg, text)| m.with(*msg, text))
+    }
+
+    /// Return how numbers are displayed, either from the configured locale or the environment
+    ///
+    pub fn number_format(&self) -> NumberFormat {
+        match self.locale {
+            Some(ref locale) => NumberFormat::new(locale),
+            None => NumberFormat::from_env(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::dice::rules::Span;
+    use crate::dice::{Dice, Rollable};
+    use crate::makepath;
+
+    use super::*;
+
+    #[test]
+    fn test_config_default() {
+        let c = Config::default();
+
+        assert_eq!(PS1, c.prompt);
+        assert_eq!(Format::Text, c.format);
+        assert!(c.color);
+        assert!(c.banner);
+        assert_eq!(EditMode::Emacs, c.edit_mode);
+        assert_eq!(Completion::Circular, c.completion);
+        assert!(c.history);
+        assert!(c.history_file.is_none());
+        assert_eq!(HISTORY_SIZE, c.history_size);
+        assert_eq!(DEFAULT_DICE, c.default_dice);
+        assert!(c.alias_file.is_none());
+        assert!(c.locale.is_none());
+    }
+
+    #[test]
+    fn test_config_load() {
+        let fname: PathBuf = makepath!("testdata", "config.toml");
+
+        let c = Config::load(&fname).unwrap();
+
+        assert_eq!("Roll> ", c.prompt);
+        assert_eq!(Format::Debug, c.format);
+        assert_eq!(EditMode::Vi, c.edit_mode);
+        assert_eq!(500, c.history_size);
+        assert_eq!("D10", c.default_dice);
+        assert_eq!(Some(makepath!("testdata", "aliases")), c.alias_file);
+        assert_eq!(Some(fname), c.path);
+    }
+
+    #[test]
+    fn test_config_load_missing() {
+        let fname: PathBuf = makepath!("testdata", "nonexistent.toml");
+
+        let c = Config::load(&fname).unwrap();
+        assert_eq!(Config::default(), c);
+    }
+
+    #[test]
+    fn test_config_parse_partial() {
+        let c = Config::parse("prompt = \"> \"").unwrap();
+
+        assert_eq!("> ", c.prompt);
+        assert_eq!(HISTORY_SIZE, c.history_size);
+    }
+
+    #[test]
+    fn test_config_history() {
+        let c = Config::parse("history = false\nhistory_file = \"/tmp/hist\"\nhistory_size = 10")
+            .unwrap();
+
+        assert!(!c.history);
+        assert_eq!(Some(PathBuf::from("/tmp/hist")), c.history_file);
+        assert_eq!(10, c.history_size);
+    }
+
+    #[test]
+    fn test_config_roller() {
+        let c = Config::parse("roller = \"crypto\"").unwrap();
+
+        assert_eq!(RollerKind::Crypto, c.roller);
+        assert!(Config::parse("roller = \"dev_random\"").is_err());
+    }
+
+    #[test]
+    fn test_config_max_explosions() {
+        assert_eq!(MAX_EXPLOSIONS, Config::default().max_explosions);
+
+        let c = Config::parse("max_explosions = 0").unwrap();
+        let r = c.scope(|rng| Dice::Open(2).roll_with(rng));
+        assert_eq!(1, r.list.len());
+        assert_eq!(r.list[0] == 2, r.capped);
+    }
+
+    #[test]
+    fn test_config_roll_log() {
+        let c = Config::parse("roll_log = \"rolls.log\"").unwrap();
+
+        assert_eq!(Some(PathBuf::from("rolls.log")), c.roll_log);
+    }
+
+    #[test]
+    fn test_config_weighted() {
+        let c = Config::parse("[weighted]\nloaded = [[1, 1], [6, 2]]").unwrap();
+
+        assert_eq!(
+            Some(&Faces::try_from(vec![(1, 1), (6, 2)]).unwrap()),
+            c.weighted.get("loaded")
+        );
+        assert!(Config::parse("[weighted]\nbroken = [[1, 0]]").is_err());
+    }
+
+    #[test]
+    fn test_config_symbolic() {
+        let c = Config::parse("[symbolic]\nattack = [\"hit\", \"hit crit\", \"\"]").unwrap();
+
+        assert!(c.symbolic.contains_key("attack"));
+        assert!(Config::parse("[symbolic]\nbroken = []").is_err());
+    }
+
+    #[test]
+    fn test_config_profile() {
+        let c = Config::parse("profile = \"dnd\"").unwrap();
+
+        assert_eq!(Some("dnd".to_string()), c.profile);
+    }
+
+    #[test]
+    fn test_config_characters() {
+        let c = Config::parse("characters = [\"bob.toml\", \"alice.yaml\"]").unwrap();
+
+        assert_eq!(
+            vec![PathBuf::from("bob.toml"), PathBuf::from("alice.yaml")],
+            c.characters
+        );
+    }
+
+    #[test]
+    fn test_config_hooks() {
+        let c = Config::parse("on_crit = \"dice d6\"\non_fumble = \"table fumble\"").unwrap();
+
+        assert_eq!(Some("dice d6"), c.on_crit.as_deref());
+        assert_eq!(Some("table fumble"), c.on_fumble.as_deref());
+    }
+
+    #[test]
+    fn test_config_packs() {
+        let c = Config::parse("packs = [\"savage.yaml\"]").unwrap();
+
+        assert_eq!(vec![PathBuf::from("savage.yaml")], c.packs);
+    }
+
+    #[test]
+    fn test_config_table_dir() {
+        let c = Config::parse("table_dir = \"tables\"").unwrap();
+
+        assert_eq!(Some(PathBuf::from("tables")), c.table_dir);
+    }
+
+    #[test]
+    fn test_config_number_format() {
+        let c = Config::parse("locale = \"fr_FR.UTF-8\"").unwrap();
+
+        assert_eq!(NumberFormat::new("fr"), c.number_format());
+    }
+
+    #[test]
+    fn test_config_rules() {
+        let c = Config::parse("[rules]\ncrit = 19").unwrap();
+
+        assert_eq!(Some(Span::AtLeast(19)), c.rules.crit);
+        assert!(!c.rules.norules);
+    }
+
+    #[rstest]
+    #[case("", SizePolicy::Any)]
+    #[case("dice_sizes = \"physical\"", SizePolicy::Physical)]
+    #[case("dice_sizes = { only = [6, 10] }", SizePolicy::Only(vec![6, 10]))]
+    fn test_config_dice_sizes(#[case] content: &str, #[case] want: SizePolicy) {
+        let c = Config::parse(content).unwrap();
+
+        assert_eq!(want, c.dice_sizes);
+    }
+
+    #[rstest]
+    #[case("completion = \"list\"", Completion::List)]
+    #[case("completion = \"circular\"", Completion::Circular)]
+    fn test_config_completion(#[case] content: &str, #[case] want: Completion) {
+        let c = Config::parse(content).unwrap();
+
+        assert_eq!(want, c.completion);
+    }
+
+    #[rstest]
+    #[case("", Theme::Dark)]
+    #[case("theme = \"light\"", Theme::Light)]
+    #[case("theme = \"mono\"", Theme::Mono)]
+    fn test_config_theme(#[case] content: &str, #[case] want: Theme) {
+        let c = Config::parse(content).unwrap();
+
+        assert_eq!(want, c.theme);
+        assert_eq!(Ok(want), format!("{want:?}").to_lowercase().parse());
+    }
+
+    #[test]
+    fn test_config_messages() {
+        let c = Config::parse(
+            "locale = \"fr_FR\"\n[messages]\nunknown_command = \"{0} ? connais pas\"",
+        )
+        .unwrap();
+        let m = c.messages();
+
+        assert_eq!("{0} ? connais pas", m.get(Msg::UnknownCommand));
+        assert_eq!("dont bonus", m.get(Msg::InclBonus));
+        assert!(Config::parse("[messages]\nfoo = \"bar\"").is_err());
+    }
+
+    #[test]
+    fn test_config_parse_invalid() {
+        assert!(Config::parse("edit_mode = \"nano\"").is_err());
+    }
+
+    #[test]
+    fn test_config_theme_invalid() {
+        assert!(Config::parse("theme = \"pink\"").is_err());
+        assert!("pink".parse::<Theme>().is_err());
+    }
+}
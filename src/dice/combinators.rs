@@ -0,0 +1,202 @@
+//! Combinators to build complex mechanics out of simple `Rollable` things.
+//!
+//! - `Best(a, b)`      roll both, keep the best total
+//! - `Worst(a, b)`     roll both, keep the worst total
+//! - `Sum(vec)`        roll everything and add the results
+//! - `Repeat(n, r)`    roll `r` `n` times and add the results
+//! - `Map(r, f)`       roll `r` and transform the result with `f`
+//!
+//! They can not fail, so the string grammar only uses them where that holds: the `adv` and
+//! `dis` rules keep the best or worst with `Best` and `Worst` (or `best` and `worst` for an
+//! `Expr`), and the dice terms of an `Expr` are rolled with `Map`.  The operators of an `Expr` are evaluated by `Expr::eval`
+//! itself, as a division by a roll of zero is an error.
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::combinators::{Best, Map, Repeat};
+//! use dices_rs::dice::result::Res;
+//! use dices_rs::dice::{Dice, Rollable};
+//!
+//! // 4D6 twice, keep the best, then add 1
+//! let r = Map(Best(Repeat(4, Dice::Regular(6)), Repeat(4, Dice::Regular(6))), |mut r: Res| {
+//!     r.sum += 1;
+//!     r
+//! });
+//!
+//! println!("{}", r.roll());
+//! ```
+
+use crate::dice::internal::Roller;
+use crate::dice::result::Res;
+use crate::dice::Rollable;
+
+/// Roll both, keep the best total
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Best<A, B>(pub A, pub B);
+
+/// Roll both, keep the worst total
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Worst<A, B>(pub A, pub B);
+
+/// Roll everything and add the results
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sum<T>(pub Vec<T>);
+
+/// Roll the same thing `n` times and add the results
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Repeat<T>(pub usize, pub T);
+
+/// Roll and transform the result
+///
+#[derive(Clone, Debug)]
+pub struct Map<T, F>(pub T, pub F);
+
+/// The result `Best` keeps, the first one on a tie
+///
+pub fn best(a: Res, b: Res) -> Res {
+    if a.sum >= b.sum {
+        a
+    } else {
+        b
+    }
+}
+
+/// The result `Worst` keeps, the first one on a tie
+///
+pub fn worst(a: Res, b: Res) -> Res {
+    if a.sum <= b.sum {
+        a
+    } else {
+        b
+    }
+}
+
+impl<A: Rollable, B: Rollable> Rollable for Best<A, B> {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        let a = self.0.roll_with(rng);
+        best(a, self.1.roll_with(rng))
+    }
+}
+
+impl<A: Rollable, B: Rollable> Rollable for Worst<A, B> {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        let a = self.0.roll_with(rng);
+        worst(a, self.1.roll_with(rng))
+    }
+}
+
+impl<T: Rollable> Rollable for Sum<T> {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        self.0
+            .iter()
+            .fold(Res::new(), |acc, r| acc + r.roll_with(rng))
+    }
+}
+
+impl<T: Rollable> Rollable for Repeat<T> {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        (0..self.0).fold(Res::new(), |acc, _| acc + self.1.roll_with(rng))
+    }
+}
+
+impl<T: Rollable, F: Fn(Res) -> Res> Rollable for Map<T, F> {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        (self.1)(self.0.roll_with(rng))
+    }
+}
+
+/// Allow combinators to borrow what they roll
+///
+impl<T: Rollable + ?Sized> Rollable for &T {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        (**self).roll_with(rng)
+    }
+}
+
+/// Allow combinators over trait objects
+///
+impl<T: Rollable + ?Sized> Rollable for Box<T> {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        (**self).roll_with(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::dice::Dice;
+
+    use super::*;
+
+    #[test]
+    fn test_best_worst() {
+        let b = Best(Dice::Constant(3), Dice::Constant(5));
+        let w = Worst(Dice::Constant(3), Dice::Constant(5));
+
+        assert_eq!(5, b.roll().sum);
+        assert_eq!(3, w.roll().sum);
+    }
+
+    #[test]
+    fn test_best_worst_tie() {
+        let a = Dice::Constant(3).roll();
+        let b = Dice::Bonus(3).roll();
+
+        assert_eq!(a, best(a.clone(), b.clone()));
+        assert_eq!(a, worst(a.clone(), b));
+    }
+
+    #[test]
+    fn test_sum() {
+        let s = Sum(vec![Dice::Constant(3), Dice::Constant(5), Dice::Bonus(-1)]);
+
+        let r = s.roll();
+        assert_eq!(7, r.sum);
+        assert_eq!(vec![3, 5], r.list);
+        assert_eq!(-1, r.bonus);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let r = Repeat(4, Dice::Regular(6)).roll();
+
+        assert_eq!(4, r.list.len());
+        assert!(r.sum >= 4 && r.sum <= 24);
+    }
+
+    #[test]
+    fn test_map() {
+        let m = Map(Dice::Constant(10), |mut r: Res| {
+            r.sum /= 2;
+            r
+        });
+
+        assert_eq!(5, m.roll().sum);
+    }
+
+    #[test]
+    fn test_sum_dyn() {
+        let s: Sum<Box<dyn Rollable>> = Sum(vec![
+            Box::new(Dice::Constant(3)),
+            Box::new(Repeat(2, Dice::Constant(5))),
+        ]);
+
+        assert_eq!(13, s.roll().sum);
+    }
+
+    #[test]
+    fn test_nested_seeded() {
+        let d = Repeat(3, Dice::Regular(6));
+        let r = Best(&d, &d);
+
+        let a = r.roll_with(&mut Roller::new(StdRng::seed_from_u64(7)));
+        let b = r.roll_with(&mut Roller::new(StdRng::seed_from_u64(7)));
+        assert_eq!(a, b);
+        assert_eq!(3, a.list.len());
+    }
+}
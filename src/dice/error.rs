@@ -0,0 +1,125 @@
+//! Errors returned by the `dice` module.
+//!
+
+use std::fmt::{Display, Formatter};
+
+use nom::error::{Error as NomError, ErrorKind};
+use thiserror::Error;
+
+/// Most dices in a group like `255D6`
+pub const MAX_DICES: usize = u8::MAX as usize;
+
+/// Everything that can go wrong while parsing or rolling dices
+///
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum DiceError {
+    /// The expression is not valid dice notation
+    #[error(transparent)]
+    Parse(ParseError),
+    /// More dices in a group than `MAX_DICES`, like `1000D6`
+    #[error("dice count too large (max {MAX_DICES})")]
+    TooManyDices,
+    /// Dice size refused by the `SizePolicy`
+    #[error("D{size} is not allowed, use one of {allowed}")]
+    InvalidSize { size: usize, allowed: String },
+    /// Something is left after the expression, `col` is where it starts in `input`
+    #[error("unexpected {rest:?} at column {}\n  {input}\n  {}^", .col + 1, " ".repeat(*.col))]
+    Trailing {
+        input: String,
+        rest: String,
+        col: usize,
+    },
+    /// Division by zero in an expression
+    #[error("division by zero")]
+    DivisionByZero,
+    /// A total too large to compute, like `99999*99999*99999*99999*99999`
+    #[error("result too large")]
+    Overflow,
+}
+
+/// A count of dices too large is worth its own error
+///
+impl From<ParseError> for DiceError {
+    fn from(e: ParseError) -> Self {
+        match e.expected {
+            Expected::Count => DiceError::TooManyDices,
+            _ => DiceError::Parse(e),
+        }
+    }
+}
+
+/// What the parser was looking for when it failed
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Expected {
+    /// Start of an expression
+    Dice,
+    /// Size of the dice after `D`
+    Size,
+    /// Right side of an operator
+    Operand,
+    /// End of a parenthesis
+    Paren,
+    /// A pick or reroll keeping at least one value
+    Keep,
+    /// Name of a weighted dice after `DW:`
+    Weighted,
+    /// At most `MAX_DICES` dices in a group
+    Count,
+}
+
+impl Display for Expected {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expected::Dice => write!(f, "a dice like 3D6 or a number"),
+            Expected::Size => write!(f, "the size or name of the dice after D"),
+            Expected::Operand => write!(f, "a number, a dice or '(' after the operator"),
+            Expected::Paren => write!(f, "')'"),
+            Expected::Keep => write!(f, "a pick or reroll keeping at least one value"),
+            Expected::Weighted => write!(f, "a weighted dice defined in the configuration"),
+            Expected::Count => write!(f, "at most {MAX_DICES} dices"),
+        }
+    }
+}
+
+/// Where and why a dice expression does not parse, `offset` is in bytes from the start of
+/// `input`
+///
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+#[error("invalid dice expression {input:?}: expected {expected} at offset {offset}")]
+pub struct ParseError {
+    pub input: String,
+    pub offset: usize,
+    pub expected: Expected,
+}
+
+impl ParseError {
+    /// Make sense of the `nom` error `e` raised while parsing `input`
+    ///
+    pub fn new(input: &str, e: &nom::Err<NomError<&str>>) -> Self {
+        let (rest, code) = match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => (e.input, e.code),
+            nom::Err::Incomplete(_) => ("", ErrorKind::Eof),
+        };
+        let input = input.trim_start();
+        let offset = input.len().saturating_sub(rest.len());
+        let before = input[..offset].trim_end();
+        let expected = match code {
+            ErrorKind::Verify => Expected::Keep,
+            ErrorKind::TooLarge => Expected::Count,
+            ErrorKind::Char => Expected::Paren,
+            ErrorKind::MapOpt if before.ends_with(':') => Expected::Weighted,
+            _ => match before.chars().last() {
+                Some('d' | 'D') => Expected::Size,
+                Some('+' | '-' | '*' | '/' | '(') => Expected::Operand,
+                _ => Expected::Dice,
+            },
+        };
+        let input = input.trim_end();
+        ParseError {
+            input: input.to_string(),
+            offset: offset.min(input.len()),
+            expected,
+        }
+    }
+}
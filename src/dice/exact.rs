@@ -0,0 +1,219 @@
+//! Exact probabilities of a dice expression, computed by convolution.
+//!
+//! Regular, constant and weighted dices are independent so the probability of each total of
+//! an expression can be computed exactly by combining them two by two.  Anything else (open
+//! dices, rerolls, picks, custom dices) has no exact form here and `exact` returns `None`, a
+//! simulation (see `simulate`) being the way to go, as it is for totals too large to compute.
+//! Divisions are rounded towards zero, `exact_rounded` rounding them like `Expr::eval_rounded`.
+//!
+//! ```
+//! use dices_rs::dice::exact::exact;
+//! use dices_rs::dice::expr::Expr;
+//!
+//! let p = exact(&Expr::parse("2D6").unwrap()).unwrap();
+//!
+//! assert!((p.0[&7] - 6.0 / 36.0).abs() < 1e-9);
+//! assert!((p.mean() - 7.0).abs() < 1e-9);
+//! assert!(exact(&Expr::parse("4D6dl1").unwrap()).is_none());
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::dice::expr::Expr;
+use crate::dice::rules::Rounding;
+use crate::dice::simulate::Distribution;
+use crate::dice::{Dice, DiceSet};
+
+/// Combining two distributions with more pairs of values than this is left to a simulation
+pub const MAX_PAIRS: usize = 1_000_000;
+
+/// Probability of each total, summing to 1
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Probabilities(pub BTreeMap<isize, f64>);
+
+impl Probabilities {
+    /// Always `v`
+    ///
+    fn point(v: isize) -> Self {
+        Probabilities(BTreeMap::from([(v, 1.0)]))
+    }
+
+    /// Totals of `a` and `b` combined with `f`, `None` if there are too many or `f` fails
+    ///
+    fn combine(&self, other: &Self, f: impl Fn(isize, isize) -> Option<isize>) -> Option<Self> {
+        if self.0.len() * other.0.len() > MAX_PAIRS {
+            return None;
+        }
+        let mut r = BTreeMap::new();
+        for (a, pa) in &self.0 {
+            for (b, pb) in &other.0 {
+                *r.entry(f(*a, *b)?).or_insert(0.0) += pa * pb;
+            }
+        }
+        Some(Probabilities(r))
+    }
+
+    /// Probability of rolling `v` or more
+    ///
+    pub fn at_least(&self, v: isize) -> f64 {
+        self.0.range(v..).map(|(_, p)| p).sum()
+    }
+
+    /// Average total
+    ///
+    pub fn mean(&self) -> f64 {
+        self.0.iter().map(|(v, p)| *v as f64 * p).sum()
+    }
+}
+
+impl From<&Distribution> for Probabilities {
+    /// Frequencies of a simulation
+    ///
+    fn from(d: &Distribution) -> Self {
+        let n = d.n.max(1) as f64;
+        Probabilities(d.counts.iter().map(|(v, c)| (*v, *c as f64 / n)).collect())
+    }
+}
+
+/// Exact probabilities of the totals of `expr`, if it only has independent dices
+///
+pub fn exact(expr: &Expr) -> Option<Probabilities> {
+    exact_rounded(expr, Rounding::default())
+}
+
+/// Same as `exact` with divisions rounded as told
+///
+pub fn exact_rounded(expr: &Expr, rounding: Rounding) -> Option<Probabilities> {
+    let exact = |e| exact_rounded(e, rounding);
+    match expr {
+        Expr::Num(n) => Some(Probabilities::point(*n)),
+        Expr::Dices(ds) => dices(ds),
+        Expr::Pick(..) => None,
+        Expr::Neg(e) => exact(e)?
+            .0
+            .into_iter()
+            .map(|(v, p)| Some((v.checked_neg()?, p)))
+            .collect::<Option<_>>()
+            .map(Probabilities),
+        Expr::Add(a, b) => exact(a)?.combine(&exact(b)?, isize::checked_add),
+        Expr::Sub(a, b) => exact(a)?.combine(&exact(b)?, isize::checked_sub),
+        Expr::Mul(a, b) => exact(a)?.combine(&exact(b)?, isize::checked_mul),
+        Expr::Div(a, b) => exact(a)?.combine(&exact(b)?, |a, b| rounding.div(a, b)),
+    }
+}
+
+/// Sum of the dices of a set, bonuses are not part of it (see `Expr::eval`)
+///
+fn dices(ds: &DiceSet) -> Option<Probabilities> {
+    ds.iter().try_fold(Probabilities::point(0), |acc, d| {
+        acc.combine(&dice(d)?, isize::checked_add)
+    })
+}
+
+/// Probabilities of the faces of one dice
+///
+fn dice(d: &Dice) -> Option<Probabilities> {
+    match d {
+        Dice::Regular(s) => {
+            let p = 1.0 / *s as f64;
+            Some(Probabilities((1..=*s as isize).map(|v| (v, p)).collect()))
+        }
+        Dice::Constant(c) => Some(Probabilities::point(*c as isize)),
+        Dice::Bonus(_) | Dice::Symbolic(_) => Some(Probabilities::point(0)),
+        Dice::Weighted(faces) => {
+            let total = faces.iter().map(|(_, w)| w).sum::<usize>().max(1) as f64;
+            let mut p = BTreeMap::new();
+            faces
+                .iter()
+                .for_each(|(f, w)| *p.entry(*f as isize).or_insert(0.0) += *w as f64 / total);
+            Some(Probabilities(p))
+        }
+        Dice::Open(_) | Dice::OpenHighLow(_) | Dice::Reroll(..) | Dice::Custom(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn p(input: &str) -> Option<Probabilities> {
+        exact(&Expr::parse(input).unwrap())
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[rstest]
+    #[case("3D6", 3, 18, 10.5)]
+    #[case("d20 + 5", 6, 25, 15.5)]
+    #[case("-d4", -4, -1, -2.5)]
+    #[case("2 * d6", 2, 12, 7.0)]
+    #[case("(d6 + 1) / 2", 1, 3, 2.0)]
+    #[case("d6 - d6", -5, 5, 0.0)]
+    fn test_exact(#[case] input: &str, #[case] min: isize, #[case] max: isize, #[case] mean: f64) {
+        let p = p(input).unwrap();
+
+        assert_eq!(Some(&min), p.0.keys().next());
+        assert_eq!(Some(&max), p.0.keys().next_back());
+        assert!(close(1.0, p.0.values().sum()), "{p:?}");
+        assert!(close(mean, p.mean()), "{p:?}");
+    }
+
+    #[rstest]
+    #[case(Rounding::Zero, 2.0)]
+    #[case(Rounding::Up, 2.5)]
+    #[case(Rounding::Near, 2.5)]
+    fn test_exact_rounded(#[case] rounding: Rounding, #[case] mean: f64) {
+        let p = exact_rounded(&Expr::parse("(d6 + 1) / 2").unwrap(), rounding).unwrap();
+        assert!(close(mean, p.mean()), "{p:?}");
+    }
+
+    #[test]
+    fn test_exact_3d6() {
+        let p = p("3D6").unwrap();
+
+        assert!(close(1.0 / 216.0, p.0[&3]));
+        assert!(close(27.0 / 216.0, p.0[&10]));
+        assert!(close(0.5, p.at_least(11)));
+        assert!(close(1.0, p.at_least(-1)));
+        assert_eq!(0.0, p.at_least(19));
+    }
+
+    #[test]
+    fn test_exact_weighted() {
+        let p = dice(&Dice::Weighted(vec![(1, 1), (6, 2), (6, 1)])).unwrap();
+
+        assert!(close(0.75, p.0[&6]));
+    }
+
+    #[rstest]
+    #[case("open")]
+    #[case("4D6dl1")]
+    #[case("2D6r1")]
+    #[case("d6 / (d6 - d6)")]
+    #[case("60D20 * 60D20")]
+    #[case("4294967295*4294967295*4294967295")]
+    #[case("-(4294967295*4294967295) - 4294967295*4294967295")]
+    fn test_exact_none(#[case] input: &str) {
+        let expr = match input {
+            "open" => Expr::Dices(DiceSet::from(Dice::Open(6))),
+            input => Expr::parse(input).unwrap(),
+        };
+        assert!(exact(&expr).is_none());
+    }
+
+    #[test]
+    fn test_from_distribution() {
+        let mut d = Distribution::default();
+        [1, 1, 2, 4].iter().for_each(|v| d.add(*v));
+        let p = Probabilities::from(&d);
+
+        assert_eq!(Some(&0.5), p.0.get(&1));
+        assert_eq!(0.25, p.at_least(3));
+        assert_eq!(2.0, p.mean());
+    }
+}
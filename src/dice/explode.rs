@@ -0,0 +1,62 @@
+//! How many times open-ended dices may explode in one roll.
+//!
+//! An open `D6` rolls again on every 6, a `D100` of Rolemaster on every 96+ and so on, which
+//! does not end when every roll explodes.  After `Roller::max_explosions()` more rolls the
+//! dice stops and the result has `Res::capped` set.  The cap is `MAX_EXPLOSIONS` unless
+//! changed with `Roller::with_max_explosions` (`max_explosions` in the configuration).
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::internal::Roller;
+//! use dices_rs::dice::{Dice, Rollable};
+//!
+//! let r = Dice::Open(2).roll_with(&mut Roller::default().with_max_explosions(0));
+//!
+//! assert_eq!(1, r.list.len());
+//! assert_eq!(r.list[0] == 2, r.capped);
+//! ```
+
+/// Explosions allowed by default
+pub const MAX_EXPLOSIONS: usize = 100;
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use crate::dice::internal::Roller;
+    use crate::dice::{Dice, DiceSet, Rollable};
+
+    use super::*;
+
+    #[test]
+    fn test_max_explosions() {
+        assert_eq!(MAX_EXPLOSIONS, Roller::default().max_explosions());
+        assert_eq!(3, Roller::default().with_max_explosions(3).max_explosions());
+    }
+
+    #[rstest]
+    #[case(Dice::Open(2))]
+    #[case(Dice::OpenHighLow(2))]
+    fn test_capped(#[case] d: Dice) {
+        let mut rng = Roller::new(StdRng::seed_from_u64(42)).with_max_explosions(2);
+
+        let all = (0..200).map(|_| d.roll_with(&mut rng)).collect::<Vec<_>>();
+        assert!(all.iter().all(|r| r.list.len() <= 3));
+        assert!(all.iter().any(|r| r.capped));
+        assert!(all.iter().filter(|r| r.capped).all(|r| r.list.len() == 3));
+        assert!(all.iter().any(|r| !r.capped));
+    }
+
+    #[test]
+    fn test_capped_set() {
+        let ds = DiceSet::parse("3D2").unwrap();
+        let d = DiceSet::from(Dice::Open(2));
+        let mut rng = Roller::new(StdRng::seed_from_u64(7)).with_max_explosions(0);
+
+        let r = (0..50)
+            .map(|_| d.roll_with(&mut rng) + ds.roll_with(&mut rng))
+            .find(|r| r.capped);
+        assert!(r.is_some());
+    }
+}
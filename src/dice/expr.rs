@@ -0,0 +1,714 @@
+//! Arithmetic expressions with dice terms.
+//!
+//! Grammar, from lowest to highest precedence:
+//!
+//! ```text
+//! expr   := term   ( ('+' | '-') term )*
+//! term   := factor ( ('*' | '/') factor )*
+//! factor := '(' expr ')' | '-' factor | <n>D<s>[pick] | number
+//! ```
+//!
+//! Spaces are allowed around operators.  Division is an integer one, rounding towards zero.
+//! Totals too large to compute are a `DiceError::Overflow`.
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::expr::parse_expr;
+//! use dices_rs::dice::internal::Roller;
+//!
+//! let (_, e) = parse_expr("(2D6 + 3) * 2").unwrap();
+//! let r = e.eval(&mut Roller::default()).unwrap();
+//!
+//! assert_eq!(2, r.list.len());
+//! assert_eq!(6, r.bonus);
+//! ```
+//!
+//! Two expressions rolling the same thing can be compared through their canonical form, which
+//! is also what `Display` is meant to be used on for keys.  `==` stays the derived, structural
+//! equality of the trees: `canonicalize` and the parser tests need it and a canonicalizing
+//! `PartialEq` would build two new trees for every comparison.  `equivalent` is the canonical
+//! one, used for instance to find macros rolling the same dices (see `engine::aliases`):
+//! ```
+//! use dices_rs::dice::expr::parse_expr;
+//!
+//! let (_, a) = parse_expr("1 + d20 + 2").unwrap();
+//! let (_, b) = parse_expr("D20+3").unwrap();
+//!
+//! assert!(a.equivalent(&b));
+//! assert_eq!("D20 + 3", a.canonicalize().to_string());
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use log::trace;
+use nom::{
+    branch::alt,
+    character::complete::{char, one_of, space0, u32},
+    combinator::{cut, map},
+    multi::fold_many0,
+    sequence::{delimited, pair, preceded},
+    IResult,
+};
+
+use crate::dice::combinators::Map;
+use crate::dice::error::{DiceError, ParseError};
+use crate::dice::internal::Roller;
+use crate::dice::parse::{check_trailing, parse_picked, Defined};
+use crate::dice::pick::Pick;
+use crate::dice::policy::SizePolicy;
+use crate::dice::result::Res;
+use crate::dice::rules::Rounding;
+use crate::dice::{Dice, DiceSet, Rollable};
+
+/// Our expression tree
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    /// A constant
+    Num(isize),
+    /// Some dices
+    Dices(DiceSet),
+    /// Some dices, only some of them kept
+    Pick(DiceSet, Pick),
+    /// Unary minus
+    Neg(Box<Expr>),
+    /// Binary operations
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Parse the whole of `input`, anything left after the expression being an error
+    ///
+    pub fn parse(input: &str) -> Result<Self, DiceError> {
+        Self::parse_with(input, &Defined::default())
+    }
+
+    /// Same as `parse` with the dices of `defined`
+    ///
+    pub fn parse_with(input: &str, defined: &Defined) -> Result<Self, DiceError> {
+        let (rest, e) = preceded(space0, |i| parse_expr_with(i, defined))(input)
+            .map_err(|e| DiceError::from(ParseError::new(input, &e)))?;
+        check_trailing(input, rest)?;
+        Ok(e)
+    }
+
+    /// Roll all dices and compute the value.  `list` has all the dices rolled and `bonus`
+    /// is the part not coming from dices, scaled when multiplied or divided by a constant.
+    /// Divisions are rounded towards zero.
+    ///
+    pub fn eval(&self, rng: &mut Roller) -> Result<Res, DiceError> {
+        self.eval_rounded(rng, Rounding::default())
+    }
+
+    /// Same as `eval` with divisions rounded as told
+    ///
+    pub fn eval_rounded(&self, rng: &mut Roller, rounding: Rounding) -> Result<Res, DiceError> {
+        let eval = |e: &Expr, rng: &mut Roller| e.eval_rounded(rng, rounding);
+        let res = match self {
+            Expr::Num(n) => {
+                let mut r = Res::new();
+                r.add_bonus(*n);
+                r
+            }
+            Expr::Dices(ds) => Map(ds, dices_only).roll_with(rng),
+            Expr::Pick(ds, p) => Map(ds, |r| p.apply(dices_only(r))).roll_with(rng),
+            Expr::Neg(e) => negated(eval(e, rng)?)?,
+            Expr::Add(a, b) => added(eval(a, rng)?, eval(b, rng)?)?,
+            Expr::Sub(a, b) => added(eval(a, rng)?, negated(eval(b, rng)?)?)?,
+            Expr::Mul(a, b) => {
+                let (a, b) = (eval(a, rng)?, eval(b, rng)?);
+                let sum = a.sum.checked_mul(b.sum).ok_or(DiceError::Overflow)?;
+                let bonus = scaled_bonus(&a, &b, isize::checked_mul)?;
+                Res {
+                    sum,
+                    bonus,
+                    ..joined(a, b)
+                }
+            }
+            Expr::Div(a, b) => {
+                let (a, b) = (eval(a, rng)?, eval(b, rng)?);
+                let div = |x| match b.sum {
+                    0 => Err(DiceError::DivisionByZero),
+                    d => rounding.div(x, d).ok_or(DiceError::Overflow),
+                };
+                let sum = div(a.sum)?;
+                let bonus = if b.list.is_empty() { div(a.bonus)? } else { 0 };
+                Res {
+                    sum,
+                    bonus,
+                    ..joined(a, b)
+                }
+            }
+        };
+        Ok(res)
+    }
+
+    /// Check every dice in the expression against the `SizePolicy`
+    ///
+    pub fn validate(&self, policy: &SizePolicy) -> Result<(), DiceError> {
+        match self {
+            Expr::Num(_) => Ok(()),
+            Expr::Dices(ds) | Expr::Pick(ds, _) => policy.check(ds),
+            Expr::Neg(e) => e.validate(policy),
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) => {
+                a.validate(policy)?;
+                b.validate(policy)
+            }
+        }
+    }
+
+    /// Sizes of all the dices in the expression, in order
+    ///
+    pub fn sizes(&self) -> Vec<usize> {
+        match self {
+            Expr::Num(_) => vec![],
+            Expr::Dices(ds) | Expr::Pick(ds, _) => ds.sizes(),
+            Expr::Neg(e) => e.sizes(),
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) => {
+                [a.sizes(), b.sizes()].concat()
+            }
+        }
+    }
+}
+
+impl Expr {
+    /// Canonical form: sums and products are flattened and sorted (dices by decreasing size
+    /// first, constants last), constants are merged and identical dices are grouped so that
+    /// `1 + d6 + 2` and `D6 +3` give the same tree.
+    ///
+    pub fn canonicalize(&self) -> Expr {
+        match self {
+            Expr::Num(_) | Expr::Pick(..) => self.clone(),
+            Expr::Dices(ds) => {
+                let mut v = ds.terms().to_vec();
+                v.sort_by_key(|(_, d)| Reverse(d.size()));
+                let mut ds = DiceSet::from_vec(vec![]);
+                v.into_iter().for_each(|(n, d)| ds.push_n(n, d));
+                Expr::Dices(ds)
+            }
+            Expr::Neg(e) => match e.canonicalize() {
+                Expr::Num(n) if n != isize::MIN => Expr::Num(-n),
+                Expr::Neg(e) => *e,
+                e => Expr::Neg(Box::new(e)),
+            },
+            Expr::Add(..) | Expr::Sub(..) => {
+                let mut terms = vec![];
+                self.terms(true, &mut terms);
+                canonical_sum(terms)
+            }
+            Expr::Mul(..) => {
+                let mut factors = vec![];
+                self.factors(&mut factors);
+                canonical_product(factors)
+            }
+            Expr::Div(a, b) => Expr::Div(Box::new(a.canonicalize()), Box::new(b.canonicalize())),
+        }
+    }
+
+    /// Do both expressions roll the same thing?
+    ///
+    pub fn equivalent(&self, other: &Expr) -> bool {
+        self.canonicalize() == other.canonicalize()
+    }
+
+    /// Flatten a sum into its signed (canonical) terms
+    ///
+    fn terms(&self, sign: bool, acc: &mut Vec<(bool, Expr)>) {
+        match self {
+            Expr::Add(a, b) => {
+                a.terms(sign, acc);
+                b.terms(sign, acc);
+            }
+            Expr::Sub(a, b) => {
+                a.terms(sign, acc);
+                b.terms(!sign, acc);
+            }
+            Expr::Neg(e) => e.terms(!sign, acc),
+            e => acc.push((sign, e.canonicalize())),
+        }
+    }
+
+    /// Flatten a product into its (canonical) factors
+    ///
+    fn factors(&self, acc: &mut Vec<Expr>) {
+        match self {
+            Expr::Mul(a, b) => {
+                a.factors(acc);
+                b.factors(acc);
+            }
+            e => acc.push(e.canonicalize()),
+        }
+    }
+
+    /// Precedence for `Display`, higher binds tighter
+    ///
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Add(..) | Expr::Sub(..) => 1,
+            Expr::Mul(..) | Expr::Div(..) => 2,
+            Expr::Neg(_) => 3,
+            _ => 4,
+        }
+    }
+
+    /// Display `self`, within parentheses if it binds less than `min`
+    ///
+    fn fmt_prec(&self, f: &mut Formatter<'_>, min: u8) -> std::fmt::Result {
+        if self.precedence() < min {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+}
+
+/// Rebuild a sum from its terms: dices, then everything else, then the constant
+///
+fn canonical_sum(terms: Vec<(bool, Expr)>) -> Expr {
+    let mut cst: isize = 0;
+    let mut dices: BTreeMap<(Reverse<usize>, bool), usize> = BTreeMap::new();
+    let mut others = vec![];
+
+    for (sign, e) in terms {
+        match e {
+            // Constants too large to merge are kept as they are
+            //
+            Expr::Num(n) => match (if sign { Some(n) } else { n.checked_neg() })
+                .and_then(|n| cst.checked_add(n))
+            {
+                Some(c) => cst = c,
+                None => others.push((sign, Expr::Num(n))),
+            },
+            Expr::Dices(ds) => match regular_size(&ds) {
+                Some(size) => *dices.entry((Reverse(size), !sign)).or_default() += ds.len(),
+                None => others.push((sign, Expr::Dices(ds))),
+            },
+            e => others.push((sign, e)),
+        }
+    }
+    others.sort_by_cached_key(|(sign, e)| (!sign, e.to_string()));
+
+    let mut all = dices
+        .into_iter()
+        .map(|((Reverse(size), neg), n)| {
+            (!neg, Expr::Dices(DiceSet::counted(n, Dice::Regular(size))))
+        })
+        .chain(others)
+        .collect::<Vec<_>>();
+    if cst != 0 || all.is_empty() {
+        all.push(match cst.checked_abs() {
+            Some(n) => (cst >= 0, Expr::Num(n)),
+            None => (true, Expr::Num(cst)),
+        });
+    }
+
+    let mut all = all.into_iter();
+    let first = match all.next() {
+        Some((true, e)) => e,
+        Some((false, e)) => Expr::Neg(Box::new(e)).canonicalize(),
+        None => Expr::Num(0),
+    };
+    all.fold(first, |acc, (sign, e)| match sign {
+        true => Expr::Add(Box::new(acc), Box::new(e)),
+        false => Expr::Sub(Box::new(acc), Box::new(e)),
+    })
+}
+
+/// Rebuild a product from its factors, the constant last
+///
+fn canonical_product(factors: Vec<Expr>) -> Expr {
+    let mut cst: isize = 1;
+    let mut others = vec![];
+
+    for e in factors {
+        match e {
+            // Constants too large to merge are kept as they are
+            //
+            Expr::Num(n) => match cst.checked_mul(n) {
+                Some(c) => cst = c,
+                None => others.push(Expr::Num(n)),
+            },
+            Expr::Neg(e) => {
+                match cst.checked_neg() {
+                    Some(c) => cst = c,
+                    None => others.push(Expr::Num(-1)),
+                }
+                others.push(*e);
+            }
+            e => others.push(e),
+        }
+    }
+    others.sort_by_cached_key(|e| (e.precedence() != 4, e.to_string()));
+
+    let mut all = others.into_iter();
+    let first = match all.next() {
+        Some(e) => e,
+        None => return Expr::Num(cst),
+    };
+    let prod = all.fold(first, |acc, e| Expr::Mul(Box::new(acc), Box::new(e)));
+    match cst {
+        1 => prod,
+        -1 => Expr::Neg(Box::new(prod)),
+        n => Expr::Mul(Box::new(prod), Box::new(Expr::Num(n))),
+    }
+}
+
+/// Size of the dices if the set only has regular dices of the same size
+///
+fn regular_size(ds: &DiceSet) -> Option<usize> {
+    match ds.terms() {
+        [(_, Dice::Regular(s))] => Some(*s),
+        _ => None,
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Num(n) => write!(f, "{n}"),
+            Expr::Dices(ds) => write!(f, "{}", ds.groups().join(" + ")),
+            Expr::Pick(ds, p) => write!(f, "{}{p}", Expr::Dices(ds.clone())),
+            Expr::Neg(e) => {
+                write!(f, "-")?;
+                e.fmt_prec(f, 3)
+            }
+            Expr::Add(a, b) | Expr::Sub(a, b) => {
+                let op = if matches!(self, Expr::Add(..)) {
+                    '+'
+                } else {
+                    '-'
+                };
+                a.fmt_prec(f, 1)?;
+                write!(f, " {op} ")?;
+                b.fmt_prec(f, 2)
+            }
+            Expr::Mul(a, b) | Expr::Div(a, b) => {
+                let op = if matches!(self, Expr::Mul(..)) {
+                    '*'
+                } else {
+                    '/'
+                };
+                a.fmt_prec(f, 2)?;
+                write!(f, " {op} ")?;
+                b.fmt_prec(f, 3)
+            }
+        }
+    }
+}
+
+/// Dices only, bonuses are not part of a `DiceSet` in expressions
+///
+fn dices_only(mut r: Res) -> Res {
+    r.bonus = 0;
+    r.sum = r.list.iter().sum::<usize>() as isize;
+    r
+}
+
+/// Both results together, `sum` and `bonus` being left to the caller
+///
+fn joined(mut a: Res, mut b: Res) -> Res {
+    (a.sum, a.bonus, b.sum, b.bonus) = (0, 0, 0, 0);
+    a + b
+}
+
+/// `a + b` as long as the totals fit
+///
+fn added(a: Res, b: Res) -> Result<Res, DiceError> {
+    let sum = a.sum.checked_add(b.sum).ok_or(DiceError::Overflow)?;
+    let bonus = a.bonus.checked_add(b.bonus).ok_or(DiceError::Overflow)?;
+    Ok(Res {
+        sum,
+        bonus,
+        ..joined(a, b)
+    })
+}
+
+/// `-r` as long as the totals fit
+///
+fn negated(mut r: Res) -> Result<Res, DiceError> {
+    r.sum = r.sum.checked_neg().ok_or(DiceError::Overflow)?;
+    r.bonus = r.bonus.checked_neg().ok_or(DiceError::Overflow)?;
+    Ok(r)
+}
+
+/// Only a constant factor can scale the bonus, otherwise we can not tell anymore
+///
+fn scaled_bonus(
+    a: &Res,
+    b: &Res,
+    f: fn(isize, isize) -> Option<isize>,
+) -> Result<isize, DiceError> {
+    let bonus = match (a.list.is_empty(), b.list.is_empty()) {
+        (_, true) => f(a.bonus, b.sum),
+        (true, false) => f(b.bonus, a.sum),
+        _ => Some(0),
+    };
+    bonus.ok_or(DiceError::Overflow)
+}
+
+/// `'(' expr ')' | '-' factor | <n>D<s>[pick] | number`
+///
+fn parse_factor<'i>(input: &'i str, defined: &Defined) -> IResult<&'i str, Expr> {
+    let expr = |i| parse_expr_with(i, defined);
+    let factor = |i| parse_factor(i, defined);
+    preceded(
+        space0,
+        alt((
+            delimited(char('('), cut(expr), cut(preceded(space0, char(')')))),
+            map(preceded(char('-'), cut(factor)), |e| Expr::Neg(Box::new(e))),
+            map(
+                |i| parse_picked(i, defined),
+                |(ds, p)| match p {
+                    Some(p) => Expr::Pick(ds, p),
+                    None => Expr::Dices(ds),
+                },
+            ),
+            map(u32, |n| Expr::Num(n as isize)),
+        )),
+    )(input)
+}
+
+/// `factor ( ('*' | '/') factor )*`
+///
+fn parse_term<'i>(input: &'i str, defined: &Defined) -> IResult<&'i str, Expr> {
+    let factor = |i| parse_factor(i, defined);
+    let (input, first) = factor(input)?;
+    fold_many0(
+        pair(preceded(space0, one_of("*/")), cut(factor)),
+        move || first.clone(),
+        |acc, (op, e)| match op {
+            '*' => Expr::Mul(Box::new(acc), Box::new(e)),
+            _ => Expr::Div(Box::new(acc), Box::new(e)),
+        },
+    )(input)
+}
+
+/// `term ( ('+' | '-') term )*`, this is the entry point for regular dices.
+///
+pub fn parse_expr(input: &str) -> IResult<&str, Expr> {
+    parse_expr_with(input, &Defined::default())
+}
+
+/// Same as `parse_expr` with the dices of `defined`
+///
+pub fn parse_expr_with<'i>(input: &'i str, defined: &Defined) -> IResult<&'i str, Expr> {
+    trace!("parse_expr({input})");
+    let term = |i| parse_term(i, defined);
+    let (input, first) = term(input)?;
+    fold_many0(
+        pair(preceded(space0, one_of("+-")), cut(term)),
+        move || first.clone(),
+        |acc, (op, e)| match op {
+            '+' => Expr::Add(Box::new(acc), Box::new(e)),
+            _ => Expr::Sub(Box::new(acc), Box::new(e)),
+        },
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use super::*;
+
+    fn eval(input: &str) -> Res {
+        let (rest, e) = parse_expr(input).unwrap();
+        assert_eq!("", rest);
+        e.eval(&mut Roller::new(StdRng::seed_from_u64(1))).unwrap()
+    }
+
+    #[rstest]
+    #[case("1 + 2 * 3", 7)]
+    #[case("(1 + 2) * 3", 9)]
+    #[case("10 - 2 - 3", 5)]
+    #[case("7 / 2", 3)]
+    #[case("-7 / 2", -3)]
+    #[case("-(2 + 3)", -5)]
+    #[case("2*(3+(4-1))", 12)]
+    fn test_constant_expr(#[case] input: &str, #[case] want: isize) {
+        let r = eval(input);
+        assert_eq!(want, r.sum);
+        assert_eq!(want, r.bonus);
+        assert!(r.list.is_empty());
+    }
+
+    #[rstest]
+    #[case("7 / 2", Rounding::Up, 4, 4)]
+    #[case("-7 / 2", Rounding::Down, -4, -4)]
+    #[case("(d1 + 4) / 2", Rounding::Near, 3, 2)]
+    #[case("(d1 + 4) / 2", Rounding::Zero, 2, 2)]
+    #[case("(d1 + 5) / 2", Rounding::Up, 3, 3)]
+    fn test_eval_rounded(
+        #[case] input: &str,
+        #[case] rounding: Rounding,
+        #[case] sum: isize,
+        #[case] bonus: isize,
+    ) {
+        let (_, e) = parse_expr(input).unwrap();
+        let r = e
+            .eval_rounded(&mut Roller::new(StdRng::seed_from_u64(1)), rounding)
+            .unwrap();
+
+        assert_eq!(sum, r.sum);
+        assert_eq!(bonus, r.bonus);
+    }
+
+    #[test]
+    fn test_parse_expr_tree() {
+        let (_, e) = parse_expr("d20 + 2d4 - 1").unwrap();
+
+        let want = Expr::Sub(
+            Box::new(Expr::Add(
+                Box::new(Expr::Dices(DiceSet::from(Dice::Regular(20)))),
+                Box::new(Expr::Dices(DiceSet::from_vec(vec![
+                    Dice::Regular(4),
+                    Dice::Regular(4),
+                ]))),
+            )),
+            Box::new(Expr::Num(1)),
+        );
+        assert_eq!(want, e);
+    }
+
+    #[test]
+    fn test_eval_dices() {
+        let r = eval("d20 + 2d4 - 1");
+
+        assert_eq!(3, r.list.len());
+        assert_eq!(-1, r.bonus);
+        assert_eq!(r.list.iter().sum::<usize>() as isize - 1, r.sum);
+    }
+
+    #[test]
+    fn test_eval_mul() {
+        let r = eval("(2d6+3)*2");
+
+        assert_eq!(2, r.list.len());
+        assert_eq!(6, r.bonus);
+        assert_eq!(2 * (r.list.iter().sum::<usize>() as isize + 3), r.sum);
+    }
+
+    #[test]
+    fn test_eval_old_syntax() {
+        let r = eval("3D6 +2 -1");
+
+        assert_eq!(3, r.list.len());
+        assert_eq!(1, r.bonus);
+    }
+
+    #[test]
+    fn test_eval_div_zero() {
+        let (_, e) = parse_expr("d6 / (2 - 2)").unwrap();
+        assert_eq!(
+            Err(DiceError::DivisionByZero),
+            e.eval(&mut Roller::new(StdRng::seed_from_u64(1)))
+        );
+    }
+
+    #[rstest]
+    #[case("99999*99999*99999*99999*99999")]
+    #[case("-(99999*99999*99999*99999) * 99999")]
+    #[case("99999*99999*99999*99999 * d6 * 99999")]
+    #[case("4294967295*4294967295 + 4294967295*4294967295 - 0")]
+    fn test_eval_overflow(#[case] input: &str) {
+        let (_, e) = parse_expr(input).unwrap();
+        assert_eq!(
+            Err(DiceError::Overflow),
+            e.eval(&mut Roller::new(StdRng::seed_from_u64(1)))
+        );
+    }
+
+    #[rstest]
+    #[case("99999*99999*99999*99999*99999", "99999 * 99999 * 999970000299999")]
+    #[case(
+        "4294967295*4294967295 + 4294967295*4294967295",
+        "4294967295 * 4294967295 + 4294967295 * 4294967295"
+    )]
+    fn test_canonicalize_overflow(#[case] input: &str, #[case] want: &str) {
+        let (_, e) = parse_expr(input).unwrap();
+        assert_eq!(want, e.canonicalize().to_string());
+    }
+
+    #[rstest]
+    #[case("d6 adv", " adv")]
+    #[case("d6 + 1 foo", " foo")]
+    #[case("(d6", "(d6")]
+    fn test_parse_expr_leftover(#[case] input: &str, #[case] rest: &str) {
+        match parse_expr(input) {
+            Ok((r, _)) => assert_eq!(rest, r),
+            Err(_) => assert_eq!(input, rest),
+        }
+    }
+
+    #[rstest]
+    #[case("5d6dl1", 4, 1)]
+    #[case("4d6kh3 + 2", 3, 1)]
+    #[case("2d20kl1", 1, 1)]
+    fn test_eval_pick(#[case] input: &str, #[case] kept: usize, #[case] dropped: usize) {
+        let r = eval(input);
+
+        assert_eq!(kept, r.list.len());
+        assert_eq!(dropped, r.dropped.len());
+        assert_eq!(r.list.iter().sum::<usize>() as isize + r.bonus, r.sum);
+    }
+
+    #[test]
+    fn test_validate() {
+        let (_, e) = parse_expr("d20 + (2d7 * 2)").unwrap();
+
+        assert!(e.validate(&SizePolicy::Any).is_ok());
+        assert!(e.validate(&SizePolicy::Physical).is_err());
+    }
+
+    #[rstest]
+    #[case("3", vec![])]
+    #[case("d20 + 2", vec![20])]
+    #[case("-(2d6 + 1) * d4", vec![6, 6, 4])]
+    fn test_sizes(#[case] input: &str, #[case] want: Vec<usize>) {
+        let (_, e) = parse_expr(input).unwrap();
+        assert_eq!(want, e.sizes());
+    }
+
+    #[rstest]
+    #[case("d6", "D6")]
+    #[case("1 + d20 + 2", "D20 + 3")]
+    #[case("2d4 + d20 - 1", "D20 + 2D4 - 1")]
+    #[case("3d6 + 2d6", "5D6")]
+    #[case("3 - d6", "-D6 + 3")]
+    #[case("2 * (d6 + 1) * 3", "(D6 + 1) * 6")]
+    #[case("-(-d8)", "D8")]
+    #[case("1 + 2 - 3", "0")]
+    #[case("(d6 + 1) / 2", "(D6 + 1) / 2")]
+    fn test_canonicalize(#[case] input: &str, #[case] want: &str) {
+        let (_, e) = parse_expr(input).unwrap();
+        assert_eq!(want, e.canonicalize().to_string());
+    }
+
+    #[rstest]
+    #[case("d20 + 2d4 + 1", "1 + 2D4 + D20", true)]
+    #[case("2 * d6", "D6*2", true)]
+    #[case("d6 - d4", "d4 - d6", false)]
+    #[case("d6 / 2", "2 / d6", false)]
+    fn test_equivalent(#[case] a: &str, #[case] b: &str, #[case] want: bool) {
+        let (_, a) = parse_expr(a).unwrap();
+        let (_, b) = parse_expr(b).unwrap();
+        assert_eq!(want, a.equivalent(&b));
+    }
+
+    #[test]
+    fn test_canonical_display_parses() {
+        let (_, e) = parse_expr("(2d6+3)*2 - d4 / 2 + 2d6rr<3 + 5d6dl1").unwrap();
+        let c = e.canonicalize();
+        let s = c.to_string();
+        let (rest, back) = parse_expr(&s).unwrap();
+
+        assert_eq!("", rest);
+        assert_eq!(c, back.canonicalize());
+    }
+}
@@ -9,22 +9,209 @@
 //! println!("Roll = {}", r);
 //! ```
 //!
+//! or with a given generator:
+//! ```
+//! use rand::{rngs::StdRng, SeedableRng};
+//! use dices_rs::dice::internal::internal_roll_with;
+//!
+//! let mut rng = StdRng::seed_from_u64(42);
+//! let r = internal_roll_with(&mut rng, 6);
+//!
+//! println!("Roll = {}", r);
+//! ```
+//!
+//! How dices are rolled is chosen with `RollerKind` (`roller` in the configuration): `Fast`
+//! draws uniformly with `gen_range` from `thread_rng`, `Crypto` does the same from the system
+//! CSPRNG (`OsRng`) and `Legacy` is the old sequential biased-coin loop, kept to compare.
+//! Dices are rolled with a `Roller`, a generator with the algorithm to use:
+//!
+//! ```
+//! use dices_rs::dice::internal::{Roller, RollerKind};
+//!
+//! let r = RollerKind::Crypto.scope(|rng| rng.roll(20));
+//! assert!((1..=20).contains(&r));
+//!
+//! let mut rng = Roller::from(RollerKind::Legacy);
+//! assert!((1..=6).contains(&rng.roll(6)));
+//! ```
+//!
+
+use std::fmt::{Debug, Display, Formatter};
 
 /// Include the [rand] family
 use rand::prelude::*;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+use crate::dice::explode::MAX_EXPLOSIONS;
+
+/// Generator and algorithm used for rolls
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RollerKind {
+    /// Uniform draw from `thread_rng`
+    #[default]
+    Fast,
+    /// Sequential biased-coin loop from `thread_rng`, slower
+    Legacy,
+    /// Uniform draw from the system CSPRNG
+    Crypto,
+}
+
+impl RollerKind {
+    /// A new generator of that kind
+    ///
+    pub fn rng(&self) -> Box<dyn RngCore> {
+        match self {
+            RollerKind::Fast | RollerKind::Legacy => Box::new(thread_rng()),
+            RollerKind::Crypto => Box::new(OsRng),
+        }
+    }
+
+    /// Call `f` with a `Roller` of that kind
+    ///
+    pub fn scope<T, F: FnOnce(&mut Roller) -> T>(&self, f: F) -> T {
+        f(&mut Roller::from(*self))
+    }
+}
+
+impl Display for RollerKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RollerKind::Fast => write!(f, "fast (thread_rng)"),
+            RollerKind::Legacy => write!(f, "legacy (thread_rng, biased loop)"),
+            RollerKind::Crypto => write!(f, "crypto (OsRng)"),
+        }
+    }
+}
+
+/// A generator with the algorithm used to roll dices and how far open-ended dices go, given
+/// to everything rolling
+///
+pub struct Roller {
+    /// Source of randomness
+    rng: Box<dyn RngCore>,
+    /// Algorithm
+    kind: RollerKind,
+    /// Explosions allowed in one roll, see `explode`
+    max_explosions: usize,
+}
+
+impl Roller {
+    /// Uniform draws from `rng`
+    ///
+    pub fn new<R: RngCore + 'static>(rng: R) -> Self {
+        Roller {
+            rng: Box::new(rng),
+            kind: RollerKind::Fast,
+            max_explosions: MAX_EXPLOSIONS,
+        }
+    }
+
+    /// Use the algorithm of `kind`, the generator staying the same
+    ///
+    pub fn with_kind(mut self, kind: RollerKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Allow at most `max` explosions in one roll of an open-ended dice
+    ///
+    pub fn with_max_explosions(mut self, max: usize) -> Self {
+        self.max_explosions = max;
+        self
+    }
+
+    /// Algorithm in use
+    ///
+    pub fn kind(&self) -> RollerKind {
+        self.kind
+    }
+
+    /// Explosions allowed in one roll of an open-ended dice
+    ///
+    pub fn max_explosions(&self) -> usize {
+        self.max_explosions
+    }
+
+    /// Return a roll of a dice of size `sides`
+    ///
+    pub fn roll(&mut self, sides: usize) -> usize {
+        match self.kind {
+            RollerKind::Legacy => legacy_roll_with(&mut self.rng, sides),
+            _ => internal_roll_with(&mut self.rng, sides),
+        }
+    }
+}
+
+/// Uniform draws from `thread_rng`
+impl Default for Roller {
+    fn default() -> Self {
+        Roller::new(thread_rng())
+    }
+}
+
+/// The generator and algorithm of `kind`
+impl From<RollerKind> for Roller {
+    fn from(kind: RollerKind) -> Self {
+        Roller {
+            rng: kind.rng(),
+            kind,
+            max_explosions: MAX_EXPLOSIONS,
+        }
+    }
+}
+
+impl Debug for Roller {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Roller")
+            .field("kind", &self.kind)
+            .field("max_explosions", &self.max_explosions)
+            .finish()
+    }
+}
+
+impl RngCore for Roller {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
 
 /// Head or Tail?
-fn biased_dice(p: f64) -> bool {
-    let mut rng = rand::thread_rng();
+fn biased_dice<R: Rng + ?Sized>(rng: &mut R, p: f64) -> bool {
     let f: f64 = rng.gen();
     f < p
 }
 
 /// Return a roll of a dice of size `sides`
 pub fn internal_roll(sides: usize) -> usize {
+    internal_roll_with(&mut thread_rng(), sides)
+}
+
+/// Return a roll of a dice of size `sides` using `rng` as source, drawn uniformly whatever
+/// the kind of a `Roller` (use `Roller::roll` for that)
+pub fn internal_roll_with<R: Rng + ?Sized>(rng: &mut R, sides: usize) -> usize {
+    rng.gen_range(1..=sides.max(1))
+}
+
+/// The old way: one biased coin per face until one says yes
+pub fn legacy_roll_with<R: Rng + ?Sized>(rng: &mut R, sides: usize) -> usize {
     let mut i = 0;
     loop {
-        if biased_dice(1.0 / (sides - i) as f64) {
+        if biased_dice(rng, 1.0 / (sides - i) as f64) {
             return i + 1;
         }
         i += 1;
@@ -43,4 +230,51 @@ mod tests {
             assert!(r <= 6)
         }
     }
+
+    #[test]
+    fn test_internal_roll_with() {
+        let mut a = StdRng::seed_from_u64(1234);
+        let mut b = StdRng::seed_from_u64(1234);
+
+        for _i in 0..10 {
+            let r = internal_roll_with(&mut a, 20);
+
+            assert!((1..=20).contains(&r));
+            assert_eq!(r, internal_roll_with(&mut b, 20));
+        }
+    }
+
+    #[test]
+    fn test_legacy_roll_with() {
+        let mut a = StdRng::seed_from_u64(1234);
+        let mut b = StdRng::seed_from_u64(1234);
+
+        let mut roller = Roller::new(a.clone()).with_kind(RollerKind::Legacy);
+        for _i in 0..10 {
+            let r = roller.roll(20);
+
+            assert!((1..=20).contains(&r));
+            assert_eq!(r, legacy_roll_with(&mut b, 20));
+        }
+        let mut roller = Roller::new(a.clone());
+        assert_eq!(internal_roll_with(&mut a, 20), roller.roll(20));
+    }
+
+    #[test]
+    fn test_roller_uniform() {
+        let mut seen = [0; 6];
+        for kind in [RollerKind::Fast, RollerKind::Legacy, RollerKind::Crypto] {
+            kind.scope(|rng| (0..600).for_each(|_| seen[rng.roll(6) - 1] += 1));
+        }
+        assert!(seen.iter().all(|n| *n > 200), "{seen:?}");
+    }
+
+    #[test]
+    fn test_roller_kind() {
+        assert_eq!(RollerKind::Fast, Roller::default().kind());
+        assert_eq!(
+            RollerKind::Crypto,
+            RollerKind::Crypto.scope(|rng| rng.kind())
+        );
+    }
 }
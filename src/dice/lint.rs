@@ -0,0 +1,186 @@
+//! Look for likely mistakes in a dice expression before rolling it.
+//!
+//! Nothing here is an error, the roll is still valid, but these usually mean a typo:
+//!
+//! - `D0` dices without sides and `0D6` without any dice
+//! - enormous counts like `255D100` (more than `MAX_COUNT` dices in a group)
+//! - bonuses larger than the maximum of the dices, like `D6 +20`
+//! - a `=` where `+` was probably meant, `3D6 =2` rolls `3D6` and ignores the rest when not
+//!   in strict mode (see `Config::strict`)
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::lint::{lint, Lint};
+//! use dices_rs::dice::rules::Rules;
+//!
+//! assert_eq!(vec![Lint::Equals("=2".to_string())], lint("3D6 =2", Rules::default()));
+//! assert!(lint("3D6 +2 adv", Rules::default()).is_empty());
+//! ```
+
+use thiserror::Error;
+
+use crate::dice::expr::{parse_expr, Expr};
+use crate::dice::rules::{parse_rules, Rules};
+use crate::dice::{Dice, DiceSet};
+
+/// More dices than this in a single group is probably a typo
+pub const MAX_COUNT: usize = 100;
+
+/// Something suspicious in an expression
+///
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum Lint {
+    /// A dice with zero sides
+    #[error("D0 has no sides")]
+    NoSides,
+    /// A group without any dice
+    #[error("0Dn rolls nothing")]
+    NoDices,
+    /// Unusually large group of dices
+    #[error("{count}D{size} rolls a lot of dices, more than {MAX_COUNT}")]
+    TooMany { count: usize, size: usize },
+    /// The bonus is more than the dices can ever roll
+    #[error("bonus {bonus} is larger than the maximum roll {max}")]
+    BonusTooLarge { bonus: isize, max: isize },
+    /// Trailing `=...`, ignored by the parser
+    #[error("{0:?} is ignored, did you mean {}?", .0.replacen('=', "+", 1))]
+    Equals(String),
+}
+
+/// All the problems in `input`, a dice expression optionally followed by house rules (see
+/// `rules`).  An expression that does not parse has no lint, it is an error anyway.
+///
+pub fn lint(input: &str, rules: Rules) -> Vec<Lint> {
+    let Ok((rest, expr)) = parse_expr(input.trim_start()) else {
+        return vec![];
+    };
+    let rest = match parse_rules(rest, rules) {
+        Ok((rest, _)) => rest,
+        Err(_) => rest,
+    };
+
+    let mut found = vec![];
+    groups(&expr, &mut found);
+
+    let mut terms = vec![];
+    sum_terms(&expr, true, &mut terms);
+    let bonus: isize = terms
+        .iter()
+        .filter_map(|(sign, e)| match e {
+            Expr::Num(n) if *sign => Some(*n),
+            Expr::Num(n) => Some(-n),
+            _ => None,
+        })
+        .sum();
+    let max: isize = terms
+        .iter()
+        .filter_map(|(_, e)| match e {
+            Expr::Dices(ds) | Expr::Pick(ds, _) => Some(ds.sizes().iter().sum::<usize>() as isize),
+            _ => None,
+        })
+        .sum();
+    if max > 0 && bonus.abs() > max {
+        found.push(Lint::BonusTooLarge { bonus, max });
+    }
+
+    let rest = rest.trim();
+    if rest.starts_with('=') {
+        found.push(Lint::Equals(rest.to_string()));
+    }
+    found
+}
+
+/// Look at every group of dices
+///
+fn groups(expr: &Expr, found: &mut Vec<Lint>) {
+    match expr {
+        Expr::Num(_) => (),
+        Expr::Dices(ds) | Expr::Pick(ds, _) => group(ds, found),
+        Expr::Neg(e) => groups(e, found),
+        Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) | Expr::Div(a, b) => {
+            groups(a, found);
+            groups(b, found);
+        }
+    }
+}
+
+/// Size and count of one group
+///
+fn group(ds: &DiceSet, found: &mut Vec<Lint>) {
+    // Symbol dices have no size and add nothing to the total
+    //
+    if ds.iter().any(|d| matches!(d, Dice::Symbolic(_))) {
+        return;
+    }
+    let dices = ds
+        .iter()
+        .filter(|d| !matches!(d, Dice::Bonus(_) | Dice::Custom(_)))
+        .collect::<Vec<_>>();
+    match dices.first() {
+        None => found.push(Lint::NoDices),
+        Some(d) if d.size() == 0 => found.push(Lint::NoSides),
+        Some(d) if dices.len() > MAX_COUNT => found.push(Lint::TooMany {
+            count: dices.len(),
+            size: d.size(),
+        }),
+        _ => (),
+    }
+}
+
+/// Signed terms of the top-level sum
+///
+fn sum_terms<'a>(expr: &'a Expr, sign: bool, acc: &mut Vec<(bool, &'a Expr)>) {
+    match expr {
+        Expr::Add(a, b) => {
+            sum_terms(a, sign, acc);
+            sum_terms(b, sign, acc);
+        }
+        Expr::Sub(a, b) => {
+            sum_terms(a, sign, acc);
+            sum_terms(b, !sign, acc);
+        }
+        Expr::Neg(e) => sum_terms(e, !sign, acc),
+        e => acc.push((sign, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("3D6 +2")]
+    #[case("4D6kh3 + D8 - 1")]
+    #[case("D20 +5 adv crit 19")]
+    #[case("100D6")]
+    #[case("D6 + 6")]
+    #[case("(2D6 + 3) * 2")]
+    #[case("3D6 *")]
+    #[case("foo")]
+    fn test_lint_clean(#[case] input: &str) {
+        assert_eq!(Vec::<Lint>::new(), lint(input, Rules::default()));
+    }
+
+    #[rstest]
+    #[case("3D0", Lint::NoSides)]
+    #[case("0D6 +1", Lint::NoDices)]
+    #[case("255D100", Lint::TooMany { count: 255, size: 100 })]
+    #[case("D6 +20", Lint::BonusTooLarge { bonus: 20, max: 6 })]
+    #[case("2D6 - 3 - 10", Lint::BonusTooLarge { bonus: -13, max: 12 })]
+    #[case("3D6 =2", Lint::Equals("=2".to_string()))]
+    #[case("3d6=2", Lint::Equals("=2".to_string()))]
+    #[case("D20 adv =5", Lint::Equals("=5".to_string()))]
+    fn test_lint(#[case] input: &str, #[case] want: Lint) {
+        assert_eq!(vec![want], lint(input, Rules::default()));
+    }
+
+    #[test]
+    fn test_lint_message() {
+        assert_eq!(
+            r#""=2" is ignored, did you mean +2?"#,
+            Lint::Equals("=2".to_string()).to_string()
+        );
+    }
+}
@@ -8,8 +8,16 @@
 //!   yield a value between 1 and `size`
 //! - `Open(size)`
 //!   like a regular dice but if value is `size`, reroll one more.
+//! - `Reroll(size, reroll)`
+//!   like a regular dice but some values are rolled again (`2D6r1`, `2D6rr<3`)
 //! - `Bonus(size)`
 //!   Simulate a dice to store the bonus along with dices
+//! - `Weighted(faces)`
+//!   unfair dice, each face having a weight (see `weighted`)
+//! - `Symbolic(faces)`
+//!   dice showing symbols instead of numbers, counted apart from the total (see `symbolic`)
+//! - `Custom(r)`
+//!   Anything else implementing `Rollable`
 //!
 //! One can use the `Dice` type for individual dices & rolls or the easier `DiceSet` type which
 //! has a `parse()` method which simplify the process.
@@ -36,39 +44,142 @@
 //!     Err(e) => panic!("Error: {}", e)
 //! };
 //!
+//! assert_eq!("3D6 +1", ds.to_string());
 //! println!("{:#?}", ds.roll());
 //! ```
+//!
+//! And even easier, without dealing with `DiceSet` at all:
+//!
+//! ```
+//! let r = dices_rs::roll("3D6 +2").unwrap();
+//! println!("{}", r);
+//!
+//! // Always the same result for a given seed
+//! let r = dices_rs::roll_seeded("3D6 +2", 42).unwrap();
+//! assert_eq!(r, dices_rs::roll_seeded("3D6 +2", 42).unwrap());
+//! ```
 
+use std::fmt::{Debug, Display, Formatter};
+use std::iter::{self, FlatMap, RepeatN};
+use std::sync::Arc;
+use std::{slice, vec};
+
+use itertools::Itertools;
 use log::trace;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use internal::internal_roll;
-use parse::parse_with_bonus;
+use internal::Roller;
+use parse::{check_trailing, parse_defined_with_bonus, Defined};
+use reroll::{Reroll, MAX_REROLLS};
 use result::Res;
 
+use crate::dice::policy::SizePolicy;
 use crate::dice::result::Special;
 
+use self::error::{DiceError, ParseError};
+
+pub mod combinators;
+pub mod error;
+pub mod exact;
+pub mod explode;
+pub mod expr;
 pub mod internal;
+pub mod lint;
 pub mod parse;
+pub mod pick;
+pub mod policy;
+pub mod pool;
+pub mod reroll;
 pub mod result;
+pub mod rules;
+pub mod simulate;
+pub mod symbolic;
+pub mod weighted;
 
 /// Is this thing a Dice or DiceSet?
 ///
+/// The trait is object-safe so that different kinds of dices can be mixed as
+/// `Box<dyn Rollable>`.
+///
 pub trait Rollable {
-    fn roll(&self) -> Res;
+    /// Roll using the default generator
+    ///
+    fn roll(&self) -> Res {
+        self.roll_with(&mut Roller::default())
+    }
+
+    /// Roll using `rng` as the source of randomness and for its algorithm
+    ///
+    fn roll_with(&self, rng: &mut Roller) -> Res;
+}
+
+/// Parse and roll `expr` in one go, e.g. `3D6 +2`.  Dice sizes are checked against the
+/// default `SizePolicy`.
+///
+pub fn roll(expr: &str) -> Result<Res, DiceError> {
+    trace!("roll({expr})");
+    let ds = DiceSet::parse(expr)?;
+    SizePolicy::default().check(&ds)?;
+    Ok(ds.roll())
+}
+
+/// Same as `roll()` but with a seeded generator to get reproducible results.
+///
+pub fn roll_seeded(expr: &str, seed: u64) -> Result<Res, DiceError> {
+    trace!("roll_seeded({expr}, {seed})");
+    let ds = DiceSet::parse(expr)?;
+    SizePolicy::default().check(&ds)?;
+    Ok(ds.roll_with(&mut Roller::new(StdRng::seed_from_u64(seed))))
 }
 
+/// A user-defined `Rollable` that can be stored in a `DiceSet`.
+///
+/// Two custom dices are equal only if they share the same object.
+///
+#[derive(Clone)]
+pub struct CustomDice(pub Arc<dyn Rollable + Send + Sync>);
+
+impl Debug for CustomDice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CustomDice(..)")
+    }
+}
+
+impl PartialEq for CustomDice {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomDice {}
+
 /// Our different types of `Dice`.
 ///
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// Serialized with the variant in snake case like `{"regular": 6}`, custom dices can not be.
+///
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Dice {
     /// Always yield the same result
     Constant(usize),
     /// A dice that will re-roll by itself if roll is max
     Open(usize),
+    /// Rolemaster-style open-ended dice, high rolls add another roll and low ones subtract it
+    OpenHighLow(usize),
     /// Your regular type of dice
     Regular(usize),
+    /// Regular dice rolled again when matching the `Reroll` condition
+    Reroll(usize, Reroll),
     /// Used to register any bonus, same as a Regular but easier to spot
     Bonus(isize),
+    /// Unfair dice as `(face, weight)`
+    Weighted(Vec<(usize, usize)>),
+    /// Dice whose faces are symbols, one list of symbols per face
+    Symbolic(Vec<Vec<String>>),
+    /// Anything else implementing `Rollable`
+    #[serde(skip)]
+    Custom(CustomDice),
 }
 
 /// Implement the dice methods
@@ -76,18 +187,49 @@ pub enum Dice {
 impl Dice {
     /// Return the size of a dice
     ///
-    pub fn size(self) -> usize {
+    pub fn size(&self) -> usize {
+        match *self {
+            Dice::Constant(s)
+            | Dice::Regular(s)
+            | Dice::Open(s)
+            | Dice::OpenHighLow(s)
+            | Dice::Reroll(s, _) => s,
+            Dice::Weighted(ref faces) => faces.iter().map(|&(f, _)| f).max().unwrap_or(0),
+            Dice::Bonus(_) | Dice::Symbolic(_) | Dice::Custom(_) => 0,
+        }
+    }
+}
+
+impl Display for Dice {
+    /// Dice notation, open-ended dices being written like regular ones as in `open D6`.
+    /// Weighted, symbol and custom dices only show their kind (`DW`, `DS` and `D?`) as they
+    /// do not know their name.
+    ///
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Dice::Constant(s) | Dice::Regular(s) | Dice::Open(s) => s,
-            Dice::Bonus(_) => 0,
+            Dice::Constant(c) => write!(f, "{c}"),
+            Dice::Regular(s) | Dice::Open(s) | Dice::OpenHighLow(s) => write!(f, "D{s}"),
+            Dice::Reroll(s, r) => write!(f, "D{s}{r}"),
+            Dice::Bonus(b) => write!(f, "{b:+}"),
+            Dice::Weighted(_) => write!(f, "DW"),
+            Dice::Symbolic(_) => write!(f, "DS"),
+            Dice::Custom(_) => write!(f, "D?"),
         }
     }
 }
 
+impl From<Box<dyn Rollable + Send + Sync>> for Dice {
+    /// Wrap any `Rollable` into a `Dice`
+    ///
+    fn from(r: Box<dyn Rollable + Send + Sync>) -> Self {
+        Dice::Custom(CustomDice(Arc::from(r)))
+    }
+}
+
 impl Rollable for Dice {
-    /// Implement `roll()` for each type of dices
+    /// Implement `roll_with()` for each type of dices
     ///
-    fn roll(&self) -> Res {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
         let mut res = Res::new();
 
         let r = match *self {
@@ -99,29 +241,30 @@ impl Rollable for Dice {
             Dice::Regular(s) => {
                 trace!("dice::regular({s})");
 
-                let rr = match internal_roll(s) {
-                    1 => {
-                        trace!("fumble");
-                        (1, Special::Fumble)
-                    }
-                    r => {
-                        if s == r {
-                            trace!("natural");
-                            (s, Special::Natural)
-                        } else {
-                            (r, Special::None)
-                        }
+                let r = rng.roll(s);
+                res.append(r).set(regular_flag(r, s))
+            }
+            Dice::Reroll(s, reroll) => {
+                trace!("dice::reroll({s}, {reroll})");
+
+                let mut r = rng.roll(s);
+                while reroll.matches(r) && res.rerolled.len() < MAX_REROLLS {
+                    res.rerolled.push(r);
+                    r = rng.roll(s);
+                    if !reroll.repeat {
+                        break;
                     }
-                };
-                res.append(rr.0).set(rr.1)
+                }
+                res.append(r).set(regular_flag(r, s))
             }
             Dice::Open(s) => {
                 trace!("dice::open({s})");
 
-                // While roll is size
+                // While roll is size, up to the cap
                 //
+                let max = rng.max_explosions();
                 loop {
-                    let rr = internal_roll(s);
+                    let rr = rng.roll(s);
                     res.append(rr);
                     // Check for first roll only
                     //
@@ -135,14 +278,73 @@ impl Rollable for Dice {
                     if rr != s {
                         break;
                     }
+                    if res.list.len() > max {
+                        trace!("capped");
+                        res.capped = true;
+                        break;
+                    }
+                }
+                &mut res
+            }
+            Dice::OpenHighLow(s) => {
+                trace!("dice::open_high_low({s})");
+
+                // Top and bottom 5%, so 96+ and 05- for a D100
+                //
+                let t = (s / 20).max(1);
+                let first = rng.roll(s);
+                res.append(first);
+
+                let sign = if first > s - t {
+                    res.set(Special::OpenHigh);
+                    1
+                } else if first <= t {
+                    res.set(Special::OpenLow);
+                    -1
+                } else {
+                    0
+                };
+                // Follow-up rolls are open-ended high themselves
+                //
+                if sign != 0 {
+                    let max = rng.max_explosions();
+                    loop {
+                        if res.list.len() > max {
+                            trace!("capped");
+                            res.capped = true;
+                            break;
+                        }
+                        let rr = rng.roll(s);
+                        res.list.push(rr);
+                        res.sum += sign * rr as isize;
+                        if rr <= s - t {
+                            break;
+                        }
+                    }
                 }
                 &mut res
             }
+            Dice::Weighted(ref faces) => {
+                trace!("dice::weighted({faces:?})");
+
+                let r = weighted::roll_weighted(rng, faces);
+                res.append(r).set(regular_flag(r, self.size()))
+            }
+            Dice::Symbolic(ref faces) => {
+                trace!("dice::symbolic({faces:?})");
+
+                res = symbolic::roll_symbolic(rng, faces);
+                &mut res
+            }
             Dice::Bonus(s) => {
                 trace!("dice::bonus({s})");
 
-                res.sum = s;
-                res.bonus = s;
+                res.add_bonus(s)
+            }
+            Dice::Custom(ref c) => {
+                trace!("dice::custom");
+
+                res = c.0.roll_with(rng);
                 &mut res
             }
         };
@@ -151,10 +353,42 @@ impl Rollable for Dice {
     }
 }
 
-/// The more interesting thing, a set of dices
+/// Fumble on 1 and natural on the max
 ///
-#[derive(Clone, Debug, PartialEq)]
-pub struct DiceSet(Vec<Dice>);
+fn regular_flag(r: usize, s: usize) -> Special {
+    match r {
+        1 => {
+            trace!("fumble");
+            Special::Fumble
+        }
+        r if r == s => {
+            trace!("natural");
+            Special::Natural
+        }
+        _ => Special::None,
+    }
+}
+
+/// The more interesting thing, a set of dices, serialized as the list of its dices.
+///
+/// Same dices following each other are kept once with their number, so `255D100` is one term
+/// and not 255 copies of `Regular(100)`.  Sets are equal when they have the same dices in the
+/// same order, see `equivalent` to compare their `normalize()` form.
+///
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
+#[serde(from = "Vec<Dice>", into = "Vec<Dice>")]
+pub struct DiceSet(Vec<(usize, Dice)>);
+
+/// Iterator over the dices of a `DiceSet`, each one repeated by its number
+pub type Iter<'a> = FlatMap<
+    slice::Iter<'a, (usize, Dice)>,
+    RepeatN<&'a Dice>,
+    fn(&'a (usize, Dice)) -> RepeatN<&'a Dice>,
+>;
+
+/// Same as `Iter` for an owned `DiceSet`
+pub type IntoIter =
+    FlatMap<vec::IntoIter<(usize, Dice)>, RepeatN<Dice>, fn((usize, Dice)) -> RepeatN<Dice>>;
 
 /// a Dice set
 ///
@@ -163,53 +397,250 @@ impl DiceSet {
     /// Used by the nom parser.
     ///
     pub fn from_vec(v: Vec<Dice>) -> Self {
-        Self(v)
+        v.into_iter().collect()
+    }
+
+    /// `n` times the same dice, like `3D6`
+    ///
+    pub fn counted(n: usize, d: Dice) -> Self {
+        let mut ds = DiceSet(Vec::new());
+        ds.push_n(n, d);
+        ds
     }
 
     /// Add a dice to a `DiceSet`
     ///
     pub fn add(&mut self, d: Dice) -> &mut Self {
-        self.0.push(d);
+        self.push(d);
+        self
+    }
+
+    /// Same as `add` without chaining
+    ///
+    pub fn push(&mut self, d: Dice) {
+        self.push_n(1, d);
+    }
+
+    /// Add `n` times the same dice, counted with the last one if it is the same
+    ///
+    pub fn push_n(&mut self, n: usize, d: Dice) {
+        match self.0.last_mut() {
+            _ if n == 0 => (),
+            Some((count, last)) if *last == d => *count += n,
+            _ => self.0.push((n, d)),
+        }
+    }
+
+    /// Add any `Rollable` to a `DiceSet`
+    ///
+    pub fn add_boxed(&mut self, r: Box<dyn Rollable + Send + Sync>) -> &mut Self {
+        self.push(Dice::from(r));
         self
     }
 
+    /// Same dices following each other with their number, bonuses included
+    ///
+    pub fn terms(&self) -> &[(usize, Dice)] {
+        &self.0
+    }
+
+    /// All the dices in order one by one, bonuses included
+    ///
+    pub fn dice(&self) -> Vec<Dice> {
+        self.iter().cloned().collect()
+    }
+
+    /// Iterate over the dices one by one, bonuses included
+    ///
+    pub fn iter(&self) -> Iter<'_> {
+        self.0.iter().flat_map(|(n, d)| iter::repeat_n(d, *n))
+    }
+
+    /// Number of dices, bonuses included
+    ///
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|(n, _)| n).sum()
+    }
+
+    /// No dice at all?
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Sum of all the bonuses
+    ///
+    pub fn bonus(&self) -> isize {
+        self.0
+            .iter()
+            .filter_map(|(n, d)| match d {
+                Dice::Bonus(b) => Some(*n as isize * b),
+                _ => None,
+            })
+            .sum()
+    }
+
+    /// Sizes of all the dices, bonuses and custom ones excluded
+    ///
+    pub fn sizes(&self) -> Vec<usize> {
+        self.iter().map(Dice::size).filter(|&s| s > 0).collect()
+    }
+
     /// Parse a string with the following format:
     ///  `<n>*D<s>[ [+-]<b>+]`
     /// and return a `DiceSet` with `[n * Regular(s), Bonus(b)]`
     ///
-    pub fn parse(s: &str) -> Result<Self, String> {
-        match parse_with_bonus(s) {
-            Ok((_, ds)) => Ok(ds),
-            Err(e) => Err(e.to_string()),
+    pub fn parse(s: &str) -> Result<Self, DiceError> {
+        Self::parse_with(s, &Defined::default())
+    }
+
+    /// Same as `parse()` with the dices of `defined`
+    ///
+    pub fn parse_with(s: &str, defined: &Defined) -> Result<Self, DiceError> {
+        Self::parse_rest(s, defined).map(|(_, ds)| ds)
+    }
+
+    /// Same as `parse()` but nothing else than spaces may follow the dices
+    ///
+    pub fn parse_strict(s: &str) -> Result<Self, DiceError> {
+        let (rest, ds) = Self::parse_rest(s, &Defined::default())?;
+        check_trailing(s, rest)?;
+        Ok(ds)
+    }
+
+    /// The `DiceSet` and what is left of `s`
+    ///
+    fn parse_rest<'i>(s: &'i str, defined: &Defined) -> Result<(&'i str, Self), DiceError> {
+        match parse_defined_with_bonus(s, defined) {
+            Ok(r) => Ok(r),
+            Err(e) => Err(ParseError::new(s, &e).into()),
         }
     }
+
+    /// Compact form: same dices following each other counted once like `3×D6`, then all the
+    /// bonuses added up (none if they cancel out)
+    ///
+    pub fn normalize(&self) -> Vec<(usize, Dice)> {
+        let mut all = self
+            .0
+            .iter()
+            .filter(|(_, d)| !matches!(d, Dice::Bonus(_)))
+            .group_by(|(_, d)| d)
+            .into_iter()
+            .map(|(d, g)| (g.map(|(n, _)| n).sum(), d.clone()))
+            .collect::<Vec<_>>();
+        match self.bonus() {
+            0 => (),
+            b => all.push((1, Dice::Bonus(b))),
+        }
+        all
+    }
+
+    /// Do both sets have the same `normalize()` form, like `D6 +2 -1` and `D6 +1`?
+    ///
+    pub fn equivalent(&self, other: &DiceSet) -> bool {
+        self.normalize() == other.normalize()
+    }
+
+    /// Same dices following each other written once with their number like `3D6`, constants
+    /// one by one
+    ///
+    pub(crate) fn groups(&self) -> Vec<String> {
+        self.normalize()
+            .into_iter()
+            .flat_map(|(n, d)| match (d, n) {
+                (d @ Dice::Constant(_), n) => vec![d.to_string(); n],
+                (d, 1) => vec![d.to_string()],
+                (d, n) => vec![format!("{n}{d}")],
+            })
+            .collect()
+    }
+}
+
+impl Display for DiceSet {
+    /// Canonical notation like `3D6 +1`.  Only one group of regular or reroll dices with its
+    /// bonus parses back to the same set: open dices read back as regular ones, while several
+    /// groups, constants like `3 3` and the kinds shown for other dices (see `Dice`) do not
+    /// parse.
+    ///
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.groups().join(" "))
+    }
+}
+
+impl Debug for DiceSet {
+    /// Normalized like `DiceSet[3×Regular(6), Bonus(1)]` instead of one line per dice
+    ///
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let all = self
+            .normalize()
+            .into_iter()
+            .map(|(n, d)| match n {
+                1 => format!("{d:?}"),
+                n => format!("{n}×{d:?}"),
+            })
+            .join(", ");
+        write!(f, "DiceSet[{all}]")
+    }
 }
 
 impl From<Dice> for DiceSet {
     /// Create a single dice "dice set"
     ///
     fn from(d: Dice) -> Self {
-        DiceSet(vec![d])
+        DiceSet::counted(1, d)
+    }
+}
+
+impl FromIterator<Dice> for DiceSet {
+    fn from_iter<I: IntoIterator<Item = Dice>>(iter: I) -> Self {
+        let mut ds = DiceSet(Vec::new());
+        iter.into_iter().for_each(|d| ds.push(d));
+        ds
+    }
+}
+
+impl IntoIterator for DiceSet {
+    type Item = Dice;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter().flat_map(|(n, d)| iter::repeat_n(d, n))
+    }
+}
+
+impl<'a> IntoIterator for &'a DiceSet {
+    type Item = &'a Dice;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl From<Vec<Dice>> for DiceSet {
+    fn from(v: Vec<Dice>) -> Self {
+        DiceSet::from_vec(v)
+    }
+}
+
+impl From<DiceSet> for Vec<Dice> {
+    fn from(ds: DiceSet) -> Self {
+        ds.into_iter().collect()
     }
 }
 
 impl Rollable for DiceSet {
     /// Get all Res and sum them
     ///
-    fn roll(&self) -> Res {
-        let res = self
-            .0
-            .iter()
-            .map(|d| {
-                let r = d.roll();
-                let f = r.flag();
-                (r, f)
-            })
-            .fold(Res::new(), |acc, (r, f)| {
-                let mut s = r;
-                acc + s.set(f).clone()
-            });
-        res
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        let mut all = Res::new();
+        for (n, d) in &self.0 {
+            for _ in 0..*n {
+                all += d.roll_with(rng);
+            }
+        }
+        all
     }
 }
 
@@ -217,8 +648,59 @@ impl Rollable for DiceSet {
 mod tests {
     use rstest::rstest;
 
+    use crate::dice::error::Expected;
+
     use super::*;
 
+    #[test]
+    fn test_open_high_low() {
+        let d = Dice::OpenHighLow(100);
+        let (mut high, mut low) = (false, false);
+
+        for seed in 0..2000 {
+            let r = d.roll_with(&mut Roller::new(StdRng::seed_from_u64(seed)));
+            let rest = r.list[1..].iter().sum::<usize>() as isize;
+            match r.flag {
+                Special::OpenHigh => {
+                    high = true;
+                    assert!(r.list[0] >= 96 && r.list.len() > 1);
+                    assert_eq!(r.list[0] as isize + rest, r.sum);
+                }
+                Special::OpenLow => {
+                    low = true;
+                    assert!(r.list[0] <= 5 && r.list.len() > 1);
+                    assert_eq!(r.list[0] as isize - rest, r.sum);
+                }
+                _ => {
+                    assert_eq!(1, r.list.len());
+                    assert!((6..=95).contains(&r.list[0]));
+                }
+            }
+        }
+        assert!(high && low);
+    }
+
+    #[rstest]
+    #[case(Reroll { value: 1, below: false, repeat: false })]
+    #[case(Reroll { value: 3, below: true, repeat: true })]
+    fn test_reroll_roll(#[case] reroll: Reroll) {
+        let d = Dice::Reroll(6, reroll);
+        let mut seen = false;
+
+        for seed in 0..200 {
+            let r = d.roll_with(&mut Roller::new(StdRng::seed_from_u64(seed)));
+            assert_eq!(1, r.list.len());
+            assert!(r.rerolled.iter().all(|&v| reroll.matches(v)));
+            if reroll.repeat {
+                assert!(!reroll.matches(r.list[0]));
+            } else {
+                assert!(r.rerolled.len() <= 1);
+            }
+            seen |= !r.rerolled.is_empty();
+        }
+        assert!(seen);
+    }
+
     #[test]
     fn test_constant_new() {
         let f = Dice::Constant(6);
@@ -321,14 +803,25 @@ mod tests {
             Err(e) => panic!("Unparsable {}", e),
         };
 
-        let rf = DiceSet(v);
+        let rf = DiceSet::from_vec(v);
 
         assert_eq!(rf, ds);
     }
 
+    #[test]
+    fn test_dices_parse_strict() {
+        assert_eq!(DiceSet::parse("D8 -1"), DiceSet::parse_strict("D8 -1 "));
+        assert!(DiceSet::parse("3d6 oops").is_ok());
+        assert!(matches!(
+            DiceSet::parse_strict("3d6 oops"),
+            Err(DiceError::Trailing { col: 4, .. })
+        ));
+        assert!(DiceSet::parse_strict("foo").is_err());
+    }
+
     #[test]
     fn test_dices_roll() {
-        let rf = DiceSet(vec![
+        let rf = DiceSet::from_vec(vec![
             Dice::Regular(6),
             Dice::Regular(6),
             Dice::Regular(6),
@@ -341,6 +834,179 @@ mod tests {
         assert_eq!(3, r.list.len())
     }
 
+    #[test]
+    fn test_roll() {
+        let r = roll("3D6 +2").unwrap();
+
+        assert_eq!(3, r.list.len());
+        assert_eq!(2, r.bonus);
+        assert!(r.sum >= 5 && r.sum <= 20);
+    }
+
+    #[test]
+    fn test_roll_invalid() {
+        assert!(matches!(
+            roll("foo"),
+            Err(DiceError::Parse(ParseError {
+                offset: 0,
+                expected: Expected::Dice,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_roll_invalid_size() {
+        assert!(matches!(
+            roll("3D1"),
+            Err(DiceError::InvalidSize { size: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_roll_seeded() {
+        let a = roll_seeded("10D20 -1", 42).unwrap();
+        let b = roll_seeded("10D20 -1", 42).unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(10, a.list.len());
+    }
+
+    #[test]
+    fn test_dyn_rollable() {
+        let v: Vec<Box<dyn Rollable>> = vec![
+            Box::new(Dice::Constant(3)),
+            Box::new(DiceSet::from_vec(vec![Dice::Constant(2), Dice::Bonus(1)])),
+        ];
+
+        let sum: isize = v.iter().map(|r| r.roll().sum).sum();
+        assert_eq!(6, sum);
+    }
+
+    #[test]
+    fn test_dices_custom() {
+        let mut ds = DiceSet::from(Dice::Regular(6));
+        ds.add_boxed(Box::new(DiceSet::from_vec(vec![
+            Dice::Constant(10),
+            Dice::Constant(10),
+        ])));
+
+        let r = ds.roll();
+        assert_eq!(3, r.list.len());
+        assert!(r.sum >= 21 && r.sum <= 26);
+
+        // Clones share the same custom dice
+        //
+        assert_eq!(ds, ds.clone());
+    }
+
+    #[test]
+    fn test_diceset_accessors() {
+        let mut ds = DiceSet::parse("3D6 +2 -1").unwrap();
+        ds.push(Dice::Regular(8));
+
+        assert_eq!(5, ds.len());
+        assert!(!ds.is_empty());
+        assert_eq!(1, ds.bonus());
+        assert_eq!(Some(&Dice::Regular(8)), ds.dice().last());
+        assert_eq!(4, ds.iter().filter(|d| d.size() > 0).count());
+        assert_eq!(ds.iter().count(), (&ds).into_iter().count());
+    }
+
+    #[rstest]
+    #[case("3D6 +1", vec![(3, Dice::Regular(6)), (1, Dice::Bonus(1))])]
+    #[case("D6 +2 -2", vec![(1, Dice::Regular(6))])]
+    #[case("4D8 -1", vec![(4, Dice::Regular(8)), (1, Dice::Bonus(-1))])]
+    fn test_diceset_normalize(#[case] expr: &str, #[case] want: Vec<(usize, Dice)>) {
+        assert_eq!(want, DiceSet::parse(expr).unwrap().normalize());
+    }
+
+    #[test]
+    fn test_diceset_counted() {
+        let mut ds = DiceSet::parse("255D100 +1").unwrap();
+
+        assert_eq!(
+            &[(255, Dice::Regular(100)), (1, Dice::Bonus(1))],
+            ds.terms()
+        );
+        assert_eq!(256, ds.len());
+        assert_eq!(255, ds.roll().list.len());
+
+        ds.push(Dice::Bonus(1));
+        ds.push_n(0, Dice::Regular(4));
+        assert_eq!(
+            &[(255, Dice::Regular(100)), (2, Dice::Bonus(1))],
+            ds.terms()
+        );
+        assert_eq!(2, ds.bonus());
+    }
+
+    #[test]
+    fn test_diceset_debug() {
+        let ds = DiceSet::parse("200D6 +1").unwrap();
+
+        assert_eq!("DiceSet[200×Regular(6), Bonus(1)]", format!("{ds:?}"));
+        assert_eq!("200D6 +1", ds.to_string());
+    }
+
+    #[test]
+    fn test_diceset_eq() {
+        let a = DiceSet::from_vec(vec![Dice::Bonus(2), Dice::Regular(6), Dice::Bonus(-1)]);
+
+        assert_ne!(DiceSet::parse("D6 +1").unwrap(), a);
+        assert!(DiceSet::parse("D6 +1").unwrap().equivalent(&a));
+        assert!(!DiceSet::parse("D6 +2").unwrap().equivalent(&a));
+        let (d6, d8) = (Dice::Regular(6), Dice::Regular(8));
+        assert_ne!(
+            DiceSet::from_vec(vec![d6.clone(), d8.clone()]),
+            DiceSet::from_vec(vec![d8, d6])
+        );
+    }
+
+    #[test]
+    fn test_diceset_from_iter() {
+        let ds = std::iter::repeat_n(Dice::Regular(6), 3)
+            .chain([Dice::Bonus(1)])
+            .collect::<DiceSet>();
+
+        assert_eq!(DiceSet::parse("3D6 +1").unwrap(), ds);
+        let sizes = ds.into_iter().map(|d| d.size()).collect::<Vec<_>>();
+        assert_eq!(vec![6, 6, 6, 0], sizes);
+        assert!(DiceSet::from_iter([]).is_empty());
+    }
+
+    #[rstest]
+    #[case("3D6 +1", r#"[{"regular":6},{"regular":6},{"regular":6},{"bonus":1}]"#)]
+    #[case("2D6r<3", r#"[{"reroll":[6,{"value":3,"below":true,"repeat":false}]},{"reroll":[6,{"value":3,"below":true,"repeat":false}]}]"#)]
+    #[case("D8 -2", r#"[{"regular":8},{"bonus":-2}]"#)]
+    fn test_diceset_serde(#[case] expr: &str, #[case] want: &str) {
+        let ds = DiceSet::parse(expr).unwrap();
+
+        let json = serde_json::to_string(&ds).unwrap();
+        assert_eq!(want, json);
+        assert_eq!(ds, serde_json::from_str(&json).unwrap());
+        let yaml = serde_yaml::to_string(&ds).unwrap();
+        assert_eq!(ds, serde_yaml::from_str(&yaml).unwrap());
+    }
+
+    #[rstest]
+    #[case(Dice::Open(6))]
+    #[case(Dice::OpenHighLow(100))]
+    #[case(Dice::Constant(2))]
+    #[case(Dice::Weighted(vec![(1, 1), (6, 3)]))]
+    #[case(Dice::Symbolic(vec![vec!["hit".to_string()], vec![]]))]
+    fn test_dice_serde(#[case] d: Dice) {
+        let json = serde_json::to_string(&d).unwrap();
+        assert_eq!(d, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn test_dice_serde_custom() {
+        let d = Dice::from(Box::new(Dice::Constant(1)) as Box<dyn Rollable + Send + Sync>);
+
+        assert!(serde_json::to_string(&d).is_err());
+    }
+
     #[rstest]
     #[case(Dice::Regular(6), 6)]
     #[case(Dice::Constant(8), 8)]
@@ -349,4 +1015,82 @@ mod tests {
     fn test_size(#[case] d: Dice, #[case] want: usize) {
         assert_eq!(want, d.size());
     }
+    #[rstest]
+    #[case(parse::parse_with_bonus, "3D6 +2", 2)]
+    #[case(parse::parse_with_bonus, "D8 -1 -2", -3)]
+    #[case(parse::parse_open_bonus, "2D6 +3", 3)]
+    #[case(parse::parse_open_bonus, "D10 -4", -4)]
+    #[case(parse::parse_open100, " +5", 5)]
+    #[case(parse::parse_open100, "", 0)]
+    fn test_bonus_accounting(
+        #[case] parse: fn(&str) -> nom::IResult<&str, DiceSet>,
+        #[case] input: &str,
+        #[case] bonus: isize,
+    ) {
+        let (_, ds) = parse(input).unwrap();
+        let mut rng = Roller::new(StdRng::seed_from_u64(42));
+
+        for _ in 0..100 {
+            let r = ds.roll_with(&mut rng);
+
+            assert_eq!(bonus, r.bonus, "{input}: {r:?}");
+            assert_eq!(r.total(), r.raw_sum() + r.bonus, "{input}: {r:?}");
+            if r.flag != Special::OpenLow {
+                assert_eq!(r.list.iter().sum::<usize>() as isize, r.raw_sum());
+            }
+        }
+    }
+
+    #[rstest]
+    #[case(Dice::Regular(6), "D6")]
+    #[case(Dice::Open(12), "D12")]
+    #[case(Dice::Constant(8), "8")]
+    #[case(Dice::Bonus(-1), "-1")]
+    #[case(Dice::Bonus(2), "+2")]
+    #[case(Dice::Reroll(6, Reroll { value: 3, below: true, repeat: true }), "D6rr<3")]
+    #[case(Dice::Weighted(vec![(1, 1), (6, 2)]), "DW")]
+    fn test_dice_display(#[case] d: Dice, #[case] want: &str) {
+        assert_eq!(want, d.to_string());
+    }
+
+    #[rstest]
+    #[case("D100", "D100")]
+    #[case("3d6 +1", "3D6 +1")]
+    #[case("2D8 -1 -2", "2D8 -3")]
+    #[case("4D6r1", "4D6r1")]
+    fn test_dices_display(#[case] input: &str, #[case] want: &str) {
+        let ds = DiceSet::parse(input).unwrap();
+
+        assert_eq!(want, ds.to_string());
+        assert_eq!(ds, DiceSet::parse_strict(&ds.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_dices_display_mixed() {
+        let ds = DiceSet::from_vec(vec![
+            Dice::Regular(6),
+            Dice::Regular(6),
+            Dice::Open(6),
+            Dice::Constant(3),
+            Dice::Constant(3),
+            Dice::Bonus(1),
+        ]);
+
+        assert_eq!("2D6 D6 3 3 +1", ds.to_string());
+    }
+
+    #[rstest]
+    #[case(vec![Dice::Regular(20)], "D20")]
+    #[case(vec![Dice::Regular(6), Dice::Regular(6), Dice::Bonus(-2)], "2D6 -2")]
+    #[case(vec![Dice::Reroll(6, Reroll { value: 1, below: false, repeat: false })], "D6r1")]
+    #[case(
+        vec![Dice::Reroll(10, Reroll { value: 3, below: true, repeat: true }); 2],
+        "2D10rr<3"
+    )]
+    fn test_dices_display_parses(#[case] dices: Vec<Dice>, #[case] want: &str) {
+        let ds = DiceSet::from_vec(dices);
+
+        assert_eq!(want, ds.to_string());
+        assert_eq!(ds, DiceSet::parse_strict(&ds.to_string()).unwrap());
+    }
 }
@@ -8,26 +8,80 @@
 //! Public API:
 //!
 //! - `parse_dice` for a single regular dice
+//! - `parse_dice_with` for a single dice, regular or defined in the configuration
 //! - `parse_open` for an open-ended dice
-//! - `parse_with_bonus` for regular dices
-//! - `parse_open_bonus`  for an open-ended dice
+//! - `parse_with_bonus` for regular dices, `parse_defined_with_bonus` with defined ones
+//! - `parse_open_bonus`  for open-ended dices
+//! - `parse_open100`  for Rolemaster open-ended D100
+//! - `check_trailing` to refuse anything left by the parsers (strict mode)
+//!
+//! Dices defined in the configuration are given to the parsers as a `Defined`, the ones
+//! without it know only regular dices.
+
+use std::collections::BTreeMap;
 
 use itertools::Itertools;
+use log::trace;
 use nom::{
-    character::complete::{i8, one_of, space0, u32, u8},
-    combinator::{map, opt},
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, digit1, i8, one_of, space0, u32},
+    combinator::{cut, map, opt, value, verify},
+    error::{Error, ErrorKind},
     multi::fold_many0,
-    sequence::{pair, preceded},
+    sequence::{pair, preceded, tuple},
     IResult,
 };
 
+use crate::dice::error::{DiceError, MAX_DICES};
+use crate::dice::pick::Pick;
+use crate::dice::reroll::{Reroll, GWF};
+use crate::dice::symbolic::{parse_symbolic, Symbols};
+use crate::dice::weighted::{parse_weighted, Faces};
 use crate::dice::{Dice, DiceSet};
 
+/// No weighted dice
+static NO_WEIGHTED: BTreeMap<String, Faces> = BTreeMap::new();
+
+/// No symbol dice
+static NO_SYMBOLIC: BTreeMap<String, Symbols> = BTreeMap::new();
+
+/// Dices defined in the configuration, found by name by the parsers
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Defined<'a> {
+    /// Weighted dices, `DW:<name>`
+    pub weighted: &'a BTreeMap<String, Faces>,
+    /// Symbol dices, `D<name>`
+    pub symbolic: &'a BTreeMap<String, Symbols>,
+}
+
+/// Nothing defined
+impl Default for Defined<'_> {
+    fn default() -> Self {
+        Defined {
+            weighted: &NO_WEIGHTED,
+            symbolic: &NO_SYMBOLIC,
+        }
+    }
+}
+
+/// A regular dice like `D6`
+///
 #[inline]
 pub fn parse_dice(input: &str) -> IResult<&str, Dice> {
+    parse_dice_with(input, &Defined::default())
+}
+
+/// A regular dice like `D6` or one of `defined`, weighted like `DW:loaded` or symbolic like
+/// `DBoost`
+///
+pub fn parse_dice_with<'i>(input: &'i str, defined: &Defined) -> IResult<&'i str, Dice> {
     let into_dice = |s: u32| Dice::Regular(s as usize);
-    let r = preceded(one_of("dD"), u32);
-    map(r, into_dice)(input)
+    let weighted = |i| parse_weighted(i, defined.weighted);
+    let symbolic = |i| parse_symbolic(i, defined.symbolic);
+    let r = alt((map(u32, into_dice), weighted, symbolic));
+    preceded(one_of("dD"), cut(r))(input)
 }
 
 #[inline]
@@ -37,15 +91,85 @@ pub fn parse_open(input: &str) -> IResult<&str, DiceSet> {
     map(r, into_dice)(input)
 }
 
+/// Reroll modifier right after a dice: `r1`, `r<3`, `rr1`, `rr<3` or `gwf`
+///
 #[inline]
-fn parse_ndices(input: &str) -> IResult<&str, DiceSet> {
-    let into_set = |(n, d): (Option<std::primitive::u8>, Dice)| {
-        let n = n.unwrap_or(1);
-        let v: Vec<Dice> = (1..=n).map(|_| d).collect();
-        DiceSet::from_vec(v)
+fn parse_reroll(input: &str) -> IResult<&str, Reroll> {
+    let into_reroll =
+        |((_, repeat), below, value): ((char, Option<char>), Option<char>, u32)| Reroll {
+            value: value as usize,
+            below: below.is_some(),
+            repeat: repeat.is_some(),
+        };
+    let r = tuple((pair(char('r'), opt(char('r'))), opt(char('<')), u32));
+    alt((value(GWF, tag("gwf")), map(r, into_reroll)))(input)
+}
+
+/// Several dices like `3D6`, with an optional reroll modifier which has to keep at least one
+/// value of the dice
+///
+#[inline]
+pub(crate) fn parse_ndices<'i>(input: &'i str, defined: &Defined) -> IResult<&'i str, DiceSet> {
+    let mut r = verify(
+        tuple((
+            opt(digit1),
+            |i| parse_dice_with(i, defined),
+            opt(parse_reroll),
+        )),
+        |(_, d, r): &(_, Dice, Option<Reroll>)| r.is_none_or(|r| r.is_possible(d.size())),
+    );
+    let (rest, (n, d, r)) = r(input)?;
+    let n = dice_count(input, n)?;
+    let d = match r {
+        Some(r) => Dice::Reroll(d.size(), r),
+        None => d,
     };
-    let r = pair(opt(u8), parse_dice);
-    map(r, into_set)(input)
+    Ok((rest, DiceSet::counted(n, d)))
+}
+
+/// Number of dices in front of a `D`, one if there is none.  More than `MAX_DICES` is a
+/// failure, only known once the dice is parsed as `1000` alone is a number.
+///
+fn dice_count<'i>(input: &'i str, n: Option<&str>) -> Result<usize, nom::Err<Error<&'i str>>> {
+    match n.map(str::parse::<usize>) {
+        None => Ok(1),
+        Some(Ok(n)) if n <= MAX_DICES => Ok(n),
+        Some(_) => Err(nom::Err::Failure(Error::new(input, ErrorKind::TooLarge))),
+    }
+}
+
+/// `dl<n>`, `dh<n>`, `kh<n>` or `kl<n>` after a group of dices
+///
+#[inline]
+fn parse_pick(input: &str) -> IResult<&str, Pick> {
+    let into_pick = |(op, n): (&str, u32)| {
+        let n = n as usize;
+        match op {
+            "dl" => Pick::DropLowest(n),
+            "dh" => Pick::DropHighest(n),
+            "kh" => Pick::KeepHighest(n),
+            _ => Pick::KeepLowest(n),
+        }
+    };
+    let r = pair(alt((tag("dl"), tag("dh"), tag("kh"), tag("kl"))), u32);
+    map(r, into_pick)(input)
+}
+
+/// Several dices with an optional `Pick`, which has to keep at least one of them.  This is
+/// a failure and not an error so that `2d6dl2` is not read as the number `2` by `alt()`.
+///
+#[inline]
+pub(crate) fn parse_picked<'i>(
+    input: &'i str,
+    defined: &Defined,
+) -> IResult<&'i str, (DiceSet, Option<Pick>)> {
+    let (rest, (ds, p)) = pair(|i| parse_ndices(i, defined), opt(parse_pick))(input)?;
+    match p {
+        Some(p) if !p.is_possible(ds.len()) => {
+            Err(nom::Err::Failure(Error::new(input, ErrorKind::Verify)))
+        }
+        _ => Ok((rest, (ds, p))),
+    }
 }
 
 #[inline]
@@ -59,9 +183,11 @@ fn parse_bonus(input: &str) -> IResult<&str, std::primitive::i8> {
     map(r, get_sign)(input)
 }
 
+/// Bonuses are summed as `isize` so that many of them can not overflow
+///
 #[inline]
-fn parse_nbonus(input: &str) -> IResult<&str, std::primitive::i8> {
-    let sum = |v: Vec<std::primitive::i8>| v.iter().sum1().unwrap_or(0);
+fn parse_nbonus(input: &str) -> IResult<&str, isize> {
+    let sum = |v: Vec<std::primitive::i8>| v.iter().map(|&b| b as isize).sum1().unwrap_or(0);
     let r = fold_many0(
         preceded(space0, parse_bonus),
         Vec::new,
@@ -76,24 +202,64 @@ fn parse_nbonus(input: &str) -> IResult<&str, std::primitive::i8> {
 /// Extracted from parse_with_bonus
 ///
 #[inline]
-fn add_bonus((mut ds, b): (DiceSet, std::primitive::i8)) -> DiceSet {
-    dbg!(&ds, &b);
+fn add_bonus((mut ds, b): (DiceSet, isize)) -> DiceSet {
+    trace!("add_bonus({ds:?}, {b})");
     if b != 0 {
-        ds.0.push(Dice::Bonus(b.into()))
+        ds.push(Dice::Bonus(b))
     };
     ds
 }
 
+/// Several open-ended dices like `3D6`, each one exploding on its own
+///
+#[inline]
+pub(crate) fn parse_nopen(input: &str) -> IResult<&str, DiceSet> {
+    let (rest, (n, s)) = pair(opt(digit1), preceded(one_of("dD"), u32))(input)?;
+    let n = dice_count(input, n)?;
+    Ok((rest, DiceSet::counted(n, Dice::Open(s as usize))))
+}
+
 pub fn parse_open_bonus(input: &str) -> IResult<&str, DiceSet> {
-    let r = pair(parse_open, parse_nbonus);
+    let r = pair(parse_nopen, parse_nbonus);
     map(r, add_bonus)(input)
 }
 
+/// Rolemaster open-ended D100 with optional bonuses, the dice is implied
+///
+pub fn parse_open100(input: &str) -> IResult<&str, DiceSet> {
+    let into_set = |b| add_bonus((DiceSet::from(Dice::OpenHighLow(100)), b));
+    map(parse_nbonus, into_set)(input)
+}
+
 pub fn parse_with_bonus(input: &str) -> IResult<&str, DiceSet> {
-    let r = pair(parse_ndices, parse_nbonus);
+    parse_defined_with_bonus(input, &Defined::default())
+}
+
+/// Same as `parse_with_bonus` with the dices of `defined`
+///
+pub fn parse_defined_with_bonus<'i>(
+    input: &'i str,
+    defined: &Defined,
+) -> IResult<&'i str, DiceSet> {
+    let r = pair(|i| parse_ndices(i, defined), parse_nbonus);
     map(r, add_bonus)(input)
 }
 
+/// Error out if `rest`, what is left after parsing `input`, is not only spaces
+///
+pub fn check_trailing(input: &str, rest: &str) -> Result<(), DiceError> {
+    let (input, rest) = (input.trim(), rest.trim());
+    if rest.is_empty() {
+        return Ok(());
+    }
+    let at = input.len().saturating_sub(rest.len());
+    Err(DiceError::Trailing {
+        input: input.to_string(),
+        rest: rest.to_string(),
+        col: input[..at].chars().count(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::vec;
@@ -107,8 +273,11 @@ mod tests {
     #[case("D6", DiceSet::from_vec(vec![Dice::Regular(6)]))]
     #[case("d8", DiceSet::from_vec(vec![Dice::Regular(8)]))]
     #[case("3D6", DiceSet::from_vec(vec![Dice::Regular(6), Dice::Regular(6), Dice::Regular(6)]))]
+    #[case("2d6r1", DiceSet::from_vec(vec![Dice::Reroll(6, Reroll { value: 1, below: false, repeat: false }); 2]))]
+    #[case("2d6rr<3", DiceSet::from_vec(vec![Dice::Reroll(6, Reroll { value: 3, below: true, repeat: true }); 2]))]
+    #[case("2d6gwf", DiceSet::from_vec(vec![Dice::Reroll(6, GWF); 2]))]
     fn test_parse_dice(#[case] input: &str, #[case] res: DiceSet) {
-        let r = parse_ndices(input);
+        let r = parse_ndices(input, &Defined::default());
         assert!(r.is_ok());
         let r = r.unwrap();
         assert_eq!(res, r.1);
@@ -129,6 +298,56 @@ mod tests {
         assert_eq!(res, r.1);
     }
 
+    #[test]
+    fn test_parse_defined_with_bonus() {
+        let weighted =
+            BTreeMap::from([("loaded".to_string(), Faces::try_from(vec![(6, 1)]).unwrap())]);
+        let defined = Defined {
+            weighted: &weighted,
+            ..Defined::default()
+        };
+
+        let (rest, ds) = parse_defined_with_bonus("2dW:loaded +1", &defined).unwrap();
+        assert_eq!("", rest);
+        assert_eq!(
+            DiceSet::from_vec(vec![
+                Dice::Weighted(vec![(6, 1)]),
+                Dice::Weighted(vec![(6, 1)]),
+                Dice::Bonus(1)
+            ]),
+            ds
+        );
+        assert!(parse_with_bonus("2dW:loaded +1").is_err());
+    }
+
+    #[rstest]
+    #[case("5d6dl1", Some(Pick::DropLowest(1)))]
+    #[case("4D6kh3", Some(Pick::KeepHighest(3)))]
+    #[case("2d20kl1", Some(Pick::KeepLowest(1)))]
+    #[case("3d6dh1", Some(Pick::DropHighest(1)))]
+    #[case("3d6", None)]
+    fn test_parse_picked(#[case] input: &str, #[case] pick: Option<Pick>) {
+        let (rest, (_, p)) = parse_picked(input, &Defined::default()).unwrap();
+        assert_eq!("", rest);
+        assert_eq!(pick, p);
+    }
+
+    #[rstest]
+    #[case("2d6dl2")]
+    #[case("2d6kh0")]
+    #[case("2d6kl3")]
+    fn test_parse_picked_impossible(#[case] input: &str) {
+        assert!(parse_picked(input, &Defined::default()).is_err());
+    }
+
+    #[rstest]
+    #[case("d1r1")]
+    #[case("2d6rr<7")]
+    #[case("d2gwf")]
+    fn test_parse_reroll_impossible(#[case] input: &str) {
+        assert!(parse_ndices(input, &Defined::default()).is_err());
+    }
+
     #[rstest]
     #[case("D6", DiceSet::from(Dice::Open(6)))]
     #[case("d4", DiceSet::from(Dice::Open(4)))]
@@ -146,7 +365,8 @@ mod tests {
     #[case("+1 +2 +3 -2 +7", 11)]
     #[case("+2 +3 +7", 12)]
     #[case(" -1 +2 -2 +7", 6)]
-    fn test_parse_nbonus(#[case] input: &str, #[case] sum: i8) {
+    #[case("+100 +100 +100", 300)]
+    fn test_parse_nbonus(#[case] input: &str, #[case] sum: isize) {
         let (_input, s) = parse_nbonus(input).unwrap();
         assert_eq!(sum, s);
     }
@@ -155,6 +375,8 @@ mod tests {
     #[case("d6", DiceSet::from_vec(vec ! [Dice::Open(6)]))]
     #[case("d6 +1", DiceSet::from_vec(vec ! [Dice::Open(6), Dice::Bonus(1)]))]
     #[case("D4 -2", DiceSet::from_vec(vec ! [Dice::Open(4), Dice::Bonus(- 2)]))]
+    #[case("3d6", DiceSet::from_vec(vec ! [Dice::Open(6), Dice::Open(6), Dice::Open(6)]))]
+    #[case("2D8 +2", DiceSet::from_vec(vec ! [Dice::Open(8), Dice::Open(8), Dice::Bonus(2)]))]
     fn test_parse_open_bonus(#[case] input: &str, #[case] out: DiceSet) {
         let r = parse_open_bonus(input);
         assert!(r.is_ok());
@@ -163,11 +385,43 @@ mod tests {
     }
 
     #[rstest]
-    #[case(DiceSet(vec ! [Dice::Open(6)]), 0, DiceSet(vec ! [Dice::Open(6)]))]
-    #[case(DiceSet(vec ! [Dice::Open(6)]), 1, DiceSet(vec ! [Dice::Open(6), Dice::Bonus(1)]))]
-    #[case(DiceSet(vec ! [Dice::Regular(4)]), - 2, DiceSet(vec ! [Dice::Regular(4), Dice::Bonus(- 2)]))]
-    fn test_add_bonus(#[case] input: DiceSet, #[case] bonus: i8, #[case] out: DiceSet) {
+    #[case("", DiceSet::from(Dice::OpenHighLow(100)))]
+    #[case(" +25", DiceSet::from_vec(vec ! [Dice::OpenHighLow(100), Dice::Bonus(25)]))]
+    fn test_parse_open100(#[case] input: &str, #[case] out: DiceSet) {
+        let (_input, ds) = parse_open100(input).unwrap();
+        assert_eq!(out, ds);
+    }
+
+    #[rstest]
+    #[case(DiceSet::from_vec(vec ! [Dice::Open(6)]), 0, DiceSet::from_vec(vec ! [Dice::Open(6)]))]
+    #[case(DiceSet::from_vec(vec ! [Dice::Open(6)]), 1, DiceSet::from_vec(vec ! [Dice::Open(6), Dice::Bonus(1)]))]
+    #[case(DiceSet::from_vec(vec ! [Dice::Regular(4)]), - 2, DiceSet::from_vec(vec ! [Dice::Regular(4), Dice::Bonus(- 2)]))]
+    fn test_add_bonus(#[case] input: DiceSet, #[case] bonus: isize, #[case] out: DiceSet) {
         let ds = add_bonus((input, bonus));
         assert_eq!(out, ds);
     }
+
+    #[rstest]
+    #[case("3D6", "", None)]
+    #[case("3D6  ", "  ", None)]
+    #[case(" 3D6 oops ", " oops ", Some(4))]
+    #[case("é 3D6 x", "x", Some(6))]
+    fn test_check_trailing(#[case] input: &str, #[case] rest: &str, #[case] col: Option<usize>) {
+        match col {
+            None => assert!(check_trailing(input, rest).is_ok()),
+            Some(col) => {
+                let e = check_trailing(input, rest).unwrap_err();
+                assert_eq!(
+                    format!(
+                        "unexpected {:?} at column {}\n  {}\n  {}^",
+                        rest.trim(),
+                        col + 1,
+                        input.trim(),
+                        " ".repeat(col)
+                    ),
+                    e.to_string()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,131 @@
+//! Keep or drop some dices of a group, after they are rolled.
+//!
+//! - `5D6dl1`  drop the lowest dice
+//! - `5D6dh2`  drop the two highest dices
+//! - `4D6kh3`  keep the three highest dices
+//! - `2D20kl1` keep the lowest dice
+//!
+//! Dropped dices are not part of `sum`, they are moved from `Res::list` to `Res::dropped`.
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::expr::parse_expr;
+//! use dices_rs::dice::internal::Roller;
+//!
+//! let (_, e) = parse_expr("4d6dl1").unwrap();
+//! let r = e.eval(&mut Roller::default()).unwrap();
+//!
+//! assert_eq!(3, r.list.len());
+//! assert_eq!(1, r.dropped.len());
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::dice::result::Res;
+
+/// Which dices of a group are kept
+///
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Pick {
+    /// Drop the `n` lowest
+    DropLowest(usize),
+    /// Drop the `n` highest
+    DropHighest(usize),
+    /// Keep the `n` highest
+    KeepHighest(usize),
+    /// Keep the `n` lowest
+    KeepLowest(usize),
+}
+
+impl Pick {
+    /// Can it be applied to a group of `n` dices, keeping at least one?
+    ///
+    pub fn is_possible(&self, n: usize) -> bool {
+        match *self {
+            Pick::DropLowest(k) | Pick::DropHighest(k) => k < n,
+            Pick::KeepHighest(k) | Pick::KeepLowest(k) => (1..=n).contains(&k),
+        }
+    }
+
+    /// Move the dropped dices of `res` out of `list` and `sum`, the order of the kept ones
+    /// does not change
+    ///
+    pub fn apply(&self, mut res: Res) -> Res {
+        let n = res.list.len();
+        let (count, lowest) = match *self {
+            Pick::DropLowest(k) => (k.min(n), true),
+            Pick::DropHighest(k) => (k.min(n), false),
+            Pick::KeepHighest(k) => (n - k.min(n), true),
+            Pick::KeepLowest(k) => (n - k.min(n), false),
+        };
+
+        // Indices of the dices to drop, sorted from the first to go
+        //
+        let mut order = (0..n).collect::<Vec<_>>();
+        order.sort_by_key(|&i| res.list[i]);
+        if !lowest {
+            order.reverse();
+        }
+        let mut gone = order[..count].to_vec();
+        gone.sort_unstable();
+
+        for i in gone.into_iter().rev() {
+            let v = res.list.remove(i);
+            res.sum -= v as isize;
+            res.dropped.push(v);
+        }
+        res
+    }
+}
+
+impl Display for Pick {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pick::DropLowest(k) => write!(f, "dl{k}"),
+            Pick::DropHighest(k) => write!(f, "dh{k}"),
+            Pick::KeepHighest(k) => write!(f, "kh{k}"),
+            Pick::KeepLowest(k) => write!(f, "kl{k}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn res(list: Vec<usize>) -> Res {
+        Res {
+            sum: list.iter().sum::<usize>() as isize,
+            list,
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case(Pick::DropLowest(1), vec![3, 6, 4], vec![1])]
+    #[case(Pick::DropHighest(2), vec![3, 1], vec![6, 4])]
+    #[case(Pick::KeepHighest(3), vec![3, 6, 4], vec![1])]
+    #[case(Pick::KeepLowest(1), vec![1], vec![6, 4, 3])]
+    fn test_pick_apply(#[case] p: Pick, #[case] list: Vec<usize>, #[case] dropped: Vec<usize>) {
+        let r = p.apply(res(vec![3, 1, 6, 4]));
+
+        assert_eq!(list.iter().sum::<usize>() as isize, r.sum);
+        assert_eq!(list, r.list);
+        let mut d = r.dropped.clone();
+        d.sort_unstable_by(|a, b| b.cmp(a));
+        let mut want = dropped;
+        want.sort_unstable_by(|a, b| b.cmp(a));
+        assert_eq!(want, d);
+    }
+
+    #[rstest]
+    #[case(Pick::DropLowest(4), 5, true)]
+    #[case(Pick::DropLowest(5), 5, false)]
+    #[case(Pick::KeepHighest(5), 5, true)]
+    #[case(Pick::KeepLowest(0), 5, false)]
+    fn test_pick_possible(#[case] p: Pick, #[case] n: usize, #[case] want: bool) {
+        assert_eq!(want, p.is_possible(n));
+    }
+}
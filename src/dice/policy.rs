@@ -0,0 +1,120 @@
+//! Which dice sizes are allowed.
+//!
+//! By default any size between 2 and 1000 is fine but one can restrict it to the physical
+//! dices (4, 6, 8, 10, 12, 20 and 100) or an explicit list.
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::policy::SizePolicy;
+//! use dices_rs::dice::DiceSet;
+//!
+//! let ds = DiceSet::parse("D7").unwrap();
+//!
+//! assert!(SizePolicy::Any.check(&ds).is_ok());
+//! assert!(SizePolicy::Physical.check(&ds).is_err());
+//! ```
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::dice::error::DiceError;
+use crate::dice::{Dice, DiceSet};
+
+/// Smallest and largest dices for `SizePolicy::Any`
+pub const MIN_SIZE: usize = 2;
+pub const MAX_SIZE: usize = 1000;
+
+/// Dices you can actually buy
+pub const PHYSICAL: [usize; 7] = [4, 6, 8, 10, 12, 20, 100];
+
+/// Policy for dice sizes
+///
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizePolicy {
+    /// Anything between `MIN_SIZE` and `MAX_SIZE`
+    #[default]
+    Any,
+    /// Only the physical dices
+    Physical,
+    /// Only these sizes
+    Only(Vec<usize>),
+}
+
+impl SizePolicy {
+    /// Is a dice of this size allowed?
+    ///
+    pub fn is_valid(&self, size: usize) -> bool {
+        match self {
+            SizePolicy::Any => (MIN_SIZE..=MAX_SIZE).contains(&size),
+            SizePolicy::Physical => PHYSICAL.contains(&size),
+            SizePolicy::Only(list) => list.contains(&size),
+        }
+    }
+
+    /// Check every dice of the set, bonuses and custom dices are always fine
+    ///
+    pub fn check(&self, ds: &DiceSet) -> Result<(), DiceError> {
+        let bad = ds.iter().find_map(|d| match *d {
+            Dice::Regular(s) | Dice::Open(s) | Dice::OpenHighLow(s) | Dice::Reroll(s, _)
+                if !self.is_valid(s) =>
+            {
+                Some(s)
+            }
+            _ => None,
+        });
+        match bad {
+            Some(size) => Err(DiceError::InvalidSize {
+                size,
+                allowed: self.allowed(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe what is allowed, for error messages
+    ///
+    fn allowed(&self) -> String {
+        let list = |l: &[usize]| l.iter().map(|s| format!("D{s}")).join(", ");
+        match self {
+            SizePolicy::Any => format!("D{MIN_SIZE} to D{MAX_SIZE}"),
+            SizePolicy::Physical => list(&PHYSICAL),
+            SizePolicy::Only(l) => list(l),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(SizePolicy::Any, 2, true)]
+    #[case(SizePolicy::Any, 7, true)]
+    #[case(SizePolicy::Any, 1000, true)]
+    #[case(SizePolicy::Any, 1, false)]
+    #[case(SizePolicy::Any, 0, false)]
+    #[case(SizePolicy::Any, 1001, false)]
+    #[case(SizePolicy::Physical, 20, true)]
+    #[case(SizePolicy::Physical, 7, false)]
+    #[case(SizePolicy::Only(vec![3, 7]), 7, true)]
+    #[case(SizePolicy::Only(vec![3, 7]), 6, false)]
+    fn test_is_valid(#[case] p: SizePolicy, #[case] size: usize, #[case] want: bool) {
+        assert_eq!(want, p.is_valid(size));
+    }
+
+    #[test]
+    fn test_check() {
+        let ds = DiceSet::from_vec(vec![Dice::Regular(6), Dice::Open(7), Dice::Bonus(1)]);
+
+        assert!(SizePolicy::Any.check(&ds).is_ok());
+
+        let e = SizePolicy::Physical.check(&ds).unwrap_err();
+        assert_eq!(
+            "D7 is not allowed, use one of D4, D6, D8, D10, D12, D20, D100",
+            e.to_string()
+        );
+    }
+}
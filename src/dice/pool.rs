@@ -0,0 +1,174 @@
+//! Success pools of the old World of Darkness, `wod 7d10 diff 6`.
+//!
+//! Every d10 at or above the difficulty is a success, every 1 of the pool takes one success
+//! away and every 10 is rolled again, the new dice adding successes (their 1s do not count).
+//! A pool without any success and with at least one 1 is a botch, flagged `Botch`.
+//!
+//! The total is the number of successes left, never below 0, and the note says what happened,
+//! in the language of the `Messages` given to `roll_localized`.
+//!
+//! ```
+//! use dices_rs::dice::pool::Pool;
+//! use dices_rs::dice::result::Special;
+//!
+//! let r = Pool::new(5, 6).resolve(&[10, 8, 1, 3, 4], &[7]);
+//! assert_eq!(2, r.sum);
+//! assert_eq!("2 successes", r.note.unwrap());
+//!
+//! let r = Pool::new(3, 6).resolve(&[1, 2, 5], &[]);
+//! assert_eq!(Special::Botch, r.flag);
+//! ```
+
+use log::debug;
+
+use crate::dice::internal::Roller;
+use crate::dice::result::{Res, Special};
+use crate::dice::Rollable;
+use crate::messages::{Messages, Msg};
+
+/// Dices of a pool
+pub const SIDES: usize = 10;
+
+/// Difficulty when none is given
+pub const DIFFICULTY: usize = 6;
+
+/// Largest pool rolled
+pub const MAX_POOL: usize = 100;
+
+/// `count` d10 against `difficulty`
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Pool {
+    pub count: usize,
+    pub difficulty: usize,
+}
+
+impl Pool {
+    pub fn new(count: usize, difficulty: usize) -> Self {
+        Pool { count, difficulty }
+    }
+
+    /// Result of the pool from the dices rolled, `extra` being the ones rolled for the 10s
+    ///
+    pub fn resolve(&self, dices: &[usize], extra: &[usize]) -> Res {
+        self.resolve_localized(dices, extra, &Messages::default())
+    }
+
+    /// Same as `resolve` with the note taken from `m`
+    ///
+    pub fn resolve_localized(&self, dices: &[usize], extra: &[usize], m: &Messages) -> Res {
+        let hits = |list: &[usize]| list.iter().filter(|d| **d >= self.difficulty).count();
+        let successes = hits(dices) + hits(extra);
+        let ones = dices.iter().filter(|d| **d == 1).count();
+        let left = successes.saturating_sub(ones);
+
+        let mut res = Res::new();
+        dices.iter().chain(extra).for_each(|d| {
+            res.list.push(*d);
+        });
+        res.sum = left as isize;
+        let (flag, note) = outcome(res.sum, successes == 0 && ones > 0, m);
+        res.set(flag);
+        debug!("pool {dices:?} + {extra:?} vs {}: {note}", self.difficulty);
+        res.note = Some(note);
+        res
+    }
+}
+
+impl Pool {
+    /// Roll the pool with the note taken from `m`
+    ///
+    pub fn roll_localized(&self, rng: &mut Roller, m: &Messages) -> Res {
+        let dices = (0..self.count).map(|_| rng.roll(SIDES)).collect::<Vec<_>>();
+
+        // Every 10 rolls one more dice, which can be a 10 again, up to the cap
+        //
+        let mut tens = dices.iter().filter(|d| **d == SIDES).count();
+        let mut extra = vec![];
+        while tens > 0 && extra.len() < rng.max_explosions() {
+            let d = rng.roll(SIDES);
+            if d != SIDES {
+                tens -= 1;
+            }
+            extra.push(d);
+        }
+        let mut res = self.resolve_localized(&dices, &extra, m);
+        res.capped = tens > 0;
+        res
+    }
+}
+
+/// Flag and note of `left` successes, `botch` when there was none but some 1s
+///
+pub fn outcome(left: isize, botch: bool, m: &Messages) -> (Special, String) {
+    match left {
+        _ if botch => (Special::Botch, m.get(Msg::PoolBotch).to_string()),
+        ..=0 => (Special::None, m.get(Msg::PoolFailure).to_string()),
+        1 => (Special::None, m.get(Msg::OneSuccess).to_string()),
+        n => (Special::None, m.fill(Msg::Successes, &[&n.to_string()])),
+    }
+}
+
+/// Notes in English
+///
+impl Rollable for Pool {
+    fn roll_with(&self, rng: &mut Roller) -> Res {
+        self.roll_localized(rng, &Messages::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(&[7, 8, 2], &[], 2, Special::None, "2 successes")]
+    #[case(&[7, 1, 2], &[], 0, Special::None, "failure")]
+    #[case(&[7, 1, 1], &[], 0, Special::None, "failure")]
+    #[case(&[2, 1, 5], &[], 0, Special::Botch, "botch")]
+    #[case(&[2, 3, 5], &[], 0, Special::None, "failure")]
+    #[case(&[10, 1, 3], &[6], 1, Special::None, "1 success")]
+    #[case(&[10, 4], &[10, 1], 2, Special::None, "2 successes")]
+    fn test_resolve(
+        #[case] dices: &[usize],
+        #[case] extra: &[usize],
+        #[case] sum: isize,
+        #[case] flag: Special,
+        #[case] note: &str,
+    ) {
+        let r = Pool::new(dices.len(), 6).resolve(dices, extra);
+
+        assert_eq!(sum, r.sum);
+        assert_eq!(flag, r.flag);
+        assert_eq!(Some(note), r.note.as_deref());
+        assert_eq!(dices.len() + extra.len(), r.list.len());
+    }
+
+    #[rstest]
+    #[case(&[7, 8, 2], "2 succès")]
+    #[case(&[7, 1, 2], "échec")]
+    #[case(&[2, 1, 5], "échec total")]
+    #[case(&[7, 2, 3], "1 succès")]
+    fn test_resolve_french(#[case] dices: &[usize], #[case] note: &str) {
+        let m = Messages::new("fr_FR.UTF-8");
+        let r = Pool::new(dices.len(), 6).resolve_localized(dices, &[], &m);
+
+        assert_eq!(Some(note), r.note.as_deref());
+    }
+
+    #[test]
+    fn test_roll_tens() {
+        let p = Pool::new(10, 8);
+
+        for seed in 0..200 {
+            let r = p.roll_with(&mut Roller::new(StdRng::seed_from_u64(seed)));
+            let tens = r.list[..10].iter().filter(|d| **d == 10).count();
+            let again = r.list[10..].iter().filter(|d| **d == 10).count();
+            assert_eq!(tens + again, r.list.len() - 10, "{r:?}");
+            assert!(r.sum >= 0 && r.sum as usize <= r.list.len());
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Reroll modifiers for regular dices.
+//!
+//! - `2D6r1`   roll again any 1, once
+//! - `2D6r<3`  roll again anything under 3, once
+//! - `2D6rr1`  roll again as long as it is 1
+//! - `2D6rr<3` roll again as long as it is under 3
+//! - `2D6gwf`  Great Weapon Fighting, roll again any 1 or 2 once like `r<3`
+//!
+//! The discarded values are kept in `Res::rerolled`.
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::{DiceSet, Rollable};
+//!
+//! let r = DiceSet::parse("2D6rr<3").unwrap().roll();
+//!
+//! assert!(r.list.iter().all(|&v| v >= 3));
+//! assert!(r.rerolled.iter().all(|&v| v < 3));
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+/// Safety net against conditions matching every value of a dice built by hand
+pub const MAX_REROLLS: usize = 100;
+
+/// Great Weapon Fighting from D&D 5e: 1s and 2s are rolled again once, the new value is kept
+pub const GWF: Reroll = Reroll {
+    value: 3,
+    below: true,
+    repeat: false,
+};
+
+/// When to roll a dice again
+///
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Reroll {
+    /// Value to reroll, or the limit with `below`
+    pub value: usize,
+    /// Reroll anything under `value` instead of only `value`
+    pub below: bool,
+    /// Go on as long as the new roll matches
+    pub repeat: bool,
+}
+
+impl Reroll {
+    /// Should `v` be rolled again?
+    ///
+    pub fn matches(&self, v: usize) -> bool {
+        match self.below {
+            true => v < self.value,
+            false => v == self.value,
+        }
+    }
+
+    /// Is there at least one value of a dice of `size` we would keep?
+    ///
+    pub fn is_possible(&self, size: usize) -> bool {
+        (1..=size).any(|v| !self.matches(v))
+    }
+}
+
+impl Display for Reroll {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let r = if self.repeat { "rr" } else { "r" };
+        let below = if self.below { "<" } else { "" };
+        write!(f, "{r}{below}{}", self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use crate::dice::internal::Roller;
+    use crate::dice::{DiceSet, Rollable};
+
+    use super::*;
+
+    #[rstest]
+    #[case(1, false, 1, true)]
+    #[case(1, false, 2, false)]
+    #[case(3, true, 2, true)]
+    #[case(3, true, 3, false)]
+    fn test_reroll_matches(
+        #[case] value: usize,
+        #[case] below: bool,
+        #[case] v: usize,
+        #[case] want: bool,
+    ) {
+        let r = Reroll {
+            value,
+            below,
+            repeat: true,
+        };
+        assert_eq!(want, r.matches(v));
+    }
+
+    #[rstest]
+    #[case(1, false, 6, true)]
+    #[case(1, false, 1, false)]
+    #[case(6, true, 6, true)]
+    #[case(7, true, 6, false)]
+    fn test_reroll_possible(
+        #[case] value: usize,
+        #[case] below: bool,
+        #[case] size: usize,
+        #[case] want: bool,
+    ) {
+        let r = Reroll {
+            value,
+            below,
+            repeat: false,
+        };
+        assert_eq!(want, r.is_possible(size));
+    }
+
+    #[test]
+    fn test_gwf_independent() {
+        let ds = DiceSet::parse_strict("2d6gwf+4").unwrap();
+        let mut rng = Roller::new(StdRng::seed_from_u64(42));
+        let mut triggered = [0; 3];
+
+        for _ in 0..500 {
+            let r = ds.roll_with(&mut rng);
+
+            assert_eq!(4, r.bonus);
+            assert_eq!(2, r.list.len());
+            assert!(r.rerolled.iter().all(|&v| v < 3), "{r:?}");
+            triggered[r.rerolled.len()] += 1;
+        }
+        // Neither, one or both dices rolled again, about 4/9, 4/9 and 1/9 of the time
+        //
+        assert!(triggered.iter().all(|&n| n > 20), "{triggered:?}");
+        assert!(triggered[1] > triggered[2] * 2, "{triggered:?}");
+    }
+
+    #[test]
+    fn test_reroll_display() {
+        let r = Reroll {
+            value: 3,
+            below: true,
+            repeat: true,
+        };
+        assert_eq!("rr<3", r.to_string());
+        assert_eq!(
+            "r1",
+            Reroll {
+                value: 1,
+                below: false,
+                repeat: false
+            }
+            .to_string()
+        );
+    }
+}
@@ -3,27 +3,64 @@
 //! All functions returns self to allow for chaining
 //!
 
+use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
-use std::ops::Add;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign};
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::locale::NumberFormat;
+use crate::messages::{Messages, Msg};
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum Special {
     None,
     Fumble,
     Natural,
+    /// Open-ended high: the roll went up
+    OpenHigh,
+    /// Open-ended low: follow-up rolls were subtracted
+    OpenLow,
+    /// Success pool without any success and with a 1
+    Botch,
 }
 
 /// Holds a result which is all the rolls for a given set of dices.
-#[derive(Clone, Debug, Eq, PartialEq)]
+///
+/// Bonuses are always applied: `sum` is the total of the roll and `bonus` the part of it not
+/// coming from dices, so `total() == raw_sum() + bonus` whatever rolled it.  Use `add_bonus()`
+/// to apply one.
+///
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Res {
     /// Store all the rolled dices
     pub list: Vec<usize>,
-    /// Sum of all dices
+    /// Total of the roll, bonus included (see `total()`)
     pub sum: isize,
-    /// If there is a malus/bonus to apply
+    /// Part of `sum` coming from bonuses and maluses, already applied
     pub bonus: isize,
     /// Special result?
     pub flag: Special,
+    /// Discarded values of rerolled dices
+    #[serde(default)]
+    pub rerolled: Vec<usize>,
+    /// Dices rolled but not counted (`dl`, `kh`, ...)
+    #[serde(default)]
+    pub dropped: Vec<usize>,
+    /// How many times each symbol came up on symbol dices
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub symbols: BTreeMap<String, usize>,
+    /// Annotation given with the roll, like `goblin damage`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Who rolled, given with `as <player>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player: Option<String>,
+    /// An open-ended dice stopped exploding at the cap, see `dice::explode`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub capped: bool,
 }
 
 /// Allow for `.unwrap_or_default()` calls.
@@ -36,11 +73,7 @@ impl Default for Res {
 /// Display trait
 impl Display for Res {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "total: {} - incl. bonus: {} ({:?})",
-            self.sum, self.bonus, self.flag
-        )
+        write!(f, "{}", self.format_with(&NumberFormat::default()))
     }
 }
 
@@ -55,6 +88,12 @@ impl Res {
             sum: 0,
             bonus: 0,
             flag: Special::None,
+            rerolled: Vec::new(),
+            dropped: Vec::new(),
+            symbols: BTreeMap::new(),
+            note: None,
+            player: None,
+            capped: false,
         }
     }
 
@@ -66,16 +105,33 @@ impl Res {
         self
     }
 
-    /// Merge two sets a & b.  b is empty afterwards.
+    /// Apply a bonus (or a malus if negative) to the total
+    ///
+    pub fn add_bonus(&mut self, b: isize) -> &mut Self {
+        self.sum += b;
+        self.bonus += b;
+        self
+    }
+
+    /// Merge two sets a & b like `a += b`.  b is empty afterwards.
     ///
     pub fn merge(&mut self, r: &mut Res) -> &mut Self {
-        self.list.append(&mut r.list);
-        self.sum += r.sum;
-        self.bonus += r.bonus;
-        self.flag = Special::None;
+        *self += std::mem::take(r);
         self
     }
 
+    /// Total of the roll, dices and bonus
+    ///
+    pub fn total(&self) -> isize {
+        self.sum
+    }
+
+    /// Total of the dices alone, bonus excluded
+    ///
+    pub fn raw_sum(&self) -> isize {
+        self.sum - self.bonus
+    }
+
     /// Set the flag to something `Special`.
     ///
     pub fn set(&mut self, f: Special) -> &mut Self {
@@ -83,12 +139,136 @@ impl Res {
         self
     }
 
+    /// Annotate the result with `note`
+    ///
+    pub fn with_note(mut self, note: Option<&str>) -> Self {
+        self.note = note.map(String::from);
+        self
+    }
+
     /// Get the special flag
     ///
     pub fn flag(&self) -> Special {
         self.flag
     }
 
+    /// Symbols of symbol dices with their count, like `2 success, 1 advantage`
+    ///
+    pub fn format_symbols(&self) -> String {
+        self.symbols
+            .iter()
+            .map(|(s, n)| format!("{n} {s}"))
+            .join(", ")
+    }
+
+    /// The note after a result, ` - goblin damage`, or nothing
+    ///
+    pub fn format_note(&self) -> String {
+        self.note
+            .as_ref()
+            .map(|n| format!(" - {n}"))
+            .unwrap_or_default()
+    }
+
+    /// ` - explosions capped` in the language of `m` if an open-ended dice hit the cap, or
+    /// nothing
+    ///
+    pub fn format_capped(&self, m: &Messages) -> String {
+        match self.capped {
+            true => format!(" - {}", m.get(Msg::Capped)),
+            false => String::new(),
+        }
+    }
+
+    /// Who rolled before a result, `Alice: `, or nothing
+    ///
+    pub fn format_player(&self) -> String {
+        self.player
+            .as_ref()
+            .map(|p| format!("{p}: "))
+            .unwrap_or_default()
+    }
+
+    /// Same as `Display` but with numbers formatted according to `nf`
+    ///
+    pub fn format_with(&self, nf: &NumberFormat) -> String {
+        self.format_localized(nf, &Messages::default())
+    }
+
+    /// Same as `format_with` in the language of `m`
+    ///
+    pub fn format_localized(&self, nf: &NumberFormat, m: &Messages) -> String {
+        let extra = |what: Msg, list: &[usize]| match list.is_empty() {
+            true => String::new(),
+            false => format!(" - {}: {}", m.get(what), list.iter().join(", ")),
+        };
+        let symbols = match self.symbols.is_empty() {
+            true => String::new(),
+            false => format!(" - {}: {}", m.get(Msg::Symbols), self.format_symbols()),
+        };
+        format!(
+            "{}{}: {} - {}: {}{}{}{}{} ({}){}",
+            self.format_player(),
+            m.get(Msg::Total),
+            nf.int(self.sum as i64),
+            m.get(Msg::InclBonus),
+            nf.int(self.bonus as i64),
+            extra(Msg::Rerolled, &self.rerolled),
+            extra(Msg::Dropped, &self.dropped),
+            symbols,
+            self.format_capped(m),
+            m.flag(self.flag),
+            self.format_note()
+        )
+    }
+
+    /// Same as `format_markdown_with` with the default number format
+    ///
+    pub fn format_markdown(&self) -> String {
+        self.format_markdown_with(&NumberFormat::default())
+    }
+
+    /// Markdown for chat rooms (Discord, Matrix, ...): bold total, dices with the dropped ones
+    /// struck through, bonus, rerolled values also struck through and the special flag if any
+    ///
+    pub fn format_markdown_with(&self, nf: &NumberFormat) -> String {
+        self.format_markdown_localized(nf, &Messages::default())
+    }
+
+    /// Same as `format_markdown_with` in the language of `m`
+    ///
+    pub fn format_markdown_localized(&self, nf: &NumberFormat, m: &Messages) -> String {
+        let struck = |v: &usize| format!("~~{v}~~");
+        let dices = self
+            .list
+            .iter()
+            .map(|v| v.to_string())
+            .chain(self.dropped.iter().map(struck))
+            .join(", ");
+
+        let mut md = format!("{}**{}**", self.format_player(), nf.int(self.sum as i64));
+        if !dices.is_empty() {
+            md.push_str(&format!(" ({dices})"));
+        }
+        if self.bonus != 0 {
+            let sign = if self.bonus > 0 { "+" } else { "" };
+            md.push_str(&format!(" {sign}{}", nf.int(self.bonus as i64)));
+        }
+        if !self.rerolled.is_empty() {
+            let rerolled = self.rerolled.iter().map(struck).join(", ");
+            md.push_str(&format!(" - {}: {rerolled}", m.get(Msg::Rerolled)));
+        }
+        if !self.symbols.is_empty() {
+            md.push_str(&format!(" - {}", self.format_symbols()));
+        }
+        md.push_str(&self.format_capped(m));
+        if self.flag != Special::None {
+            md.push_str(&format!(" _{}_", m.flag(self.flag)));
+        }
+        md.push_str(&self.format_note());
+        md
+    }
+
     /// Do we have a "natural" result?
     ///
     pub fn natural(&self) -> bool {
@@ -100,6 +280,17 @@ impl Add for Res {
     type Output = Res;
 
     fn add(self, rhs: Self) -> Self::Output {
+        // Only dices have special results, keep the flag if the other side has none
+        //
+        let flag = match (self.list.is_empty(), rhs.list.is_empty()) {
+            (true, _) => rhs.flag,
+            (_, true) => self.flag,
+            _ => Special::None,
+        };
+        let symbols = rhs.symbols.into_iter().fold(self.symbols, |mut c, (s, n)| {
+            *c.entry(s).or_default() += n;
+            c
+        });
         let list = rhs.list.iter().fold(self.list, |mut c, e| {
             c.push(*e);
             c
@@ -107,16 +298,52 @@ impl Add for Res {
         Self {
             sum: self.sum + rhs.sum,
             bonus: self.bonus + rhs.bonus,
-            flag: Special::None,
+            flag,
             list,
+            rerolled: [self.rerolled, rhs.rerolled].concat(),
+            dropped: [self.dropped, rhs.dropped].concat(),
+            symbols,
+            note: self.note.or(rhs.note),
+            player: self.player.or(rhs.player),
+            capped: self.capped || rhs.capped,
         }
     }
 }
 
+impl AddAssign for Res {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = std::mem::take(self) + rhs;
+    }
+}
+
+/// All the rolls added together, an empty result if there are none
+///
+impl Sum for Res {
+    fn sum<I: Iterator<Item = Res>>(iter: I) -> Self {
+        iter.fold(Res::new(), Add::add)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_res_serde() {
+        let mut r = Res::new();
+        r.append(6).append(1).append(4).add_bonus(2);
+        r.flag = Special::Natural;
+        r.note = Some("goblin".to_string());
+
+        let json = serde_json::to_string(&r).unwrap();
+        assert!(json.starts_with(r#"{"list":[6,1,4],"sum":13,"bonus":2,"flag":"Natural""#));
+        assert_eq!(r, serde_json::from_str(&json).unwrap());
+        assert_eq!(
+            r,
+            serde_yaml::from_str(&serde_yaml::to_string(&r).unwrap()).unwrap()
+        );
+    }
+
     #[test]
     fn test_new() {
         let a = Res::new();
@@ -178,7 +405,7 @@ mod tests {
         };
         let y = Res {
             list: vec![],
-            sum: 0,
+            sum: -9,
             bonus: -9,
             ..Default::default()
         };
@@ -186,13 +413,260 @@ mod tests {
         let s = x + y;
         let t = Res {
             list: vec![9, 6],
-            sum: 15,
+            sum: 6,
             bonus: -9,
             ..Default::default()
         };
         assert_eq!(t, s);
     }
 
+    #[test]
+    fn test_add_keep_flag() {
+        let mut x = Res::new();
+        x.append(20).set(Special::Natural);
+        let y = Res {
+            sum: 2,
+            bonus: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(Special::Natural, (Res::new() + x.clone()).flag);
+        assert_eq!(Special::Natural, (x.clone() + y).flag);
+        assert_eq!(Special::None, (x.clone() + x).flag);
+    }
+
+    #[test]
+    fn test_add_bonus() {
+        let mut r = Res::new();
+        r.append(4).add_bonus(3).add_bonus(-1);
+
+        assert_eq!(6, r.total());
+        assert_eq!(4, r.raw_sum());
+        assert_eq!(2, r.bonus);
+        assert_eq!(vec![4], r.list);
+    }
+
+    #[test]
+    fn test_add_assign() {
+        let mut r = Res::new();
+        r.append(20).set(Special::Natural);
+        let mut b = Res::new();
+        b.add_bonus(2);
+
+        r += b.clone();
+        assert_eq!(22, r.total());
+        assert_eq!(Special::Natural, r.flag);
+
+        let mut m = Res::new();
+        m.append(20).set(Special::Natural);
+        assert_eq!(&r, m.merge(&mut b));
+        assert_eq!(Res::new(), b);
+    }
+
+    #[test]
+    fn test_sum() {
+        let all = [3, 5, 6].map(|v| {
+            let mut r = Res::new();
+            r.append(v);
+            r
+        });
+        let mut b = Res::new();
+        b.add_bonus(-4);
+
+        let r: Res = all.into_iter().chain([b]).sum();
+        assert_eq!(10, r.total());
+        assert_eq!(-4, r.bonus);
+        assert_eq!(vec![3, 5, 6], r.list);
+        assert_eq!(Res::new(), std::iter::empty::<Res>().sum());
+    }
+
+    #[test]
+    fn test_format_with() {
+        let a = Res {
+            list: vec![6; 2000],
+            sum: 12000,
+            bonus: -1000,
+            ..Default::default()
+        };
+
+        assert_eq!("total: 12000 - incl. bonus: -1000 (None)", a.to_string());
+        assert_eq!(
+            "total: 12,000 - incl. bonus: -1,000 (None)",
+            a.format_with(&NumberFormat::new("en_US"))
+        );
+    }
+
+    #[test]
+    fn test_format_rerolled() {
+        let a = Res {
+            list: vec![3, 5],
+            sum: 8,
+            rerolled: vec![1, 2],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "total: 8 - incl. bonus: 0 - rerolled: 1, 2 (None)",
+            a.to_string()
+        );
+        assert_eq!(vec![1, 2, 1, 2], (a.clone() + a).rerolled);
+    }
+
+    #[test]
+    fn test_format_dropped() {
+        let a = Res {
+            list: vec![5, 6, 4],
+            sum: 15,
+            dropped: vec![2],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "total: 15 - incl. bonus: 0 - dropped: 2 (None)",
+            a.to_string()
+        );
+    }
+
+    #[test]
+    fn test_format_symbols() {
+        let a = Res {
+            symbols: BTreeMap::from([("hit".to_string(), 2), ("crit".to_string(), 1)]),
+            ..Default::default()
+        };
+        let b = Res {
+            symbols: BTreeMap::from([("hit".to_string(), 1)]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "total: 0 - incl. bonus: 0 - symbols: 1 crit, 2 hit (None)",
+            a.to_string()
+        );
+        assert_eq!("**0** - 1 crit, 2 hit", a.format_markdown());
+        assert_eq!(Some(&3), (a.clone() + b.clone()).symbols.get("hit"));
+
+        let mut m = a;
+        m.merge(&mut b.clone());
+        assert_eq!("1 crit, 3 hit", m.format_symbols());
+        assert!(Res::new().format_symbols().is_empty());
+    }
+
+    #[test]
+    fn test_format_capped() {
+        let a = Res {
+            list: vec![6, 6, 2],
+            sum: 14,
+            capped: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "total: 14 - incl. bonus: 0 - explosions capped (None)",
+            a.to_string()
+        );
+        assert_eq!("**14** (6, 6, 2) - explosions capped", a.format_markdown());
+        assert!((Res::new() + a).capped);
+        assert!(!serde_json::to_string(&Res::new())
+            .unwrap()
+            .contains("capped"));
+    }
+
+    #[test]
+    fn test_format_note() {
+        let a = Res {
+            list: vec![3, 5],
+            sum: 12,
+            bonus: 4,
+            ..Default::default()
+        }
+        .with_note(Some("goblin damage"));
+
+        assert_eq!(
+            "total: 12 - incl. bonus: 4 (None) - goblin damage",
+            a.to_string()
+        );
+        assert_eq!("**12** (3, 5) +4 - goblin damage", a.format_markdown());
+        assert_eq!(Some("goblin damage"), (Res::new() + a).note.as_deref());
+        assert!(Res::new().format_note().is_empty());
+    }
+
+    #[test]
+    fn test_format_markdown() {
+        let a = Res {
+            list: vec![5, 6, 4],
+            sum: 17,
+            bonus: 2,
+            flag: Special::Natural,
+            rerolled: vec![1],
+            dropped: vec![2],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            "**17** (5, 6, 4, ~~2~~) +2 - rerolled: ~~1~~ _Natural_",
+            a.format_markdown()
+        );
+    }
+
+    #[test]
+    fn test_format_player() {
+        let a = Res {
+            list: vec![4],
+            sum: 4,
+            player: Some("Alice".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!("Alice: total: 4 - incl. bonus: 0 (None)", a.to_string());
+        assert_eq!("Alice: **4** (4)", a.format_markdown());
+        assert_eq!(a.player, (Res::new() + a.clone()).player);
+    }
+
+    #[test]
+    fn test_format_localized() {
+        let a = Res {
+            list: vec![1, 3],
+            sum: 1003,
+            bonus: 999,
+            flag: Special::Fumble,
+            rerolled: vec![2],
+            dropped: vec![3],
+            ..Default::default()
+        };
+        let m = Messages::new("fr_FR");
+        let nf = NumberFormat::new("fr_FR");
+
+        assert_eq!(
+            "total: 1 003 - dont bonus: 999 - relancés: 2 - écartés: 3 (Échec critique)",
+            a.format_localized(&nf, &m)
+        );
+        assert_eq!(
+            "**1 003** (1, 3, ~~3~~) +999 - relancés: ~~2~~ _Échec critique_",
+            a.format_markdown_localized(&nf, &m)
+        );
+        assert_eq!(
+            a.to_string(),
+            a.format_localized(&NumberFormat::default(), &Messages::new("C"))
+        );
+    }
+
+    #[test]
+    fn test_format_markdown_plain() {
+        let a = Res {
+            list: vec![1000, 1000],
+            sum: 1999,
+            bonus: -1,
+            ..Default::default()
+        };
+
+        assert_eq!("**1999** (1000, 1000) -1", a.format_markdown());
+        assert_eq!(
+            "**1,999** (1000, 1000) -1",
+            a.format_markdown_with(&NumberFormat::new("en_US"))
+        );
+        assert_eq!("**0**", Res::new().format_markdown());
+    }
+
     #[test]
     fn test_natural() {
         let a = Res {
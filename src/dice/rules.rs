@@ -0,0 +1,478 @@
+//! House rules applied to a whole roll.
+//!
+//! Defaults come from the configuration and can be overridden for a single roll by options
+//! given after the dice expression:
+//!
+//! - `adv`       roll twice and keep the best total
+//! - `dis`       roll twice and keep the worst total
+//! - `crit <N>`  the roll is a natural if the dices total `N` or more (e.g. `crit19`)
+//! - `crit <N>-<M>`  same for a total between `N` and `M`
+//! - `fumble <N>`  the roll is a fumble if the dices total `N` or less, `fumble <N>-<M>` too
+//! - `up`, `down` or `near`  round divisions up, down or to the nearest (halves going away
+//!   from zero), like `d6 / 2 up` for half damage rounded up
+//! - `!norules`  forget about the defaults and all specials for this roll
+//!
+//! With `crit` or `fumble` the flag is set for the whole roll from the total of the dices
+//! (bonuses excluded) instead of the per-die results.  Without a rounding option divisions
+//! are rounded towards zero like integer divisions.
+//!
+//! Example:
+//! ```
+//! use dices_rs::dice::rules::{parse_rules, Rules, Span};
+//!
+//! let (_, r) = parse_rules(" adv crit 19-20 fumble 1", Rules::default()).unwrap();
+//! assert_eq!(Some(Span::Between(19, 20)), r.crit);
+//! assert_eq!(Some(Span::AtMost(1)), r.fumble);
+//! ```
+
+use log::trace;
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, space0, space1, u32},
+    combinator::{all_consuming, map, opt, value, verify},
+    multi::fold_many0,
+    sequence::{pair, preceded},
+    IResult,
+};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::dice::combinators::{best, worst, Best, Worst};
+use crate::dice::internal::Roller;
+use crate::dice::result::{Res, Special};
+use crate::dice::{DiceSet, Rollable};
+
+/// Roll twice and keep one?
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Advantage {
+    /// Roll once
+    #[default]
+    None,
+    /// Keep the best
+    Adv,
+    /// Keep the worst
+    Dis,
+}
+
+/// How a division is rounded
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rounding {
+    /// Towards zero, like integer divisions
+    #[default]
+    Zero,
+    /// Towards the lowest
+    Down,
+    /// Towards the highest
+    Up,
+    /// To the nearest, halves away from zero
+    Near,
+}
+
+impl Rounding {
+    /// `a / b` rounded, `None` if `b` is zero
+    ///
+    pub fn div(&self, a: isize, b: isize) -> Option<isize> {
+        let (q, r) = (a.checked_div(b)?, a % b);
+        if r == 0 {
+            return Some(q);
+        }
+        // Direction of the exact quotient from `q`, which is rounded towards zero
+        //
+        let away = if (a < 0) == (b < 0) { 1 } else { -1 };
+        let q = match self {
+            Rounding::Zero => q,
+            Rounding::Down => q.min(q + away),
+            Rounding::Up => q.max(q + away),
+            Rounding::Near if 2 * r.unsigned_abs() >= b.unsigned_abs() => q + away,
+            Rounding::Near => q,
+        };
+        Some(q)
+    }
+}
+
+/// Totals giving a natural or a fumble
+///
+/// In the configuration a number is `AtLeast` for `crit` and `AtMost` for `fumble`, a range
+/// is given as a string like `"19-20"`.
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Span {
+    /// `N` or more
+    AtLeast(usize),
+    /// `N` or less
+    AtMost(usize),
+    /// Between both, included
+    Between(usize, usize),
+}
+
+impl Span {
+    /// Is `total` in there?
+    ///
+    pub fn contains(&self, total: isize) -> bool {
+        let Ok(total) = usize::try_from(total) else {
+            return matches!(self, Span::AtMost(_));
+        };
+        match *self {
+            Span::AtLeast(n) => total >= n,
+            Span::AtMost(n) => total <= n,
+            Span::Between(a, b) => (a..=b).contains(&total),
+        }
+    }
+}
+
+impl Serialize for Span {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Span::AtLeast(n) | Span::AtMost(n) => s.serialize_u64(n as u64),
+            Span::Between(a, b) => s.serialize_str(&format!("{a}-{b}")),
+        }
+    }
+}
+
+/// A `Span` as found in the configuration
+///
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSpan {
+    Num(usize),
+    Text(String),
+}
+
+impl RawSpan {
+    /// A single number is turned into a `Span` with `single`
+    ///
+    fn into_span(self, single: fn(usize) -> Span) -> Result<Span, String> {
+        match self {
+            RawSpan::Num(n) => Ok(single(n)),
+            RawSpan::Text(s) => match all_consuming(|i| parse_span(i, single))(s.trim()) {
+                Ok((_, span)) => Ok(span),
+                Err(_) => Err(format!("invalid range {s}")),
+            },
+        }
+    }
+}
+
+fn crit_span<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Span>, D::Error> {
+    Option::<RawSpan>::deserialize(d)?
+        .map(|r| r.into_span(Span::AtLeast))
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+fn fumble_span<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Span>, D::Error> {
+    Option::<RawSpan>::deserialize(d)?
+        .map(|r| r.into_span(Span::AtMost))
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+/// All the house rules for a roll
+///
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Rules {
+    /// Roll twice and keep one
+    pub advantage: Advantage,
+    /// Totals giving a natural
+    #[serde(deserialize_with = "crit_span")]
+    pub crit: Option<Span>,
+    /// Totals giving a fumble
+    #[serde(deserialize_with = "fumble_span")]
+    pub fumble: Option<Span>,
+    /// No special results at all
+    pub norules: bool,
+    /// How divisions are rounded
+    pub rounding: Rounding,
+}
+
+/// One option given after the dice expression
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum RuleOpt {
+    Adv,
+    Dis,
+    Crit(Span),
+    Fumble(Span),
+    Round(Rounding),
+    NoRules,
+}
+
+impl Rules {
+    /// Apply one option on top of the current rules
+    ///
+    fn set(mut self, opt: RuleOpt) -> Self {
+        match opt {
+            RuleOpt::Adv => self.advantage = Advantage::Adv,
+            RuleOpt::Dis => self.advantage = Advantage::Dis,
+            RuleOpt::Crit(s) => self.crit = Some(s),
+            RuleOpt::Fumble(s) => self.fumble = Some(s),
+            RuleOpt::Round(r) => self.rounding = r,
+            RuleOpt::NoRules => {
+                self = Rules {
+                    norules: true,
+                    ..Default::default()
+                }
+            }
+        }
+        self
+    }
+
+    /// Roll `ds` following the rules
+    ///
+    pub fn roll_with(&self, ds: &DiceSet, rng: &mut Roller) -> Res {
+        trace!("rules::roll_with({self:?})");
+
+        let res = match self.advantage {
+            Advantage::None => ds.roll_with(rng),
+            Advantage::Adv => Best(ds, ds).roll_with(rng),
+            Advantage::Dis => Worst(ds, ds).roll_with(rng),
+        };
+        self.specials(res)
+    }
+
+    /// Same as `roll_with` for anything producing a `Res` that may fail, like an `Expr`
+    ///
+    pub fn apply<E, F>(&self, mut roll: F) -> Result<Res, E>
+    where
+        F: FnMut() -> Result<Res, E>,
+    {
+        trace!("rules::apply({self:?})");
+
+        let res = match self.advantage {
+            Advantage::None => roll()?,
+            Advantage::Adv => best(roll()?, roll()?),
+            Advantage::Dis => worst(roll()?, roll()?),
+        };
+        Ok(self.specials(res))
+    }
+
+    /// Set or clear the special flag, with `crit` or `fumble` from the total of the dices
+    ///
+    fn specials(&self, mut res: Res) -> Res {
+        if self.norules {
+            res.set(Special::None);
+        } else if self.crit.is_some() || self.fumble.is_some() {
+            let total = res.raw_sum();
+            let flag = if self.crit.is_some_and(|s| s.contains(total)) {
+                Special::Natural
+            } else if self.fumble.is_some_and(|s| s.contains(total)) {
+                Special::Fumble
+            } else {
+                Special::None
+            };
+            res.set(flag);
+        }
+        res
+    }
+}
+
+/// Parse `N` with `single` or `N-M`
+///
+fn parse_span(input: &str, single: fn(usize) -> Span) -> IResult<&str, Span> {
+    let into_span = |(a, b): (u32, Option<u32>)| match b {
+        Some(b) => Span::Between(a as usize, b as usize),
+        None => single(a as usize),
+    };
+    let r = verify(pair(u32, opt(preceded(char('-'), u32))), |(a, b)| {
+        b.is_none_or(|b| *a <= b)
+    });
+    map(r, into_span)(input)
+}
+
+/// Parse one option
+///
+fn parse_opt(input: &str) -> IResult<&str, RuleOpt> {
+    let crit = |i| parse_span(i, Span::AtLeast);
+    let fumble = |i| parse_span(i, Span::AtMost);
+    alt((
+        value(RuleOpt::Adv, tag("adv")),
+        value(RuleOpt::Dis, tag("dis")),
+        value(RuleOpt::NoRules, tag("!norules")),
+        value(RuleOpt::Round(Rounding::Up), tag("up")),
+        value(RuleOpt::Round(Rounding::Down), tag("down")),
+        value(RuleOpt::Round(Rounding::Near), tag("near")),
+        map(preceded(pair(tag("crit"), space0), crit), RuleOpt::Crit),
+        map(
+            preceded(pair(tag("fumble"), space0), fumble),
+            RuleOpt::Fumble,
+        ),
+    ))(input)
+}
+
+/// Parse all options after the dice expression, starting from the `defaults` rules
+///
+pub fn parse_rules(input: &str, defaults: Rules) -> IResult<&str, Rules> {
+    fold_many0(
+        preceded(space1, parse_opt),
+        move || defaults,
+        |acc: Rules, opt| acc.set(opt),
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use crate::dice::Dice;
+
+    use super::*;
+
+    #[rstest]
+    #[case("", Rules::default())]
+    #[case(" adv", Rules { advantage: Advantage::Adv, ..Default::default() })]
+    #[case(" dis crit19", Rules { advantage: Advantage::Dis, crit: Some(Span::AtLeast(19)), ..Default::default() })]
+    #[case(" crit 19-20 fumble 1", Rules {
+        crit: Some(Span::Between(19, 20)),
+        fumble: Some(Span::AtMost(1)),
+        ..Default::default()
+    })]
+    #[case(" fumble1-2", Rules { fumble: Some(Span::Between(1, 2)), ..Default::default() })]
+    #[case(" !norules", Rules { norules: true, ..Default::default() })]
+    #[case(" adv !norules dis", Rules { advantage: Advantage::Dis, norules: true, ..Default::default() })]
+    #[case(" up", Rules { rounding: Rounding::Up, ..Default::default() })]
+    #[case(" near adv down", Rules { advantage: Advantage::Adv, rounding: Rounding::Down, ..Default::default() })]
+    fn test_parse_rules(#[case] input: &str, #[case] want: Rules) {
+        let (rest, r) = parse_rules(input, Rules::default()).unwrap();
+        assert_eq!("", rest);
+        assert_eq!(want, r);
+    }
+
+    #[test]
+    fn test_parse_rules_override_defaults() {
+        let defaults = Rules {
+            crit: Some(Span::AtLeast(19)),
+            ..Default::default()
+        };
+
+        let (_, r) = parse_rules(" adv", defaults).unwrap();
+        assert_eq!(Some(Span::AtLeast(19)), r.crit);
+        assert_eq!(Advantage::Adv, r.advantage);
+
+        let (_, r) = parse_rules(" !norules", defaults).unwrap();
+        assert_eq!(None, r.crit);
+    }
+
+    #[test]
+    fn test_parse_rules_leftover() {
+        let (rest, r) = parse_rules(" adv foo", Rules::default()).unwrap();
+        assert_eq!(" foo", rest);
+        assert_eq!(Advantage::Adv, r.advantage);
+    }
+
+    #[test]
+    fn test_rules_advantage() {
+        let ds = DiceSet::from(Dice::Regular(20));
+        let adv = Rules {
+            advantage: Advantage::Adv,
+            ..Default::default()
+        };
+        let dis = Rules {
+            advantage: Advantage::Dis,
+            ..Default::default()
+        };
+
+        // Same seed means same two rolls, keep one or the other
+        //
+        let mut rng = Roller::new(StdRng::seed_from_u64(42));
+        let a = ds.roll_with(&mut rng);
+        let b = ds.roll_with(&mut rng);
+
+        let r = adv.roll_with(&ds, &mut Roller::new(StdRng::seed_from_u64(42)));
+        assert_eq!(a.sum.max(b.sum), r.sum);
+        let r = dis.roll_with(&ds, &mut Roller::new(StdRng::seed_from_u64(42)));
+        assert_eq!(a.sum.min(b.sum), r.sum);
+    }
+
+    #[test]
+    fn test_rules_crit() {
+        let ds = DiceSet::from(Dice::Constant(19));
+        let r = Rules {
+            crit: Some(Span::AtLeast(19)),
+            ..Default::default()
+        };
+
+        let res = r.roll_with(&ds, &mut Roller::new(StdRng::seed_from_u64(1)));
+        assert_eq!(Special::Natural, res.flag);
+
+        let r = r.set(RuleOpt::NoRules);
+        let res = r.roll_with(&ds, &mut Roller::new(StdRng::seed_from_u64(1)));
+        assert_eq!(Special::None, res.flag);
+    }
+
+    #[rstest]
+    #[case(vec![Dice::Constant(20)], Special::Natural)]
+    #[case(vec![Dice::Constant(18), Dice::Bonus(2)], Special::None)]
+    #[case(vec![Dice::Constant(1), Dice::Bonus(5)], Special::Fumble)]
+    #[case(vec![Dice::Constant(12), Dice::Constant(8)], Special::Natural)]
+    #[case(vec![Dice::Regular(1)], Special::Fumble)]
+    fn test_rules_crit_fumble(#[case] dices: Vec<Dice>, #[case] flag: Special) {
+        let (_, r) = parse_rules(" crit 19-20 fumble 1", Rules::default()).unwrap();
+
+        let res = r.roll_with(
+            &DiceSet::from_vec(dices),
+            &mut Roller::new(StdRng::seed_from_u64(1)),
+        );
+        assert_eq!(flag, res.flag);
+    }
+
+    #[rstest]
+    #[case(Span::AtLeast(19), 20, true)]
+    #[case(Span::AtLeast(19), 18, false)]
+    #[case(Span::AtMost(2), -3, true)]
+    #[case(Span::Between(19, 20), 21, false)]
+    #[case(Span::Between(19, 20), -19, false)]
+    fn test_span_contains(#[case] s: Span, #[case] total: isize, #[case] want: bool) {
+        assert_eq!(want, s.contains(total));
+    }
+
+    #[rstest]
+    #[case(7, 2, [3, 3, 4, 4])]
+    #[case(-7, 2, [-3, -4, -3, -4])]
+    #[case(7, -2, [-3, -4, -3, -4])]
+    #[case(5, 3, [1, 1, 2, 2])]
+    #[case(4, 3, [1, 1, 2, 1])]
+    #[case(-4, 3, [-1, -2, -1, -1])]
+    #[case(6, 3, [2, 2, 2, 2])]
+    fn test_rounding_div(#[case] a: isize, #[case] b: isize, #[case] want: [isize; 4]) {
+        let all = [Rounding::Zero, Rounding::Down, Rounding::Up, Rounding::Near];
+        assert_eq!(want.map(Some), all.map(|r| r.div(a, b)));
+        assert_eq!(None, Rounding::Up.div(a, 0));
+    }
+
+    #[test]
+    fn test_rules_serde() {
+        let r: Rules = toml::from_str("crit = \"19-20\"\nfumble = 2").unwrap();
+        assert_eq!(Some(Span::Between(19, 20)), r.crit);
+        assert_eq!(Some(Span::AtMost(2)), r.fumble);
+
+        let r: Rules = toml::from_str("crit = 19\nrounding = \"up\"").unwrap();
+        assert_eq!(Some(Span::AtLeast(19)), r.crit);
+        assert_eq!(Rounding::Up, r.rounding);
+        assert!(toml::from_str::<Rules>("crit = \"20-19\"").is_err());
+
+        let back: Rules = toml::from_str(&toml::to_string(&r).unwrap()).unwrap();
+        assert_eq!(r, back);
+    }
+
+    #[test]
+    fn test_rules_apply() {
+        let adv = Rules {
+            advantage: Advantage::Adv,
+            ..Default::default()
+        };
+        let mut n = 0;
+        let r: Result<Res, ()> = adv.apply(|| {
+            n += 1;
+            Ok(DiceSet::from(Dice::Constant(n)).roll())
+        });
+        assert_eq!(2, r.unwrap().sum);
+
+        let r: Result<Res, &str> = adv.apply(|| Err("boom"));
+        assert_eq!(Err("boom"), r);
+    }
+}
@@ -0,0 +1,282 @@
+//! Monte-Carlo simulation of a dice expression, to answer "how likely is it?".
+//!
+//! `simulate` rolls an expression many times and returns the `Distribution` of the totals,
+//! which can then be queried:
+//!
+//! ```
+//! use dices_rs::dice::simulate::{simulate, Op};
+//!
+//! let d = simulate("d20 + 5", 10_000).unwrap();
+//!
+//! // Hitting AC 15 is about 55%
+//! assert!((0.50..0.60).contains(&d.p(Op::Ge, 15)));
+//! assert!((15.0..16.0).contains(&d.mean()));
+//! assert_eq!(Some(25), d.max());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{i64, space0},
+    combinator::{all_consuming, value},
+    sequence::{preceded, separated_pair, terminated},
+    IResult,
+};
+
+use crate::dice::error::DiceError;
+use crate::dice::expr::{parse_expr_with, Expr};
+use crate::dice::internal::Roller;
+use crate::dice::parse::Defined;
+use crate::dice::rules::Rounding;
+
+/// Comparison of a total with a value
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    /// Is `a op b` true?
+    ///
+    pub fn matches(&self, a: isize, b: isize) -> bool {
+        match self {
+            Op::Eq => a == b,
+            Op::Lt => a < b,
+            Op::Le => a <= b,
+            Op::Gt => a > b,
+            Op::Ge => a >= b,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            Op::Eq => "=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+/// One of `=`, `<`, `<=`, `>` or `>=`
+///
+pub fn parse_op(input: &str) -> IResult<&str, Op> {
+    alt((
+        value(Op::Le, tag("<=")),
+        value(Op::Ge, tag(">=")),
+        value(Op::Lt, tag("<")),
+        value(Op::Gt, tag(">")),
+        value(Op::Eq, tag("=")),
+    ))(input)
+}
+
+/// Split `<expr> <op> <N>` into the expression, with what follows it before the comparison
+/// like house rules, the comparison and its value.  The expression is parsed first so that a
+/// `<` inside it, like in `2d6r<3`, is not taken for the comparison.
+///
+pub fn split_condition<'i>(input: &'i str, defined: &Defined) -> Option<(&'i str, Op, isize)> {
+    let (rest, _) = preceded(space0, |i| parse_expr_with(i, defined))(input).ok()?;
+    let at = input.len() - rest.len() + rest.find(['<', '>', '='])?;
+    let (expr, cond) = input.split_at(at);
+    let (_, (op, value)) =
+        all_consuming(terminated(separated_pair(parse_op, space0, i64), space0))(cond)
+            .map_err(|_: nom::Err<nom::error::Error<&str>>| ())
+            .ok()?;
+    Some((expr, op, value as isize))
+}
+
+/// How often each total came up
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Distribution {
+    /// Number of rolls for each total
+    pub counts: BTreeMap<isize, usize>,
+    /// Number of rolls
+    pub n: usize,
+}
+
+impl Distribution {
+    /// Add one total
+    ///
+    pub fn add(&mut self, total: isize) {
+        *self.counts.entry(total).or_default() += 1;
+        self.n += 1;
+    }
+
+    /// Probability of `total op value`, between 0 and 1
+    ///
+    pub fn p(&self, op: Op, value: isize) -> f64 {
+        let hits: usize = self
+            .counts
+            .iter()
+            .filter(|(t, _)| op.matches(**t, value))
+            .map(|(_, c)| c)
+            .sum();
+        hits as f64 / self.n.max(1) as f64
+    }
+
+    /// Average total
+    ///
+    pub fn mean(&self) -> f64 {
+        let sum: f64 = self.counts.iter().map(|(t, c)| *t as f64 * *c as f64).sum();
+        sum / self.n.max(1) as f64
+    }
+
+    /// Smallest total, if any roll was made
+    ///
+    pub fn min(&self) -> Option<isize> {
+        self.counts.keys().next().copied()
+    }
+
+    /// Largest total, if any roll was made
+    ///
+    pub fn max(&self) -> Option<isize> {
+        self.counts.keys().next_back().copied()
+    }
+
+    /// Smallest total with at least `q` (between 0 and 1) of the rolls at or below it, like
+    /// `percentile(0.5)` for the median
+    ///
+    pub fn percentile(&self, q: f64) -> Option<isize> {
+        let wanted = (q.clamp(0.0, 1.0) * self.n as f64).ceil().max(1.0) as usize;
+        let mut seen = 0;
+        self.counts.iter().find_map(|(t, c)| {
+            seen += c;
+            (seen >= wanted).then_some(*t)
+        })
+    }
+}
+
+/// Roll the dice expression `input` (as given to `dice`, without house rules) `n` times
+///
+pub fn simulate(input: &str, n: usize) -> Result<Distribution, DiceError> {
+    simulate_with(input, n, &mut Roller::default())
+}
+
+/// Same as `simulate` using `rng` as the source of randomness
+///
+pub fn simulate_with(input: &str, n: usize, rng: &mut Roller) -> Result<Distribution, DiceError> {
+    simulate_expr(&Expr::parse(input)?, n, rng)
+}
+
+/// Roll an already parsed expression `n` times
+///
+pub fn simulate_expr(expr: &Expr, n: usize, rng: &mut Roller) -> Result<Distribution, DiceError> {
+    simulate_rounded(expr, n, rng, Rounding::default())
+}
+
+/// Same as `simulate_expr` with divisions rounded as told
+///
+pub fn simulate_rounded(
+    expr: &Expr,
+    n: usize,
+    rng: &mut Roller,
+    rounding: Rounding,
+) -> Result<Distribution, DiceError> {
+    let mut d = Distribution::default();
+    for _ in 0..n {
+        d.add(expr.eval_rounded(rng, rounding)?.sum);
+    }
+    Ok(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use super::*;
+
+    fn d(totals: &[isize]) -> Distribution {
+        let mut d = Distribution::default();
+        totals.iter().for_each(|t| d.add(*t));
+        d
+    }
+
+    #[rstest]
+    #[case(">=", Op::Ge)]
+    #[case("<= 3", Op::Le)]
+    #[case("<", Op::Lt)]
+    #[case(">", Op::Gt)]
+    #[case("=", Op::Eq)]
+    fn test_parse_op(#[case] input: &str, #[case] op: Op) {
+        assert_eq!(op, parse_op(input).unwrap().1);
+        assert_eq!(input, format!("{op}{}", parse_op(input).unwrap().0));
+    }
+
+    #[rstest]
+    #[case("d20 +5 >= 15", Some(("d20 +5 ", Op::Ge, 15)))]
+    #[case(" 2d6r<3 >= 7", Some((" 2d6r<3 ", Op::Ge, 7)))]
+    #[case("3d6<10", Some(("3d6", Op::Lt, 10)))]
+    #[case("d20 adv = -1 ", Some(("d20 adv ", Op::Eq, -1)))]
+    #[case("d20", None)]
+    #[case(">= 15", None)]
+    #[case("d20 >= x", None)]
+    #[case("d20 >= 15 then", None)]
+    fn test_split_condition(#[case] input: &str, #[case] want: Option<(&str, Op, isize)>) {
+        assert_eq!(want, split_condition(input, &Defined::default()));
+    }
+
+    #[rstest]
+    #[case(Op::Ge, 3, 0.5)]
+    #[case(Op::Gt, 3, 0.25)]
+    #[case(Op::Eq, 2, 0.25)]
+    #[case(Op::Lt, 2, 0.25)]
+    #[case(Op::Le, 0, 0.0)]
+    fn test_distribution_p(#[case] op: Op, #[case] v: isize, #[case] p: f64) {
+        assert_eq!(p, d(&[1, 2, 3, 4]).p(op, v));
+    }
+
+    #[test]
+    fn test_distribution_stats() {
+        let d = d(&[3, 1, 2, 2, 7]);
+
+        assert_eq!(3.0, d.mean());
+        assert_eq!(Some(1), d.min());
+        assert_eq!(Some(7), d.max());
+        assert_eq!(Some(2), d.percentile(0.5));
+        assert_eq!(Some(7), d.percentile(1.0));
+        assert_eq!(Some(1), d.percentile(0.0));
+    }
+
+    #[test]
+    fn test_distribution_empty() {
+        let d = Distribution::default();
+
+        assert_eq!(0.0, d.p(Op::Ge, 1));
+        assert_eq!(0.0, d.mean());
+        assert_eq!(None, d.percentile(0.5));
+    }
+
+    #[test]
+    fn test_simulate() {
+        let mut rng = Roller::new(StdRng::seed_from_u64(42));
+        let d = simulate_with(" 2d6 + 1", 5000, &mut rng).unwrap();
+
+        assert_eq!(5000, d.n);
+        assert_eq!(Some(3), d.min());
+        assert_eq!(Some(13), d.max());
+        // 7 on 2D6 is 1/6
+        assert!((0.14..0.19).contains(&d.p(Op::Eq, 8)), "{d:?}");
+    }
+
+    #[rstest]
+    #[case("2d6 oops")]
+    #[case("d")]
+    #[case("d6 / 0")]
+    fn test_simulate_error(#[case] input: &str) {
+        assert!(simulate(input, 10).is_err());
+    }
+}
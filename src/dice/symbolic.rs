@@ -0,0 +1,172 @@
+//! Symbol dices, whose faces show symbols instead of numbers.
+//!
+//! Think of hit/crit/blank dices in wargames or the narrative dices of some games.  Rolling
+//! them does not change the total, the symbols are counted in `Res::symbols` instead.  They
+//! are defined by name in the `[symbolic]` section of the configuration, each face being the
+//! symbols it shows separated by spaces (an empty face is blank), and rolled as `D<name>`,
+//! like `dice 3dBoost`:
+//!
+//! ```text
+//! [symbolic]
+//! boost = ["", "", "success", "success advantage", "advantage advantage", "advantage"]
+//! attack = ["hit", "hit", "hit", "crit", "", ""]
+//! ```
+//!
+//! As for weighted dices (see `weighted`), the parser is given the definitions with a
+//! `Defined`:
+//! ```
+//! use std::collections::BTreeMap;
+//! use dices_rs::dice::parse::Defined;
+//! use dices_rs::dice::symbolic::Symbols;
+//! use dices_rs::dice::{DiceSet, Rollable};
+//!
+//! let hit = Symbols::try_from(vec!["hit".to_string(), "".to_string()]).unwrap();
+//! let symbolic = BTreeMap::from([("attack".to_string(), hit)]);
+//! let defined = Defined { symbolic: &symbolic, ..Defined::default() };
+//!
+//! let ds = DiceSet::parse_with("3dAttack", &defined).unwrap();
+//! let r = ds.roll();
+//! assert_eq!(0, r.sum);
+//! assert!(r.symbols.get("hit").unwrap_or(&0) <= &3);
+//! ```
+
+use std::collections::BTreeMap;
+
+use nom::{bytes::complete::take_while1, combinator::map_opt, IResult};
+use serde::{Deserialize, Serialize};
+
+use crate::dice::internal::Roller;
+use crate::dice::result::Res;
+use crate::dice::Dice;
+
+/// Faces of a symbol dice, each one being the symbols it shows separated by spaces
+///
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct Symbols(Vec<String>);
+
+impl TryFrom<Vec<String>> for Symbols {
+    type Error = String;
+
+    fn try_from(faces: Vec<String>) -> Result<Self, Self::Error> {
+        match faces.is_empty() {
+            true => Err("a symbol dice needs at least one face".to_string()),
+            false => Ok(Symbols(faces)),
+        }
+    }
+}
+
+impl Symbols {
+    /// The `Dice` rolling these faces
+    ///
+    pub fn dice(&self) -> Dice {
+        let faces = self
+            .0
+            .iter()
+            .map(|f| f.split_whitespace().map(str::to_lowercase).collect())
+            .collect();
+        Dice::Symbolic(faces)
+    }
+}
+
+/// The symbol dice of `defined` called `name`, whatever the case
+///
+pub fn lookup(defined: &BTreeMap<String, Symbols>, name: &str) -> Option<Dice> {
+    defined
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, s)| s.dice())
+}
+
+/// Parse the name of one of the symbol dices of `defined`, the part after the `D`
+///
+pub fn parse_symbolic<'i>(
+    input: &'i str,
+    defined: &BTreeMap<String, Symbols>,
+) -> IResult<&'i str, Dice> {
+    let name = take_while1(|c: char| c.is_alphanumeric() || c == '_');
+    map_opt(name, |n| lookup(defined, n))(input)
+}
+
+/// Roll one face and count its symbols
+///
+pub fn roll_symbolic(rng: &mut Roller, faces: &[Vec<String>]) -> Res {
+    let mut res = Res::new();
+    let face = rng.roll(faces.len());
+    if let Some(symbols) = faces.get(face - 1) {
+        symbols
+            .iter()
+            .for_each(|s| *res.symbols.entry(s.clone()).or_default() += 1);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use super::*;
+
+    fn boost() -> BTreeMap<String, Symbols> {
+        let faces = [
+            "",
+            "",
+            "success",
+            "Success Advantage",
+            "advantage advantage",
+        ];
+        let faces = faces.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        BTreeMap::from([("boost".to_string(), Symbols::try_from(faces).unwrap())])
+    }
+
+    #[rstest]
+    #[case("Boost")]
+    #[case("boost +1")]
+    fn test_parse_symbolic(#[case] input: &str) {
+        let (_, d) = parse_symbolic(input, &boost()).unwrap();
+        let Dice::Symbolic(faces) = d else {
+            panic!("not symbolic");
+        };
+        assert_eq!(5, faces.len());
+        assert_eq!(vec!["success", "advantage"], faces[3]);
+    }
+
+    #[rstest]
+    #[case("setback")]
+    #[case("6")]
+    fn test_parse_symbolic_unknown(#[case] input: &str) {
+        assert!(parse_symbolic(input, &boost()).is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert!(lookup(&boost(), "BOOST").is_some());
+        assert!(lookup(&BTreeMap::new(), "boost").is_none());
+    }
+
+    #[test]
+    fn test_roll_symbolic() {
+        let mut rng = Roller::new(StdRng::seed_from_u64(42));
+        let Dice::Symbolic(faces) = lookup(&boost(), "boost").unwrap() else {
+            panic!("not symbolic");
+        };
+        let mut all = Res::new();
+
+        for _ in 0..500 {
+            let r = roll_symbolic(&mut rng, &faces);
+            assert_eq!(0, r.sum);
+            assert!(r.list.is_empty());
+            all += r;
+        }
+        let success = all.symbols["success"];
+        let advantage = all.symbols["advantage"];
+        assert!((100..300).contains(&success), "{all:?}");
+        assert!((200..400).contains(&advantage), "{all:?}");
+    }
+
+    #[test]
+    fn test_symbols_invalid() {
+        assert!(Symbols::try_from(vec![]).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Weighted (unfair) dices, for probability experiments and some board games.
+//!
+//! Each face has a weight, a face with weight 2 comes twice as often as one with weight 1.
+//! They are defined by name in the `[weighted]` section of the configuration as a list of
+//! `[face, weight]` and used as `dW:<name>`, like `dice 2dW:loaded +1`:
+//!
+//! ```text
+//! [weighted]
+//! # A D6 rolling 6 twice as often
+//! loaded = [[1, 1], [2, 1], [3, 1], [4, 1], [5, 1], [6, 2]]
+//! ```
+//!
+//! The name goes up to the first character which is not a letter, a digit or `_` so picks and
+//! rerolls can not follow it.  The parser is given the definitions with a `Defined`, as the
+//! engine does with `Config::defined()`:
+//! ```
+//! use std::collections::BTreeMap;
+//! use dices_rs::dice::parse::Defined;
+//! use dices_rs::dice::weighted::Faces;
+//! use dices_rs::dice::{DiceSet, Rollable};
+//!
+//! let loaded = Faces::try_from(vec![(1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (6, 2)]).unwrap();
+//! let weighted = BTreeMap::from([("loaded".to_string(), loaded)]);
+//!
+//! let defined = Defined { weighted: &weighted, ..Defined::default() };
+//!
+//! let ds = DiceSet::parse_with("3dW:loaded", &defined).unwrap();
+//! assert_eq!(3, ds.roll().list.len());
+//! ```
+
+use std::collections::BTreeMap;
+
+use nom::{
+    bytes::complete::{tag_no_case, take_while1},
+    combinator::{cut, map_opt},
+    sequence::preceded,
+    IResult,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::dice::internal::Roller;
+use crate::dice::Dice;
+
+/// Faces of a weighted dice as `(face, weight)`, with at least one positive weight
+///
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "Vec<(usize, usize)>")]
+pub struct Faces(Vec<(usize, usize)>);
+
+impl TryFrom<Vec<(usize, usize)>> for Faces {
+    type Error = String;
+
+    fn try_from(faces: Vec<(usize, usize)>) -> Result<Self, Self::Error> {
+        match faces.iter().any(|&(_, w)| w > 0) {
+            true => Ok(Faces(faces)),
+            false => Err("a weighted dice needs at least one face with a weight".to_string()),
+        }
+    }
+}
+
+impl Faces {
+    /// The `Dice` rolling these faces
+    ///
+    pub fn dice(&self) -> Dice {
+        Dice::Weighted(self.0.clone())
+    }
+}
+
+/// The weighted dice of `defined` called `name`, whatever the case
+///
+pub fn lookup(defined: &BTreeMap<String, Faces>, name: &str) -> Option<Dice> {
+    defined
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, f)| f.dice())
+}
+
+/// Parse `W:<name>`, the part after the `D`, for one of the dices of `defined`
+///
+pub fn parse_weighted<'i>(
+    input: &'i str,
+    defined: &BTreeMap<String, Faces>,
+) -> IResult<&'i str, Dice> {
+    let name = take_while1(|c: char| c.is_alphanumeric() || c == '_');
+    preceded(
+        tag_no_case("w:"),
+        cut(map_opt(name, |n| lookup(defined, n))),
+    )(input)
+}
+
+/// One face drawn according to the weights
+///
+pub fn roll_weighted(rng: &mut Roller, faces: &[(usize, usize)]) -> usize {
+    let total = faces.iter().map(|&(_, w)| w).sum::<usize>();
+    let mut n = rng.gen_range(0..total.max(1));
+    for &(face, w) in faces {
+        if n < w {
+            return face;
+        }
+        n -= w;
+    }
+    faces.last().map_or(0, |&(face, _)| face)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use crate::dice::Rollable;
+
+    use super::*;
+
+    fn loaded() -> BTreeMap<String, Faces> {
+        let faces = vec![(1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (6, 2)];
+        BTreeMap::from([("loaded".to_string(), Faces::try_from(faces).unwrap())])
+    }
+
+    #[rstest]
+    #[case("W:loaded")]
+    #[case("w:LOADED +1")]
+    fn test_parse_weighted(#[case] input: &str) {
+        let (_, d) = parse_weighted(input, &loaded()).unwrap();
+        assert_eq!(6, d.size());
+    }
+
+    #[rstest]
+    #[case("W:cheat")]
+    #[case("W:")]
+    fn test_parse_weighted_unknown(#[case] input: &str) {
+        assert!(matches!(
+            parse_weighted(input, &loaded()),
+            Err(nom::Err::Failure(_))
+        ));
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert!(lookup(&loaded(), "Loaded").is_some());
+        assert!(lookup(&BTreeMap::new(), "loaded").is_none());
+    }
+
+    #[test]
+    fn test_roll_weighted() {
+        let mut rng = Roller::new(StdRng::seed_from_u64(42));
+        let faces = [(1, 1), (2, 0), (6, 2)];
+        let mut seen = [0; 7];
+
+        for _ in 0..3000 {
+            seen[roll_weighted(&mut rng, &faces)] += 1;
+        }
+        assert_eq!(0, seen[2]);
+        assert_eq!(3000, seen[1] + seen[6]);
+        // 6 comes about twice as often as 1
+        assert!((1700..2300).contains(&seen[6]), "{seen:?}");
+    }
+
+    #[test]
+    fn test_weighted_dice_roll() {
+        let d = Dice::Weighted(vec![(3, 1)]);
+        let r = d.roll();
+
+        assert_eq!(vec![3], r.list);
+        assert_eq!(3, r.sum);
+    }
+
+    #[test]
+    fn test_faces_invalid() {
+        assert!(Faces::try_from(vec![]).is_err());
+        assert!(Faces::try_from(vec![(1, 0), (2, 0)]).is_err());
+    }
+}
@@ -27,23 +27,52 @@
 //! mouv = move
 //! dice = roll
 //! ```
+//!
+//! Once loaded, aliases and macros are checked for loops (`mouv -> move -> mouv`), unknown
+//! commands, chains too long for the compiler and `dice` macros rolling the same thing (see
+//! `Expr::equivalent`); problems are reported as warnings and are available from
+//! `Engine::check_aliases()`.
 
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use itertools::Itertools;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag},
-    character::complete::{alpha1, one_of, space0, space1},
+    bytes::complete::{is_not, tag, take_while1},
+    character::complete::{alpha1, char, space0, space1},
     combinator::map,
     sequence::{delimited, preceded, separated_pair, terminated},
     IResult,
 };
 
+use thiserror::Error;
+
+use crate::compiler::Compiler;
+use crate::dice::expr::Expr;
+use crate::engine::error::EngineError;
 use crate::engine::{Command, Engine};
 
+/// Problems found in the aliases and macros
+///
+#[derive(Clone, Debug, Eq, Error, Ord, PartialEq, PartialOrd)]
+pub enum AliasError {
+    /// These expand into each other, the first one being repeated at the end
+    #[error("alias loop {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    /// `name` starts with `target` which is not a command
+    #[error("alias {name} uses unknown command {target}")]
+    Unknown { name: String, target: String },
+    /// More expansions than the compiler allows
+    #[error("alias chain too long {}", .0.join(" -> "))]
+    TooDeep(Vec<String>),
+    /// These macros roll the same dices, like `2D6 +1` and `1 + 2d6`
+    #[error("macros {} roll the same dices", .0.join(", "))]
+    Same(Vec<String>),
+}
+
 /// Parse a comment introduced by one of #, // and ! followed by a space
 ///
 fn parse_comment(input: &str) -> IResult<&str, Command> {
@@ -65,7 +94,7 @@ fn parse_alias(input: &str) -> IResult<&str, Command> {
         trace!("{}", second);
 
         Command::Macro {
-            name: first.to_string(),
+            name: first.to_lowercase(),
             cmd: second.to_string(),
         }
     };
@@ -77,67 +106,243 @@ fn parse_alias(input: &str) -> IResult<&str, Command> {
     map(r, check)(input)
 }
 
-/// Parse the new command
+/// Parse the new command, in single quotes it can have double quotes like `'rhai "..."'`
 ///
 fn parse_string(input: &str) -> IResult<&str, &str> {
     trace!("parse_string");
-    delimited(one_of("\"'"), is_not("\""), one_of("\"'"))(input)
+    alt((
+        delimited(char('"'), is_not("\""), char('"')),
+        delimited(char('\''), is_not("'"), char('\'')),
+    ))(input)
+}
+
+/// Parse `[name]`, the start of a profile
+///
+fn parse_section(input: &str) -> IResult<&str, &str> {
+    trace!("parse_section");
+    delimited(
+        terminated(tag("["), space0),
+        take_while1(|c: char| c.is_alphanumeric() || c == '-' || c == '_'),
+        preceded(space0, tag("]")),
+    )(input)
+}
+
+/// Parse `include <file>`
+///
+fn parse_include(input: &str) -> IResult<&str, &str> {
+    trace!("parse_include");
+    preceded(
+        terminated(tag("include"), space1),
+        alt((parse_string, is_not(" \t\r\n"))),
+    )(input)
+}
+
+/// One line of the aliases file
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Line<'a> {
+    Command(Command),
+    Section(&'a str),
+    Include(&'a str),
+}
+
+fn parse_line(input: &str) -> IResult<&str, Line<'_>> {
+    alt((
+        map(parse_comment, Line::Command),
+        map(parse_section, Line::Section),
+        map(parse_include, Line::Include),
+        map(parse_alias, Line::Command),
+    ))(input)
+}
+
+/// Everything defined in the aliases file and the files it includes, as `Macro`s until they
+/// are merged into the engine
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AliasSet {
+    /// Defined before any section, always loaded
+    pub common: Vec<Command>,
+    /// Defined in `[name]` sections, only loaded when `name` is the profile in use
+    pub profiles: BTreeMap<String, Vec<Command>>,
+}
+
+impl AliasSet {
+    /// Read `fname` and everything it includes, files being relative to the one including them
+    ///
+    pub fn load(fname: &Path) -> Self {
+        let mut set = AliasSet::default();
+        set.read(fname, None, &mut vec![]);
+        set
+    }
+
+    fn read(&mut self, fname: &Path, mut section: Option<String>, seen: &mut Vec<PathBuf>) {
+        trace!("Reading {:?} file...", fname);
+        let path = fname.canonicalize().unwrap_or_else(|_| fname.to_path_buf());
+        if seen.contains(&path) {
+            warn!("include loop on {fname:?}");
+            return;
+        }
+        let content = match fs::read_to_string(fname) {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("can not read aliases {fname:?}: {e}");
+                return;
+            }
+        };
+        seen.push(path);
+
+        for line in content.lines() {
+            match parse_line(line) {
+                Ok((_, Line::Command(cmd @ Command::Macro { .. }))) => match section {
+                    Some(ref name) => self.profiles.entry(name.clone()).or_default().push(cmd),
+                    None => self.common.push(cmd),
+                },
+                Ok((_, Line::Command(_))) => (),
+                Ok((_, Line::Section(name))) => {
+                    let name = name.to_lowercase();
+                    self.profiles.entry(name.clone()).or_default();
+                    section = Some(name);
+                }
+                Ok((_, Line::Include(other))) => {
+                    let other = fname.parent().unwrap_or(Path::new("")).join(other);
+                    self.read(&other, section.clone(), seen);
+                }
+                // Skip empty lines silently
+                //
+                Err(_) if line.trim().is_empty() => (),
+                Err(_) => warn!("invalid line in aliases: {line}"),
+            }
+        }
+        seen.pop();
+    }
 }
 
 impl Engine {
     /// Load aliases as a list of `Command`.
     ///
-    pub fn with(self, fname: Option<PathBuf>) -> Self {
+    pub fn with(mut self, fname: Option<PathBuf>) -> Self {
         trace!("with");
 
+        self.alias_file = fname.filter(|f| f.exists());
+        self.alias_set = match self.alias_file {
+            Some(ref fname) => AliasSet::load(fname),
+            None => AliasSet::default(),
+        };
+        self.load_aliases();
+        self
+    }
+
+    /// Use the aliases and macros of the `[name]` section of the aliases file
+    ///
+    pub fn with_profile(mut self, name: &str) -> Result<Self, EngineError> {
+        self.use_profile(name)?;
+        Ok(self)
+    }
+
+    /// Profile in use, if any
+    ///
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Switch to another profile, replacing the aliases and macros of the previous one
+    ///
+    pub fn use_profile(&mut self, name: &str) -> Result<(), EngineError> {
+        trace!("use_profile({name})");
+        let name = name.to_lowercase();
+        if !self.alias_set.profiles.contains_key(&name) {
+            return Err(EngineError::Usage(format!("unknown profile {name}")));
+        }
+        self.profile = Some(name);
+        self.load_aliases();
+        Ok(())
+    }
+
+    /// Handle `profile [name]`, listing all profiles without argument
+    ///
+    pub fn profiles(&mut self, args: &str) -> Result<String, EngineError> {
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => Ok(self
+                .alias_set
+                .profiles
+                .keys()
+                .map(|p| match Some(p.as_str()) == self.profile() {
+                    true => format!("* {p}"),
+                    false => format!("  {p}"),
+                })
+                .join("\n")),
+            [name] => {
+                self.use_profile(name)?;
+                Ok(format!("profile {}", name.to_lowercase()))
+            }
+            _ => Err(EngineError::Usage("profile [name]".to_string())),
+        }
+    }
+
+    /// Handle `unalias <name>...`: remove aliases and macros, nothing being removed if one of
+    /// the names is anything else.  What a roll of the character in use was hiding comes back
+    /// with another character.
+    ///
+    pub fn unalias(&mut self, args: &str) -> Result<String, EngineError> {
+        let names = args
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Err(EngineError::Usage("unalias <name>...".to_string()));
+        }
+        if let Some(name) = names.iter().find(|n| {
+            !matches!(
+                self.cmds.get(*n),
+                Some(Command::Alias { .. }) | Some(Command::Macro { .. })
+            )
+        }) {
+            return Err(EngineError::NotAlias(name.clone()));
+        }
+
+        names.iter().for_each(|n| {
+            self.cmds.remove(n);
+        });
+        self.check_aliases().iter().for_each(|p| warn!("{p}"));
+        self.reload = true;
+        Ok(format!("removed {}", names.join(", ")))
+    }
+
+    /// Replace all aliases and macros by the builtin ones, then those of the aliases file and
+    /// of the profile in use; the rolls of the character in use come last
+    ///
+    fn load_aliases(&mut self) {
+        self.cmds
+            .retain(|_, c| !matches!(c, Command::Alias { .. } | Command::Macro { .. }));
+
         // Always load builtins
         //
         let mut list = builtin_aliases();
         debug!("builtins = {:?}", list);
 
-        let mut added = match fname {
-            Some(fname) => {
-                if fname.exists() {
-                    trace!("Reading {:?} file...", fname);
-                    let content = fs::read_to_string(fname).unwrap_or_else(|_| "".to_string());
-
-                    // Get all from file
-                    //
-                    let added: Vec<Command> = content
-                        .lines()
-                        .filter_map(|line| {
-                            let (_input, alias) = alt((parse_comment, parse_alias))(line).unwrap();
-                            // Look at what we got
-                            //
-                            match alias {
-                                // Check whether the "new" command points to a known command then
-                                // it is an alias, not a new command
-                                //
-                                Command::Macro { name, cmd } => {
-                                    // Do we have an alias to a known command?
-                                    //
-                                    if self.exist(&cmd) {
-                                        Some(Command::Alias { name, cmd })
-                                    } else {
-                                        Some(Command::Macro { name, cmd })
-                                    }
-                                }
-                                // Builtins are fine
-                                //
-                                Command::Builtin { .. } => Some(alias),
-                                // Skip the rest
-                                //
-                                _ => None,
-                            }
-                        })
-                        .collect();
-                    added
-                } else {
-                    vec![]
-                }
-            }
-            _ => vec![],
-        };
+        let profile = self
+            .profile
+            .as_ref()
+            .and_then(|p| self.alias_set.profiles.get(p))
+            .into_iter()
+            .flatten();
+        let mut added = self
+            .alias_set
+            .common
+            .iter()
+            .chain(profile)
+            .filter_map(|alias| match alias {
+                // Check whether the "new" command points to a known command then it is an
+                // alias, not a new command
+                //
+                Command::Macro { name, cmd } if self.exist(cmd) => Some(Command::Alias {
+                    name: name.clone(),
+                    cmd: cmd.clone(),
+                }),
+                Command::Macro { .. } => Some(alias.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
         // Merge our builtin aliases
         //
@@ -147,7 +352,100 @@ impl Engine {
         debug!("aliases={list:?}");
         trace!("{} aliases/macros added", list.len());
 
-        self.merge(list)
+        self.insert(list);
+        self.check_aliases().iter().for_each(|p| warn!("{p}"));
+
+        // Character rolls go over everything else
+        //
+        if let Some(name) = self.character().map(|c| c.name.clone()) {
+            self.character = None;
+            self.shadowed.clear();
+            let _ = self.use_character(&name);
+        }
+        self.reload = true;
+    }
+
+    /// Follow every alias and macro until a builtin, looking for loops, unknown commands and
+    /// chains the compiler would refuse, then look for `dice` macros rolling the same dices
+    ///
+    pub fn check_aliases(&self) -> Vec<AliasError> {
+        // First word of what `name` expands to, if it is an alias or a macro
+        //
+        let target = |name: &str| match self.cmds.get(name) {
+            Some(Command::Alias { cmd, .. }) | Some(Command::Macro { cmd, .. }) => {
+                let end = cmd
+                    .find(|c: char| !c.is_alphanumeric())
+                    .unwrap_or(cmd.len());
+                Some(cmd[..end].to_lowercase())
+            }
+            _ => None,
+        };
+
+        let mut found = self
+            .cmds
+            .keys()
+            .sorted()
+            .filter_map(|name| {
+                let mut chain = vec![name.clone()];
+                while let Some(next) = target(chain.last().unwrap()) {
+                    if let Some(start) = chain.iter().position(|n| *n == next) {
+                        // Same loop whatever the alias we started from
+                        //
+                        let mut cycle = chain.split_off(start);
+                        let first = cycle.iter().position_min().unwrap_or(0);
+                        cycle.rotate_left(first);
+                        cycle.push(cycle[0].clone());
+                        return Some(AliasError::Cycle(cycle));
+                    }
+                    if !self.exist(&next) {
+                        let name = chain.pop().unwrap_or_default();
+                        return Some(AliasError::Unknown { name, target: next });
+                    }
+                    chain.push(next);
+                }
+                match chain.len() > Compiler::MAX_RECUR {
+                    true => Some(AliasError::TooDeep(chain)),
+                    false => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        found.sort();
+        found.dedup();
+        found.extend(self.same_macros());
+        found
+    }
+
+    /// Groups of `dice` macros whose expressions are equivalent
+    ///
+    fn same_macros(&self) -> Vec<AliasError> {
+        let defined = self.config.defined();
+        let mut same: Vec<(Expr, Vec<String>)> = vec![];
+
+        self.cmds
+            .iter()
+            .sorted_by_key(|(name, _)| *name)
+            .for_each(|(name, c)| {
+                let Command::Macro { cmd, .. } = c else {
+                    return;
+                };
+                let Some((word, args)) = cmd.trim().split_once(' ') else {
+                    return;
+                };
+                if !word.eq_ignore_ascii_case("dice") {
+                    return;
+                }
+                let Ok(e) = Expr::parse_with(args, &defined) else {
+                    return;
+                };
+                match same.iter_mut().find(|(other, _)| other.equivalent(&e)) {
+                    Some((_, names)) => names.push(name.clone()),
+                    None => same.push((e, vec![name.clone()])),
+                }
+            });
+        same.into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(_, names)| AliasError::Same(names))
+            .collect()
     }
 }
 /// Define some builtin aliases
@@ -174,6 +472,8 @@ fn builtin_aliases() -> Vec<Command> {
 mod tests {
     use std::collections::HashMap;
 
+    use rstest::rstest;
+
     use crate::makepath;
 
     use super::*;
@@ -200,13 +500,25 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_string() {
-        let a = "\"this is a string\"";
+    fn test_parse_alias_case() {
+        let (_, c) = parse_alias("Doom = \"dice 2D6\"").unwrap();
+        assert_eq!(
+            Command::Macro {
+                name: "doom".to_string(),
+                cmd: "dice 2D6".to_string(),
+            },
+            c
+        );
+    }
 
+    #[rstest]
+    #[case("\"this is a string\"", "this is a string")]
+    #[case("'rhai \"roll(`d6`); 1\"'", "rhai \"roll(`d6`); 1\"")]
+    fn test_parse_string(#[case] a: &str, #[case] want: &str) {
         let r = parse_string(a);
         assert!(r.is_ok());
         let (_input, r) = r.unwrap();
-        assert_eq!("this is a string", r);
+        assert_eq!(want, r);
     }
 
     #[test]
@@ -268,6 +580,186 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_load_aliases_with_bad_lines() {
+        let fname: PathBuf = makepath!("testdata", "aliases.bad");
+
+        let n = Engine::new().with(Some(fname));
+
+        assert!(n.exist("doom"));
+        assert!(n.exist("hit"));
+        assert!(!n.exist("foo"));
+    }
+
+    #[test]
+    fn test_check_aliases() {
+        let n = Engine::new().with(Some(makepath!("testdata", "aliases")));
+        assert!(n.check_aliases().is_empty());
+
+        let n = Engine::new().with(Some(makepath!("testdata", "aliases.loop")));
+        assert_eq!(
+            vec![
+                AliasError::Cycle(vec![
+                    "mouv".to_string(),
+                    "move".to_string(),
+                    "mouv".to_string()
+                ]),
+                AliasError::Unknown {
+                    name: "hit".to_string(),
+                    target: "attack".to_string()
+                },
+            ],
+            n.check_aliases()
+        );
+    }
+
+    #[test]
+    fn test_check_aliases_too_deep() {
+        let chain = ["a", "b", "c", "d", "e", "f"];
+        let n = Engine::new().merge(
+            chain
+                .windows(2)
+                .map(|w| Command::Macro {
+                    name: w[0].to_string(),
+                    cmd: w[1].to_string(),
+                })
+                .chain([Command::Alias {
+                    name: "f".to_string(),
+                    cmd: "dice".to_string(),
+                }])
+                .collect(),
+        );
+
+        // Same limit as the compiler
+        //
+        let found = n.check_aliases();
+        assert_eq!(2, found.len());
+        assert_eq!(
+            "alias chain too long a -> b -> c -> d -> e -> f -> dice",
+            found[0].to_string()
+        );
+        let cc = Compiler::new(&n.cmds);
+        assert!(cc.compile("b").is_err());
+        assert!(cc.compile("c").is_ok());
+    }
+
+    #[test]
+    fn test_check_aliases_same() {
+        let macros = [
+            ("doom", "dice 2D6"),
+            ("doom2", "dice 1 + 2d6 - 1"),
+            ("hit", "dice d20+2"),
+            ("save", "DICE 2 + D20"),
+            ("stats", "dice 4D6dl1"),
+        ];
+        let n = Engine::new().merge(
+            macros
+                .iter()
+                .map(|(name, cmd)| Command::Macro {
+                    name: name.to_string(),
+                    cmd: cmd.to_string(),
+                })
+                .collect(),
+        );
+
+        let found = n.check_aliases();
+        assert_eq!(
+            vec![
+                AliasError::Same(vec!["doom".to_string(), "doom2".to_string()]),
+                AliasError::Same(vec!["hit".to_string(), "save".to_string()]),
+            ],
+            found
+        );
+        assert_eq!("macros hit, save roll the same dices", found[1].to_string());
+    }
+
+    #[rstest]
+    #[case("[dnd]", Line::Section("dnd"))]
+    #[case("[ rolemaster ]", Line::Section("rolemaster"))]
+    #[case("include other", Line::Include("other"))]
+    #[case("include \"my aliases\"", Line::Include("my aliases"))]
+    #[case("# [dnd]", Line::Command(Command::Comment))]
+    fn test_parse_line(#[case] input: &str, #[case] want: Line) {
+        assert_eq!(want, parse_line(input).unwrap().1);
+    }
+
+    #[test]
+    fn test_alias_set_load() {
+        let fname: PathBuf = makepath!("testdata", "aliases.profiles");
+        let set = AliasSet::load(&fname);
+
+        // All of `aliases` and `stats`
+        //
+        assert_eq!(8, set.common.len());
+        assert_eq!(
+            vec!["dnd", "rolemaster"],
+            set.profiles.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(3, set.profiles["dnd"].len());
+        assert_eq!(2, set.profiles["rolemaster"].len());
+    }
+
+    #[test]
+    fn test_alias_set_include_loop() {
+        let fname: PathBuf = makepath!("testdata", "aliases.include");
+        let set = AliasSet::load(&fname);
+
+        assert_eq!(1, set.common.len());
+    }
+
+    #[test]
+    fn test_load_aliases_with_profile() {
+        let mut n = Engine::new()
+            .with(Some(makepath!("testdata", "aliases.profiles")))
+            .with_profile("DnD")
+            .unwrap();
+
+        assert_eq!(Some("dnd"), n.profile());
+        assert!(n.exist("save"));
+        assert!(n.exist("stats"));
+        assert!(n.exist("mouv"));
+        assert!(!n.exist("moving"));
+        assert_eq!(
+            Some(&Command::Macro {
+                name: "doom".to_string(),
+                cmd: "dice 3D6".to_string(),
+            }),
+            n.cmds.get("doom")
+        );
+
+        assert_eq!(
+            Ok("profile rolemaster".to_string()),
+            n.profiles("rolemaster")
+        );
+        assert_eq!(Ok("  dnd\n* rolemaster".to_string()), n.profiles(""));
+        assert!(!n.exist("save"));
+        assert!(n.exist("moving"));
+        assert_eq!(
+            Some(&Command::Alias {
+                name: "attack".to_string(),
+                cmd: "open100".to_string(),
+            }),
+            n.cmds.get("attack")
+        );
+        assert_eq!(
+            Some(&Command::Macro {
+                name: "doom".to_string(),
+                cmd: "dice 2D6".to_string(),
+            }),
+            n.cmds.get("doom")
+        );
+    }
+
+    #[rstest]
+    #[case("gurps")]
+    #[case("dnd rolemaster")]
+    fn test_profiles_error(#[case] args: &str) {
+        let mut n = Engine::new().with(Some(makepath!("testdata", "aliases.profiles")));
+
+        assert!(n.profiles(args).is_err());
+        assert_eq!(None, n.profile());
+    }
+
     #[test]
     fn test_load_aliases_with_none() {
         let all = HashMap::<String, Command>::from([
@@ -297,4 +789,31 @@ mod tests {
             assert_eq!(&cmd, n.cmds.get(&name).unwrap());
         });
     }
+
+    #[test]
+    fn test_unalias() {
+        let mut n = Engine::new().with(Some(makepath!("testdata", "aliases")));
+
+        assert_eq!(
+            Ok("removed doom, rulez".to_string()),
+            n.unalias(" Doom rulez")
+        );
+        assert!(!n.exist("doom"));
+        assert!(!n.list().contains("rulez"));
+        assert!(n.eval("doom").is_err());
+        assert!(n.eval("undef move").is_ok());
+        assert!(n.exist("mouv"));
+    }
+
+    #[rstest]
+    #[case("", EngineError::Usage("unalias <name>...".to_string()))]
+    #[case("roll dice", EngineError::NotAlias("dice".to_string()))]
+    #[case("roll list", EngineError::NotAlias("list".to_string()))]
+    #[case("nothing", EngineError::NotAlias("nothing".to_string()))]
+    fn test_unalias_error(#[case] args: &str, #[case] err: EngineError) {
+        let mut n = Engine::new().with(Some(makepath!("testdata", "aliases")));
+
+        assert_eq!(Err(err), n.unalias(args));
+        assert!(n.exist("roll"));
+    }
 }
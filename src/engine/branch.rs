@@ -0,0 +1,230 @@
+//! What-if exploration: `branch`, `merge` and `discard` commands.
+//!
+//! `branch` takes a snapshot of everything rolling changes (see `Snapshot`) so that experimental
+//! rolls can be done, then either kept with `merge` or thrown away with `discard` which goes
+//! back to the snapshot.  Branches can be nested.
+//!
+//! While in a branch nothing is saved to the session storage nor recorded in the roll
+//! database, `merge` of the outermost branch saves the session again.  The roll log is written
+//! as usual, each entry with the depth of its branch.
+//!
+//! What is not in the snapshot stays as changed in a branch: the commands (aliases, macros,
+//! `unalias`, the profile and the character in use), the tables and characters loaded, the
+//! configuration and the history of the commands.
+//!
+//! ```
+//! use dices_rs::engine::Engine;
+//!
+//! let mut e = Engine::new();
+//! e.set("hp 12").unwrap();
+//!
+//! e.branch();
+//! e.set("hp 0").unwrap();
+//! e.discard_branch().unwrap();
+//!
+//! assert_eq!(Some(&12), e.variables().get("hp"));
+//! ```
+
+use std::collections::BTreeMap;
+
+use log::trace;
+
+use crate::compiler::Trace;
+use crate::dice::result::Res;
+use crate::engine::deck::Deck;
+use crate::engine::error::EngineError;
+use crate::engine::storage::Session;
+use crate::engine::{Command, Engine};
+
+/// The state of an `Engine` changed by rolling and by the switches of the session, everything
+/// but the commands, tables and characters loaded, the configuration and the history
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Snapshot {
+    /// Variables and labelled rolls
+    pub session: Session,
+    /// Last roll if any
+    pub last: Option<Res>,
+    /// Number of rolls
+    pub count: usize,
+    /// Rolls and sum of their totals by player
+    pub scores: BTreeMap<String, (usize, isize)>,
+    /// Rolls hidden since the last `reveal`, with their line
+    pub hidden: Vec<(String, Res)>,
+    /// Deck of cards with the ones drawn, if any
+    pub deck: Option<Deck>,
+    /// Last dice command, for `reroll` and `again`
+    pub last_action: Option<(Command, String, Trace)>,
+    /// Expansion of the last command
+    pub last_trace: Option<Trace>,
+    /// GM screen mode
+    pub gm: bool,
+    /// Speech
+    pub speak: bool,
+    /// Showing expansions
+    pub trace: bool,
+}
+
+impl Engine {
+    /// Copy of the current state
+    ///
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            session: self.session.clone(),
+            last: self.last.clone(),
+            count: self.count,
+            scores: self.scores.clone(),
+            hidden: self.hidden.clone(),
+            deck: self.deck.clone(),
+            last_action: self.last_action.clone(),
+            last_trace: self.last_trace.clone(),
+            gm: self.gm,
+            speak: self.speak,
+            trace: self.trace,
+        }
+    }
+
+    /// Go back to a previous state
+    ///
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), EngineError> {
+        self.session = snapshot.session;
+        self.last = snapshot.last;
+        self.count = snapshot.count;
+        self.scores = snapshot.scores;
+        self.hidden = snapshot.hidden;
+        self.deck = snapshot.deck;
+        self.last_action = snapshot.last_action;
+        self.last_trace = snapshot.last_trace;
+        self.gm = snapshot.gm;
+        self.set_speak(snapshot.speak)?;
+        self.trace = snapshot.trace;
+        self.save_session()
+    }
+
+    /// Start a new branch, returns how deep we are now
+    ///
+    pub fn branch(&mut self) -> usize {
+        trace!("branch");
+        self.branches.push(self.snapshot());
+        self.branches.len()
+    }
+
+    /// Keep what has been done in the current branch, returns the remaining depth
+    ///
+    pub fn merge_branch(&mut self) -> Result<usize, EngineError> {
+        trace!("merge");
+        self.branches.pop().ok_or(EngineError::NoBranch)?;
+        self.save_session()?;
+        Ok(self.branches.len())
+    }
+
+    /// Forget the current branch and go back to where it started, returns the remaining depth
+    ///
+    pub fn discard_branch(&mut self) -> Result<usize, EngineError> {
+        trace!("discard");
+        let snapshot = self.branches.pop().ok_or(EngineError::NoBranch)?;
+        self.restore(snapshot)?;
+        Ok(self.branches.len())
+    }
+
+    /// How many branches deep we are, 0 being the real session
+    ///
+    pub fn depth(&self) -> usize {
+        self.branches.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::engine::storage::MemoryStorage;
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut e = Engine::new();
+        e.set("str 4").unwrap();
+        let s = e.snapshot();
+
+        e.set("str").unwrap();
+        e.set("dex 2").unwrap();
+        assert_ne!(s, e.snapshot());
+
+        e.restore(s.clone()).unwrap();
+        assert_eq!(s, e.snapshot());
+    }
+
+    #[test]
+    fn test_branch_nested() {
+        let mut e = Engine::new();
+        e.set("hp 12").unwrap();
+
+        assert_eq!(1, e.branch());
+        e.set("hp 8").unwrap();
+        assert_eq!(2, e.branch());
+        e.set("hp 0").unwrap();
+
+        assert_eq!(Ok(1), e.discard_branch());
+        assert_eq!(Some(&8), e.variables().get("hp"));
+        assert_eq!(Ok(0), e.merge_branch());
+        assert_eq!(Some(&8), e.variables().get("hp"));
+        assert_eq!(0, e.depth());
+    }
+
+    #[test]
+    fn test_branch_deck() {
+        let mut e = Engine::new();
+        e.eval("deck new standard52").unwrap();
+
+        e.eval("branch").unwrap();
+        e.eval("deck draw 3").unwrap();
+        assert_eq!(Some(49), e.deck.as_ref().map(|d| d.left()));
+        e.eval("discard").unwrap();
+
+        assert_eq!(Some(52), e.deck.as_ref().map(|d| d.left()));
+        assert!(e.eval("deck draw 52").is_ok());
+    }
+
+    #[test]
+    fn test_branch_state() {
+        let mut e = Engine::new();
+        e.eval("as Alice dice d6").unwrap();
+        let s = e.snapshot();
+
+        e.eval("branch").unwrap();
+        e.eval("gm on").unwrap();
+        e.eval("as Bob dice d8").unwrap();
+        e.eval("trace on").unwrap();
+        assert_eq!(1, e.hidden.len());
+        e.eval("discard").unwrap();
+
+        assert!(!e.gm && !e.trace);
+        assert!(e.hidden.is_empty());
+        assert_eq!(vec!["Alice"], e.scores.keys().collect::<Vec<_>>());
+        assert_eq!(s.last_action, e.last_action);
+        assert_eq!(s.count, e.count);
+    }
+
+    #[test]
+    fn test_branch_none() {
+        let mut e = Engine::new();
+
+        assert_eq!(Err(EngineError::NoBranch), e.merge_branch());
+        assert_eq!(Err(EngineError::NoBranch), e.discard_branch());
+    }
+
+    #[test]
+    fn test_branch_not_saved() {
+        let mut e = Engine::new()
+            .with_storage(Box::<MemoryStorage>::default())
+            .unwrap();
+        e.set("hp 12").unwrap();
+
+        e.branch();
+        e.set("hp 0").unwrap();
+        assert_eq!(Some(&12), e.storage.load().unwrap().vars.get("hp"));
+
+        e.merge_branch().unwrap();
+        assert_eq!(Some(&0), e.storage.load().unwrap().vars.get("hp"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Character files: named rolls and ability modifiers of one character.
+//!
+//! A character file is TOML, or YAML if its extension is `.yaml` or `.yml`.  Each roll becomes
+//! a macro, with `dice` in front unless it already starts with a command, and each modifier a
+//! variable usable as `$name`.  Variables set with `set` win over modifiers.
+//!
+//! Several characters can be loaded, the first one is in use and `char use <name>` switches to
+//! another one, removing the rolls of the previous character.
+//!
+//! ```no_run
+//! use dices_rs::engine::character::Character;
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Engine::new()
+//!     .with_character(Character::load("/some/location/bob.toml").unwrap())
+//!     .unwrap();
+//! ```
+//!
+//! File format:
+//! ```text
+//! # Default is the name of the file
+//! name = "Bob"
+//!
+//! [rolls]
+//! attack = "d20 +$str +3"
+//! damage = "2D6 +4"
+//! init = "open D6"
+//!
+//! [modifiers]
+//! str = 4
+//! dex = 2
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use itertools::Itertools;
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine};
+
+/// One character
+///
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Character {
+    /// Used by `char use`
+    pub name: String,
+    /// Named rolls, registered as macros
+    pub rolls: BTreeMap<String, String>,
+    /// Ability modifiers, usable as `$name`
+    pub modifiers: BTreeMap<String, i64>,
+}
+
+impl Character {
+    /// Read a character from a TOML or YAML file, named after the file if it has no `name`
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        trace!("character::load({path:?})");
+
+        let error = |e: String| EngineError::Character(format!("{path:?}: {e}"));
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let mut c: Character = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => {
+                serde_yaml::from_str(&content).map_err(|e| error(e.to_string()))?
+            }
+            _ => toml::from_str(&content).map_err(|e| error(e.to_string()))?,
+        };
+        if c.name.is_empty() {
+            c.name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+        }
+        Ok(c)
+    }
+
+    /// Rolls as macros, those not starting with a known command are given to `dice`
+    ///
+    fn macros(&self, known: impl Fn(&str) -> bool) -> Vec<Command> {
+        self.rolls
+            .iter()
+            .map(|(name, roll)| {
+                let first = roll.split_whitespace().next().unwrap_or_default();
+                let cmd = match first.chars().all(char::is_alphanumeric) && known(first) {
+                    true => roll.trim().to_string(),
+                    false => format!("dice {}", roll.trim()),
+                };
+                Command::Macro {
+                    name: name.to_lowercase(),
+                    cmd,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Engine {
+    /// Add a character, in use if it is the first one.  Rolls can not replace builtin
+    /// commands, only aliases and macros.
+    ///
+    pub fn with_character(mut self, c: Character) -> Result<Self, EngineError> {
+        let usage = |msg: String| EngineError::Character(format!("{}: {msg}", c.name));
+
+        if c.name.is_empty() {
+            return Err(usage("no name".to_string()));
+        }
+        if self.find_character(&c.name).is_some() {
+            return Err(usage("already loaded".to_string()));
+        }
+        for name in c.rolls.keys() {
+            self.check_name(name).map_err(|e| usage(e.to_string()))?;
+        }
+
+        self.characters.push(c);
+        if self.character.is_none() {
+            self.use_character(&self.characters[0].name.clone())?;
+        }
+        Ok(self)
+    }
+
+    /// Character in use, if any
+    ///
+    pub fn character(&self) -> Option<&Character> {
+        self.character.map(|i| &self.characters[i])
+    }
+
+    /// Switch to the character called `name`, whatever the case
+    ///
+    pub fn use_character(&mut self, name: &str) -> Result<&Character, EngineError> {
+        trace!("use_character({name})");
+        let idx = self
+            .find_character(name)
+            .ok_or_else(|| EngineError::Character(format!("unknown {name}")))?;
+
+        // Forget the rolls of the previous character and put back what they were hiding
+        //
+        if let Some(old) = self.character.take() {
+            self.characters[old].rolls.keys().for_each(|n| {
+                self.cmds.remove(&n.to_lowercase());
+            });
+            let shadowed = std::mem::take(&mut self.shadowed);
+            self.insert(shadowed);
+        }
+
+        let macros = self.characters[idx].macros(|n| self.exist(n));
+        self.shadowed = macros
+            .iter()
+            .filter_map(|m| match m {
+                Command::Macro { name, .. } => self.cmds.get(name).cloned(),
+                _ => None,
+            })
+            .collect();
+        self.insert(macros);
+
+        self.character = Some(idx);
+        self.reload = true;
+        Ok(&self.characters[idx])
+    }
+
+    /// Handle `char [use <name>]`, listing all characters without argument
+    ///
+    pub fn char(&mut self, args: &str) -> Result<String, EngineError> {
+        let usage = || EngineError::Usage("char [use <name>]".to_string());
+
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => Ok(self
+                .characters
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let mark = if Some(i) == self.character { '*' } else { ' ' };
+                    format!("{mark} {}", c.name)
+                })
+                .join("\n")),
+            ["use", name] => Ok(format!("playing {}", self.use_character(name)?.name)),
+            _ => Err(usage()),
+        }
+    }
+
+    fn find_character(&self, name: &str) -> Option<usize> {
+        self.characters
+            .iter()
+            .position(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rstest::rstest;
+
+    use crate::engine::Output;
+
+    use super::*;
+
+    fn bob() -> Character {
+        Character::load("testdata/bob.toml").unwrap()
+    }
+
+    #[rstest]
+    #[case("testdata/bob.toml")]
+    #[case("testdata/alice.yaml")]
+    fn test_character_load(#[case] path: &str) {
+        let c = Character::load(path).unwrap();
+
+        assert!(!c.name.is_empty());
+        assert_eq!(Some(&4), c.modifiers.get("str"));
+        assert!(c.rolls.contains_key("attack"));
+    }
+
+    #[test]
+    fn test_character_load_error() {
+        assert!(Character::load("testdata/nobody.toml").is_err());
+        assert!(Character::load("testdata/aliases").is_err());
+    }
+
+    #[test]
+    fn test_character_macros() {
+        let e = Engine::new().with_character(bob()).unwrap();
+
+        assert_eq!(Some("Bob"), e.character().map(|c| c.name.as_str()));
+        assert_eq!(
+            Some(&Command::Macro {
+                name: "attack".to_string(),
+                cmd: "dice d20 +$str +3".to_string()
+            }),
+            e.cmds.get("attack")
+        );
+        assert_eq!(
+            Some(&Command::Macro {
+                name: "init".to_string(),
+                cmd: "open D6".to_string()
+            }),
+            e.cmds.get("init")
+        );
+        assert_eq!(Some(&4), e.variables().get("str"));
+    }
+
+    #[test]
+    fn test_character_eval() {
+        let mut e = Engine::new().with_character(bob()).unwrap();
+
+        let Ok(Output::Roll(res)) = e.eval("attack") else {
+            panic!("no roll");
+        };
+        assert_eq!(7, res.bonus);
+
+        e.set("str 1").unwrap();
+        let Ok(Output::Roll(res)) = e.eval("ATTACK +1") else {
+            panic!("no roll");
+        };
+        assert_eq!(5, res.bonus);
+        assert_eq!("var \tstr = 1\nchar \tdex = 2", e.vars());
+    }
+
+    #[test]
+    fn test_character_use() {
+        let mut e = Engine::new()
+            .with(Some(PathBuf::from("testdata/aliases")))
+            .with_character(bob())
+            .unwrap()
+            .with_character(Character::load("testdata/alice.yaml").unwrap())
+            .unwrap();
+        let doom = e.cmds.get("doom").cloned();
+
+        assert_eq!(Ok("* Bob\n  alice".to_string()), e.char(""));
+        assert_eq!(Ok("playing alice".to_string()), e.char("use ALICE"));
+        assert_eq!(Some("alice"), e.character().map(|c| c.name.as_str()));
+
+        // Alice's doom hides the alias, Bob's init is gone
+        //
+        assert_ne!(doom, e.cmds.get("doom").cloned());
+        assert!(!e.exist("init"));
+        assert_eq!(Some(&1), e.variables().get("dex"));
+
+        e.char("use bob").unwrap();
+        assert_eq!(doom, e.cmds.get("doom").cloned());
+        assert!(e.exist("init"));
+
+        // What a removed roll was hiding comes back with another character
+        //
+        e.char("use alice").unwrap();
+        e.unalias("doom").unwrap();
+        assert!(!e.exist("doom"));
+        e.char("use bob").unwrap();
+        assert_eq!(doom, e.cmds.get("doom").cloned());
+    }
+
+    #[test]
+    fn test_character_profile() {
+        let mut e = Engine::new()
+            .with(Some(PathBuf::from("testdata/aliases.profiles")))
+            .with_character(bob())
+            .unwrap();
+
+        e.use_profile("dnd").unwrap();
+        assert_eq!(
+            Some(&Command::Macro {
+                name: "attack".to_string(),
+                cmd: "dice d20 +$str +3".to_string()
+            }),
+            e.cmds.get("attack")
+        );
+        assert!(e.exist("save"));
+        assert!(e.exist("init"));
+    }
+
+    #[rstest]
+    #[case("use")]
+    #[case("use bob alice")]
+    #[case("drop bob")]
+    fn test_character_usage(#[case] args: &str) {
+        let mut e = Engine::new().with_character(bob()).unwrap();
+
+        assert_eq!(
+            Err(EngineError::Usage("char [use <name>]".to_string())),
+            e.char(args)
+        );
+    }
+
+    #[test]
+    fn test_character_invalid() {
+        let mut c = bob();
+        assert!(Engine::new()
+            .with_character(c.clone())
+            .unwrap()
+            .char("use carol")
+            .is_err());
+
+        c.rolls.insert("list".to_string(), "D6".to_string());
+        assert!(Engine::new().with_character(c.clone()).is_err());
+
+        c.rolls.clear();
+        c.rolls.insert("big-hit".to_string(), "D6".to_string());
+        assert!(Engine::new().with_character(c.clone()).is_err());
+
+        c.rolls.clear();
+        assert!(Engine::new()
+            .with_character(c.clone())
+            .unwrap()
+            .with_character(c)
+            .is_err());
+    }
+}
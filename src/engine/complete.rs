@@ -1,5 +1,516 @@
 //! Completion module for `rustyline`.
 //!
+//! Command names are completed from the engine, either cycling through them in the line
+//! (`circular`, the default) or listing them all with their description (`list`).
+//!
+//! Hints are shown faint after the cursor: the rest of the last line of the history starting
+//! with what is typed (`dice ` hints the last dice rolled), the description of a command once
+//! its whole name is typed or the rest of its name when only one matches (`doo` hints `doom`).
+//! Hints other than descriptions are completed with the right arrow.
+//!
+//! Before a line is accepted, it is compiled and its dices parsed: an unknown command or a
+//! malformed dice expression is shown after the line, which is kept for editing.  Variables
+//! are only known when the line runs and are not checked.
+//!
+//! After a command rolling dices (`dice`, `open`, `prob`, `anydice` and their aliases) the
+//! partial line is parsed: common dices are suggested first, then `+` or `-` once a dice has
+//! been typed.
+//!
+//! ```no_run
+//! use dices_rs::engine::{complete::DiceHelper, Engine};
+//! use rustyline::Editor;
+//!
+//! let e = Engine::new().with(None);
+//! let mut repl = Editor::<DiceHelper>::new().unwrap();
+//! repl.set_helper(Some(DiceHelper::new(&e)));
+//! ```
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use log::trace;
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::{Hint, Hinter, HistoryHinter},
+    validate::{ValidationContext, ValidationResult, Validator},
+    Context, Helper,
+};
+
+use crate::compiler::{
+    error::CompilerError, join_continued, split, Action, Compiler, CONTINUATION,
+};
+use crate::config::Config;
+use crate::dice::DiceSet;
+use crate::engine::error::EngineError;
+use crate::engine::format::{Palette, RESET};
+use crate::engine::history::reference;
+use crate::engine::{split_label, split_note, Command, Engine};
+
+/// Dices suggested after a command rolling dices
+pub const DICE: [&str; 8] = ["d4", "d6", "d8", "d10", "d12", "d20", "d100", "3d6"];
+
+/// Suggested after a dice
+pub const BONUS: [&str; 2] = ["+", "-"];
+
+/// Complete command names and dices
+///
+#[derive(Clone, Debug, Default)]
+pub struct DiceCompleter {
+    /// Name and description of every command
+    cmds: BTreeMap<String, String>,
+    /// Commands taking dices as arguments
+    dice_cmds: BTreeSet<String>,
+}
+
+impl DiceCompleter {
+    /// Take all the commands known to `engine`
+    ///
+    pub fn new(engine: &Engine) -> Self {
+        let cmds = engine
+            .cmds
+            .keys()
+            .map(|n| (n.clone(), engine.describe(n).unwrap_or_default()))
+            .collect();
+        let takes_dices = |c: Option<&Command>| match c {
+            Some(Command::Builtin { cmd, .. }) => cmd.takes_dices(),
+            Some(Command::Prob) | Some(Command::Anydice) => true,
+            _ => false,
+        };
+        let dice_cmds = engine
+            .cmds
+            .iter()
+            .filter(|(_, c)| match c {
+                Command::Alias { cmd, .. } => takes_dices(engine.cmds.get(cmd)),
+                c => takes_dices(Some(c)),
+            })
+            .map(|(n, _)| n.clone())
+            .collect();
+        DiceCompleter { cmds, dice_cmds }
+    }
+
+    /// Arguments of a command rolling dices, `words` being those before the one at `word`
+    ///
+    fn complete_dice(&self, words: &[&str], word: &str) -> Vec<Pair> {
+        let list: Vec<&str> = match words {
+            [] => DICE
+                .into_iter()
+                .filter(|d| d.starts_with(word) && d.len() > word.len())
+                .collect(),
+            [.., last] if word.is_empty() && DiceSet::parse_strict(last).is_ok() => BONUS.to_vec(),
+            _ => vec![],
+        };
+        list.into_iter()
+            .map(|d| Pair {
+                display: d.to_string(),
+                replacement: d.to_string(),
+            })
+            .collect()
+    }
+}
+
+impl Completer for DiceCompleter {
+    type Candidate = Pair;
+
+    /// The first word is a command, dices may follow some of them
+    ///
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        trace!("complete({line}, {pos})");
+        let word = line[..pos].to_lowercase();
+        if let Some((cmd, _)) = word.split_once(char::is_whitespace) {
+            if !self.dice_cmds.contains(cmd) {
+                return Ok((pos, vec![]));
+            }
+            let start = word.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+            let words = word[..start].split_whitespace().skip(1).collect::<Vec<_>>();
+            let list = self.complete_dice(&words, &word[start..]);
+            return Ok((if list.is_empty() { pos } else { start }, list));
+        }
+
+        let list = self
+            .cmds
+            .iter()
+            .filter(|(n, _)| n.starts_with(&word))
+            .map(|(n, d)| Pair {
+                display: format!("{n:<10} {d}"),
+                replacement: n.clone(),
+            })
+            .collect();
+        Ok((0, list))
+    }
+}
+
+/// End of the line being typed, or description of the command which is never inserted in
+/// the line
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommandHint {
+    text: String,
+    complete: bool,
+}
+
+impl Hint for CommandHint {
+    fn display(&self) -> &str {
+        &self.text
+    }
+
+    fn completion(&self) -> Option<&str> {
+        self.complete.then_some(self.text.as_str())
+    }
+}
+
+/// Our `rustyline` helper
+///
+#[derive(Clone, Debug, Default)]
+pub struct DiceHelper {
+    completer: DiceCompleter,
+    /// Commands used to validate lines
+    cmds: HashMap<String, Command>,
+    /// Rules, dice sizes and named dices used to validate lines
+    config: Config,
+}
+
+impl DiceHelper {
+    /// Helper for all the commands of `engine`
+    ///
+    pub fn new(engine: &Engine) -> Self {
+        DiceHelper {
+            completer: DiceCompleter::new(engine),
+            cmds: engine.cmds.clone(),
+            config: engine.config.clone(),
+        }
+    }
+
+    /// Colors of the prompt and hints, none if colors are disabled
+    ///
+    fn palette(&self) -> Option<Palette> {
+        self.config.color.then(|| self.config.theme.palette())
+    }
+
+    /// Compile every command of `line` and parse their dices without rolling them, history
+    /// references are left to the engine
+    ///
+    pub fn check(&self, line: &str) -> Result<(), EngineError> {
+        let cc = Compiler::new(&self.cmds).with_defined(self.config.defined());
+        split(&join_continued(line))
+            .into_iter()
+            .filter(|cmd| reference(cmd).is_none())
+            .try_for_each(|cmd| match cc.compile(cmd) {
+                Ok(Action::Execute(Command::Builtin { cmd, .. }, args, _))
+                    if !args.trim().is_empty() =>
+                {
+                    let (args, _) = split_label(split_note(&args).0);
+                    cmd.check_with(args, &self.config)
+                }
+                Ok(Action::Execute(Command::Mechanic { name, mechanic }, args, _)) => {
+                    let (args, _) = split_label(split_note(&args).0);
+                    mechanic.parse_args(&name, args, &self.config).map(|_| ())
+                }
+                Ok(Action::Conditional(Command::Builtin { cmd, .. }, test, _, then, _)) => {
+                    let (then, _) = split_label(split_note(&then).0);
+                    cmd.check_with(&test, &self.config)
+                        .and_then(|_| cmd.check_with(then, &self.config))
+                }
+                Ok(_) | Err(CompilerError::UnknownVariable(_)) => Ok(()),
+                Err(e) => Err(e.into()),
+            })
+    }
+}
+
+impl From<&Engine> for DiceHelper {
+    fn from(engine: &Engine) -> Self {
+        DiceHelper::new(engine)
+    }
+}
+
+impl Completer for DiceHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        self.completer.complete(line, pos, ctx)
+    }
+}
+
+impl Hinter for DiceHelper {
+    type Hint = CommandHint;
+
+    /// History first, then commands
+    ///
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<CommandHint> {
+        if line.is_empty() || pos < line.len() {
+            return None;
+        }
+        if let Some(text) = (HistoryHinter {}).hint(line, pos, ctx) {
+            return Some(CommandHint {
+                text,
+                complete: true,
+            });
+        }
+
+        let word = line.to_lowercase();
+        if let Some(d) = self.completer.cmds.get(&word) {
+            return (!d.is_empty()).then(|| CommandHint {
+                text: format!("  {d}"),
+                complete: false,
+            });
+        }
+        match self
+            .completer
+            .cmds
+            .keys()
+            .filter(|n| n.starts_with(&word))
+            .collect::<Vec<_>>()[..]
+        {
+            [n] => Some(CommandHint {
+                text: n[word.len()..].to_string(),
+                complete: true,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Highlighter for DiceHelper {
+    /// Prompt in the color of the theme
+    ///
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        match self.palette() {
+            Some(p) => Cow::Owned(format!("{}{prompt}{RESET}", p.prompt)),
+            None => Cow::Borrowed(prompt),
+        }
+    }
+
+    /// Faint hints
+    ///
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        match self.palette() {
+            Some(p) => Cow::Owned(format!("{}{hint}{RESET}", p.faint)),
+            None => Cow::Borrowed(hint),
+        }
+    }
+}
+
+impl Validator for DiceHelper {
+    fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
+        if ctx.input().ends_with(CONTINUATION) {
+            return Ok(ValidationResult::Incomplete);
+        }
+        Ok(match self.check(ctx.input()) {
+            Ok(()) => ValidationResult::Valid(None),
+            Err(e) => {
+                ValidationResult::Invalid(Some(format!("  {}", self.config.messages().error(&e))))
+            }
+        })
+    }
+}
+
+impl Helper for DiceHelper {}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rstest::rstest;
+    use rustyline::history::History;
+
+    use crate::config::Theme;
+    use crate::engine::io::{BufSource, LineSource, WriteSink};
+
+    use super::*;
+
+    fn complete(line: &str) -> (usize, Vec<String>) {
+        let e = Engine::new().with(None);
+        let h = DiceHelper::new(&e);
+        let history = History::new();
+        let ctx = Context::new(&history);
+
+        let (start, list) = h.complete(line, line.len(), &ctx).unwrap();
+        (start, list.into_iter().map(|p| p.replacement).collect())
+    }
+
+    #[test]
+    fn test_complete_command() {
+        assert_eq!(
+            (0, vec!["dice".to_string(), "discard".to_string()]),
+            complete("di")
+        );
+        assert_eq!(0, complete("zz").1.len());
+    }
+
+    #[test]
+    fn test_complete_args() {
+        assert_eq!((8, vec![]), complete("dice d20"));
+    }
+
+    #[rstest]
+    #[case("dice ", 5, &DICE)]
+    #[case("dice d1", 5, &["d10", "d12", "d100"])]
+    #[case("Open D1", 5, &["d10", "d12", "d100"])]
+    #[case("dice 3", 5, &["3d6"])]
+    #[case("anydice d10", 8, &["d100"])]
+    #[case("roll ", 5, &DICE)]
+    #[case("dice d20 ", 9, &BONUS)]
+    #[case("prob 3d6 ", 9, &BONUS)]
+    #[case("dice d20 +", 10, &[])]
+    #[case("dice +3 ", 8, &[])]
+    #[case("dice d20 +3 ", 12, &[])]
+    #[case("table ", 6, &[])]
+    fn test_complete_dice(#[case] line: &str, #[case] start: usize, #[case] list: &[&str]) {
+        let (s, l) = complete(line);
+        assert_eq!(
+            (start, list.to_vec()),
+            (s, l.iter().map(String::as_str).collect())
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("doom")]
+    #[case("dice 3d6 +2 adv")]
+    #[case("roll d20")]
+    #[case("dice")]
+    #[case("dice d20 +$str")]
+    #[case("open d6")]
+    #[case("flip 3")]
+    #[case("vars")]
+    #[case("dice d20; dice 2d6+3")]
+    #[case("set x 2; dice d20 +$x")]
+    #[case("dice \\\n3d6")]
+    #[case(" ; ")]
+    #[case("# heading for this fight")]
+    #[case("dice d20 # initiative")]
+    #[case("dice d20 +2 as init")]
+    #[case("3d6+2")]
+    #[case("!!; !dice +2")]
+    #[case("dice 2d6+4 \"goblin damage\"")]
+    #[case("dice d20+7 >= 16 then 2d6+4 as dmg")]
+    fn test_check(#[case] line: &str) {
+        let e = Engine::new().with(None);
+
+        assert_eq!(Ok(()), DiceHelper::new(&e).check(line));
+    }
+
+    #[rstest]
+    #[case("doem 2")]
+    #[case("dice 3d")]
+    #[case("roll 2d6 +")]
+    #[case("open (d6)")]
+    #[case("#heading")]
+    #[case("dice d20; doem")]
+    #[case("dice d20 \\\n+")]
+    #[case("dice d20 >= 16 then 2d")]
+    #[case("dice d20 then 2d6")]
+    fn test_check_invalid(#[case] line: &str) {
+        let e = Engine::new().with(None);
+
+        assert!(DiceHelper::new(&e).check(line).is_err());
+    }
+
+    #[test]
+    fn test_check_reload() {
+        // Lines of a `BufSource` checked by the helper it carries
+        struct Checked(BufSource<&'static [u8]>, DiceHelper, Vec<bool>);
+
+        impl LineSource for Checked {
+            fn next_line(&mut self, prompt: &str) -> Result<Option<String>, EngineError> {
+                let line = self.0.next_line(prompt)?;
+                if let Some(l) = &line {
+                    self.2.push(self.1.check(l).is_ok());
+                }
+                Ok(line)
+            }
+
+            fn reload(&mut self, engine: &Engine) {
+                self.1 = DiceHelper::from(engine);
+            }
+        }
+
+        let mut e = Engine::new().with(Some(PathBuf::from("testdata/aliases.profiles")));
+        let input = "moving\nprofile rolemaster\nmoving\n";
+        let mut src = Checked(
+            BufSource::new(input.as_bytes()),
+            DiceHelper::new(&e),
+            vec![],
+        );
+
+        e.run(&mut src, &mut WriteSink::new(vec![])).unwrap();
+        assert_eq!(vec![false, true, true], src.2);
+    }
+
+    #[test]
+    fn test_check_message() {
+        let e = Engine::new().with(None);
+        let h = DiceHelper::new(&e);
+
+        assert_eq!(
+            "unknown command doem, did you mean doom?",
+            h.check("doem").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_hint() {
+        let e = Engine::new().with(None);
+        let h = DiceHelper::new(&e);
+        let history = History::new();
+        let ctx = Context::new(&history);
+
+        let hint = h.hint("doom", 4, &ctx).unwrap();
+        assert_eq!("  = dice 2D6", hint.display());
+        assert_eq!(None, hint.completion());
+        assert_eq!(Some("m"), h.hint("doo", 3, &ctx).unwrap().completion());
+        assert!(h.hint("d", 1, &ctx).is_none());
+        assert!(h.hint("", 0, &ctx).is_none());
+    }
+
+    #[rstest]
+    #[case(Theme::Dark, "\x1b[36mDice> \x1b[0m", "\x1b[2m 3d6\x1b[0m")]
+    #[case(Theme::Light, "\x1b[34mDice> \x1b[0m", "\x1b[90m 3d6\x1b[0m")]
+    #[case(Theme::Mono, "\x1b[1mDice> \x1b[0m", "\x1b[2m 3d6\x1b[0m")]
+    fn test_highlight(#[case] theme: Theme, #[case] prompt: &str, #[case] hint: &str) {
+        let mut h = DiceHelper::new(&Engine::new());
+        h.config.theme = theme;
+
+        assert_eq!(prompt, h.highlight_prompt("Dice> ", false));
+        assert_eq!(hint, h.highlight_hint(" 3d6"));
+
+        h.config.color = false;
+        assert_eq!("Dice> ", h.highlight_prompt("Dice> ", false));
+        assert_eq!(" 3d6", h.highlight_hint(" 3d6"));
+    }
+
+    #[test]
+    fn test_hint_history() {
+        let e = Engine::new().with(None);
+        let h = DiceHelper::new(&e);
+        let mut history = History::new();
+        history.add("dice 3d6 +2");
+        history.add("doom");
+        history.add("dice d20");
+        let ctx = Context::new(&history);
 
-//use anyhow::{anyhow, Result};
-//use log::{debug, error};
+        let hint = h.hint("dice ", 5, &ctx).unwrap();
+        assert_eq!("d20", hint.display());
+        assert_eq!(Some("d20"), hint.completion());
+        assert_eq!(
+            Some("6 +2"),
+            h.hint("dice 3d", 7, &ctx).unwrap().completion()
+        );
+        assert_eq!("  = dice 2D6", h.hint("doom", 4, &ctx).unwrap().display());
+        assert!(h.hint("dice d20", 5, &ctx).is_none());
+    }
+}
@@ -2,19 +2,40 @@
 //!
 //! Dice        Your regular dice
 //! Open        Open-ended dice
+//! Open100     Rolemaster open-ended D100 (high and low)
+//! Flip        Coin flips, counted as heads and tails
+//! Choose      One of the given options, picked uniformly
+//! RollUnder   GURPS success roll, 3D6 under a skill with margin and critical results
+//! Pool        Old World of Darkness success pool of d10 with botches
+//! Action      Ironsworn action roll, d6 and modifiers against two challenge d10
+//! Forged      Blades in the Dark pool of d6, keeping the highest with criticals
 //!
 //! XXX If anyone add core commands, do not forget to document and test.
 
-use anyhow::{anyhow, Result};
-use log::{debug, error, trace};
-use nom::{character::complete::space0, sequence::preceded};
+use log::{debug, trace};
+use nom::{
+    bytes::complete::tag_no_case,
+    character::complete::{i64 as parse_i64, one_of, space0, space1, u64 as parse_u64},
+    combinator::{all_consuming, opt},
+    multi::fold_many0,
+    sequence::{delimited, pair, preceded, tuple},
+    IResult,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::config::Config;
 use crate::dice::{
-    parse::{parse_open, parse_with_bonus},
-    result::Res,
-    Rollable,
+    error::{DiceError, ParseError},
+    expr::{parse_expr_with, Expr},
+    internal::Roller,
+    parse::{check_trailing, parse_open100, parse_open_bonus},
+    pool::{Pool, DIFFICULTY, MAX_POOL, SIDES},
+    result::{Res, Special},
+    rules::{parse_rules, Rules},
+    DiceSet,
 };
+use crate::engine::error::EngineError;
+use crate::messages::{Messages, Msg};
 
 /// This describe the core commands in the rolling dice engine.
 /// Everything above will be reduced (aka compiled) into executing
@@ -28,6 +49,20 @@ pub enum Cmd {
     Invalid,
     /// Roll an open dice
     Open,
+    /// Roll a Rolemaster open-ended D100
+    Open100,
+    /// Flip N coins
+    Flip,
+    /// Pick one of the arguments
+    Choose,
+    /// Roll 3D6 under a skill, GURPS-style
+    RollUnder,
+    /// Count successes of d10, old World of Darkness
+    Pool,
+    /// Action die against two challenge dice, Ironsworn
+    Action,
+    /// Highest of a pool of d6, Forged in the Dark
+    Forged,
 }
 
 impl From<&str> for Cmd {
@@ -37,42 +72,391 @@ impl From<&str> for Cmd {
         match value {
             "dice" => Cmd::Dice,
             "open" => Cmd::Open,
+            "open100" => Cmd::Open100,
+            "flip" => Cmd::Flip,
+            "choose" => Cmd::Choose,
+            "gurps" => Cmd::RollUnder,
+            "wod" => Cmd::Pool,
+            "ironsworn" => Cmd::Action,
+            "bitd" => Cmd::Forged,
             _ => Cmd::Invalid,
         }
     }
 }
 
 impl Cmd {
-    pub fn execute(&self, input: &str) -> Result<Res> {
+    /// Does it take dices, i.e. is the default dice used when there is no argument?
+    ///
+    pub fn takes_dices(&self) -> bool {
+        matches!(self, Cmd::Dice | Cmd::Open)
+    }
+}
+
+impl Cmd {
+    /// Execute with the default configuration
+    ///
+    pub fn execute(&self, input: &str) -> Result<Res, EngineError> {
+        self.execute_with(input, &Config::default())
+    }
+
+    /// Execute using `cfg` for the default rules (which can be overridden by options after
+    /// the dices), allowed dice sizes, weighted and symbol dices and the kind of roller
+    ///
+    pub fn execute_with(&self, input: &str, cfg: &Config) -> Result<Res, EngineError> {
         trace!("cmd::execute");
-        let r = match self {
-            Cmd::Dice => preceded(space0, parse_with_bonus)(input),
-            Cmd::Open => preceded(space0, parse_open)(input),
-            _ => return Err(anyhow!("invalid Cmd")),
-        };
-        let ds = match r {
-            Ok((_input, ds)) => {
-                debug!("{:?}", ds);
-                ds
+        cfg.scope(|rng| self.execute_rng(input, cfg, rng))
+    }
+
+    /// Check the dices and options like `execute_with` without rolling anything, the
+    /// arguments of other commands are always fine
+    ///
+    pub fn check_with(&self, input: &str, cfg: &Config) -> Result<(), EngineError> {
+        match self {
+            Cmd::Dice => parse_dice_args(input, cfg).map(|_| ()),
+            Cmd::Open | Cmd::Open100 => self.parse_open_args(input, cfg).map(|_| ()),
+            Cmd::RollUnder => parse_skill(input).map(|_| ()),
+            Cmd::Pool => parse_pool(input).map(|_| ()),
+            Cmd::Action => parse_action(input).map(|_| ()),
+            Cmd::Forged => parse_forged(input).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Same as `execute_with` using `rng` as the source of randomness
+    ///
+    fn execute_rng(&self, input: &str, cfg: &Config, rng: &mut Roller) -> Result<Res, EngineError> {
+        match self {
+            Cmd::Dice => {
+                let (expr, rules) = parse_dice_args(input, cfg)?;
+                Ok(rules.apply(|| expr.eval_rounded(rng, rules.rounding))?)
             }
-            Err(e) => {
-                error!("{:?}", e.to_string());
-                return Err(anyhow!("error parsing input"));
+            Cmd::Open | Cmd::Open100 => {
+                let (ds, rules) = self.parse_open_args(input, cfg)?;
+                Ok(rules.roll_with(&ds, rng))
             }
+            Cmd::Flip => flip(input, rng),
+            Cmd::Choose => choose(input, rng),
+            Cmd::RollUnder => roll_under(input, rng, &cfg.messages()),
+            Cmd::Pool => Ok(parse_pool(input)?.roll_localized(rng, &cfg.messages())),
+            Cmd::Action => action_roll(input, rng, &cfg.messages()),
+            Cmd::Forged => forged_roll(input, rng, &cfg.messages()),
+            _ => Err(EngineError::InvalidCmd),
+        }
+    }
+
+    /// Open-ended dices and options of `open` or `open100`
+    ///
+    fn parse_open_args(&self, input: &str, cfg: &Config) -> Result<(DiceSet, Rules), EngineError> {
+        let opts = |input| parse_rules(input, cfg.rules);
+        let parse: fn(&str) -> IResult<&str, DiceSet> = match self {
+            Cmd::Open => parse_open_bonus,
+            _ => parse_open100,
+        };
+        let (ds, rules) = match preceded(space0, pair(parse, opts))(input) {
+            Ok((rest, r)) => strict(cfg, input, rest, r)?,
+            Err(e) => return Err(parse_error(input, e)),
         };
-        Ok(ds.roll())
+        debug!("{ds} with {rules:?}");
+        cfg.dice_sizes.check(&ds)?;
+        Ok((ds, rules))
+    }
+}
+
+/// Expression and options of `dice`
+///
+fn parse_dice_args(input: &str, cfg: &Config) -> Result<(Expr, Rules), EngineError> {
+    let opts = |input| parse_rules(input, cfg.rules);
+    let expr = |input| parse_expr_with(input, &cfg.defined());
+    let (expr, rules) = match preceded(space0, pair(expr, opts))(input) {
+        Ok((rest, r)) => strict(cfg, input, rest, r)?,
+        Err(e) => return Err(parse_error(input, e)),
+    };
+    debug!("{expr} with {rules:?}");
+    expr.validate(&cfg.dice_sizes)?;
+    Ok((expr, rules))
+}
+
+/// Most coins flipped at once
+pub const MAX_FLIPS: usize = 1_000;
+
+/// Flip `N` coins (1 by default, at most `MAX_FLIPS`), the total being the number of heads
+///
+fn flip(input: &str, rng: &mut Roller) -> Result<Res, EngineError> {
+    let n = match input.trim() {
+        "" => 1,
+        n => n
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=MAX_FLIPS).contains(n))
+            .ok_or_else(|| EngineError::Usage("flip [N]".to_string()))?,
+    };
+    let heads = (0..n).filter(|_| rng.roll(2) == 1).count();
+    debug!("flip {n}: {heads} heads");
+
+    let mut res = Res::new();
+    res.sum = heads as isize;
+    [("heads", heads), ("tails", n - heads)]
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .for_each(|(side, count)| {
+            res.symbols.insert(side.to_string(), count);
+        });
+    Ok(res)
+}
+
+/// Pick one of the space-separated options, the total being its position
+///
+fn choose(input: &str, rng: &mut Roller) -> Result<Res, EngineError> {
+    let options = input.split_whitespace().collect::<Vec<_>>();
+    if options.is_empty() {
+        return Err(EngineError::Usage("choose <option>...".to_string()));
+    }
+    let i = rng.roll(options.len());
+    debug!("choose {options:?}: {i}");
+
+    let mut res = Res::new();
+    res.sum = i as isize;
+    res.symbols.insert(options[i - 1].to_string(), 1);
+    Ok(res)
+}
+
+/// Roll 3D6 against a skill with its modifiers (`12`, `12 -2`), GURPS-style: the total is
+/// the roll, the note tells by how much it succeeded or failed and critical results are
+/// flagged `Natural` or `Fumble`
+///
+fn roll_under(input: &str, rng: &mut Roller, m: &Messages) -> Result<Res, EngineError> {
+    let skill = parse_skill(input)?;
+
+    let mut res = Res::new();
+    (0..3).for_each(|_| {
+        res.append(rng.roll(6));
+    });
+    let (flag, outcome) = success(res.sum, skill);
+    debug!("gurps {skill}: {} is {outcome:?}", res.sum);
+
+    let margin = (skill - res.sum).abs();
+    res.note = Some(m.fill(Msg::Margin, &[m.get(outcome), &margin.to_string()]));
+    res.set(flag);
+    Ok(res)
+}
+
+/// Outcome of a roll of 3D6 against `skill` (Basic Set p. 348): 3 and 4 always succeed, 17 and
+/// 18 always fail and critical results depend on the skill
+///
+fn success(roll: isize, skill: isize) -> (Special, Msg) {
+    let critical = roll <= 4 || (roll == 5 && skill >= 15) || (roll == 6 && skill >= 16);
+    let fumble = roll == 18 || (roll == 17 && skill <= 15) || roll >= skill + 10;
+    match roll {
+        _ if critical => (Special::Natural, Msg::CriticalSuccess),
+        _ if fumble => (Special::Fumble, Msg::CriticalFailure),
+        17.. => (Special::None, Msg::Failure),
+        r if r <= skill => (Special::None, Msg::Success),
+        _ => (Special::None, Msg::Failure),
+    }
+}
+
+/// Highest action score of Ironsworn
+const MAX_ACTION: isize = 10;
+
+/// Roll the action die with its modifiers against two challenge dice, Ironsworn-style: the
+/// total is the action score (at most 10), beating both challenge dice is a strong hit, one a
+/// weak hit.  Challenge dice with the same value are a match, flagged `Natural` on a strong hit
+/// and `Fumble` on a miss.
+///
+fn action_roll(input: &str, rng: &mut Roller, m: &Messages) -> Result<Res, EngineError> {
+    let bonus = parse_action(input)?;
+
+    let action = rng.roll(6);
+    let challenge = [rng.roll(10), rng.roll(10)];
+    let score = (action as isize + bonus).min(MAX_ACTION);
+    let (flag, outcome) = action_outcome(score, challenge, m);
+    debug!("ironsworn {score} vs {challenge:?}: {outcome}");
+
+    let mut res = Res::new();
+    [action, challenge[0], challenge[1]]
+        .into_iter()
+        .for_each(|d| {
+            res.list.push(d);
+        });
+    res.sum = score;
+    res.bonus = score - action as isize;
+    res.note = Some(m.fill(
+        Msg::Versus,
+        &[
+            &outcome,
+            &challenge[0].to_string(),
+            &challenge[1].to_string(),
+        ],
+    ));
+    res.set(flag);
+    Ok(res)
+}
+
+/// Outcome of an action score against the two challenge dice, which it has to beat
+///
+fn action_outcome(score: isize, challenge: [usize; 2], m: &Messages) -> (Special, String) {
+    let beaten = challenge.iter().filter(|c| score > **c as isize).count();
+    let matched = challenge[0] == challenge[1];
+    let (flag, outcome) = match (beaten, matched) {
+        (2, true) => (Special::Natural, Msg::StrongHit),
+        (2, false) => (Special::None, Msg::StrongHit),
+        (1, _) => (Special::None, Msg::WeakHit),
+        (_, true) => (Special::Fumble, Msg::ActionMiss),
+        (_, false) => (Special::None, Msg::ActionMiss),
+    };
+    match matched {
+        true => (flag, m.fill(Msg::WithMatch, &[m.get(outcome)])),
+        false => (flag, m.get(outcome).to_string()),
+    }
+}
+
+/// Modifiers like `+1 -3`, added up
+///
+pub(crate) fn modifiers(input: &str) -> IResult<&str, i64> {
+    fn modifier(input: &str) -> IResult<&str, (char, i64)> {
+        preceded(space0, pair(one_of("+-"), preceded(space0, parse_i64)))(input)
     }
+
+    fold_many0(
+        modifier,
+        || 0,
+        |acc, (sign, n)| if sign == '-' { acc - n } else { acc + n },
+    )(input)
+}
+
+/// Skill of `gurps`, a number followed by modifiers like `12 +1 -3`
+///
+fn parse_skill(input: &str) -> Result<isize, EngineError> {
+    match all_consuming(delimited(space0, pair(parse_i64, modifiers), space0))(input) {
+        Ok((_, (skill, modifiers))) => Ok((skill + modifiers) as isize),
+        Err(_) => Err(EngineError::Usage("gurps <skill> [+-N]...".to_string())),
+    }
+}
+
+/// Modifiers of `ironsworn` added to the action die, `+2` or `+3 -1`, none at all being 0
+///
+fn parse_action(input: &str) -> Result<isize, EngineError> {
+    match all_consuming(delimited(space0, modifiers, space0))(input) {
+        Ok((_, n)) => Ok(n as isize),
+        Err(_) => Err(EngineError::Usage("ironsworn [+-N]...".to_string())),
+    }
+}
+
+/// Pool of `wod`, `7d10 diff 6` or `7`, the difficulty being 6 by default
+///
+fn parse_pool(input: &str) -> Result<Pool, EngineError> {
+    let usage = || EngineError::Usage("wod <N>[d10] [diff <2-10>]".to_string());
+
+    let d10 = tag_no_case("d10");
+    let diff = preceded(tuple((space1, tag_no_case("diff"), space0)), parse_u64);
+    let (_, (count, _, difficulty)) = all_consuming(delimited(
+        space0,
+        tuple((parse_u64, opt(d10), opt(diff))),
+        space0,
+    ))(input)
+    .map_err(|_: nom::Err<nom::error::Error<&str>>| usage())?;
+
+    let pool = Pool::new(
+        count as usize,
+        difficulty.map_or(DIFFICULTY, |d| d as usize),
+    );
+    match (pool.count, pool.difficulty) {
+        (1..=MAX_POOL, 2..=SIDES) => Ok(pool),
+        _ => Err(usage()),
+    }
+}
+
+/// Roll a Forged in the Dark pool of d6 and keep the highest: 6 is a full success, 4 or 5 a
+/// partial one and anything lower a failure, several 6s being a critical flagged `Natural`.
+/// A pool of 0 rolls two dices and keeps the lowest, without any critical.
+///
+fn forged_roll(input: &str, rng: &mut Roller, m: &Messages) -> Result<Res, EngineError> {
+    let count = parse_forged(input)?;
+
+    let dices = (0..if count == 0 { 2 } else { count })
+        .map(|_| rng.roll(6))
+        .collect::<Vec<_>>();
+    Ok(forged_outcome(&dices, count == 0, m))
+}
+
+/// Result of the dices of a Forged in the Dark pool, `zero` when the pool was empty
+///
+fn forged_outcome(dices: &[usize], zero: bool, m: &Messages) -> Res {
+    let kept = match zero {
+        true => dices.iter().min(),
+        false => dices.iter().max(),
+    }
+    .copied()
+    .unwrap_or_default();
+    let sixes = dices.iter().filter(|d| **d == 6).count();
+
+    let mut res = Res::new();
+    dices.iter().for_each(|d| {
+        res.list.push(*d);
+    });
+    res.sum = kept as isize;
+    let note = match kept {
+        6 if sixes > 1 && !zero => {
+            res.set(Special::Natural);
+            m.get(Msg::Critical)
+        }
+        6 => m.get(Msg::FullSuccess),
+        4 | 5 => m.get(Msg::PartialSuccess),
+        _ => m.get(Msg::ForgedFailure),
+    };
+    debug!("bitd {dices:?}, zero pool {zero}: {note}");
+    res.note = Some(match zero {
+        true => m.fill(Msg::ZeroPool, &[note]),
+        false => note.to_string(),
+    });
+    res
+}
+
+/// Size of the pool of `bitd`, 0 to roll two dices and keep the lowest
+///
+fn parse_forged(input: &str) -> Result<usize, EngineError> {
+    let usage = || EngineError::Usage("bitd <N>".to_string());
+
+    let (_, count) = all_consuming(delimited(space0, parse_u64, space0))(input)
+        .map_err(|_: nom::Err<nom::error::Error<&str>>| usage())?;
+    match count as usize {
+        n @ 0..=MAX_POOL => Ok(n),
+        _ => Err(usage()),
+    }
+}
+
+/// In strict mode, refuse anything left after the dices and the options
+///
+fn strict<T>(cfg: &Config, input: &str, rest: &str, parsed: T) -> Result<T, DiceError> {
+    if cfg.strict {
+        check_trailing(input, rest)?;
+    }
+    Ok(parsed)
+}
+
+/// Turn the nom error into our own, with position and what was expected
+///
+fn parse_error(input: &str, e: nom::Err<nom::error::Error<&str>>) -> EngineError {
+    trace!("{e:?}");
+    DiceError::from(ParseError::new(input, &e)).into()
 }
 
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
 
+    use crate::dice::error::Expected;
+    use crate::dice::policy::SizePolicy;
+
     use super::*;
 
     #[rstest]
     #[case("dice", Cmd::Dice)]
     #[case("open", Cmd::Open)]
+    #[case("open100", Cmd::Open100)]
+    #[case("flip", Cmd::Flip)]
+    #[case("choose", Cmd::Choose)]
+    #[case("gurps", Cmd::RollUnder)]
     #[case("doce", Cmd::Invalid)]
     #[case("doom", Cmd::Invalid)]
     #[case("whatever", Cmd::Invalid)]
@@ -85,10 +469,428 @@ mod tests {
     #[case("dice", "2d4", Cmd::Dice)]
     #[case("open", "d4", Cmd::Open)]
     #[case("open", "D4", Cmd::Open)]
+    #[case("dice", "d20 adv crit19", Cmd::Dice)]
+    #[case("dice", "8d6 !norules", Cmd::Dice)]
+    #[case("open", "d6 dis", Cmd::Open)]
+    #[case("open", "3d6 +2", Cmd::Open)]
+    #[case("open100", "", Cmd::Open100)]
+    #[case("open100", "+15", Cmd::Open100)]
+    #[case("dice", "(2d6+3)*2", Cmd::Dice)]
+    #[case("dice", "d20 + 2d4 - 1 adv", Cmd::Dice)]
+    #[case("flip", "", Cmd::Flip)]
+    #[case("flip", " 3", Cmd::Flip)]
+    #[case("choose", " left right", Cmd::Choose)]
+    #[case("gurps", " 12 -2", Cmd::RollUnder)]
+    #[case("dice", "d6 /2 up", Cmd::Dice)]
     fn test_cmd_execute(#[case] cmd: &str, #[case] arg: &str, #[case] ds: Cmd) {
         let d = Cmd::from(cmd);
         assert_eq!(ds, d);
         let res = d.execute(arg);
         assert!(res.is_ok());
     }
+
+    #[rstest]
+    #[case("7 / 2", 3)]
+    #[case("7 / 2 up", 4)]
+    #[case("7/2 down", 3)]
+    #[case("-7 / 2 down", -4)]
+    #[case("(5 + 3) / 3 near", 3)]
+    #[case("7 / 2 up !norules", 3)]
+    fn test_cmd_execute_rounding(#[case] arg: &str, #[case] want: isize) {
+        assert_eq!(want, Cmd::Dice.execute(arg).unwrap().sum);
+    }
+
+    #[test]
+    fn test_cmd_execute_count() {
+        assert_eq!(255, Cmd::Dice.execute("255d2").unwrap().list.len());
+        assert!(Cmd::Dice.execute("1000 + d2").unwrap().sum > 1000);
+        assert_eq!(
+            "dice count too large (max 255)",
+            Cmd::Dice.execute("1000d1000").unwrap_err().to_string()
+        );
+    }
+
+    fn parse(input: &str, offset: usize, expected: Expected) -> EngineError {
+        EngineError::Dice(DiceError::Parse(ParseError {
+            input: input.to_string(),
+            offset,
+            expected,
+        }))
+    }
+
+    #[rstest]
+    #[case(Cmd::Dice, "foo", parse("foo", 0, Expected::Dice))]
+    #[case(Cmd::Open, " 3", parse("3", 1, Expected::Dice))]
+    #[case(Cmd::Invalid, "D6", EngineError::InvalidCmd)]
+    #[case(Cmd::Dice, "d6 / 0", EngineError::Dice(DiceError::DivisionByZero))]
+    #[case(Cmd::Dice, "d6 / 0 up", EngineError::Dice(DiceError::DivisionByZero))]
+    #[case(
+        Cmd::Dice,
+        "99999*99999*99999*99999*99999",
+        EngineError::Dice(DiceError::Overflow)
+    )]
+    #[case(Cmd::Dice, "2d6dl2", parse("2d6dl2", 0, Expected::Keep))]
+    #[case(Cmd::Dice, " 3d", parse("3d", 2, Expected::Size))]
+    #[case(Cmd::Dice, "(2d6 + 1", parse("(2d6 + 1", 8, Expected::Paren))]
+    #[case(Cmd::Dice, "3d6 + ", parse("3d6 +", 5, Expected::Operand))]
+    #[case(Cmd::Dice, "2 * -x", parse("2 * -x", 5, Expected::Operand))]
+    #[case(Cmd::Dice, "dW:loaded", parse("dW:loaded", 3, Expected::Weighted))]
+    #[case(Cmd::Dice, "3dx", parse("3dx", 2, Expected::Size))]
+    #[case(Cmd::Dice, "1000d1000", EngineError::Dice(DiceError::TooManyDices))]
+    #[case(
+        Cmd::Dice,
+        "d20 + 256d6kh1",
+        EngineError::Dice(DiceError::TooManyDices)
+    )]
+    #[case(Cmd::Open, "300d6 +1", EngineError::Dice(DiceError::TooManyDices))]
+    fn test_cmd_execute_error(#[case] cmd: Cmd, #[case] arg: &str, #[case] err: EngineError) {
+        assert_eq!(Err(err), cmd.execute(arg));
+    }
+
+    #[test]
+    fn test_cmd_execute_physical() {
+        let cfg = Config {
+            dice_sizes: SizePolicy::Physical,
+            ..Default::default()
+        };
+
+        assert!(Cmd::Dice.execute_with("3D6", &cfg).is_ok());
+        assert!(matches!(
+            Cmd::Dice.execute_with("D7", &cfg),
+            Err(EngineError::Dice(DiceError::InvalidSize { size: 7, .. }))
+        ));
+        assert!(Cmd::Dice.execute("D7").is_ok());
+        assert!(Cmd::Dice.execute_with("d6 + 2d7", &cfg).is_err());
+    }
+
+    #[rstest]
+    #[case(Cmd::Dice, "3d6 +2 adv", true)]
+    #[case(Cmd::Dice, " dW:loaded + 1", true)]
+    #[case(Cmd::Dice, "d6 / 0", true)]
+    #[case(Cmd::Dice, "3d", false)]
+    #[case(Cmd::Dice, "D7", false)]
+    #[case(Cmd::Dice, "dW:unknown", false)]
+    #[case(Cmd::Open, "d6 +1", true)]
+    #[case(Cmd::Open, "(d6)", false)]
+    #[case(Cmd::Flip, "two", true)]
+    #[case(Cmd::RollUnder, "14+1", true)]
+    #[case(Cmd::RollUnder, "d6", false)]
+    fn test_cmd_check(#[case] cmd: Cmd, #[case] arg: &str, #[case] ok: bool) {
+        let cfg =
+            Config::parse("dice_sizes = \"physical\"\n[weighted]\nloaded = [[6, 1]]").unwrap();
+
+        assert_eq!(ok, cmd.check_with(arg, &cfg).is_ok());
+    }
+
+    #[test]
+    fn test_cmd_execute_weighted() {
+        let cfg = Config::parse(
+            "dice_sizes = \"physical\"\n[weighted]\nloaded = [[6, 1]]\ncoin = [[0, 1], [1, 1]]",
+        )
+        .unwrap();
+
+        let res = Cmd::Dice.execute_with("3dW:loaded + 1", &cfg).unwrap();
+        assert_eq!(vec![6, 6, 6], res.list);
+        assert_eq!(19, res.sum);
+
+        let res = Cmd::Dice.execute_with("10DW:Coin", &cfg).unwrap();
+        assert!(res.list.iter().all(|&f| f <= 1));
+        assert!(Cmd::Dice
+            .execute_with("dW:loaded * 2 crit 12", &cfg)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_cmd_execute_symbolic() {
+        let cfg = Config::parse(
+            "dice_sizes = \"physical\"\n[symbolic]\nattack = [\"hit\"]\nboost = [\"Success advantage\"]",
+        )
+        .unwrap();
+
+        let res = Cmd::Dice
+            .execute_with("3dAttack + dBoost + 2", &cfg)
+            .unwrap();
+        assert!(res.list.is_empty());
+        assert_eq!(2, res.sum);
+        assert_eq!("1 advantage, 3 hit, 1 success", res.format_symbols());
+        assert!(Cmd::Dice.execute_with("2D6 + 2dattack", &cfg).is_ok());
+        assert!(Cmd::Dice.execute("3dAttack").is_err());
+    }
+
+    #[test]
+    fn test_cmd_execute_flip() {
+        let res = Cmd::Flip.execute("100").unwrap();
+        let heads = res.symbols.get("heads").copied().unwrap_or_default();
+        let tails = res.symbols.get("tails").copied().unwrap_or_default();
+
+        assert_eq!(100, heads + tails);
+        assert_eq!(heads as isize, res.sum);
+        assert!(res.list.is_empty());
+        assert_eq!(1, Cmd::Flip.execute("").unwrap().symbols.len());
+    }
+
+    #[rstest]
+    #[case(" 0")]
+    #[case(" heads")]
+    #[case(" 1001")]
+    #[case(" 18446744073709551615")]
+    fn test_cmd_execute_flip_usage(#[case] input: &str) {
+        assert_eq!(
+            Err(EngineError::Usage("flip [N]".to_string())),
+            Cmd::Flip.execute(input)
+        );
+    }
+
+    #[test]
+    fn test_cmd_execute_choose() {
+        let options = ["left", "right", "straight"];
+        let res = Cmd::Choose.execute(" left right straight").unwrap();
+        let (choice, n) = res.symbols.iter().next().unwrap();
+
+        assert_eq!(1, *n);
+        assert_eq!(options[res.sum as usize - 1], choice);
+        assert_eq!(1, Cmd::Choose.execute("wait").unwrap().sum);
+    }
+
+    #[rstest]
+    #[case(Cmd::Flip, "0", "flip [N]")]
+    #[case(Cmd::Flip, "two", "flip [N]")]
+    #[case(Cmd::Flip, "-1", "flip [N]")]
+    #[case(Cmd::Choose, " ", "choose <option>...")]
+    #[case(Cmd::RollUnder, "", "gurps <skill> [+-N]...")]
+    #[case(Cmd::RollUnder, " 12 +", "gurps <skill> [+-N]...")]
+    #[case(Cmd::RollUnder, " 3d6", "gurps <skill> [+-N]...")]
+    fn test_cmd_execute_usage(#[case] cmd: Cmd, #[case] arg: &str, #[case] usage: &str) {
+        assert_eq!(Err(EngineError::Usage(usage.to_string())), cmd.execute(arg));
+    }
+
+    #[test]
+    fn test_cmd_execute_roll_under() {
+        let res = Cmd::RollUnder.execute(" 10 +3 -1").unwrap();
+        let note = res.note.unwrap();
+
+        assert_eq!(3, res.list.len());
+        assert!((3..=18).contains(&res.sum));
+        assert!(
+            note.ends_with(&format!(" by {}", (12 - res.sum).abs())),
+            "{note}"
+        );
+    }
+
+    #[rstest]
+    #[case(" 12", 12)]
+    #[case("12 -2", 10)]
+    #[case(" 14+1 - 3 ", 12)]
+    #[case("-3", -3)]
+    fn test_parse_skill(#[case] input: &str, #[case] skill: isize) {
+        assert_eq!(Ok(skill), parse_skill(input));
+    }
+
+    #[rstest]
+    #[case(10, 12, Special::None, "success")]
+    #[case(12, 12, Special::None, "success")]
+    #[case(13, 12, Special::None, "failure")]
+    #[case(4, 3, Special::Natural, "critical success")]
+    #[case(5, 15, Special::Natural, "critical success")]
+    #[case(5, 14, Special::None, "success")]
+    #[case(6, 16, Special::Natural, "critical success")]
+    #[case(6, 15, Special::None, "success")]
+    #[case(17, 16, Special::None, "failure")]
+    #[case(17, 15, Special::Fumble, "critical failure")]
+    #[case(18, 20, Special::Fumble, "critical failure")]
+    #[case(15, 5, Special::Fumble, "critical failure")]
+    #[case(14, 5, Special::None, "failure")]
+    fn test_success(
+        #[case] roll: isize,
+        #[case] skill: isize,
+        #[case] flag: Special,
+        #[case] outcome: &str,
+    ) {
+        let (got, msg) = success(roll, skill);
+        assert_eq!((flag, outcome), (got, Messages::default().get(msg)));
+    }
+
+    #[test]
+    fn test_cmd_roll_under_french() {
+        let cfg = Config {
+            locale: Some("fr_FR.UTF-8".to_string()),
+            ..Config::default()
+        };
+        let res = Cmd::RollUnder.execute_with(" 10", &cfg).unwrap();
+        let note = res.note.unwrap();
+
+        assert!(
+            ["réussite", "échec"].iter().any(|o| note.contains(o)),
+            "{note}"
+        );
+        assert!(note.contains(" de "), "{note}");
+    }
+
+    #[rstest]
+    #[case(7, [3, 6], Special::None, "strong hit")]
+    #[case(7, [4, 4], Special::Natural, "strong hit with a match")]
+    #[case(6, [6, 2], Special::None, "weak hit")]
+    #[case(3, [9, 9], Special::Fumble, "miss with a match")]
+    #[case(5, [5, 8], Special::None, "miss")]
+    #[case(10, [10, 10], Special::Fumble, "miss with a match")]
+    fn test_action_outcome(
+        #[case] score: isize,
+        #[case] challenge: [usize; 2],
+        #[case] flag: Special,
+        #[case] outcome: &str,
+    ) {
+        assert_eq!(
+            (flag, outcome.to_string()),
+            action_outcome(score, challenge, &Messages::default())
+        );
+    }
+
+    #[rstest]
+    #[case(7, [3, 6], "réussite forte")]
+    #[case(3, [9, 9], "échec avec une égalité")]
+    fn test_action_outcome_french(
+        #[case] score: isize,
+        #[case] challenge: [usize; 2],
+        #[case] outcome: &str,
+    ) {
+        let m = Messages::new("fr_FR.UTF-8");
+        assert_eq!(outcome, action_outcome(score, challenge, &m).1);
+    }
+
+    #[rstest]
+    #[case("", Ok(0))]
+    #[case(" +2", Ok(2))]
+    #[case("+3 -1 ", Ok(2))]
+    #[case(" 2", Err(EngineError::Usage("ironsworn [+-N]...".to_string())))]
+    #[case("+x", Err(EngineError::Usage("ironsworn [+-N]...".to_string())))]
+    fn test_parse_action(#[case] input: &str, #[case] want: Result<isize, EngineError>) {
+        assert_eq!(want, parse_action(input));
+    }
+
+    #[test]
+    fn test_cmd_action() {
+        for _ in 0..50 {
+            let res = Cmd::Action.execute(" +6").unwrap();
+
+            assert_eq!(3, res.list.len());
+            assert!((7..=MAX_ACTION).contains(&res.sum), "{res:?}");
+            assert_eq!(res.list[0] as isize + res.bonus, res.sum);
+            assert!(res.note.unwrap().contains(" vs "));
+        }
+    }
+
+    #[rstest]
+    #[case(&[2, 6, 4], false, 6, Special::None, "full success")]
+    #[case(&[6, 3, 6], false, 6, Special::Natural, "critical")]
+    #[case(&[5, 1], false, 5, Special::None, "partial success")]
+    #[case(&[4], false, 4, Special::None, "partial success")]
+    #[case(&[3, 2, 1], false, 3, Special::None, "failure")]
+    #[case(&[6, 4], true, 4, Special::None, "partial success, zero pool")]
+    #[case(&[6, 6], true, 6, Special::None, "full success, zero pool")]
+    #[case(&[6, 2], true, 2, Special::None, "failure, zero pool")]
+    fn test_forged_outcome(
+        #[case] dices: &[usize],
+        #[case] zero: bool,
+        #[case] sum: isize,
+        #[case] flag: Special,
+        #[case] note: &str,
+    ) {
+        let res = forged_outcome(dices, zero, &Messages::default());
+
+        assert_eq!(sum, res.sum);
+        assert_eq!(flag, res.flag);
+        assert_eq!(Some(note), res.note.as_deref());
+        assert_eq!(dices, &res.list[..]);
+    }
+
+    #[rstest]
+    #[case(&[6, 3, 6], false, "critique")]
+    #[case(&[6, 4], true, "réussite partielle, réserve vide")]
+    fn test_forged_outcome_french(#[case] dices: &[usize], #[case] zero: bool, #[case] note: &str) {
+        let res = forged_outcome(dices, zero, &Messages::new("fr_FR.UTF-8"));
+        assert_eq!(Some(note), res.note.as_deref());
+    }
+
+    #[rstest]
+    #[case(" 3", Ok(3))]
+    #[case("0 ", Ok(0))]
+    #[case("", Err(EngineError::Usage("bitd <N>".to_string())))]
+    #[case(" 3d6", Err(EngineError::Usage("bitd <N>".to_string())))]
+    #[case(" 101", Err(EngineError::Usage("bitd <N>".to_string())))]
+    fn test_parse_forged(#[case] input: &str, #[case] want: Result<usize, EngineError>) {
+        assert_eq!(want, parse_forged(input));
+    }
+
+    #[rstest]
+    #[case(" 3", 3)]
+    #[case(" 1", 1)]
+    #[case(" 0", 2)]
+    fn test_cmd_forged(#[case] input: &str, #[case] dices: usize) {
+        let res = Cmd::Forged.execute(input).unwrap();
+
+        assert_eq!(dices, res.list.len());
+        assert!((1..=6).contains(&res.sum));
+    }
+
+    #[rstest]
+    #[case(" 7d10 diff 6", Pool::new(7, 6))]
+    #[case("7D10", Pool::new(7, 6))]
+    #[case(" 5 diff 8 ", Pool::new(5, 8))]
+    #[case("3d10 diff10", Pool::new(3, 10))]
+    #[case("5 DIFF 7", Pool::new(5, 7))]
+    fn test_parse_pool(#[case] input: &str, #[case] pool: Pool) {
+        assert_eq!(Ok(pool), parse_pool(input));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("0d10")]
+    #[case("7d6")]
+    #[case("7d10 diff 11")]
+    #[case("7d10 diff 1")]
+    #[case("7d10diff 6")]
+    #[case("101")]
+    fn test_parse_pool_error(#[case] input: &str) {
+        assert_eq!(
+            Err(EngineError::Usage("wod <N>[d10] [diff <2-10>]".to_string())),
+            parse_pool(input)
+        );
+    }
+
+    #[test]
+    fn test_cmd_pool() {
+        let res = Cmd::Pool.execute(" 7d10 diff 6").unwrap();
+
+        assert!(res.list.len() >= 7);
+        assert!(res.list.iter().all(|d| (1..=10).contains(d)));
+        assert!(res.note.is_some());
+        assert_eq!(Ok(()), Cmd::Pool.check_with("7d10", &Config::default()));
+        assert!(Cmd::Pool.check_with("7d6", &Config::default()).is_err());
+    }
+
+    #[rstest]
+    #[case(Cmd::Dice, " 3d6 oops", "oops", 4)]
+    #[case(Cmd::Dice, "d20 adv =2", "=2", 8)]
+    #[case(Cmd::Open, "2D6 +1 x ", "x", 7)]
+    fn test_cmd_execute_strict(
+        #[case] cmd: Cmd,
+        #[case] arg: &str,
+        #[case] rest: &str,
+        #[case] col: usize,
+    ) {
+        let lenient = Config {
+            strict: false,
+            ..Default::default()
+        };
+
+        assert!(cmd.execute_with(arg, &lenient).is_ok());
+        assert_eq!(
+            Err(EngineError::Dice(DiceError::Trailing {
+                input: arg.trim().to_string(),
+                rest: rest.to_string(),
+                col,
+            })),
+            cmd.execute(arg)
+        );
+    }
 }
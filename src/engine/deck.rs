@@ -0,0 +1,225 @@
+//! Deck of cards, for games mixing cards with dices (Savage Worlds initiative, ...).
+//!
+//! The engine keeps one deck: `deck new standard52` (or `standard54` with two jokers) creates
+//! and shuffles it, `deck draw [N]` draws cards until it is empty and `deck shuffle` puts the
+//! drawn cards back.  Shuffling uses the random generator of the configuration.
+//!
+//! ```
+//! use dices_rs::engine::deck::Deck;
+//!
+//! let mut d = Deck::new("standard54").unwrap();
+//! d.shuffle(&mut rand::thread_rng());
+//!
+//! assert_eq!(3, d.draw(3).unwrap().len());
+//! assert_eq!(51, d.left());
+//! ```
+
+use log::trace;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+
+use crate::engine::error::EngineError;
+use crate::engine::Engine;
+
+/// Kinds of decks known to `Deck::new`
+pub const KINDS: [&str; 2] = ["standard52", "standard54"];
+
+const RANKS: [&str; 13] = [
+    "2", "3", "4", "5", "6", "7", "8", "9", "10", "J", "Q", "K", "A",
+];
+const SUITS: [&str; 4] = ["♣", "♦", "♥", "♠"];
+const JOKER: &str = "Joker";
+
+/// A deck with the cards left to draw and those already drawn
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Deck {
+    /// One of `KINDS`
+    pub kind: String,
+    /// Next card is the last one
+    cards: Vec<String>,
+    /// Drawn since the last shuffle
+    drawn: Vec<String>,
+}
+
+impl Deck {
+    /// A new deck of the given kind, in order
+    ///
+    pub fn new(kind: &str) -> Result<Self, EngineError> {
+        let jokers = match kind {
+            "standard52" => 0,
+            "standard54" => 2,
+            _ => {
+                return Err(EngineError::Deck(format!(
+                    "unknown kind {kind}, use one of {}",
+                    KINDS.join(", ")
+                )))
+            }
+        };
+        let cards = SUITS
+            .iter()
+            .flat_map(|s| RANKS.iter().map(move |r| format!("{r}{s}")))
+            .chain((0..jokers).map(|_| JOKER.to_string()))
+            .rev()
+            .collect();
+        Ok(Deck {
+            kind: kind.to_string(),
+            cards,
+            drawn: vec![],
+        })
+    }
+
+    /// Put the drawn cards back and shuffle everything
+    ///
+    pub fn shuffle(&mut self, rng: &mut dyn RngCore) {
+        self.cards.append(&mut self.drawn);
+        self.cards.shuffle(rng);
+    }
+
+    /// Draw `n` cards, if there are enough left
+    ///
+    pub fn draw(&mut self, n: usize) -> Result<Vec<String>, EngineError> {
+        if n > self.cards.len() {
+            return Err(EngineError::Deck(format!(
+                "only {} cards left, use deck shuffle",
+                self.cards.len()
+            )));
+        }
+        let cards = self.cards.split_off(self.cards.len() - n);
+        let cards = cards.into_iter().rev().collect::<Vec<_>>();
+        self.drawn.extend(cards.iter().cloned());
+        Ok(cards)
+    }
+
+    /// Cards left to draw
+    ///
+    pub fn left(&self) -> usize {
+        self.cards.len()
+    }
+}
+
+impl Engine {
+    /// Handle `deck [new <kind>|draw [N]|shuffle]`, showing what is left without argument
+    ///
+    pub fn deck(&mut self, args: &str) -> Result<String, EngineError> {
+        trace!("deck({args})");
+        let usage = || EngineError::Usage("deck [new <kind>|draw [N]|shuffle]".to_string());
+
+        let args = args.split_whitespace().collect::<Vec<_>>();
+        if let ["new", kind] = args[..] {
+            let mut deck = Deck::new(kind)?;
+            self.config.roller.scope(|rng| deck.shuffle(rng));
+            let msg = format!("new {kind} deck, {} cards", deck.left());
+            self.deck = Some(deck);
+            return Ok(msg);
+        }
+
+        let deck = self
+            .deck
+            .as_mut()
+            .ok_or_else(|| EngineError::Deck("no deck, use deck new <kind>".to_string()))?;
+        match args[..] {
+            [] => Ok(format!(
+                "{}: {} cards left, {} drawn",
+                deck.kind,
+                deck.left(),
+                deck.drawn.len()
+            )),
+            ["draw"] => Ok(deck.draw(1)?.join(", ")),
+            ["draw", n] => {
+                let n = n
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(usage)?;
+                Ok(deck.draw(n)?.join(", "))
+            }
+            ["shuffle"] => {
+                self.config.roller.scope(|rng| deck.shuffle(rng));
+                Ok(format!("{} cards shuffled", deck.left()))
+            }
+            _ => Err(usage()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use crate::engine::Output;
+
+    use super::*;
+
+    #[rstest]
+    #[case("standard52", 52)]
+    #[case("standard54", 54)]
+    fn test_deck_new(#[case] kind: &str, #[case] n: usize) {
+        let d = Deck::new(kind).unwrap();
+
+        assert_eq!(n, d.left());
+        assert_eq!(52, d.cards.iter().filter(|c| *c != JOKER).unique().count());
+    }
+
+    #[test]
+    fn test_deck_new_unknown() {
+        assert_eq!(
+            Err(EngineError::Deck(
+                "unknown kind tarot, use one of standard52, standard54".to_string()
+            )),
+            Deck::new("tarot")
+        );
+    }
+
+    #[test]
+    fn test_deck_draw() {
+        let mut d = Deck::new("standard52").unwrap();
+
+        assert_eq!(vec!["2♣", "3♣"], d.draw(2).unwrap());
+        assert_eq!(50, d.left());
+        assert!(d.draw(51).is_err());
+        assert_eq!(50, d.draw(50).unwrap().len());
+        assert_eq!(0, d.left());
+
+        d.shuffle(&mut StdRng::seed_from_u64(42));
+        assert_eq!(52, d.left());
+        assert!(d.drawn.is_empty());
+    }
+
+    #[test]
+    fn test_engine_deck() {
+        let mut e = Engine::new();
+
+        assert!(matches!(e.deck("draw"), Err(EngineError::Deck(_))));
+        assert_eq!(
+            Ok(Output::Listing("new standard54 deck, 54 cards".to_string())),
+            e.eval("deck new standard54")
+        );
+        assert_eq!(3, e.deck("draw 3").unwrap().split(", ").count());
+        assert_eq!(1, e.deck("draw").unwrap().split(", ").count());
+        assert_eq!(
+            Ok("standard54: 50 cards left, 4 drawn".to_string()),
+            e.deck("")
+        );
+        assert_eq!(Ok("54 cards shuffled".to_string()), e.deck("shuffle"));
+    }
+
+    #[rstest]
+    #[case("draw 0")]
+    #[case("draw many")]
+    #[case("deal 3")]
+    #[case("shuffle now")]
+    fn test_engine_deck_usage(#[case] args: &str) {
+        let mut e = Engine::new();
+        e.deck("new standard52").unwrap();
+
+        assert_eq!(
+            Err(EngineError::Usage(
+                "deck [new <kind>|draw [N]|shuffle]".to_string()
+            )),
+            e.deck(args)
+        );
+    }
+}
@@ -0,0 +1,82 @@
+//! Errors returned by the `Engine`.
+//!
+
+use thiserror::Error;
+
+use crate::compiler::error::CompilerError;
+use crate::dice::error::DiceError;
+
+/// Everything that can go wrong while running commands
+///
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub enum EngineError {
+    /// Line did not compile
+    #[error(transparent)]
+    Compiler(#[from] CompilerError),
+    /// Invalid dice expression
+    #[error(transparent)]
+    Dice(#[from] DiceError),
+    /// Only builtin commands can be executed
+    #[error("you can't execute other than Builtin")]
+    NotBuiltin,
+    /// Unknown core command
+    #[error("invalid Cmd")]
+    InvalidCmd,
+    /// Error from the line editor
+    #[error("readline: {0}")]
+    Readline(String),
+    /// No previous roll (with that label)
+    #[error("no previous roll{}", if .0.is_empty() { String::new() } else { format!(" labelled {}", .0) })]
+    NoRoll(String),
+    /// Error while reading input
+    #[error("I/O error: {0}")]
+    Io(String),
+    /// Session can not be loaded or saved
+    #[error("storage: {0}")]
+    Storage(String),
+    /// Bad arguments for a command
+    #[error("usage: {0}")]
+    Usage(String),
+    /// Invalid definition of commands
+    #[error("invalid commands: {0}")]
+    Commands(String),
+    /// `merge` or `discard` outside of a branch
+    #[error("not in a branch")]
+    NoBranch,
+    /// Character file invalid or character unknown
+    #[error("character: {0}")]
+    Character(String),
+    /// Table file invalid, table unknown or without entry for a roll
+    #[error("table: {0}")]
+    Table(String),
+    /// Pack file invalid or mechanic not registered
+    #[error("pack: {0}")]
+    Pack(String),
+    /// Script failing or not available
+    #[error("script: {0}")]
+    Script(String),
+    /// No deck or not enough cards
+    #[error("deck: {0}")]
+    Deck(String),
+    /// Roll database missing or failing
+    #[error("roll database: {0}")]
+    RollDb(String),
+    /// Text-to-speech problem
+    #[error("speech: {0}")]
+    Speech(String),
+    /// Name of a new command empty or not alphanumeric
+    #[error("invalid command name {0:?}")]
+    InvalidName(String),
+    /// Name of a new command taken by a command other than an alias or a macro
+    #[error("{0} is already a command")]
+    NameTaken(String),
+    /// Only aliases and macros can be removed
+    #[error("{0} is not an alias or a macro")]
+    NotAlias(String),
+    /// History reference to no command
+    #[error("{0}: event not found")]
+    NoEvent(String),
+    /// Output format not known by the command
+    #[error("unknown output format {0}, expected table or csv")]
+    OutputFormat(String),
+}
@@ -0,0 +1,366 @@
+//! Formatter layer, this is where results are turned into text for display.
+//!
+//! Supported formats are:
+//!
+//! - `Text`    summary of the result
+//! - `Debug`   full dump of the `Res` struct
+//! - `Large`   total in large-print digits, readable from across the table
+//! - `Markdown` bold total and struck through dropped dices, to paste in a chat room
+//! - `Porcelain` `total:dices:bonus` on one line, stable for scripts, followed by
+//!   `:symbol=count,...` when symbol dices were rolled
+//!
+//! Text can be colored with ANSI sequences (`render_with`): the total is bold, marked as good
+//! for a natural and bad for a fumble, rerolled and dropped dices are faint.  The `Palette` of
+//! the `Theme` tells what these are: green, red and dimmed for `dark`, blue, red and gray for
+//! `light`, only bold, underlined and dimmed for `mono`.  The REPL uses the same palette for
+//! the prompt and the hints.
+//!
+//! Example:
+//! ```
+//! use dices_rs::config::Format;
+//! use dices_rs::dice::result::Res;
+//! use dices_rs::engine::format::render;
+//! use dices_rs::locale::NumberFormat;
+//!
+//! let mut r = Res::new();
+//! r.append(4);
+//!
+//! println!("{}", render(&r, Format::Large, &NumberFormat::default()));
+//! ```
+
+use itertools::Itertools;
+
+use crate::config::{Format, Theme};
+use crate::dice::result::{Res, Special};
+use crate::locale::NumberFormat;
+use crate::messages::{Messages, Msg};
+
+/// ANSI sequences
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const UNDERLINE: &str = "\x1b[4m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const BLUE: &str = "\x1b[34m";
+const CYAN: &str = "\x1b[36m";
+const GRAY: &str = "\x1b[90m";
+pub const RESET: &str = "\x1b[0m";
+
+/// ANSI sequences of a theme
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Palette {
+    /// Totals and symbols
+    pub bold: &'static str,
+    /// Rerolled and dropped dices, hints
+    pub faint: &'static str,
+    /// Naturals and high open rolls
+    pub good: &'static str,
+    /// Fumbles and low open rolls
+    pub bad: &'static str,
+    /// Prompt of the REPL
+    pub prompt: &'static str,
+}
+
+impl Theme {
+    /// Sequences used for this theme
+    ///
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Dark => Palette {
+                bold: BOLD,
+                faint: DIM,
+                good: GREEN,
+                bad: RED,
+                prompt: CYAN,
+            },
+            Theme::Light => Palette {
+                bold: BOLD,
+                faint: GRAY,
+                good: BLUE,
+                bad: RED,
+                prompt: BLUE,
+            },
+            Theme::Mono => Palette {
+                bold: BOLD,
+                faint: DIM,
+                good: UNDERLINE,
+                bad: UNDERLINE,
+                prompt: BOLD,
+            },
+        }
+    }
+}
+
+/// Height of the large-print glyphs
+const HEIGHT: usize = 5;
+
+/// Large-print glyphs for digits and minus sign
+const DIGITS: [[&str; HEIGHT]; 10] = [
+    [" ### ", "#   #", "#   #", "#   #", " ### "],
+    ["  #  ", " ##  ", "  #  ", "  #  ", " ### "],
+    [" ### ", "#   #", "  ## ", " #   ", "#####"],
+    ["#### ", "    #", " ### ", "    #", "#### "],
+    ["#  # ", "#  # ", "#####", "   # ", "   # "],
+    ["#####", "#    ", "#### ", "    #", "#### "],
+    [" ### ", "#    ", "#### ", "#   #", " ### "],
+    ["#####", "    #", "   # ", "  #  ", "  #  "],
+    [" ### ", "#   #", " ### ", "#   #", " ### "],
+    [" ### ", "#   #", " ####", "    #", " ### "],
+];
+const MINUS: [&str; HEIGHT] = ["     ", "     ", "#####", "     ", "     "];
+
+/// Render a result according to the chosen format
+///
+pub fn render(res: &Res, format: Format, nf: &NumberFormat) -> String {
+    render_with(res, format, nf, None, &Messages::default())
+}
+
+/// Same as `render` in the language of `m`, with the colors of `theme` if there is one
+///
+pub fn render_with(
+    res: &Res,
+    format: Format,
+    nf: &NumberFormat,
+    theme: Option<Theme>,
+    m: &Messages,
+) -> String {
+    let text = |res: &Res| match theme {
+        Some(t) => colored(res, nf, m, &t.palette()),
+        None => res.format_localized(nf, m),
+    };
+    match format {
+        Format::Text => text(res),
+        Format::Debug => format!("roll = {res:?}"),
+        Format::Large => format!("{}\n{}", large(res.total()), text(res)),
+        Format::Markdown => res.format_markdown_localized(nf, m),
+        Format::Porcelain => porcelain(res),
+    }
+}
+
+/// `total:dices:bonus`, without locale or colors, e.g. `15:6,3,6:0`, then `:hit=2,crit=1`
+/// if there are symbols
+///
+fn porcelain(res: &Res) -> String {
+    let mut s = format!(
+        "{}:{}:{}",
+        res.total(),
+        res.list.iter().join(","),
+        res.bonus
+    );
+    if !res.symbols.is_empty() {
+        let symbols = res
+            .symbols
+            .iter()
+            .map(|(s, n)| format!("{s}={n}"))
+            .join(",");
+        s.push_str(&format!(":{symbols}"));
+    }
+    s
+}
+
+/// Same as `Res::format_localized` with ANSI colors
+///
+fn colored(res: &Res, nf: &NumberFormat, m: &Messages, p: &Palette) -> String {
+    let flag = match res.flag {
+        Special::Natural | Special::OpenHigh => p.good,
+        Special::Fumble | Special::OpenLow | Special::Botch => p.bad,
+        Special::None => "",
+    };
+    let extra = |what: Msg, list: &[usize]| match list.is_empty() {
+        true => String::new(),
+        false => format!(
+            " - {}: {}{}{RESET}",
+            m.get(what),
+            p.faint,
+            list.iter().join(", ")
+        ),
+    };
+    let symbols = match res.symbols.is_empty() {
+        true => String::new(),
+        false => format!(
+            " - {}: {}{}{RESET}",
+            m.get(Msg::Symbols),
+            p.bold,
+            res.format_symbols()
+        ),
+    };
+    format!(
+        "{}{}: {}{flag}{}{RESET} - {}: {}{}{}{}{} ({flag}{}{RESET}){}",
+        res.format_player(),
+        m.get(Msg::Total),
+        p.bold,
+        nf.int(res.total() as i64),
+        m.get(Msg::InclBonus),
+        nf.int(res.bonus as i64),
+        extra(Msg::Rerolled, &res.rerolled),
+        extra(Msg::Dropped, &res.dropped),
+        symbols,
+        res.format_capped(m),
+        m.flag(res.flag),
+        res.format_note()
+    )
+}
+
+/// Render a number in large-print digits
+///
+pub fn large(n: isize) -> String {
+    let glyphs: Vec<&[&str; HEIGHT]> = n
+        .to_string()
+        .chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) => &DIGITS[d as usize],
+            None => &MINUS,
+        })
+        .collect();
+
+    (0..HEIGHT)
+        .map(|row| {
+            glyphs
+                .iter()
+                .map(|g| g[row])
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_digit() {
+        let s = large(1);
+
+        assert_eq!(HEIGHT, s.lines().count());
+        assert_eq!("  #\n ##\n  #\n  #\n ###", s);
+    }
+
+    #[test]
+    fn test_large_negative() {
+        let s = large(-7);
+
+        let first = s.lines().next().unwrap();
+        assert_eq!("       #####", first);
+        assert_eq!(HEIGHT, s.lines().count());
+    }
+
+    #[test]
+    fn test_render() {
+        let mut r = Res::new();
+        r.append(12);
+        let nf = NumberFormat::default();
+
+        assert_eq!(r.to_string(), render(&r, Format::Text, &nf));
+        assert!(render(&r, Format::Debug, &nf).starts_with("roll = Res"));
+
+        let l = render(&r, Format::Large, &nf);
+        assert_eq!(HEIGHT + 1, l.lines().count());
+        assert!(l.ends_with(&r.to_string()));
+
+        assert_eq!("**12** (12)", render(&r, Format::Markdown, &nf));
+    }
+
+    #[test]
+    fn test_render_porcelain() {
+        let r = Res {
+            list: vec![6, 3, 6],
+            sum: 17,
+            bonus: 2,
+            dropped: vec![1],
+            ..Default::default()
+        };
+        let nf = NumberFormat::new("fr");
+        let m = Messages::default();
+
+        assert_eq!(
+            "17:6,3,6:2",
+            render_with(&r, Format::Porcelain, &nf, Some(Theme::Dark), &m)
+        );
+
+        let r = Res {
+            symbols: [("hit".to_string(), 2), ("crit".to_string(), 1)].into(),
+            ..Default::default()
+        };
+        assert_eq!("0::0:crit=1,hit=2", render(&r, Format::Porcelain, &nf));
+    }
+
+    #[test]
+    fn test_render_color() {
+        let r = Res {
+            list: vec![20],
+            sum: 22,
+            bonus: 2,
+            flag: Special::Natural,
+            dropped: vec![3],
+            ..Default::default()
+        };
+        let nf = NumberFormat::default();
+        let m = Messages::default();
+
+        assert_eq!(
+            "total: \x1b[1m\x1b[32m22\x1b[0m - incl. bonus: 2 - dropped: \x1b[2m3\x1b[0m (\x1b[32mNatural\x1b[0m)",
+            render_with(&r, Format::Text, &nf, Some(Theme::Dark), &m)
+        );
+        assert_eq!(r.to_string(), render_with(&r, Format::Text, &nf, None, &m));
+        assert!(
+            render_with(&r, Format::Debug, &nf, Some(Theme::Dark), &m).starts_with("roll = Res")
+        );
+    }
+
+    #[test]
+    fn test_render_themes() {
+        let r = Res {
+            list: vec![1],
+            sum: 1,
+            flag: Special::Fumble,
+            rerolled: vec![2],
+            ..Default::default()
+        };
+        let nf = NumberFormat::default();
+        let m = Messages::default();
+
+        assert_eq!(
+            "total: \x1b[1m\x1b[31m1\x1b[0m - incl. bonus: 0 - rerolled: \x1b[90m2\x1b[0m (\x1b[31mFumble\x1b[0m)",
+            render_with(&r, Format::Text, &nf, Some(Theme::Light), &m)
+        );
+        assert_eq!(
+            "total: \x1b[1m\x1b[4m1\x1b[0m - incl. bonus: 0 - rerolled: \x1b[2m2\x1b[0m (\x1b[4mFumble\x1b[0m)",
+            render_with(&r, Format::Text, &nf, Some(Theme::Mono), &m)
+        );
+    }
+
+    #[test]
+    fn test_render_localized() {
+        let r = Res {
+            list: vec![20],
+            sum: 20,
+            flag: Special::Natural,
+            ..Default::default()
+        };
+        let nf = NumberFormat::new("fr");
+        let m = Messages::new("fr");
+
+        assert_eq!(
+            "total: \x1b[1m\x1b[32m20\x1b[0m - dont bonus: 0 (\x1b[32mNaturel\x1b[0m)",
+            render_with(&r, Format::Text, &nf, Some(Theme::Dark), &m)
+        );
+        assert_eq!(
+            "**20** (20) _Naturel_",
+            render_with(&r, Format::Markdown, &nf, None, &m)
+        );
+    }
+
+    #[test]
+    fn test_palette_mono() {
+        let p = Theme::Mono.palette();
+
+        [p.bold, p.faint, p.good, p.bad, p.prompt]
+            .iter()
+            .for_each(|s| assert!([BOLD, DIM, UNDERLINE].contains(s), "{s:?}"));
+    }
+}
@@ -0,0 +1,170 @@
+//! `help` command.
+//!
+//! The usage and description of each builtin live in `help.yaml` next to `commands.yaml`,
+//! aliases and macros are described by what they expand to.
+//!
+//! ```
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Engine::new().with(None);
+//!
+//! assert!(e.help("dice").unwrap().starts_with("dice <expr>"));
+//! assert!(e.help("doom").unwrap().contains("dice 2D6"));
+//! ```
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use log::trace;
+use serde::Deserialize;
+
+use crate::compiler::{error::CompilerError, suggest, Compiler};
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine};
+
+/// Help for one builtin command
+///
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct Help {
+    /// Syntax
+    pub usage: String,
+    /// What it does
+    pub description: String,
+}
+
+impl Engine {
+    /// Without `name` list all commands with their usage, otherwise describe `name`
+    ///
+    pub fn help(&self, name: &str) -> Result<String, EngineError> {
+        trace!("help({name})");
+        let all = help_texts()?;
+
+        if name.is_empty() {
+            let list = self
+                .cmds
+                .keys()
+                .sorted()
+                .map(|n| match (all.get(n), self.cmds.get(n)) {
+                    (Some(h), _) => h.usage.clone(),
+                    (_, Some(Command::Mechanic { mechanic, .. })) => mechanic.usage(n),
+                    _ => n.clone(),
+                })
+                .join("\n");
+            return Ok(list);
+        }
+
+        let name = &name.to_lowercase();
+        match self.cmds.get(name) {
+            Some(Command::Alias { .. }) | Some(Command::Macro { .. }) => {
+                Ok(format!("{name} = {}", self.expand(name).join(" -> ")))
+            }
+            Some(Command::Custom { .. }) => Ok(format!(
+                "{name}\n\n{}",
+                self.custom_description(name).unwrap_or_default()
+            )),
+            Some(Command::Mechanic { mechanic, .. }) => Ok(format!(
+                "{}\n\n{}",
+                mechanic.usage(name),
+                mechanic.description
+            )),
+            Some(_) => match all.get(name) {
+                Some(h) => Ok(format!("{}\n\n{}", h.usage, h.description)),
+                None => Ok(name.to_string()),
+            },
+            None => {
+                let near = suggest(name, self.cmds.keys());
+                Err(CompilerError::UnknownCommand(name.to_string(), near).into())
+            }
+        }
+    }
+
+    /// One line description of `name`, what it expands to for aliases and macros
+    ///
+    pub fn describe(&self, name: &str) -> Option<String> {
+        let name = &name.to_lowercase();
+        match self.cmds.get(name)? {
+            Command::Alias { .. } | Command::Macro { .. } => {
+                Some(format!("= {}", self.expand(name).join(" -> ")))
+            }
+            Command::Mechanic { mechanic, .. } => Some(mechanic.description.clone()),
+            Command::Custom { .. } => self.custom_description(name),
+            _ => help_texts().ok()?.remove(name).map(|h| h.description),
+        }
+    }
+
+    /// All the steps an alias or macro goes through until reaching a builtin
+    ///
+    pub(crate) fn expand(&self, name: &str) -> Vec<String> {
+        let mut steps = vec![];
+        let mut line = name.to_string();
+
+        for _ in 0..Compiler::MAX_RECUR {
+            let end = line
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(line.len());
+            let (word, rest) = line.split_at(end);
+            line = match self.cmds.get(&word.to_lowercase()) {
+                Some(Command::Alias { cmd, .. }) | Some(Command::Macro { cmd, .. }) => {
+                    format!("{cmd}{rest}")
+                }
+                _ => break,
+            };
+            steps.push(line.clone());
+        }
+        steps
+    }
+}
+
+/// Load the help texts for builtins
+///
+fn help_texts() -> Result<HashMap<String, Help>, EngineError> {
+    serde_yaml::from_str(include_str!("help.yaml"))
+        .map_err(|e| EngineError::Commands(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_help_texts() {
+        let all = help_texts().unwrap();
+        let e = Engine::new();
+
+        // Every builtin has some help
+        //
+        e.cmds
+            .keys()
+            .for_each(|n| assert!(all.contains_key(n), "{n}"));
+    }
+
+    #[rstest]
+    #[case("set", "set <name> [value]\n\nSet a variable")]
+    #[case("doom", "doom = dice 2D6")]
+    #[case("mouv", "mouv = move +7 -> dice 3D6 -9 +7")]
+    #[case("quit", "quit = exit")]
+    #[case("QUIT", "quit = exit")]
+    fn test_help(#[case] name: &str, #[case] want: &str) {
+        let e = Engine::new().with(Some(PathBuf::from("testdata/aliases")));
+        assert!(e.help(name).unwrap().starts_with(want));
+    }
+
+    #[test]
+    fn test_help_all() {
+        let e = Engine::new().with(None);
+        let h = e.help("").unwrap();
+
+        assert!(h.contains("speak [on|off]"));
+        assert!(h.contains("doom"));
+    }
+
+    #[test]
+    fn test_help_unknown() {
+        let e = Engine::new();
+        assert!(e.help("foo").is_err());
+    }
+}
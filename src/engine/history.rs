@@ -0,0 +1,130 @@
+//! Shell-style history expansion of the commands of the session.
+//!
+//! At the start of a command `!!` is the previous command, `!N` the Nth command of the session,
+//! `!-N` the Nth one before the current one and `!dice` the last command starting with `dice`.
+//! Whatever follows is kept, so `!! +2` runs the previous command with a bonus.  `!` followed
+//! by a space is a comment, not a reference.
+//!
+//! Commands are remembered once expanded, also in the history of the shell.
+//!
+//! ```
+//! use dices_rs::engine::Engine;
+//!
+//! let mut e = Engine::new();
+//! e.eval("dice 3d6").unwrap();
+//! e.eval("flip").unwrap();
+//!
+//! assert_eq!("dice 3d6 +2", e.expand_history("!dice +2").unwrap());
+//! assert_eq!("flip", e.expand_history("!!").unwrap());
+//! assert!(e.expand_history("!open").is_err());
+//! ```
+
+use crate::engine::error::EngineError;
+use crate::engine::Engine;
+
+/// Start of a history reference
+pub const EVENT: char = '!';
+
+impl Engine {
+    /// `cmd` with the history reference at its start replaced by the command it refers to
+    ///
+    pub fn expand_history(&self, cmd: &str) -> Result<String, EngineError> {
+        let cmd = cmd.trim_start();
+        let Some(event) = reference(cmd) else {
+            return Ok(cmd.to_string());
+        };
+        let end = match event.starts_with(EVENT) {
+            true => 1,
+            false => event.find(char::is_whitespace).unwrap_or(event.len()),
+        };
+        let (word, rest) = event.split_at(end);
+
+        let found = match word.parse::<isize>() {
+            _ if word == "!" => self.history.last(),
+            Ok(n) if n > 0 => self.history.get(n as usize - 1),
+            Ok(n) if n < 0 => self
+                .history
+                .len()
+                .checked_sub(n.unsigned_abs())
+                .and_then(|i| self.history.get(i)),
+            Ok(_) => None,
+            Err(_) => self.history.iter().rev().find(|c| c.starts_with(word)),
+        };
+        found
+            .map(|c| format!("{c}{rest}"))
+            .ok_or_else(|| EngineError::NoEvent(format!("{EVENT}{word}")))
+    }
+
+    /// Commands of the session, expanded
+    ///
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+}
+
+/// What follows `!` at the start of `cmd`, if it is a history reference
+///
+pub fn reference(cmd: &str) -> Option<&str> {
+    cmd.trim_start()
+        .strip_prefix(EVENT)
+        .filter(|e| e.starts_with(|c: char| !c.is_whitespace()))
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::engine::Output;
+
+    use super::*;
+
+    fn engine() -> Engine {
+        let mut e = Engine::new();
+        ["dice 3d6", "flip 2", "dice d20 +2", "vars"]
+            .iter()
+            .for_each(|c| {
+                e.eval(c).unwrap();
+            });
+        e
+    }
+
+    #[rstest]
+    #[case("!!", "vars")]
+    #[case("  !! +1", "vars +1")]
+    #[case("!1", "dice 3d6")]
+    #[case("!-2", "dice d20 +2")]
+    #[case("!dice", "dice d20 +2")]
+    #[case("!fl", "flip 2")]
+    #[case("!dice dis", "dice d20 +2 dis")]
+    #[case("dice d6", "dice d6")]
+    #[case("! comment", "! comment")]
+    #[case("!", "!")]
+    fn test_expand_history(#[case] cmd: &str, #[case] want: &str) {
+        assert_eq!(Ok(want.to_string()), engine().expand_history(cmd));
+    }
+
+    #[rstest]
+    #[case("!5", "!5")]
+    #[case("!0", "!0")]
+    #[case("!-5", "!-5")]
+    #[case("!open +2", "!open")]
+    fn test_expand_history_error(#[case] cmd: &str, #[case] event: &str) {
+        assert_eq!(
+            Err(EngineError::NoEvent(event.to_string())),
+            engine().expand_history(cmd)
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let mut e = engine();
+
+        assert!(matches!(e.eval("!dice +1"), Ok(Output::Roll(r)) if r.bonus == 3));
+        assert!(e.eval("!nothing").is_err());
+        assert!(e.eval("foo").is_err());
+        assert_eq!(6, e.history().len());
+        assert_eq!("dice d20 +2 +1", e.history()[4]);
+        assert_eq!("foo", e.history()[5]);
+        assert!(Engine::new().expand_history("!!").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Where `Engine::run` gets its lines from and where the results go.
+//!
+//! A `LineSource` can be the `rustyline` editor (with the `cli` feature), any `BufRead` like
+//! a file or a socket through `BufSource`, or anything a test harness wants.  A `Sink` gets
+//! rendered rolls, other text and errors: `LogSink` logs rolls and errors and prints the rest
+//! like the `dices` shell does, `StdoutSink` prints rolls on stdout for scripts and
+//! `WriteSink` writes everything to a `Write`.
+//!
+//! ```
+//! use dices_rs::engine::io::{BufSource, WriteSink};
+//! use dices_rs::engine::Engine;
+//!
+//! let mut e = Engine::new();
+//! let mut out = WriteSink::new(vec![]);
+//!
+//! let failed = e.run(&mut BufSource::new("dice 3D6\nfoo\n".as_bytes()), &mut out).unwrap();
+//! assert_eq!(1, failed);
+//! assert!(String::from_utf8(out.into_inner()).unwrap().starts_with("total: "));
+//! ```
+
+use std::io::{BufRead, Write};
+
+use log::{error, info};
+#[cfg(feature = "cli")]
+use rustyline::{error::ReadlineError, Editor, Helper};
+
+use crate::engine::error::EngineError;
+use crate::engine::Engine;
+
+/// Lines to execute
+///
+pub trait LineSource {
+    /// Next line, `None` when there is no more
+    ///
+    fn next_line(&mut self, prompt: &str) -> Result<Option<String>, EngineError>;
+
+    /// Where the last line came from, to prefix error messages
+    ///
+    fn location(&self) -> Option<String> {
+        None
+    }
+
+    /// The commands of `engine` changed (another character or profile)
+    ///
+    fn reload(&mut self, _engine: &Engine) {}
+
+    /// A line was run, with its history references expanded
+    ///
+    fn remember(&mut self, _line: &str) {}
+}
+
+/// Results of the lines
+///
+pub trait Sink {
+    /// A rendered roll
+    ///
+    fn roll(&mut self, text: &str) -> Result<(), EngineError>;
+
+    /// Anything else to show (lists, help, messages)
+    ///
+    fn text(&mut self, text: &str) -> Result<(), EngineError>;
+
+    /// A line failed
+    ///
+    fn error(&mut self, msg: &str) -> Result<(), EngineError>;
+}
+
+/// Interactive editor, every line run goes into the history and the helper is rebuilt when
+/// the commands change
+///
+#[cfg(feature = "cli")]
+impl<H> LineSource for Editor<H>
+where
+    H: Helper + for<'a> From<&'a Engine>,
+{
+    fn next_line(&mut self, prompt: &str) -> Result<Option<String>, EngineError> {
+        match self.readline(prompt) {
+            Ok(line) => Ok(Some(line)),
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(EngineError::Readline(e.to_string())),
+        }
+    }
+
+    fn reload(&mut self, engine: &Engine) {
+        self.set_helper(Some(H::from(engine)));
+    }
+
+    fn remember(&mut self, line: &str) {
+        self.add_history_entry(line);
+    }
+}
+
+/// Lines of a file, a pipe or anything `BufRead`, the prompt is ignored
+///
+#[derive(Debug)]
+pub struct BufSource<R> {
+    input: R,
+    /// Lines read so far
+    count: usize,
+}
+
+impl<R: BufRead> BufSource<R> {
+    pub fn new(input: R) -> Self {
+        BufSource { input, count: 0 }
+    }
+}
+
+impl<R: BufRead> LineSource for BufSource<R> {
+    fn next_line(&mut self, _prompt: &str) -> Result<Option<String>, EngineError> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                self.count += 1;
+                Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+            }
+            Err(e) => Err(EngineError::Io(e.to_string())),
+        }
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(format!("line {}", self.count))
+    }
+}
+
+/// Rolls and errors are logged, the rest is printed on stdout
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+impl Sink for LogSink {
+    fn roll(&mut self, text: &str) -> Result<(), EngineError> {
+        info!("{text}");
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), EngineError> {
+        println!("{text}");
+        Ok(())
+    }
+
+    fn error(&mut self, msg: &str) -> Result<(), EngineError> {
+        error!("{msg}");
+        Ok(())
+    }
+}
+
+/// Rolls and other text printed on stdout, only errors are logged
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn roll(&mut self, text: &str) -> Result<(), EngineError> {
+        println!("{text}");
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), EngineError> {
+        println!("{text}");
+        Ok(())
+    }
+
+    fn error(&mut self, msg: &str) -> Result<(), EngineError> {
+        error!("{msg}");
+        Ok(())
+    }
+}
+
+/// Everything written to `W`, one line each
+///
+#[derive(Debug)]
+pub struct WriteSink<W> {
+    output: W,
+}
+
+impl<W: Write> WriteSink<W> {
+    pub fn new(output: W) -> Self {
+        WriteSink { output }
+    }
+
+    /// Get `W` back
+    ///
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    fn line(&mut self, text: &str) -> Result<(), EngineError> {
+        writeln!(self.output, "{text}").map_err(|e| EngineError::Io(e.to_string()))
+    }
+}
+
+impl<W: Write> Sink for WriteSink<W> {
+    fn roll(&mut self, text: &str) -> Result<(), EngineError> {
+        self.line(text)
+    }
+
+    fn text(&mut self, text: &str) -> Result<(), EngineError> {
+        self.line(text)
+    }
+
+    fn error(&mut self, msg: &str) -> Result<(), EngineError> {
+        self.line(&format!("error: {msg}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buf_source() {
+        let mut src = BufSource::new("dice 3D6\r\n\nexit".as_bytes());
+
+        assert_eq!(Ok(Some("dice 3D6".to_string())), src.next_line("> "));
+        assert_eq!(Ok(Some("".to_string())), src.next_line("> "));
+        assert_eq!(Ok(Some("exit".to_string())), src.next_line("> "));
+        assert_eq!(Some("line 3".to_string()), src.location());
+        assert_eq!(Ok(None), src.next_line("> "));
+    }
+
+    #[test]
+    fn test_write_sink() {
+        let mut out = WriteSink::new(vec![]);
+
+        out.roll("total: 3").unwrap();
+        out.text("branch 1").unwrap();
+        out.error("line 2: oops").unwrap();
+        assert_eq!(
+            "total: 3\nbranch 1\nerror: line 2: oops\n",
+            String::from_utf8(out.into_inner()).unwrap()
+        );
+    }
+}
@@ -3,23 +3,70 @@
 //! This is where all the CLI parsing is done and stuff is executed.
 //!
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "speech")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
 use itertools::Itertools;
-use log::{error, info, trace};
-use rustyline::{error::ReadlineError, Editor};
+use log::{error, trace, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::compiler::{Action, Compiler};
-use crate::dice::result::Res;
+use crate::compiler::{
+    comment, join_continued, split, strip_comment, Action, Compiler, Trace, CONTINUATION,
+};
+use crate::config::Config;
+use crate::dice::internal::RollerKind;
+use crate::dice::lint::{self, Lint};
+use crate::dice::result::{Res, Special};
+use crate::messages::{Messages, Msg};
 
 use self::core::Cmd;
+use self::error::EngineError;
+use self::io::{BufSource, LineSource, LogSink, Sink};
+use self::observer::Event;
+use self::storage::{MemoryStorage, Session, Storage};
 
 pub mod aliases;
+pub mod branch;
+pub mod character;
+#[cfg(feature = "cli")]
 pub mod complete;
 pub mod core;
+pub mod deck;
+pub mod error;
+pub mod format;
+pub mod help;
+pub mod history;
+pub mod io;
+pub mod observer;
+pub mod pack;
+pub mod plugin;
+pub mod prob;
+pub mod prompt;
+#[cfg(feature = "sqlite")]
+pub mod rolldb;
+pub mod rolllog;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod shared;
+#[cfg(feature = "speech")]
+pub mod speech;
+pub mod storage;
+pub mod table;
+pub mod usage;
+pub mod version;
+
+/// Who is rolling when neither the configuration nor `$USER` tell
+pub const DEFAULT_ACTOR: &str = "player";
+
+/// Most rolls of one `again`
+pub const MAX_AGAIN: usize = 100;
 
 /// This describe all possibilities for commands and aliases
 ///
@@ -29,6 +76,15 @@ pub enum Command {
     Macro { name: String, cmd: String },
     /// Builtin command
     Builtin { name: String, cmd: Cmd },
+    /// Mechanic of a system pack
+    Mechanic {
+        name: String,
+        mechanic: pack::Mechanic,
+    },
+    /// Command registered from Rust, see `plugin`
+    Custom { name: String },
+    /// Run a Rhai script
+    Script,
     /// Alias of an existing command
     Alias { name: String, cmd: String },
     /// Comment
@@ -41,25 +97,170 @@ pub enum Command {
     Aliases,
     /// List all macros
     Macros,
+    /// Speak results
+    Speak,
+    /// Show the last (or a labelled) roll
+    Last,
+    /// Version and build information
+    Version,
+    /// Name, version and description
+    Banner,
+    /// Usage of commands
+    Help,
+    /// Set a variable
+    Set,
+    /// List all variables
+    Vars,
+    /// Search the roll database
+    Query,
+    /// Start a what-if branch
+    Branch,
+    /// Keep the current branch
+    Merge,
+    /// Forget the current branch
+    Discard,
+    /// Show alias expansions
+    Trace,
+    /// Roll the last dice command again
+    Reroll,
+    /// Roll the last dice command again N times
+    Again,
+    /// List or switch characters
+    Char,
+    /// List or switch alias profiles
+    Profile,
+    /// Roll a usage die, which gets smaller on 1-2
+    Usage,
+    /// List or roll on random tables
+    Table,
+    /// Draw cards from a deck
+    Deck,
+    /// Chances of a roll, by simulation
+    Prob,
+    /// Full probability table of a roll
+    Anydice,
+    /// Remove aliases and macros
+    Unalias,
+    /// Hide rolls from the screen
+    Gm,
+    /// Show the hidden rolls
+    Reveal,
+    /// Roll for a player
+    As,
+    /// Rolls of each player
+    Scores,
 }
 
 impl Command {
-    /// Execute defers to `Cmd::execute` for `Builtin`.
+    /// Execute defers to `Cmd::execute` for `Builtin`, and to the mechanic of a pack.
     ///
-    pub fn execute(&self, input: &str) -> Result<Res> {
+    pub fn execute(&self, input: &str) -> Result<Res, EngineError> {
+        self.execute_with(input, &Config::default())
+    }
+
+    /// Same as `execute` with a given configuration.
+    ///
+    pub fn execute_with(&self, input: &str, cfg: &Config) -> Result<Res, EngineError> {
         match self {
-            Command::Builtin { cmd, .. } => cmd.execute(input),
-            _ => Err(anyhow!("you can't execute other than Builtin")),
+            Command::Builtin { cmd, .. } => cmd.execute_with(input, cfg),
+            Command::Mechanic { name, mechanic } => mechanic.execute_with(name, input, cfg),
+            _ => Err(EngineError::NotBuiltin),
         }
     }
 }
 
-const PS1: &str = "Dices> ";
+/// Compiled-in builtin commands
+pub const DEFAULT_COMMANDS: &str = include_str!("commands.yaml");
+
+/// What a line produced, see `Engine::eval`.  Nothing is printed by the engine itself, `run`
+/// gives every output to `Sink` and programs can serialize it, as `{"type": "roll", "value":
+/// {...}}`.
+///
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum Output {
+    /// A new roll, or the one shown by `last`
+    Roll(Res),
+    /// Several rolls, from `again N`
+    Rolls(Vec<Res>),
+    /// Text for the user: lists, help, messages
+    Listing(String),
+    /// What a line gave followed by what the hooks of its rolls gave, see `on_crit`
+    Chained(Vec<Output>),
+    /// Nothing to show, like after `set`
+    Empty,
+    /// `exit` was given
+    Exit,
+}
 
 /// Easier to carry around
 ///
 pub struct Engine {
     pub cmds: HashMap<String, Command>,
+    /// Runtime configuration
+    pub config: Config,
+    /// Last roll if any
+    last: Option<Res>,
+    /// Number of rolls this session
+    count: usize,
+    /// Speak results?
+    speak: bool,
+    /// Shared with the speaker, once there is one
+    #[cfg(feature = "speech")]
+    speaking: Option<Arc<AtomicBool>>,
+    /// Show alias expansions?
+    trace: bool,
+    /// Hide rolls, GM screen mode?
+    gm: bool,
+    /// Rolls hidden since the last `reveal`, with their line
+    hidden: Vec<(String, Res)>,
+    /// Player given with `as` for the command being executed
+    player: Option<String>,
+    /// Number of rolls and sum of their totals by player
+    scores: BTreeMap<String, (usize, isize)>,
+    /// Expansion of the last executed command
+    last_trace: Option<Trace>,
+    /// Last dice command with its arguments, for `reroll`
+    last_action: Option<(Command, String, Trace)>,
+    /// Variables and labelled rolls
+    session: Session,
+    /// Where the session is saved
+    storage: Box<dyn Storage>,
+    /// Aliases file actually loaded, if any
+    alias_file: Option<PathBuf>,
+    /// Everything defined in the aliases file
+    alias_set: aliases::AliasSet,
+    /// Section of the aliases file in use
+    profile: Option<String>,
+    /// State when each open branch started
+    branches: Vec<branch::Snapshot>,
+    /// Where every roll is recorded
+    #[cfg(feature = "sqlite")]
+    rolldb: Option<rolldb::RollDb>,
+    /// Where every roll is logged
+    roll_log: Option<rolllog::RollLog>,
+    /// Characters loaded
+    characters: Vec<character::Character>,
+    /// Random tables by name
+    tables: BTreeMap<String, table::Table>,
+    /// Deck of cards, once created with `deck new`
+    deck: Option<deck::Deck>,
+    /// Character in use
+    character: Option<usize>,
+    /// Aliases and macros hidden by the rolls of the character in use
+    shadowed: Vec<Command>,
+    /// Commands changed since the input was told about them
+    reload: bool,
+    /// Told about everything happening
+    observers: Vec<Box<dyn observer::EngineObserver>>,
+    /// Commands registered from Rust, by name
+    custom: HashMap<String, Arc<dyn plugin::CustomCommand>>,
+    /// Hooks due after the rolls of the line being executed
+    hooks: Vec<String>,
+    /// Running a hook, its own rolls trigger nothing
+    hooking: bool,
+    /// Commands of the session, expanded
+    history: Vec<String>,
 }
 
 /// Default implementation for clippy
@@ -71,95 +272,626 @@ impl Default for Engine {
 }
 
 impl Engine {
-    /// Create a new instance
+    /// Create a new instance, with only `exit` if the builtin commands can not be loaded
     ///
     pub fn new() -> Self {
+        Self::try_new().unwrap_or_else(|e| {
+            error!("{}", e);
+            Engine::from(HashMap::from([("exit".to_string(), Command::Exit)]))
+        })
+    }
+
+    /// Create a new instance, checking the builtin commands
+    ///
+    pub fn try_new() -> Result<Self, EngineError> {
         Self::builtin_commands()
     }
 
-    /// Main loop here, refactored from `main()`.
+    /// Replace the default configuration
+    ///
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Roll with another generator than the configured one
     ///
-    pub fn run(&mut self, repl: &mut Editor<()>) -> Result<()> {
-        let cc = Compiler::new(&self.cmds);
+    pub fn with_roller(mut self, roller: RollerKind) -> Self {
+        self.config.roller = roller;
+        self
+    }
 
+    /// Main loop here, refactored from `main()`: execute every command from `input` going on
+    /// after errors, results go to `output`.  Commands on the same line are separated by `;` and
+    /// a line ending with `\` goes on with the next one.  Empty lines are skipped.  Returns the
+    /// number of commands that failed.
+    ///
+    pub fn run<S, K>(&mut self, input: &mut S, output: &mut K) -> Result<usize, EngineError>
+    where
+        S: LineSource + ?Sized,
+        K: Sink + ?Sized,
+    {
         trace!("Start our input loop");
-        loop {
-            // Get next line
+        let mut failed = 0;
+        while let Some(mut line) = input.next_line(&self.prompt())? {
+            // A `\` at the end goes on with the next line
             //
-            let line = match repl.readline(PS1) {
-                Ok(line) => line,
-                Err(ReadlineError::Interrupted) => break,
-                Err(e) => {
-                    error!("{:?}", e);
-                    break;
+            while line.ends_with(CONTINUATION) {
+                match input.next_line(prompt::CONTINUED)? {
+                    Some(next) => line = format!("{line}\n{next}"),
+                    None => break,
                 }
-            };
+            }
+            let line = join_continued(line.strip_suffix(CONTINUATION).unwrap_or(&line));
 
-            trace!("{}", line);
+            let start = self.history.len();
+            let mut exit = false;
+            for cmd in split(&line) {
+                trace!("{}", cmd);
 
-            // Save it
-            //
-            repl.add_history_entry(line.as_str());
+                // Show what a history reference stands for, like shells do
+                //
+                if history::reference(cmd).is_some() {
+                    if let Ok(expanded) = self.expand_history(cmd) {
+                        self.show(&Output::Listing(expanded), output)?;
+                    }
+                }
+                match self.eval_with(cmd) {
+                    Ok(Output::Exit) => {
+                        exit = true;
+                        break;
+                    }
+                    Ok(out) => {
+                        if let Some(t) = self
+                            .last_trace
+                            .as_ref()
+                            .filter(|t| self.trace && t.expanded())
+                        {
+                            self.show(&Output::Listing(format!("trace: {t}")), output)?;
+                        }
+                        self.show(&out, output)?
+                    }
+                    Err(e) => {
+                        let e = self.config.messages().error(&e);
+                        let msg = match input.location() {
+                            Some(at) => format!("{at}: {e}"),
+                            None => e.to_string(),
+                        };
+                        output.error(&msg)?;
+                        failed += 1;
+                    }
+                }
 
-            // Some actions have to be executed here because they do not involve the "core" dice-related
-            // commands and interact with the interactive shell like `exit` and `list`
-            //
-            let action = cc.compile(&line);
+                // Another character or profile brings other commands, the compiler sees them at
+                // once but the input has to know about them too
+                //
+                if self.reload {
+                    input.reload(self);
+                    self.reload = false;
+                }
+            }
 
-            // Now do something with this output of the compiler
+            // The history of the shell gets the line as expanded, keeping a leading space
             //
-            trace!("got ({action:?} as output");
-            let res = match action {
-                Action::Exit => break,
-                Action::List => {
-                    println!("{}", self.list());
-                    continue;
+            if self.history.len() > start {
+                let lead = &line[..line.len() - line.trim_start().len()];
+                input.remember(&format!("{lead}{}", self.history[start..].join("; ")));
+            }
+            if exit {
+                break;
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Execute every line of `input` without readline, results are logged.  Returns the number
+    /// of lines that failed.
+    ///
+    pub fn run_batch<R: BufRead>(&mut self, input: R) -> Result<usize, EngineError> {
+        trace!("Start batch");
+        self.run(&mut BufSource::new(input), &mut LogSink)
+    }
+
+    /// Compile and execute one line without displaying anything, for tests and programs
+    /// driving the engine themselves
+    ///
+    pub fn eval(&mut self, line: &str) -> Result<Output, EngineError> {
+        self.eval_with(line)
+    }
+
+    /// Same as `eval` for a line with several commands separated by `;`, executed in order
+    /// until `exit`.  There is one result for each command.
+    ///
+    pub fn eval_all(&mut self, line: &str) -> Vec<Result<Output, EngineError>> {
+        let mut all = vec![];
+        for cmd in split(&join_continued(line)) {
+            let out = self.eval_with(cmd);
+            let exit = matches!(out, Ok(Output::Exit));
+            all.push(out);
+            if exit {
+                break;
+            }
+        }
+        all
+    }
+
+    /// Send what a line produced to `output`, rolls rendered in the configured format
+    ///
+    fn show<K: Sink + ?Sized>(&self, out: &Output, output: &mut K) -> Result<(), EngineError> {
+        match out {
+            Output::Roll(res) => {
+                let nf = self.config.number_format();
+                output.roll(&format::render_with(
+                    res,
+                    self.config.format,
+                    &nf,
+                    self.config.color.then_some(self.config.theme),
+                    &self.config.messages(),
+                ))
+            }
+            Output::Rolls(all) => all
+                .iter()
+                .try_for_each(|res| self.show(&Output::Roll(res.clone()), output)),
+            Output::Listing(text) => output.text(text),
+            Output::Chained(all) => all.iter().try_for_each(|out| self.show(out, output)),
+            Output::Empty | Output::Exit => Ok(()),
+        }
+    }
+
+    /// Expand history references then execute, telling observers about errors
+    ///
+    fn eval_with(&mut self, line: &str) -> Result<Output, EngineError> {
+        let flow = self.expand_history(line).and_then(|cmd| {
+            self.history.push(cmd.clone());
+            self.exec(&cmd)
+        });
+        if let Err(error) = &flow {
+            self.hooks.clear();
+            self.notify(Event::Error { line, error });
+        }
+        flow.map(|out| self.hooked(out))
+    }
+
+    /// Run the hooks due after the rolls of a line, their results coming after `out`.  `reroll`
+    /// and `trace` still refer to the line.
+    ///
+    fn hooked(&mut self, out: Output) -> Output {
+        let due = std::mem::take(&mut self.hooks);
+        if due.is_empty() {
+            return out;
+        }
+        let (last_trace, last_action) = (self.last_trace.take(), self.last_action.take());
+        self.hooking = true;
+        let mut all = vec![out];
+        for hook in due {
+            trace!("hook({hook})");
+            match self.exec(&hook) {
+                Ok(out) => all.push(out),
+                Err(error) => {
+                    error!("{hook}: {error}");
+                    self.notify(Event::Error {
+                        line: &hook,
+                        error: &error,
+                    });
                 }
-                Action::Aliases => {
-                    println!("{}", self.aliases());
-                    continue;
+            }
+        }
+        self.hooking = false;
+        self.last_trace = last_trace;
+        self.last_action = last_action;
+        Output::Chained(all)
+    }
+
+    /// Compile against the current commands and execute one line
+    ///
+    fn exec(&mut self, line: &str) -> Result<Output, EngineError> {
+        if self.observed() {
+            let steps = self.expand(strip_comment(line).trim_start());
+            if !steps.is_empty() {
+                self.notify(Event::Expanded {
+                    line,
+                    steps: &steps,
+                });
+            }
+        }
+
+        // Some actions have to be executed here because they do not involve the "core" dice-related
+        // commands and interact with the interactive shell like `exit` and `list`
+        //
+        self.last_trace = None;
+        let action = Compiler::new(&self.cmds)
+            .with_defined(self.config.defined())
+            .compile_with(line, &self.variables())?;
+        self.notify(Event::Compiled {
+            line,
+            action: &action,
+        });
+
+        // Now do something with this output of the compiler
+        //
+        trace!("got ({action:?} as output");
+        let m = self.config.messages();
+        match action {
+            Action::Exit => Ok(Output::Exit),
+            Action::Comment => Ok(Output::Empty),
+            Action::List => Ok(Output::Listing(self.list())),
+            Action::Aliases => Ok(Output::Listing(self.aliases())),
+            Action::Macros => Ok(Output::Listing(self.macros())),
+            Action::Version => Ok(Output::Listing(self.version())),
+            Action::Banner => Ok(Output::Listing(self.banner())),
+            Action::Help(arg) => Ok(Output::Listing(self.help(arg.trim())?)),
+            Action::Branch => {
+                let depth = self.branch().to_string();
+                Ok(Output::Listing(m.fill(Msg::Branch, &[&depth])))
+            }
+            Action::Merge => {
+                let name = branch_name(&m, self.merge_branch()?);
+                Ok(Output::Listing(m.fill(Msg::Merged, &[&name])))
+            }
+            Action::Discard => {
+                let name = branch_name(&m, self.discard_branch()?);
+                Ok(Output::Listing(m.fill(Msg::Discarded, &[&name])))
+            }
+            Action::Vars => Ok(Output::Listing(self.vars())),
+            #[cfg(feature = "sqlite")]
+            Action::Query(arg) => Ok(Output::Listing(self.query(arg.trim())?)),
+            #[cfg(not(feature = "sqlite"))]
+            Action::Query(_) => Err(EngineError::RollDb(
+                "built without the sqlite feature".to_string(),
+            )),
+            Action::Last(arg) => Ok(Output::Roll(self.last_roll(arg.trim())?.clone())),
+            Action::Set(arg) => {
+                self.set(&arg)?;
+                Ok(Output::Empty)
+            }
+            Action::Speak(arg) => {
+                self.set_speak(toggle(&arg, self.speak, "speak [on|off]")?)?;
+                let state = m.switch(self.speak, Msg::SpeechOn, Msg::SpeechOff);
+                Ok(Output::Listing(state.to_string()))
+            }
+            Action::Trace(arg) => {
+                self.trace = toggle(&arg, self.trace, "trace [on|off]")?;
+                let state = m.switch(self.trace, Msg::TraceOn, Msg::TraceOff);
+                Ok(Output::Listing(state.to_string()))
+            }
+            Action::Gm(arg) => {
+                self.gm = toggle(&arg, self.gm, "gm [on|off]")?;
+                let state = m.switch(self.gm, Msg::GmOn, Msg::GmOff);
+                Ok(Output::Listing(state.to_string()))
+            }
+            Action::Reveal => Ok(Output::Listing(self.reveal())),
+            Action::As(arg) => {
+                let (player, cmd) = split_player(&arg)
+                    .ok_or_else(|| EngineError::Usage("as <player> <command>".to_string()))?;
+                let before = self.player.replace(player.to_string());
+                let out = self.exec(cmd);
+                self.player = before;
+                out
+            }
+            Action::Scores => Ok(Output::Listing(self.scores())),
+            Action::Custom(name, arg) => self.custom(line, &name, &arg),
+            #[cfg(feature = "script")]
+            Action::Script(arg) => self.script(line, &arg),
+            #[cfg(not(feature = "script"))]
+            Action::Script(_) => Err(EngineError::Script(
+                "built without the script feature".to_string(),
+            )),
+            Action::Char(arg) => Ok(Output::Listing(self.char(&arg)?)),
+            Action::Profile(arg) => Ok(Output::Listing(self.profiles(&arg)?)),
+            Action::Usage(arg) => Ok(Output::Listing(self.usage(line, &arg)?)),
+            Action::Table(arg) => Ok(Output::Listing(self.table(line, &arg)?)),
+            Action::Deck(arg) => Ok(Output::Listing(self.deck(&arg)?)),
+            Action::Prob(arg) => Ok(Output::Listing(self.prob(&arg)?)),
+            Action::Anydice(arg) => Ok(Output::Listing(self.anydice(&arg)?)),
+            Action::Unalias(arg) => Ok(Output::Listing(self.unalias(&arg)?)),
+            Action::Reroll => {
+                let (cmd, input, steps) = self.last_action()?;
+                self.last_trace = Some(steps);
+                let res = self.roll(line, &cmd, &input, None)?;
+                Ok(self.shown(vec![res]))
+            }
+            Action::Again(arg) => {
+                let n = match arg.trim() {
+                    "" => 1,
+                    n => n
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| (1..=MAX_AGAIN).contains(n))
+                        .ok_or_else(|| EngineError::Usage("again [N]".to_string()))?,
+                };
+                let (cmd, input, steps) = self.last_action()?;
+                self.last_trace = Some(steps);
+                let rolls = (0..n)
+                    .map(|_| self.roll(line, &cmd, &input, None))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(match self.gm {
+                    true => self.shown(rolls),
+                    false => Output::Rolls(rolls),
+                })
+            }
+            // Something we can call `execute()` on.
+            //
+            Action::Execute(cmd, input, steps) => {
+                trace!("exec={:?}", cmd);
+                self.last_trace = Some(steps.clone());
+
+                let (input, note) = split_note(&input);
+                let (input, label) = split_label(input);
+                let note = note.or_else(|| comment(line));
+
+                // Use the default dice if nothing is specified
+                //
+                let takes_dices = matches!(&cmd, Command::Builtin { cmd, .. } if cmd.takes_dices());
+                let input = if takes_dices && input.trim().is_empty() {
+                    self.config.default_dice.as_str()
+                } else {
+                    input
                 }
-                Action::Macros => {
-                    println!("{}", self.macros());
-                    continue;
+                .to_string();
+                let res = self.roll(line, &cmd, &input, note)?;
+                self.last_action = Some((cmd, input, steps));
+                if let Some(label) = label {
+                    self.session.labels.insert(label.to_string(), res.clone());
+                    self.save_session()?;
                 }
-                // Something we can call `execute()` on.
-                //
-                Action::Execute(cmd, input) => {
-                    trace!("exec={:?}", cmd);
+                Ok(self.shown(vec![res]))
+            }
+            // Second roll only if the first passes, `reroll` and `again` repeat the first one
+            //
+            Action::Conditional(cmd, test, cond, then, steps) => {
+                self.last_trace = Some(steps.clone());
+
+                let (then, note) = split_note(&then);
+                let (then, label) = split_label(then);
+                let note = note.or_else(|| comment(line));
 
-                    let res = cmd.execute(&input);
-                    dbg!(&res);
-                    res
+                let mut first = self.roll(line, &cmd, &test, note)?;
+                let passed = cond.passes(first.total());
+                let m = self.config.messages();
+                let outcome = format!("{cond}: {}", m.switch(passed, Msg::Hit, Msg::Miss));
+                first.note = Some(match first.note {
+                    Some(said) => format!("{said}, {outcome}"),
+                    None => outcome,
+                });
+                let mut rolls = vec![first];
+                if passed {
+                    rolls.push(self.roll(line, &cmd, then, note)?);
                 }
-                Action::Error(s) => Err(anyhow!("impossible action: {}", s)),
-            };
-            match res {
-                Ok(res) => info!("roll = {:?}", res),
-                Err(e) => error!("{}", e.to_string()),
+                self.last_action = Some((cmd, test, steps));
+                if let Some(label) = label {
+                    let res = rolls.last().cloned().unwrap_or_default();
+                    self.session.labels.insert(label.to_string(), res);
+                    self.save_session()?;
+                }
+                Ok(self.shown(rolls))
             }
         }
+    }
+
+    /// Execute an already compiled dice command, recording the result annotated with `note`
+    ///
+    fn roll(
+        &mut self,
+        line: &str,
+        cmd: &Command,
+        input: &str,
+        note: Option<&str>,
+    ) -> Result<Res, EngineError> {
+        if let Command::Builtin { cmd: Cmd::Dice, .. } = cmd {
+            self.lint(input).iter().for_each(|l| warn!("{l}"));
+        }
+        let res = cmd.execute_with(input, &self.config);
+        trace!("roll={res:?}");
+        self.keep(line, cmd, input, res?, note)
+    }
+
+    /// Record and count a roll already made, annotated with `note`
+    ///
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_variables))]
+    fn keep(
+        &mut self,
+        line: &str,
+        cmd: &Command,
+        input: &str,
+        mut res: Res,
+        note: Option<&str>,
+    ) -> Result<Res, EngineError> {
+        res.player = self.player.clone();
+        if let Some(note) = note {
+            // Keep what the command said, like the margin of `gurps`
+            //
+            res.note = Some(match &res.note {
+                Some(said) => format!("{note}: {said}"),
+                None => note.to_string(),
+            });
+        }
+        self.notify(Event::Rolled {
+            line,
+            res: &res,
+            hidden: self.gm,
+        });
+        self.log_roll(line, &res)?;
+        #[cfg(feature = "sqlite")]
+        self.record(cmd, input, &res)?;
+
+        self.count += 1;
+        if self.gm {
+            self.hidden.push((line.to_string(), res.clone()));
+            return Ok(res);
+        }
+        let hook = match res.flag {
+            Special::Natural => self.config.on_crit.as_ref(),
+            Special::Fumble | Special::Botch => self.config.on_fumble.as_ref(),
+            _ => None,
+        };
+        if let Some(hook) = hook.filter(|_| !self.hooking) {
+            self.hooks.push(hook.clone());
+        }
+        let score = self.scores.entry(self.actor()).or_default();
+        score.0 += 1;
+        score.1 += res.total();
+        self.last = Some(res.clone());
+        Ok(res)
+    }
+
+    /// Turn speech on or off, the first time on registering the speaker as an observer
+    ///
+    pub(crate) fn set_speak(&mut self, on: bool) -> Result<(), EngineError> {
+        #[cfg(feature = "speech")]
+        {
+            if on && self.speaking.is_none() {
+                let cmd = self
+                    .config
+                    .speak_command
+                    .as_deref()
+                    .unwrap_or(speech::SPEAK_COMMAND);
+                let speaking = Arc::new(AtomicBool::new(false));
+                let speaker = speech::Speaker::new(cmd, speaking.clone());
+                self.observers.push(Box::new(speaker));
+                self.speaking = Some(speaking);
+            }
+            if let Some(speaking) = &self.speaking {
+                speaking.store(on, Ordering::Relaxed);
+            }
+        }
+        #[cfg(not(feature = "speech"))]
+        if on {
+            return Err(EngineError::Speech(
+                "built without the speech feature".to_string(),
+            ));
+        }
+        self.speak = on;
         Ok(())
     }
 
+    /// What to show of new rolls: only how many were hidden in GM screen mode
+    ///
+    fn shown(&self, mut rolls: Vec<Res>) -> Output {
+        let m = self.config.messages();
+        let last = self.hidden.len();
+        match (self.gm, rolls.len()) {
+            (false, 1) => Output::Roll(rolls.remove(0)),
+            (false, _) => Output::Rolls(rolls),
+            (true, 1) => Output::Listing(m.fill(Msg::HiddenRoll, &[&last.to_string()])),
+            (true, n) => {
+                let first = (last + 1 - n).to_string();
+                Output::Listing(m.fill(Msg::HiddenRolls, &[&first, &last.to_string()]))
+            }
+        }
+    }
+
+    /// Handle `scores`: number of rolls and average total of each player
+    ///
+    fn scores(&self) -> String {
+        let m = self.config.messages();
+        if self.scores.is_empty() {
+            return m.get(Msg::NoRollYet).to_string();
+        }
+        let nf = self.config.number_format();
+        self.scores
+            .iter()
+            .map(|(player, (n, sum))| {
+                let average = nf.float(*sum as f64 / *n as f64, 1);
+                m.fill(Msg::Score, &[player, &n.to_string(), &average])
+            })
+            .join("\n")
+    }
+
+    /// Handle `reveal`: every hidden roll with the line it came from, then forget them
+    ///
+    fn reveal(&mut self) -> String {
+        let m = self.config.messages();
+        if self.hidden.is_empty() {
+            return m.get(Msg::NoHiddenRoll).to_string();
+        }
+        let nf = self.config.number_format();
+        self.hidden
+            .drain(..)
+            .enumerate()
+            .map(|(i, (line, res))| format!("#{} {line}: {}", i + 1, res.format_localized(&nf, &m)))
+            .join("\n")
+    }
+
+    /// Last dice command executed, for `reroll` and `again`
+    ///
+    fn last_action(&self) -> Result<(Command, String, Trace), EngineError> {
+        self.last_action
+            .clone()
+            .ok_or_else(|| EngineError::NoRoll(String::new()))
+    }
+
+    /// How aliases and macros were expanded for the last executed command, if any
+    ///
+    pub fn last_trace(&self) -> Option<&Trace> {
+        self.last_trace.as_ref()
+    }
+
+    /// Who is rolling: the player given with `as`, else `actor` from the configuration, `$USER`
+    /// by default and `DEFAULT_ACTOR` without it
+    ///
+    pub fn actor(&self) -> String {
+        if let Some(player) = &self.player {
+            return player.clone();
+        }
+        self.config
+            .actor
+            .clone()
+            .or_else(|| env::var("USER").ok())
+            .filter(|actor| !actor.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_ACTOR.to_string())
+    }
+
+    /// Likely mistakes in the arguments of `dice`, like `3D6 =2` or `D6 +20`
+    ///
+    pub fn lint(&self, expr: &str) -> Vec<Lint> {
+        lint::lint(expr, self.config.rules)
+    }
+
     /// Check whether a given command exist
     ///
     pub fn exist(&self, name: &str) -> bool {
-        self.cmds.contains_key(name)
+        self.cmds.contains_key(&name.to_lowercase())
     }
 
-    /// Merge a list of commands into the main engine.
+    /// Can a new command be called `name`?  It has to be alphanumeric and may only replace an
+    /// alias or a macro.
+    ///
+    pub(crate) fn check_name(&self, name: &str) -> Result<(), EngineError> {
+        if name.is_empty() || !name.chars().all(char::is_alphanumeric) {
+            return Err(EngineError::InvalidName(name.to_string()));
+        }
+        match self.cmds.get(&name.to_lowercase()) {
+            None | Some(Command::Alias { .. }) | Some(Command::Macro { .. }) => Ok(()),
+            Some(_) => Err(EngineError::NameTaken(name.to_string())),
+        }
+    }
+
+    /// Merge a list of commands into the main engine.  Builtin and special commands are never
+    /// replaced, an alias or macro with the same name is skipped with a warning.
     ///
     pub fn merge(mut self, aliases: Vec<Command>) -> Self {
-        // And merge in aliases
+        self.insert(aliases);
+        self
+    }
+
+    /// Same as `merge` in place
+    ///
+    fn insert(&mut self, aliases: Vec<Command>) {
+        // And merge in aliases, without shadowing `dice` or `exit`
         //
-        aliases.iter().for_each(|a| match a {
+        aliases.into_iter().for_each(|a| match a {
             Command::Macro { ref name, .. } | Command::Alias { ref name, .. } => {
-                self.cmds.insert(name.to_owned(), a.to_owned());
+                let key = name.to_lowercase();
+                match self.cmds.get(&key) {
+                    None | Some(Command::Alias { .. }) | Some(Command::Macro { .. }) => {
+                        self.cmds.insert(key, a);
+                    }
+                    Some(_) => warn!("{name} is a builtin command, not redefined"),
+                }
             }
             _ => (),
         });
-        self
+        self.reload = true;
     }
 
     /// Lists all available commands
@@ -171,6 +903,8 @@ impl Engine {
                 let tag = match c {
                     Command::Alias { .. } => "alias",
                     Command::Builtin { .. } => "builtin",
+                    Command::Mechanic { .. } => "mechanic",
+                    Command::Custom { .. } => "custom",
                     Command::Macro { .. } => "macro",
                     _ => "special",
                 };
@@ -183,8 +917,8 @@ impl Engine {
     ///
     pub fn aliases(&self) -> String {
         self.cmds
-            .iter()
-            .filter_map(|(_name, cmd, ..)| match cmd {
+            .values()
+            .filter_map(|cmd| match cmd {
                 Command::Alias { name, cmd } => Some((name.to_owned(), cmd)),
                 _ => None,
             })
@@ -196,8 +930,8 @@ impl Engine {
     ///
     pub fn macros(&self) -> String {
         self.cmds
-            .iter()
-            .filter_map(|(_name, cmd)| match cmd {
+            .values()
+            .filter_map(|cmd| match cmd {
                 Command::Macro { name, cmd } => Some((name.to_owned(), cmd)),
                 _ => None,
             })
@@ -205,14 +939,244 @@ impl Engine {
             .join("\n")
     }
 
+    /// Returns all variables, sorted by name
+    ///
+    pub fn vars(&self) -> String {
+        let modifiers = self
+            .modifiers()
+            .into_iter()
+            .filter(|(n, _)| !self.session.vars.contains_key(n))
+            .sorted()
+            .map(|(n, v)| format!("char \t{n} = {v}"));
+        self.session
+            .vars
+            .iter()
+            .sorted()
+            .map(|(n, v)| format!("var \t{n} = {v}"))
+            .chain(modifiers)
+            .join("\n")
+    }
+
+    /// Last roll or, if `name` is not empty, the last one labelled `name`
+    ///
+    pub fn last_roll(&self, name: &str) -> Result<&Res, EngineError> {
+        let res = match name {
+            "" => self.last.as_ref(),
+            name => self.session.labels.get(name),
+        };
+        res.ok_or_else(|| EngineError::NoRoll(name.to_string()))
+    }
+
+    /// All values usable as `$name`: modifiers of the character in use, variables and totals
+    /// of labelled rolls
+    ///
+    pub fn variables(&self) -> HashMap<String, i64> {
+        let labels = self
+            .session
+            .labels
+            .iter()
+            .map(|(n, r)| (n.clone(), r.total() as i64));
+        self.modifiers()
+            .into_iter()
+            .chain(self.session.vars.clone())
+            .chain(labels)
+            .collect()
+    }
+
+    /// Modifiers of the character in use, if any
+    ///
+    fn modifiers(&self) -> HashMap<String, i64> {
+        self.character()
+            .map(|c| c.modifiers.clone().into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Handle `set <name> [value]`, without a value the variable is removed
+    ///
+    pub fn set(&mut self, args: &str) -> Result<(), EngineError> {
+        let usage = || EngineError::Usage("set <name> [value]".to_string());
+
+        let args = args.split_whitespace().collect::<Vec<_>>();
+        let (name, value) = match args[..] {
+            [name] => (name, None),
+            [name, value] => (name, Some(value)),
+            _ => return Err(usage()),
+        };
+        if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err(usage());
+        }
+        match value {
+            Some(v) => {
+                let v = v.parse::<i64>().map_err(|_| usage())?;
+                self.session.vars.insert(name.to_string(), v);
+            }
+            None => {
+                self.session.vars.remove(name);
+            }
+        }
+        self.save_session()
+    }
+
+    /// Save the session, unless in a branch
+    ///
+    fn save_session(&mut self) -> Result<(), EngineError> {
+        match self.branches.is_empty() {
+            true => self.storage.save(&self.session),
+            false => Ok(()),
+        }
+    }
+
+    /// Keep the session in `storage`, loading what is already there
+    ///
+    pub fn with_storage(mut self, storage: Box<dyn Storage>) -> Result<Self, EngineError> {
+        self.session = storage.load()?;
+        self.storage = storage;
+        Ok(self)
+    }
+
+    /// Create an engine with only the given commands instead of the default builtins
+    ///
+    pub fn from_commands(cmds: HashMap<String, Command>) -> Self {
+        Engine::from(cmds)
+    }
+
+    /// Replace all commands with the ones defined in `fname`, in the same YAML format as the
+    /// compiled-in `commands.yaml`
+    ///
+    pub fn with_commands_file(mut self, fname: &Path) -> Result<Self, EngineError> {
+        trace!("with_commands_file({fname:?})");
+        let content =
+            fs::read_to_string(fname).map_err(|e| EngineError::Commands(e.to_string()))?;
+        self.cmds = parse_commands(&content)?;
+        Ok(self)
+    }
+
     /// Build a list of `Command` from the builtin commands using a YAML file representing
     /// the list of commands and their type
     ///
-    fn builtin_commands() -> Engine {
+    fn builtin_commands() -> Result<Engine, EngineError> {
         trace!("builtin_commands(commands.yaml)");
-        let all: HashMap<String, Command> =
-            serde_yaml::from_str(include_str!("../bin/dices/commands.yaml")).unwrap();
-        Engine { cmds: all }
+        Ok(Engine::from(parse_commands(DEFAULT_COMMANDS)?))
+    }
+}
+
+/// Parse a list of commands in YAML
+///
+fn parse_commands(content: &str) -> Result<HashMap<String, Command>, EngineError> {
+    serde_yaml::from_str(content)
+        .map(lower_keys)
+        .map_err(|e| EngineError::Commands(e.to_string()))
+}
+
+/// Command names are case-insensitive, keep them all in lowercase
+///
+fn lower_keys(cmds: HashMap<String, Command>) -> HashMap<String, Command> {
+    cmds.into_iter()
+        .map(|(name, cmd)| (name.to_lowercase(), cmd))
+        .collect()
+}
+
+/// New state of an `on`/`off` switch, toggled if `arg` is empty
+///
+fn toggle(arg: &str, current: bool, usage: &str) -> Result<bool, EngineError> {
+    match arg.trim() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        "" => Ok(!current),
+        _ => Err(EngineError::Usage(usage.to_string())),
+    }
+}
+
+/// How to call the branch at `depth`
+///
+fn branch_name(m: &Messages, depth: usize) -> String {
+    match depth {
+        0 => m.get(Msg::Session).to_string(),
+        n => m.fill(Msg::Branch, &[&n.to_string()]),
+    }
+}
+
+/// Split `<args> as <label>` into the arguments and the label
+///
+pub(crate) fn split_label(input: &str) -> (&str, Option<&str>) {
+    match input.rsplit_once(" as ") {
+        Some((args, label))
+            if !label.trim().is_empty()
+                && label
+                    .trim()
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_') =>
+        {
+            (args, Some(label.trim()))
+        }
+        _ => (input, None),
+    }
+}
+
+/// Split `<player> <command>` given to `as`, a player name is like a label
+///
+fn split_player(input: &str) -> Option<(&str, &str)> {
+    input
+        .trim()
+        .split_once(char::is_whitespace)
+        .filter(|(player, cmd)| {
+            !cmd.trim().is_empty() && player.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+        .map(|(player, cmd)| (player, cmd.trim_start()))
+}
+
+/// Split `<args> "<note>"` into the arguments and the note
+///
+pub(crate) fn split_note(input: &str) -> (&str, Option<&str>) {
+    let Some(rest) = input.trim_end().strip_suffix('"') else {
+        return (input, None);
+    };
+    match rest.rsplit_once('"') {
+        Some((args, note)) if !note.trim().is_empty() => (args, Some(note.trim())),
+        _ => (input, None),
+    }
+}
+
+impl From<HashMap<String, Command>> for Engine {
+    /// Create an engine with a given set of commands
+    ///
+    fn from(cmds: HashMap<String, Command>) -> Self {
+        Engine {
+            cmds: lower_keys(cmds),
+            config: Config::default(),
+            last: None,
+            count: 0,
+            speak: false,
+            #[cfg(feature = "speech")]
+            speaking: None,
+            trace: false,
+            gm: false,
+            hidden: Vec::new(),
+            player: None,
+            scores: BTreeMap::new(),
+            last_trace: None,
+            last_action: None,
+            session: Session::default(),
+            storage: Box::<MemoryStorage>::default(),
+            alias_file: None,
+            alias_set: aliases::AliasSet::default(),
+            profile: None,
+            branches: Vec::new(),
+            #[cfg(feature = "sqlite")]
+            rolldb: None,
+            roll_log: None,
+            characters: Vec::new(),
+            tables: BTreeMap::new(),
+            deck: None,
+            character: None,
+            shadowed: Vec::new(),
+            reload: false,
+            observers: Vec::new(),
+            custom: HashMap::new(),
+            hooks: Vec::new(),
+            hooking: false,
+            history: Vec::new(),
+        }
     }
 }
 
@@ -246,6 +1210,34 @@ mod tests {
             ("list".to_string(), Command::List),
             ("aliases".to_string(), Command::Aliases),
             ("macros".to_string(), Command::Macros),
+            ("speak".to_string(), Command::Speak),
+            ("set".to_string(), Command::Set),
+            ("last".to_string(), Command::Last),
+            ("version".to_string(), Command::Version),
+            ("banner".to_string(), Command::Banner),
+            ("help".to_string(), Command::Help),
+            ("vars".to_string(), Command::Vars),
+            ("query".to_string(), Command::Query),
+            ("branch".to_string(), Command::Branch),
+            ("merge".to_string(), Command::Merge),
+            ("discard".to_string(), Command::Discard),
+            ("trace".to_string(), Command::Trace),
+            ("reroll".to_string(), Command::Reroll),
+            ("again".to_string(), Command::Again),
+            ("char".to_string(), Command::Char),
+            ("profile".to_string(), Command::Profile),
+            ("usage".to_string(), Command::Usage),
+            ("table".to_string(), Command::Table),
+            ("deck".to_string(), Command::Deck),
+            ("prob".to_string(), Command::Prob),
+            ("anydice".to_string(), Command::Anydice),
+            ("unalias".to_string(), Command::Unalias),
+            ("undef".to_string(), Command::Unalias),
+            ("gm".to_string(), Command::Gm),
+            ("reveal".to_string(), Command::Reveal),
+            ("as".to_string(), Command::As),
+            ("scores".to_string(), Command::Scores),
+            ("rhai".to_string(), Command::Script),
             (
                 "open".to_string(),
                 Command::Builtin {
@@ -253,9 +1245,58 @@ mod tests {
                     cmd: Cmd::Open,
                 },
             ),
+            (
+                "open100".to_string(),
+                Command::Builtin {
+                    name: "open100".to_string(),
+                    cmd: Cmd::Open100,
+                },
+            ),
+            (
+                "flip".to_string(),
+                Command::Builtin {
+                    name: "flip".to_string(),
+                    cmd: Cmd::Flip,
+                },
+            ),
+            (
+                "choose".to_string(),
+                Command::Builtin {
+                    name: "choose".to_string(),
+                    cmd: Cmd::Choose,
+                },
+            ),
+            (
+                "gurps".to_string(),
+                Command::Builtin {
+                    name: "gurps".to_string(),
+                    cmd: Cmd::RollUnder,
+                },
+            ),
+            (
+                "wod".to_string(),
+                Command::Builtin {
+                    name: "wod".to_string(),
+                    cmd: Cmd::Pool,
+                },
+            ),
+            (
+                "ironsworn".to_string(),
+                Command::Builtin {
+                    name: "ironsworn".to_string(),
+                    cmd: Cmd::Action,
+                },
+            ),
+            (
+                "bitd".to_string(),
+                Command::Builtin {
+                    name: "bitd".to_string(),
+                    cmd: Cmd::Forged,
+                },
+            ),
         ]);
 
-        let n = Engine::builtin_commands();
+        let n = Engine::builtin_commands().unwrap();
         all.into_iter().for_each(|(name, cmd)| {
             assert!(n.cmds.contains_key(&name));
             assert_eq!(&cmd, n.cmds.get(&name).unwrap());
@@ -294,19 +1335,699 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_engine_merge_builtin() {
+        let n = Engine::new().merge(vec![
+            Command::Macro {
+                name: "Dice".to_string(),
+                cmd: "dice 3D6".to_string(),
+            },
+            Command::Alias {
+                name: "exit".to_string(),
+                cmd: "list".to_string(),
+            },
+            Command::Alias {
+                name: "roll".to_string(),
+                cmd: "open".to_string(),
+            },
+        ]);
+
+        assert!(matches!(n.cmds.get("dice"), Some(Command::Builtin { .. })));
+        assert_eq!(Some(&Command::Exit), n.cmds.get("exit"));
+        assert!(matches!(n.cmds.get("roll"), Some(Command::Alias { cmd, .. }) if cmd == "open"));
+    }
+
+    #[test]
+    fn test_engine_lint() {
+        let mut e = Engine::new();
+
+        assert_eq!(vec![Lint::Equals("=2".to_string())], e.lint(" 3D6 =2"));
+        assert!(e.lint("3D6 +2").is_empty());
+
+        // Only a warning, the roll is still done
+        //
+        assert!(matches!(e.eval("dice D6 +20"), Ok(Output::Roll(_))));
+    }
+
     #[rstest]
     #[case("list", true)]
     #[case("exit", true)]
+    #[case("List", true)]
     #[case("foo", false)]
     fn test_engine_exist(#[case] input: &str, #[case] value: bool) {
-        let e = Engine::builtin_commands();
+        let e = Engine::builtin_commands().unwrap();
         assert_eq!(value, e.exist(input));
     }
 
+    #[test]
+    fn test_engine_from() {
+        let e = Engine::from(HashMap::from([("exit".to_string(), Command::Exit)]));
+
+        assert!(e.exist("exit"));
+        assert!(!e.exist("dice"));
+    }
+
+    #[test]
+    fn test_engine_with_config() {
+        let cfg = Config {
+            prompt: "> ".to_string(),
+            ..Default::default()
+        };
+
+        let e = Engine::new().with_config(cfg.clone());
+        assert_eq!(cfg, e.config);
+    }
+
+    #[rstest]
+    #[case(Output::Empty, r#"{"type":"empty"}"#)]
+    #[case(Output::Listing("trace is on".to_string()), r#"{"type":"listing","value":"trace is on"}"#)]
+    #[case(
+        Output::Chained(vec![Output::Exit]),
+        r#"{"type":"chained","value":[{"type":"exit"}]}"#
+    )]
+    fn test_output_json(#[case] out: Output, #[case] want: &str) {
+        assert_eq!(want, serde_json::to_string(&out).unwrap());
+    }
+
+    #[test]
+    fn test_output_json_roll() {
+        let mut e = Engine::new();
+        let out = e.eval("dice 3d6 +1").unwrap();
+
+        let json = serde_json::to_value(&out).unwrap();
+        assert_eq!("roll", json["type"]);
+        assert_eq!(1, json["value"]["bonus"]);
+    }
+
+    #[rstest]
+    #[case("dice d6 crit 1-6", 2)]
+    #[case("dice d6 fumble 1-6", 3)]
+    #[case("dice d6 crit 7", 1)]
+    fn test_engine_hooks(#[case] line: &str, #[case] want: usize) {
+        let cfg = Config {
+            on_crit: Some("dice 2d6".to_string()),
+            on_fumble: Some("dice 3d6 fumble 3-18".to_string()),
+            ..Default::default()
+        };
+        let mut e = Engine::new().with_config(cfg);
+
+        let dices = match e.eval(line).unwrap() {
+            Output::Chained(all) => match &all[..] {
+                [Output::Roll(_), Output::Roll(hook)] => hook.list.len(),
+                all => panic!("{all:?}"),
+            },
+            Output::Roll(res) => res.list.len(),
+            out => panic!("{out:?}"),
+        };
+        assert_eq!(want, dices);
+
+        // `reroll` is about the line, not its hook
+        //
+        let (_, input, _) = e.last_action().unwrap();
+        assert_eq!(line.strip_prefix("dice"), Some(input.as_str()));
+    }
+
+    #[test]
+    fn test_engine_hooks_gm() {
+        let cfg = Config {
+            on_crit: Some("dice 2d6".to_string()),
+            ..Default::default()
+        };
+        let mut e = Engine::new().with_config(cfg);
+        e.eval("gm on").unwrap();
+
+        assert_eq!(
+            Output::Listing("hidden roll #1".to_string()),
+            e.eval("dice d6 crit 1-6").unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case(RollerKind::Fast)]
+    #[case(RollerKind::Legacy)]
+    #[case(RollerKind::Crypto)]
+    fn test_engine_with_roller(#[case] kind: RollerKind) {
+        let mut e = Engine::new().with_roller(kind);
+        assert_eq!(kind, e.config.roller);
+
+        let Ok(Output::Roll(res)) = e.eval("dice 4D6") else {
+            panic!("no roll")
+        };
+        assert!(res.list.iter().all(|d| (1..=6).contains(d)));
+    }
+
     #[test]
     fn test_aliases() {
-        let e = Engine::builtin_commands();
+        let e = Engine::builtin_commands().unwrap();
         let v_str = e.aliases();
         assert!(v_str.is_empty());
     }
+
+    #[test]
+    fn test_engine_set() {
+        let mut e = Engine::new();
+
+        assert!(e.set("str 4").is_ok());
+        assert!(e.set("dex_bonus -1").is_ok());
+        assert_eq!("var \tdex_bonus = -1\nvar \tstr = 4", e.vars());
+
+        assert!(e.set("dex_bonus").is_ok());
+        assert_eq!("var \tstr = 4", e.vars());
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("str four")]
+    #[case("st.r 4")]
+    #[case("str 4 5")]
+    fn test_engine_set_error(#[case] input: &str) {
+        let mut e = Engine::new();
+        assert!(matches!(e.set(input), Err(EngineError::Usage(_))));
+    }
+
+    #[rstest]
+    #[case(" d20+5 as attack", " d20+5", Some("attack"))]
+    #[case(" d20+5", " d20+5", None)]
+    #[case(" as dmg", "", Some("dmg"))]
+    #[case(" d6 as two words", " d6 as two words", None)]
+    fn test_split_label(#[case] input: &str, #[case] args: &str, #[case] label: Option<&str>) {
+        assert_eq!((args, label), split_label(input));
+    }
+
+    #[rstest]
+    #[case(" Alice dice d20+3", Some(("Alice", "dice d20+3")))]
+    #[case(" Bob  doom as dmg", Some(("Bob", "doom as dmg")))]
+    #[case(" Alice", None)]
+    #[case(" Alice ", None)]
+    #[case(" Al!ce dice", None)]
+    #[case("", None)]
+    fn test_split_player(#[case] input: &str, #[case] want: Option<(&str, &str)>) {
+        assert_eq!(want, split_player(input));
+    }
+
+    #[rstest]
+    #[case(" 2d6+4 \"goblin damage\"", " 2d6+4 ", Some("goblin damage"))]
+    #[case(" 2d6+4 as dmg \" goblin \" ", " 2d6+4 as dmg ", Some("goblin"))]
+    #[case(" 2d6+4", " 2d6+4", None)]
+    #[case(" 2d6+4 \"\"", " 2d6+4 \"\"", None)]
+    #[case(" goblin\"", " goblin\"", None)]
+    fn test_split_note(#[case] input: &str, #[case] args: &str, #[case] note: Option<&str>) {
+        assert_eq!((args, note), split_note(input));
+    }
+
+    #[test]
+    fn test_engine_note() {
+        let mut e = Engine::new();
+
+        let Ok(Output::Roll(res)) = e.eval("dice 2d6+4 \"goblin damage\" ") else {
+            panic!("no roll");
+        };
+        assert_eq!(Some("goblin damage"), res.note.as_deref());
+
+        let Ok(Output::Roll(res)) = e.eval("dice 2d6+4 as dmg # goblin damage") else {
+            panic!("no roll");
+        };
+        assert_eq!(Some("goblin damage"), res.note.as_deref());
+        assert_eq!(Ok(res), e.last_roll("dmg").cloned());
+
+        let Ok(Output::Roll(res)) = e.eval("reroll") else {
+            panic!("no roll");
+        };
+        assert_eq!(None, res.note);
+
+        let Ok(Output::Roll(res)) = e.eval("gurps 12 # dodge") else {
+            panic!("no roll");
+        };
+        assert!(res.note.unwrap().starts_with("dodge: "));
+    }
+
+    #[test]
+    fn test_engine_last_roll() {
+        let mut e = Engine::new();
+        assert_eq!(Err(EngineError::NoRoll("".to_string())), e.last_roll(""));
+
+        let mut res = Res::new();
+        res.append(15);
+        e.last = Some(res.clone());
+        e.session.labels.insert("attack".to_string(), res.clone());
+        e.set("str 2").unwrap();
+
+        assert_eq!(Ok(&res), e.last_roll(""));
+        assert_eq!(Ok(&res), e.last_roll("attack"));
+        assert!(e.last_roll("damage").is_err());
+        assert_eq!(Some(&15), e.variables().get("attack"));
+        assert_eq!(Some(&2), e.variables().get("str"));
+    }
+
+    #[test]
+    fn test_engine_run_batch() {
+        let mut e = Engine::new();
+        let input = "set str 2\n\ndice d20 +$str as attack\nfoo\ndice 2d6 +$attack\nexit\nfoo\n";
+
+        assert_eq!(Ok(1), e.run_batch(input.as_bytes()));
+        assert_eq!(2, e.count);
+        assert!(e.last_roll("attack").is_ok());
+    }
+
+    #[test]
+    fn test_engine_run() {
+        let mut e = Engine::new();
+        let mut out = io::WriteSink::new(vec![]);
+        let input = "set str 2\nvars\n\nfoo\ndice 2d6 +$str\nexit\ndice 3d6\n";
+
+        assert_eq!(
+            Ok(1),
+            e.run(&mut BufSource::new(input.as_bytes()), &mut out)
+        );
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(3, lines.len());
+        assert_eq!("var \tstr = 2", lines[0]);
+        assert!(lines[1].starts_with("error: line 4: "));
+        assert!(lines[2].starts_with("total: "));
+    }
+
+    #[test]
+    fn test_engine_run_commands_changed() {
+        let mut e = Engine::new().with(Some(PathBuf::from("testdata/aliases.profiles")));
+        let mut out = io::WriteSink::new(vec![]);
+        let input = "moving\nprofile rolemaster; moving\nunalias moving\nmoving\n";
+
+        assert_eq!(
+            Ok(2),
+            e.run(&mut BufSource::new(input.as_bytes()), &mut out)
+        );
+        assert_eq!(1, e.count);
+    }
+
+    #[test]
+    fn test_engine_run_sequence() {
+        let mut e = Engine::new();
+        let mut out = io::WriteSink::new(vec![]);
+        let input =
+            "dice d20; dice 2d6+3\nset s 2 ; foo;dice d20 +$s\ndice \\\n3d6\n;;\nexit; dice d4\n";
+
+        assert_eq!(
+            Ok(1),
+            e.run(&mut BufSource::new(input.as_bytes()), &mut out)
+        );
+        assert_eq!(4, e.count);
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        assert_eq!(4, out.lines().filter(|l| l.starts_with("total: ")).count());
+        assert!(out.contains("error: line 2: "));
+    }
+
+    #[test]
+    fn test_engine_run_history() {
+        // Lines remembered by the source
+        struct Remembered(BufSource<&'static [u8]>, Vec<String>);
+
+        impl LineSource for Remembered {
+            fn next_line(&mut self, prompt: &str) -> Result<Option<String>, EngineError> {
+                self.0.next_line(prompt)
+            }
+
+            fn remember(&mut self, line: &str) {
+                self.1.push(line.to_string());
+            }
+        }
+
+        let mut e = Engine::new();
+        let mut out = io::WriteSink::new(vec![]);
+        let mut src = Remembered(
+            BufSource::new("dice 3d6\n\n!! +1; !3\n # hidden\n flip\nexit\n".as_bytes()),
+            vec![],
+        );
+
+        assert_eq!(Ok(1), e.run(&mut src, &mut out));
+        assert_eq!(vec!["dice 3d6", "dice 3d6 +1", " flip", "exit"], src.1);
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        assert!(out.contains("dice 3d6 +1\n"));
+        assert!(out.contains("!3: event not found"));
+    }
+
+    #[test]
+    fn test_engine_eval_all() {
+        let mut e = Engine::new();
+
+        let all = e.eval_all("set str 2; dice d20 +$str; foo; exit; dice d4");
+        assert_eq!(4, all.len());
+        assert_eq!(Ok(Output::Empty), all[0]);
+        assert!(matches!(&all[1], Ok(Output::Roll(r)) if r.bonus == 2));
+        assert!(all[2].is_err());
+        assert_eq!(Ok(Output::Exit), all[3]);
+        assert_eq!(1, e.count);
+        assert!(e.eval_all("  ").is_empty());
+    }
+
+    #[test]
+    fn test_engine_run_trace() {
+        let mut e = Engine::new().with(Some(PathBuf::from("testdata/aliases")));
+        let mut out = io::WriteSink::new(vec![]);
+        let input = "doom\ntrace on\ndice 2D6\ndoom\n";
+
+        assert_eq!(
+            Ok(0),
+            e.run(&mut BufSource::new(input.as_bytes()), &mut out)
+        );
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        let traces = out
+            .lines()
+            .filter(|l| l.starts_with("trace: "))
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["trace: doom -> dice 2D6"], traces);
+        assert_eq!("doom -> dice 2D6", e.last_trace().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_engine_eval() {
+        let mut e = Engine::new();
+
+        assert_eq!(Ok(Output::Empty), e.eval("set str 2"));
+        let Ok(Output::Roll(res)) = e.eval("dice d20 +$str as attack") else {
+            panic!("no roll");
+        };
+        assert_eq!(2, res.bonus);
+        assert_eq!(Ok(Output::Roll(res)), e.eval("last attack"));
+        assert_eq!(1, e.count);
+
+        let Ok(Output::Roll(res)) = e.eval("3d6+$str") else {
+            panic!("no roll");
+        };
+        assert_eq!(3, res.list.len());
+        assert_eq!(2, res.bonus);
+        assert_eq!(2, e.count);
+
+        assert_eq!(Ok(Output::Empty), e.eval("# heading for this fight"));
+        assert!(matches!(
+            e.eval("dice d20 // initiative"),
+            Ok(Output::Roll(_))
+        ));
+        assert_eq!(3, e.count);
+
+        assert_eq!(Ok(Output::Exit), e.eval("exit"));
+        assert!(e.eval("foo").is_err());
+    }
+
+    #[test]
+    fn test_engine_reroll() {
+        let mut e = Engine::new().with(Some(PathBuf::from("testdata/aliases")));
+
+        assert_eq!(Err(EngineError::NoRoll("".to_string())), e.eval("reroll"));
+        e.eval("set str 2").unwrap();
+        e.eval("mouv +$str as first").unwrap();
+        e.eval("set str 5").unwrap();
+
+        let Ok(Output::Roll(res)) = e.eval("reroll") else {
+            panic!("no roll");
+        };
+        assert_eq!(-9 + 7 + 2, res.bonus);
+        assert_eq!(3, res.list.len());
+        assert_eq!("mouv +$str as first", e.last_trace().unwrap().0[0]);
+
+        let Ok(Output::Rolls(all)) = e.eval("again 3") else {
+            panic!("no rolls");
+        };
+        assert_eq!(3, all.len());
+        assert!(all.iter().all(|r| r.bonus == 0));
+        assert_eq!(Some(&all[2]), e.last.as_ref());
+        assert_eq!(5, e.count);
+    }
+
+    #[test]
+    fn test_engine_gm() {
+        let mut e = Engine::new();
+        e.eval("dice 3D6").unwrap();
+        let shown = e.last.clone();
+
+        assert_eq!(
+            Ok(Output::Listing("gm screen is on".to_string())),
+            e.eval("gm on")
+        );
+        assert_eq!(
+            Ok(Output::Listing("hidden roll #1".to_string())),
+            e.eval("dice 2D6 +1")
+        );
+        assert_eq!(
+            Ok(Output::Listing("hidden rolls #2 to #3".to_string())),
+            e.eval("again 2")
+        );
+        assert_eq!(shown, e.last);
+        assert_eq!(4, e.count);
+
+        let Ok(Output::Listing(all)) = e.eval("reveal") else {
+            panic!("nothing revealed");
+        };
+        let all = all.lines().collect::<Vec<_>>();
+        assert_eq!(3, all.len());
+        assert!(all[0].starts_with("#1 dice 2D6 +1: total: "), "{}", all[0]);
+        assert!(all[2].starts_with("#3 again 2: total: "), "{}", all[2]);
+        assert_eq!(
+            Ok(Output::Listing("no hidden roll".to_string())),
+            e.eval("reveal")
+        );
+
+        e.eval("gm").unwrap();
+        assert!(matches!(e.eval("dice 2D6"), Ok(Output::Roll(_))));
+        assert!(e.reveal().starts_with("no"));
+    }
+
+    #[test]
+    fn test_engine_as() {
+        let mut e = Engine::new().with(Some(PathBuf::from("testdata/aliases")));
+        e.config.actor = Some("GM".to_string());
+        assert_eq!(
+            Ok(Output::Listing("no roll yet".to_string())),
+            e.eval("scores")
+        );
+
+        let Ok(Output::Roll(res)) = e.eval("as Alice dice 2D6 +3") else {
+            panic!("no roll");
+        };
+        assert_eq!(Some("Alice"), res.player.as_deref());
+        assert!(res.to_string().starts_with("Alice: total: "));
+        assert_eq!("GM", e.actor());
+        e.config.actor = Some(" ".to_string());
+        assert_eq!(DEFAULT_ACTOR, e.actor());
+        e.config.actor = Some("GM".to_string());
+
+        e.eval("as Alice mouv as first").unwrap();
+        e.eval("dice 1D6").unwrap();
+        assert_eq!(None, e.last.as_ref().unwrap().player);
+        assert_eq!(
+            Some("Alice"),
+            e.last_roll("first").unwrap().player.as_deref()
+        );
+
+        let Ok(Output::Listing(scores)) = e.eval("scores") else {
+            panic!("no scores");
+        };
+        let scores = scores.lines().collect::<Vec<_>>();
+        assert!(scores[1].starts_with("GM\t1 roll(s), average "));
+        assert!(scores[0].starts_with("Alice\t2 roll(s), average "));
+    }
+
+    #[rstest]
+    #[case("as")]
+    #[case("as Alice")]
+    #[case("as Al!ce dice d6")]
+    fn test_engine_as_usage(#[case] line: &str) {
+        assert_eq!(
+            Err(EngineError::Usage("as <player> <command>".to_string())),
+            Engine::new().eval(line)
+        );
+    }
+
+    #[rstest]
+    #[case("dice d20+30 >= 16 then 2d6+4", vec![">= 16: hit", ""])]
+    #[case("dice d20 > 20 then 2d6+4", vec!["> 20: miss"])]
+    #[case("open d6 +100 > 0 then d4 \"bite\"", vec!["bite, > 0: hit", "bite"])]
+    #[case("dice 2d6r<3 +30 >= 7 then 2d6", vec![">= 7: hit", ""])]
+    #[case("dice d20 \"hide then run\"", vec!["hide then run"])]
+    fn test_engine_conditional(#[case] line: &str, #[case] notes: Vec<&str>) {
+        let mut e = Engine::new();
+
+        let all = match e.eval(line) {
+            Ok(Output::Roll(res)) => vec![res],
+            Ok(Output::Rolls(all)) => all,
+            other => panic!("{other:?}"),
+        };
+        let got = all
+            .iter()
+            .map(|r| r.note.as_deref().unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(notes, got);
+        assert_eq!(all.len(), e.count);
+        assert_eq!(all.last().unwrap().list, e.last.as_ref().unwrap().list);
+    }
+
+    #[rstest]
+    #[case("dice d20+30 >= 16 then 2d6+4", vec![">= 16: réussi", ""])]
+    #[case("dice d20 > 20 then 2d6+4", vec!["> 20: raté"])]
+    fn test_engine_conditional_french(#[case] line: &str, #[case] notes: Vec<&str>) {
+        let mut e = Engine::new();
+        e.config.locale = Some("fr_FR.UTF-8".to_string());
+
+        let all = match e.eval(line) {
+            Ok(Output::Roll(res)) => vec![res],
+            Ok(Output::Rolls(all)) => all,
+            other => panic!("{other:?}"),
+        };
+        let got = all
+            .iter()
+            .map(|r| r.note.as_deref().unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(notes, got);
+    }
+
+    #[test]
+    fn test_engine_conditional_label() {
+        let mut e = Engine::new();
+
+        let Ok(Output::Rolls(all)) = e.eval("dice d20+30 >= 16 then 2d6+4 as dmg") else {
+            panic!("no rolls");
+        };
+        assert_eq!(Ok(&all[1]), e.last_roll("dmg"));
+
+        let Ok(Output::Roll(res)) = e.eval("reroll") else {
+            panic!("no roll");
+        };
+        assert_eq!(30, res.bonus);
+    }
+
+    #[test]
+    fn test_engine_gm_usage() {
+        assert_eq!(
+            Err(EngineError::Usage("gm [on|off]".to_string())),
+            Engine::new().eval("gm maybe")
+        );
+    }
+
+    #[test]
+    fn test_engine_no_default_dice() {
+        let mut e = Engine::new();
+
+        let Ok(Output::Roll(res)) = e.eval("flip") else {
+            panic!("no flip");
+        };
+        assert_eq!(1, res.symbols.values().sum::<usize>());
+        assert!(e.eval("open100").is_ok());
+        assert!(matches!(e.eval("choose"), Err(EngineError::Usage(_))));
+    }
+
+    #[rstest]
+    #[case("again 0")]
+    #[case("again -1")]
+    #[case("again many")]
+    #[case("again 101")]
+    #[case("again 18446744073709551616")]
+    fn test_engine_again_usage(#[case] line: &str) {
+        let mut e = Engine::new();
+
+        e.eval("dice 3D6").unwrap();
+        assert_eq!(
+            Err(EngineError::Usage("again [N]".to_string())),
+            e.eval(line)
+        );
+    }
+
+    #[rstest]
+    #[case("vars", "var \tstr = 2")]
+    #[case("branch", "branch 1")]
+    #[case("speak off", "speech is off")]
+    #[case("trace", "trace is on")]
+    fn test_engine_eval_listing(#[case] line: &str, #[case] want: &str) {
+        let mut e = Engine::new();
+        e.set("str 2").unwrap();
+
+        assert_eq!(Ok(Output::Listing(want.to_string())), e.eval(line));
+    }
+
+    #[test]
+    fn test_engine_eval_french() {
+        let mut e = Engine::new();
+        e.config.locale = Some("fr_FR.UTF-8".to_string());
+
+        let listing = |e: &mut Engine, line| match e.eval(line) {
+            Ok(Output::Listing(text)) => text,
+            other => panic!("{line}: {other:?}"),
+        };
+        assert_eq!("aucun jet pour l'instant", listing(&mut e, "scores"));
+        assert_eq!("branche 1", listing(&mut e, "branch"));
+        assert_eq!("écran du MJ activé", listing(&mut e, "gm on"));
+        assert_eq!("jet caché n°1", listing(&mut e, "dice d6"));
+        assert_eq!("abandonnée, retour à session", listing(&mut e, "discard"));
+        assert_eq!("aucun jet caché", listing(&mut e, "reveal"));
+        assert_eq!("trace activée", listing(&mut e, "trace on"));
+    }
+
+    #[test]
+    fn test_engine_speak() {
+        let mut e = Engine::new();
+        e.config.speak_command = Some("cat".to_string());
+
+        let on = e.eval("speak on");
+        #[cfg(feature = "speech")]
+        assert_eq!(Ok(Output::Listing("speech is on".to_string())), on);
+        #[cfg(not(feature = "speech"))]
+        assert!(matches!(on, Err(EngineError::Speech(_))));
+        assert!(e.eval("dice d6").is_ok());
+        assert_eq!(
+            Ok(Output::Listing("speech is off".to_string())),
+            e.eval("speak off")
+        );
+    }
+
+    #[test]
+    fn test_engine_from_commands() {
+        let e = Engine::from_commands(HashMap::from([
+            ("exit".to_string(), Command::Exit),
+            (
+                "roll".to_string(),
+                Command::Builtin {
+                    name: "roll".to_string(),
+                    cmd: Cmd::Dice,
+                },
+            ),
+        ]));
+
+        assert!(e.exist("roll"));
+        assert!(!e.exist("dice"));
+    }
+
+    #[test]
+    fn test_engine_with_commands_file() {
+        let e = Engine::new()
+            .with_commands_file(Path::new("testdata/commands.yaml"))
+            .unwrap();
+
+        assert!(e.exist("roll"));
+        assert!(e.exist("quit"));
+        assert!(!e.exist("dice"));
+    }
+
+    #[test]
+    fn test_engine_with_commands_file_error() {
+        let e = Engine::new().with_commands_file(Path::new("testdata/nonexistent.yaml"));
+        assert!(matches!(e, Err(EngineError::Commands(_))));
+
+        let e = Engine::new().with_commands_file(Path::new("testdata/aliases"));
+        assert!(matches!(e, Err(EngineError::Commands(_))));
+    }
+
+    #[test]
+    fn test_engine_with_storage() {
+        let mut st = MemoryStorage::default();
+        st.save(&Session {
+            vars: HashMap::from([("str".to_string(), 3)]),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut e = Engine::new().with_storage(Box::new(st)).unwrap();
+        assert_eq!(Some(&3), e.variables().get("str"));
+
+        e.set("dex 1").unwrap();
+        assert_eq!(Some(&1), e.storage.load().unwrap().vars.get("dex"));
+    }
 }
@@ -0,0 +1,171 @@
+//! Events sent by the engine while it runs, for GUIs, bots or logging.
+//!
+//! Anything implementing `EngineObserver` can be registered with `Engine::with_observer`, it
+//! is then called for every line executed by `run` or `run_batch`.  Closures are observers
+//! too, which makes forwarding events to a channel easy:
+//!
+//! ```
+//! use std::io::Cursor;
+//! use std::sync::mpsc::channel;
+//!
+//! use dices_rs::engine::observer::Event;
+//! use dices_rs::engine::Engine;
+//!
+//! let (tx, rx) = channel();
+//! let mut e = Engine::new().with_observer(Box::new(move |ev: &Event<'_>| {
+//!     if let Event::Rolled { res, .. } = ev {
+//!         tx.send(res.sum).unwrap();
+//!     }
+//! }));
+//!
+//! e.run_batch(Cursor::new("dice 2D6")).unwrap();
+//! assert!((2..=12).contains(&rx.recv().unwrap()));
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::Action;
+use crate::dice::result::Res;
+use crate::engine::error::EngineError;
+use crate::engine::Engine;
+
+/// What happened to a line
+///
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// The first word of `line` is an alias or a macro, `steps` are the successive expansions
+    Expanded { line: &'a str, steps: &'a [String] },
+    /// `line` has been compiled into `action`
+    Compiled { line: &'a str, action: &'a Action },
+    /// `line` rolled some dices, `hidden` behind the GM screen
+    Rolled {
+        line: &'a str,
+        res: &'a Res,
+        hidden: bool,
+    },
+    /// `line` failed
+    Error {
+        line: &'a str,
+        error: &'a EngineError,
+    },
+}
+
+impl Display for Event<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Expanded { line, steps } => write!(f, "{line} -> {}", steps.join(" -> ")),
+            Event::Compiled { line, action } => write!(f, "{line}: {action:?}"),
+            Event::Rolled { line, res, .. } => write!(f, "{line}: {res}"),
+            Event::Error { line, error } => write!(f, "{line}: {error}"),
+        }
+    }
+}
+
+/// Something interested in what the engine does
+///
+pub trait EngineObserver: Send + Sync {
+    /// Called for every event, in order
+    ///
+    fn notify(&self, event: &Event<'_>);
+}
+
+impl<F> EngineObserver for F
+where
+    F: Fn(&Event<'_>) + Send + Sync,
+{
+    fn notify(&self, event: &Event<'_>) {
+        self(event)
+    }
+}
+
+impl Engine {
+    /// Register `observer`, called after the ones already there
+    ///
+    pub fn with_observer(mut self, observer: Box<dyn EngineObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Send `event` to all observers
+    ///
+    pub(crate) fn notify(&self, event: Event<'_>) {
+        self.observers.iter().for_each(|o| o.notify(&event));
+    }
+
+    /// Do we have anyone to notify?
+    ///
+    pub(crate) fn observed(&self) -> bool {
+        !self.observers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Keep every event as a string
+    ///
+    fn recorder(e: Engine) -> (Engine, Arc<Mutex<Vec<String>>>) {
+        let all = Arc::new(Mutex::new(vec![]));
+        let log = all.clone();
+        let e = e.with_observer(Box::new(move |ev: &Event<'_>| {
+            let kind = match ev {
+                Event::Expanded { .. } => "expanded",
+                Event::Compiled { .. } => "compiled",
+                Event::Rolled { .. } => "rolled",
+                Event::Error { .. } => "error",
+            };
+            log.lock().unwrap().push(format!("{kind} {ev}"));
+        }));
+        (e, all)
+    }
+
+    #[test]
+    fn test_observer_roll() {
+        let (mut e, log) = recorder(Engine::new());
+
+        e.run_batch(Cursor::new("dice 3D6\n")).unwrap();
+        let log = log.lock().unwrap();
+        assert_eq!(2, log.len());
+        assert!(log[0].starts_with("compiled dice 3D6: Execute("));
+        assert!(log[1].starts_with("rolled dice 3D6:"), "{log:?}");
+    }
+
+    #[test]
+    fn test_observer_alias() {
+        let e = Engine::new().with(Some(PathBuf::from("testdata/aliases")));
+        let (mut e, log) = recorder(e);
+
+        e.run_batch(Cursor::new("doom\n")).unwrap();
+        let log = log.lock().unwrap();
+        assert_eq!("expanded doom -> dice 2D6", log[0]);
+        assert!(log[1].starts_with("compiled doom:"));
+        assert!(log[2].starts_with("rolled doom:"));
+    }
+
+    #[test]
+    fn test_observer_error() {
+        let (mut e, log) = recorder(Engine::new());
+
+        assert_eq!(1, e.run_batch(Cursor::new("merge\n")).unwrap());
+        let log = log.lock().unwrap();
+        assert_eq!(
+            vec!["compiled merge: Merge", "error merge: not in a branch"],
+            *log
+        );
+    }
+
+    #[test]
+    fn test_observer_several() {
+        let (e, first) = recorder(Engine::new());
+        let (mut e, second) = recorder(e);
+
+        e.run_batch(Cursor::new("vars\n")).unwrap();
+        assert_eq!(*first.lock().unwrap(), *second.lock().unwrap());
+        assert_eq!(1, first.lock().unwrap().len());
+    }
+}
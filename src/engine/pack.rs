@@ -0,0 +1,586 @@
+//! System packs: mechanics of a game described in YAML instead of being builtins.
+//!
+//! A pack names a few mechanics, each one built from these primitives and registered as a
+//! command named after it:
+//!
+//! - `pool`     number of dices rolled, 1 by default
+//! - `dice`     their sides, 6 by default
+//! - `extra`    sides of dices always rolled along the pool, like the wild die of Savage Worlds
+//! - `explode`  dices showing their highest face are rolled again and added to it
+//! - `keep`     only the `highest` or `lowest` N dices count
+//! - `target`   count the dices at or above it instead of adding them, each roll of an exploding
+//!   dice counting on its own
+//! - `botch`    with `target`, each 1 takes a success away and no success but a 1 is a `Botch`
+//! - `compare`  notes for the total, the highest threshold reached being given
+//!
+//! A mechanic is rolled with `<name> [N][dS] [+-M]... [vs T]`: `N` and `S` replace the pool and
+//! its sides, the modifiers are added to the total and `T` replaces the target.  Packs are given
+//! with `--pack` or `packs` in `config.toml`, examples for D&D 5e, the old World of Darkness and
+//! Savage Worlds being in `testdata/packs`.
+//!
+//! ```text
+//! # savage.yaml, default name is the name of the file
+//! name: savage
+//! description: Savage Worlds
+//! mechanics:
+//!   trait:
+//!     description: Trait die with the wild die, both acing, the best one counting
+//!     dice: 8
+//!     extra: [6]
+//!     explode: true
+//!     keep: !highest 1
+//!     compare: {4: success, 8: raise}
+//! ```
+//!
+//! ```no_run
+//! use dices_rs::engine::pack::Pack;
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Engine::new()
+//!     .with_pack(Pack::load("/some/location/savage.yaml").unwrap())
+//!     .unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::iter;
+use std::path::Path;
+
+use itertools::Itertools;
+use log::{debug, trace};
+use nom::{
+    bytes::complete::{tag, tag_no_case},
+    character::complete::{space0, space1, u64 as parse_u64},
+    combinator::{all_consuming, opt},
+    sequence::{delimited, preceded, tuple},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::dice::internal::Roller;
+use crate::dice::pool::{outcome, MAX_POOL};
+use crate::dice::reroll::MAX_REROLLS;
+use crate::dice::result::Res;
+use crate::engine::core::modifiers;
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine};
+use crate::messages::Messages;
+
+/// Dices counted after the roll
+///
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Keep {
+    Highest(usize),
+    Lowest(usize),
+}
+
+/// One mechanic of a game, see the module documentation for each primitive
+///
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, PartialOrd, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mechanic {
+    /// Shown by `help`
+    pub description: String,
+    /// Dices rolled
+    pub pool: usize,
+    /// Sides of the dices of the pool
+    pub dice: usize,
+    /// Sides of the dices rolled along the pool
+    pub extra: Vec<usize>,
+    /// Roll again and add dices showing their highest face
+    pub explode: bool,
+    /// Dices counted
+    pub keep: Option<Keep>,
+    /// Count successes at or above it
+    pub target: Option<usize>,
+    /// 1s take successes away
+    pub botch: bool,
+    /// Note of the total, by lowest total
+    pub compare: BTreeMap<isize, String>,
+}
+
+impl Default for Mechanic {
+    fn default() -> Self {
+        Mechanic {
+            description: String::new(),
+            pool: 1,
+            dice: 6,
+            extra: vec![],
+            explode: false,
+            keep: None,
+            target: None,
+            botch: false,
+            compare: BTreeMap::new(),
+        }
+    }
+}
+
+/// What a roll changes from the mechanic
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Args {
+    pub pool: usize,
+    pub dice: usize,
+    pub bonus: isize,
+    pub target: Option<usize>,
+}
+
+impl Mechanic {
+    /// Syntax of the mechanic called `name`
+    ///
+    pub fn usage(&self, name: &str) -> String {
+        match self.target {
+            Some(_) => format!("{name} [N][dS] [+-M]... [vs T]"),
+            None => format!("{name} [N][dS] [+-M]..."),
+        }
+    }
+
+    /// Refuse what can not be rolled
+    ///
+    pub fn check(&self) -> Result<(), String> {
+        if self.dice < 2 || self.extra.iter().any(|s| *s < 2) {
+            return Err("dices need at least 2 sides".to_string());
+        }
+        if !(1..=MAX_POOL).contains(&self.pool) {
+            return Err(format!("pool of 1 to {MAX_POOL} dices"));
+        }
+        match (self.keep, self.target) {
+            (Some(Keep::Highest(0) | Keep::Lowest(0)), _) => {
+                Err("keep at least one dice".to_string())
+            }
+            (_, Some(0)) => Err("target of 0".to_string()),
+            (_, None) if self.botch => Err("botch without target".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Arguments of the mechanic called `name`, checking the sides against the configuration
+    ///
+    pub fn parse_args(&self, name: &str, input: &str, cfg: &Config) -> Result<Args, EngineError> {
+        let usage = || EngineError::Usage(self.usage(name));
+
+        let count = opt(parse_u64);
+        let sides = opt(preceded(tag_no_case("d"), parse_u64));
+        let vs = opt(preceded(tuple((space1, tag("vs"), space1)), parse_u64));
+        let (_, (count, sides, bonus, target)) = all_consuming(delimited(
+            space0,
+            tuple((count, sides, modifiers, vs)),
+            space0,
+        ))(input)
+        .map_err(|_: nom::Err<nom::error::Error<&str>>| usage())?;
+
+        let args = Args {
+            pool: count.map_or(self.pool, |n| n as usize),
+            dice: sides.map_or(self.dice, |s| s as usize),
+            bonus: bonus as isize,
+            target: match target {
+                Some(_) if self.target.is_none() => return Err(usage()),
+                Some(t) => Some(t as usize),
+                None => self.target,
+            },
+        };
+        let sides_ok = iter::once(&args.dice)
+            .chain(&self.extra)
+            .all(|s| cfg.dice_sizes.is_valid(*s));
+        match (args.pool, args.target) {
+            (1..=MAX_POOL, None | Some(1..)) if sides_ok => Ok(args),
+            _ => Err(usage()),
+        }
+    }
+
+    /// Roll the mechanic called `name` with its arguments
+    ///
+    pub fn execute_with(&self, name: &str, input: &str, cfg: &Config) -> Result<Res, EngineError> {
+        let args = self.parse_args(name, input, cfg)?;
+        let m = cfg.messages();
+        Ok(cfg.scope(|rng| self.roll_with(&args, rng, &m)))
+    }
+
+    /// Roll every dice, the notes being taken from `m`
+    ///
+    pub fn roll_with(&self, args: &Args, rng: &mut Roller, m: &Messages) -> Res {
+        let chains = self
+            .sides(args)
+            .map(|sides| self.chain(sides, rng))
+            .collect::<Vec<_>>();
+        self.resolve(args, &chains, m)
+    }
+
+    /// Sides of every dice rolled, the pool then the extra ones
+    ///
+    fn sides(&self, args: &Args) -> impl Iterator<Item = usize> + '_ {
+        iter::repeat_n(args.dice, args.pool).chain(self.extra.iter().copied())
+    }
+
+    /// Every roll of one dice, more than one only if it explodes
+    ///
+    fn chain(&self, sides: usize, rng: &mut Roller) -> Vec<usize> {
+        let mut rolls = vec![rng.roll(sides)];
+        while self.explode && rolls.last() == Some(&sides) && rolls.len() <= MAX_REROLLS {
+            rolls.push(rng.roll(sides));
+        }
+        rolls
+    }
+
+    /// Result from the rolls of each dice
+    ///
+    pub fn resolve(&self, args: &Args, chains: &[Vec<usize>], m: &Messages) -> Res {
+        let value = |i: &usize| chains[*i].iter().sum::<usize>();
+        let order = (0..chains.len()).sorted_by_key(value);
+        let kept = match self.keep {
+            Some(Keep::Highest(n)) => order.rev().take(n).collect::<Vec<_>>(),
+            Some(Keep::Lowest(n)) => order.take(n).collect(),
+            None => order.collect(),
+        };
+
+        let mut res = Res::new();
+        for (i, rolls) in chains.iter().enumerate() {
+            match (kept.contains(&i), args.target) {
+                (false, _) => res.dropped.push(value(&i)),
+                (true, None) => {
+                    res.append(value(&i));
+                }
+                (true, Some(_)) => rolls.iter().for_each(|d| {
+                    res.list.push(*d);
+                }),
+            }
+        }
+        let mut notes = vec![];
+        if let Some(target) = args.target {
+            let counted = kept.iter().map(|i| &chains[*i]);
+            let successes = counted.clone().flatten().filter(|d| **d >= target).count();
+            let ones = match self.botch {
+                true => counted.filter(|rolls| rolls[0] == 1).count(),
+                false => 0,
+            };
+            res.sum = successes.saturating_sub(ones) as isize;
+            res.add_bonus(args.bonus);
+            let (flag, note) = outcome(res.sum, successes == 0 && ones > 0, m);
+            res.set(flag);
+            notes.push(note);
+        } else {
+            res.add_bonus(args.bonus);
+        }
+        if let Some((_, text)) = self.compare.range(..=res.sum).next_back() {
+            notes.push(text.clone());
+        }
+        debug!("mechanic {chains:?} with {args:?}: {notes:?}");
+        res.note = (!notes.is_empty()).then(|| notes.join(", "));
+        res
+    }
+
+    /// Sizes of the dices rolled with `input`, for the roll database
+    ///
+    pub fn sizes(&self, input: &str) -> Vec<usize> {
+        let args = self
+            .parse_args("", input, &Config::default())
+            .unwrap_or(Args {
+                pool: self.pool,
+                dice: self.dice,
+                bonus: 0,
+                target: self.target,
+            });
+        self.sides(&args).collect()
+    }
+}
+
+/// Mechanics of one game
+///
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pack {
+    /// Default is the name of the file
+    pub name: String,
+    /// What game it is
+    pub description: String,
+    /// Mechanics by command name
+    pub mechanics: BTreeMap<String, Mechanic>,
+}
+
+impl Pack {
+    /// Read a pack from a YAML file, named after the file if it has no `name`
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        trace!("pack::load({path:?})");
+
+        let error = |e: String| EngineError::Pack(format!("{path:?}: {e}"));
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let mut p: Pack = serde_yaml::from_str(&content).map_err(|e| error(e.to_string()))?;
+        if p.name.is_empty() {
+            p.name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+        }
+        Ok(p)
+    }
+}
+
+impl Engine {
+    /// Register every mechanic of `pack` as a command.  They can replace aliases and macros
+    /// but no other command, mechanics of other packs included.
+    ///
+    pub fn with_pack(mut self, pack: Pack) -> Result<Self, EngineError> {
+        let error = |msg: String| EngineError::Pack(format!("{}: {msg}", pack.name));
+
+        for (name, mechanic) in &pack.mechanics {
+            self.check_name(name).map_err(|e| error(e.to_string()))?;
+            mechanic
+                .check()
+                .map_err(|e| error(format!("{name}: {e}")))?;
+        }
+
+        pack.mechanics.into_iter().for_each(|(name, mechanic)| {
+            let name = name.to_lowercase();
+            self.cmds
+                .insert(name.clone(), Command::Mechanic { name, mechanic });
+        });
+        self.reload = true;
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+    use rstest::rstest;
+
+    use crate::dice::result::Special;
+
+    use super::*;
+
+    fn args(pool: usize, dice: usize, bonus: isize, target: Option<usize>) -> Args {
+        Args {
+            pool,
+            dice,
+            bonus,
+            target,
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let p = Pack::load("testdata/packs/savage.yaml").unwrap();
+
+        assert_eq!("savage", p.name);
+        let t = &p.mechanics["trait"];
+        assert_eq!(vec![6], t.extra);
+        assert_eq!(Some(Keep::Highest(1)), t.keep);
+        assert!(t.explode);
+    }
+
+    #[rstest]
+    #[case("dnd5e")]
+    #[case("wod")]
+    #[case("savage")]
+    fn test_load_examples(#[case] name: &str) {
+        let p = Pack::load(format!("testdata/packs/{name}.yaml")).unwrap();
+
+        assert_eq!(name, p.name);
+        assert!(p.mechanics.values().all(|m| m.check().is_ok()));
+        assert!(Engine::new().with_pack(p).is_ok());
+    }
+
+    #[test]
+    fn test_load_invalid() {
+        let p = Pack::load("testdata/aliases");
+        assert!(matches!(p, Err(EngineError::Pack(_))));
+    }
+
+    #[rstest]
+    #[case("", args(2, 10, 0, Some(6)))]
+    #[case(" 7", args(7, 10, 0, Some(6)))]
+    #[case(" 7d8 +1 -3", args(7, 8, -2, Some(6)))]
+    #[case(" d12 vs 8 ", args(2, 12, 0, Some(8)))]
+    #[case(" 5 +2 vs 7", args(5, 10, 2, Some(7)))]
+    fn test_parse_args(#[case] input: &str, #[case] want: Args) {
+        let m = Mechanic {
+            pool: 2,
+            dice: 10,
+            target: Some(6),
+            ..Mechanic::default()
+        };
+        assert_eq!(Ok(want), m.parse_args("pool", input, &Config::default()));
+    }
+
+    #[rstest]
+    #[case(" 0")]
+    #[case(" 101")]
+    #[case(" 3d1")]
+    #[case(" 3 vs 0")]
+    #[case(" 2 oops")]
+    fn test_parse_args_invalid(#[case] input: &str) {
+        let m = Mechanic {
+            target: Some(6),
+            ..Mechanic::default()
+        };
+        let want = EngineError::Usage("pool [N][dS] [+-M]... [vs T]".to_string());
+
+        assert_eq!(Err(want), m.parse_args("pool", input, &Config::default()));
+    }
+
+    #[test]
+    fn test_parse_args_no_target() {
+        let m = Mechanic::default();
+        let want = EngineError::Usage("check [N][dS] [+-M]...".to_string());
+
+        assert_eq!(
+            Err(want),
+            m.parse_args("check", " 2 vs 7", &Config::default())
+        );
+    }
+
+    #[rstest]
+    #[case(Mechanic { dice: 1, ..Mechanic::default() })]
+    #[case(Mechanic { extra: vec![0], ..Mechanic::default() })]
+    #[case(Mechanic { pool: 0, ..Mechanic::default() })]
+    #[case(Mechanic { keep: Some(Keep::Lowest(0)), ..Mechanic::default() })]
+    #[case(Mechanic { target: Some(0), ..Mechanic::default() })]
+    #[case(Mechanic { botch: true, ..Mechanic::default() })]
+    fn test_check_invalid(#[case] m: Mechanic) {
+        assert!(m.check().is_err());
+    }
+
+    #[rstest]
+    #[case(None, vec![vec![3], vec![5], vec![2]], &[3, 5, 2], &[], 12)]
+    #[case(Some(Keep::Highest(2)), vec![vec![3], vec![5], vec![2]], &[3, 5], &[2], 10)]
+    #[case(Some(Keep::Lowest(1)), vec![vec![3], vec![5], vec![2]], &[2], &[3, 5], 4)]
+    #[case(Some(Keep::Highest(1)), vec![vec![8, 3], vec![6, 6, 1]], &[13], &[11], 15)]
+    fn test_resolve_sum(
+        #[case] keep: Option<Keep>,
+        #[case] chains: Vec<Vec<usize>>,
+        #[case] list: &[usize],
+        #[case] dropped: &[usize],
+        #[case] sum: isize,
+    ) {
+        let m = Mechanic {
+            keep,
+            ..Mechanic::default()
+        };
+        let r = m.resolve(
+            &args(chains.len(), 6, 2, None),
+            &chains,
+            &Messages::default(),
+        );
+
+        assert_eq!(list, &r.list[..]);
+        assert_eq!(dropped, &r.dropped[..]);
+        assert_eq!(sum, r.sum);
+        assert_eq!(2, r.bonus);
+        assert_eq!(None, r.note);
+    }
+
+    #[rstest]
+    #[case(vec![vec![7], vec![8], vec![2]], 2, Special::None, "2 successes")]
+    #[case(vec![vec![7], vec![1], vec![2]], 0, Special::None, "failure")]
+    #[case(vec![vec![2], vec![1], vec![5]], 0, Special::Botch, "botch")]
+    #[case(vec![vec![10, 3], vec![1], vec![6]], 1, Special::None, "1 success")]
+    #[case(vec![vec![10, 10, 1], vec![4]], 2, Special::None, "2 successes")]
+    fn test_resolve_target(
+        #[case] chains: Vec<Vec<usize>>,
+        #[case] sum: isize,
+        #[case] flag: Special,
+        #[case] note: &str,
+    ) {
+        let m = Mechanic {
+            dice: 10,
+            explode: true,
+            target: Some(6),
+            botch: true,
+            ..Mechanic::default()
+        };
+        let r = m.resolve(
+            &args(chains.len(), 10, 0, Some(6)),
+            &chains,
+            &Messages::default(),
+        );
+
+        assert_eq!(sum, r.sum);
+        assert_eq!(flag, r.flag);
+        assert_eq!(Some(note), r.note.as_deref());
+        assert_eq!(chains.concat(), r.list);
+    }
+
+    #[rstest]
+    #[case(3, 0, Some("failure"))]
+    #[case(4, 0, Some("success"))]
+    #[case(7, 0, Some("success"))]
+    #[case(7, 1, Some("raise"))]
+    #[case(1, -1, None)]
+    fn test_resolve_compare(#[case] d: usize, #[case] bonus: isize, #[case] want: Option<&str>) {
+        let m = Mechanic {
+            compare: BTreeMap::from([
+                (1, "failure".to_string()),
+                (4, "success".to_string()),
+                (8, "raise".to_string()),
+            ]),
+            ..Mechanic::default()
+        };
+        let r = m.resolve(&args(1, 8, bonus, None), &[vec![d]], &Messages::default());
+
+        assert_eq!(want, r.note.as_deref());
+    }
+
+    #[test]
+    fn test_roll_explode() {
+        let m = Mechanic {
+            dice: 4,
+            pool: 3,
+            explode: true,
+            target: Some(3),
+            ..Mechanic::default()
+        };
+        let a = m.parse_args("x", "", &Config::default()).unwrap();
+
+        for seed in 0..200 {
+            let r = m.roll_with(
+                &a,
+                &mut Roller::new(StdRng::seed_from_u64(seed)),
+                &Messages::default(),
+            );
+            let fours = r.list.iter().filter(|d| **d == 4).count();
+            assert_eq!(3 + fours, r.list.len(), "{r:?}");
+        }
+    }
+
+    #[test]
+    fn test_sizes() {
+        let p = Pack::load("testdata/packs/savage.yaml").unwrap();
+        let t = &p.mechanics["trait"];
+
+        assert_eq!(vec![8, 6], t.sizes(""));
+        assert_eq!(vec![12, 6], t.sizes(" d12 +1"));
+    }
+
+    #[test]
+    fn test_with_pack() {
+        let e = Engine::new()
+            .with_pack(Pack::load("testdata/packs/wod.yaml").unwrap())
+            .unwrap();
+
+        assert!(matches!(
+            e.cmds.get("storyteller"),
+            Some(Command::Mechanic { .. })
+        ));
+        let again = Pack::load("testdata/packs/wod.yaml").unwrap();
+        assert!(e.with_pack(again).is_err());
+    }
+
+    #[rstest]
+    #[case("dice")]
+    #[case("wod")]
+    #[case("bad name")]
+    fn test_with_pack_invalid(#[case] name: &str) {
+        let p = Pack {
+            name: "test".to_string(),
+            description: String::new(),
+            mechanics: BTreeMap::from([(name.to_string(), Mechanic::default())]),
+        };
+        assert!(matches!(
+            Engine::new().with_pack(p),
+            Err(EngineError::Pack(_))
+        ));
+    }
+}
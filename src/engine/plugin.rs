@@ -0,0 +1,260 @@
+//! Commands written in Rust by other crates, without touching the builtins.
+//!
+//! Anything implementing `CustomCommand` can be given to `Engine::register`, its name then
+//! being a command like any other: aliases and macros can use it and `help` shows its
+//! description.  It gets an `EngineCtx` to read the configuration and variables and to roll
+//! dices, recorded and hidden in GM screen mode like every other roll.
+//!
+//! ```
+//! use dices_rs::engine::error::EngineError;
+//! use dices_rs::engine::plugin::{CustomCommand, EngineCtx};
+//! use dices_rs::engine::{Engine, Output};
+//!
+//! /// Roll d20 twice and keep both
+//! struct Twice;
+//!
+//! impl CustomCommand for Twice {
+//!     fn name(&self) -> &str {
+//!         "twice"
+//!     }
+//!
+//!     fn execute(&self, _input: &str, ctx: &mut EngineCtx<'_>) -> Result<Output, EngineError> {
+//!         let rolls = vec![ctx.roll("d20")?, ctx.roll("d20")?];
+//!         Ok(ctx.shown(rolls))
+//!     }
+//! }
+//!
+//! let mut e = Engine::new();
+//! e.register(Box::new(Twice)).unwrap();
+//!
+//! assert!(matches!(e.eval("twice"), Ok(Output::Rolls(r)) if r.len() == 2));
+//! ```
+
+use std::collections::HashMap;
+
+use log::trace;
+
+use crate::config::Config;
+use crate::dice::internal::Roller;
+use crate::dice::result::Res;
+use crate::engine::core::Cmd;
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine, Output};
+
+/// A command added from Rust
+///
+pub trait CustomCommand: Send + Sync {
+    /// What is typed to run it, alphanumeric and case-insensitive
+    ///
+    fn name(&self) -> &str;
+
+    /// One line shown by `help` and the completion
+    ///
+    fn description(&self) -> String {
+        String::new()
+    }
+
+    /// Run with `input`, what was typed after the name
+    ///
+    fn execute(&self, input: &str, ctx: &mut EngineCtx<'_>) -> Result<Output, EngineError>;
+}
+
+/// What a custom command can do with the engine running it
+///
+pub struct EngineCtx<'a> {
+    engine: &'a mut Engine,
+    /// Line being executed, recorded with the rolls
+    line: &'a str,
+}
+
+impl EngineCtx<'_> {
+    /// Runtime configuration
+    ///
+    pub fn config(&self) -> &Config {
+        &self.engine.config
+    }
+
+    /// All variables, modifiers of the character in use included
+    ///
+    pub fn variables(&self) -> HashMap<String, i64> {
+        self.engine.variables()
+    }
+
+    /// Roll a dice expression like `dice` does, recording and speaking it
+    ///
+    pub fn roll(&mut self, expr: &str) -> Result<Res, EngineError> {
+        let cmd = Command::Builtin {
+            name: "dice".to_string(),
+            cmd: Cmd::Dice,
+        };
+        self.engine.roll(self.line, &cmd, expr, None)
+    }
+
+    /// What to show of `rolls`, only how many were hidden in GM screen mode
+    ///
+    pub fn shown(&self, rolls: Vec<Res>) -> Output {
+        self.engine.shown(rolls)
+    }
+
+    /// Run `f` with the configured generator, for anything rolled without `roll`
+    ///
+    pub fn scope<T, F: FnOnce(&mut Roller) -> T>(&self, f: F) -> T {
+        self.engine.config.scope(f)
+    }
+}
+
+impl Engine {
+    /// Add `cmd` as a command.  It can replace an alias or a macro, never another command.
+    ///
+    pub fn register(&mut self, cmd: Box<dyn CustomCommand>) -> Result<(), EngineError> {
+        let name = cmd.name().to_lowercase();
+        trace!("register({name})");
+
+        self.check_name(&name)?;
+        self.cmds
+            .insert(name.clone(), Command::Custom { name: name.clone() });
+        self.custom.insert(name, cmd.into());
+        self.reload = true;
+        Ok(())
+    }
+
+    /// Run the custom command `name`
+    ///
+    pub(crate) fn custom(
+        &mut self,
+        line: &str,
+        name: &str,
+        input: &str,
+    ) -> Result<Output, EngineError> {
+        let cmd = self
+            .custom
+            .get(name)
+            .cloned()
+            .ok_or(EngineError::InvalidCmd)?;
+        cmd.execute(input, &mut EngineCtx { engine: self, line })
+    }
+
+    /// Description of the custom command `name`
+    ///
+    pub(crate) fn custom_description(&self, name: &str) -> Option<String> {
+        self.custom.get(name).map(|c| c.description())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dice::result::Special;
+
+    use super::*;
+
+    /// Roll `input` and tell whether it beats 10
+    ///
+    struct Beat;
+
+    impl CustomCommand for Beat {
+        fn name(&self) -> &str {
+            "Beat"
+        }
+
+        fn description(&self) -> String {
+            "Roll and beat 10".to_string()
+        }
+
+        fn execute(&self, input: &str, ctx: &mut EngineCtx<'_>) -> Result<Output, EngineError> {
+            let mut res = ctx.roll(input)?;
+            if res.total() > 10 {
+                res.set(Special::Natural);
+            }
+            Ok(ctx.shown(vec![res]))
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let mut e = Engine::new();
+        e.register(Box::new(Beat)).unwrap();
+
+        assert_eq!(
+            Some(&Command::Custom {
+                name: "beat".to_string()
+            }),
+            e.cmds.get("beat")
+        );
+        match e.eval("beat 3D6 +20").unwrap() {
+            Output::Roll(res) => assert_eq!(Special::Natural, res.flag),
+            o => panic!("{o:?}"),
+        }
+        assert_eq!("beat\n\nRoll and beat 10", e.help("beat").unwrap());
+    }
+
+    #[test]
+    fn test_register_alias() {
+        let mut e = Engine::new().merge(vec![Command::Macro {
+            name: "hard".to_string(),
+            cmd: "beat 2D6 +$bonus".to_string(),
+        }]);
+        e.register(Box::new(Beat)).unwrap();
+        e.eval("set bonus 100").unwrap();
+
+        assert!(matches!(e.eval("hard"), Ok(Output::Roll(res)) if res.sum > 100));
+        assert!(e.eval("beat oops").is_err());
+    }
+
+    #[test]
+    fn test_register_gm() {
+        let mut e = Engine::new();
+        e.register(Box::new(Beat)).unwrap();
+        e.eval("gm on").unwrap();
+
+        assert_eq!(
+            Output::Listing("hidden roll #1".to_string()),
+            e.eval("beat d6").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_register_builtin() {
+        struct Dice;
+
+        impl CustomCommand for Dice {
+            fn name(&self) -> &str {
+                "dice"
+            }
+
+            fn execute(&self, _: &str, _: &mut EngineCtx<'_>) -> Result<Output, EngineError> {
+                Ok(Output::Empty)
+            }
+        }
+
+        let mut e = Engine::new();
+        assert_eq!(
+            Err(EngineError::NameTaken("dice".to_string())),
+            e.register(Box::new(Dice))
+        );
+        e.register(Box::new(Beat)).unwrap();
+        assert_eq!(
+            Err(EngineError::NameTaken("beat".to_string())),
+            e.register(Box::new(Beat))
+        );
+    }
+
+    #[test]
+    fn test_register_invalid() {
+        struct Bad;
+
+        impl CustomCommand for Bad {
+            fn name(&self) -> &str {
+                "be at"
+            }
+
+            fn execute(&self, _: &str, _: &mut EngineCtx<'_>) -> Result<Output, EngineError> {
+                Ok(Output::Empty)
+            }
+        }
+
+        assert_eq!(
+            Err(EngineError::InvalidName("be at".to_string())),
+            Engine::new().register(Box::new(Bad))
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! `prob`: how likely is a roll to succeed, estimated by simulation, and `anydice`: the full
+//! table of the totals of a roll.
+//!
+//! ```text
+//! Dices> prob d20 +5 >= 15
+//! d20 +5 >= 15: 55.1% (mean 15.5 over 10000 rolls)
+//! Dices> anydice 2d4
+//! 2d4: exact, mean 5.00
+//! value  probability  at least
+//!     2        6.25%   100.00%
+//!     3       12.50%    93.75%
+//! ...
+//! ```
+//!
+//! The expression is anything `dice` accepts without house rules, variables can be used as
+//! for `dice` and divisions are rounded like `dice` does with the configured `rounding`.  `anydice` computes sums of independent dices exactly and simulates anything
+//! else.  See `dice::exact` and `dice::simulate` for the library side.
+
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{alphanumeric1, space0, space1},
+    combinator::all_consuming,
+    sequence::{preceded, terminated},
+};
+
+use crate::dice::exact::{exact_rounded, Probabilities};
+use crate::dice::expr::Expr;
+use crate::dice::simulate::{simulate_rounded, split_condition};
+use crate::engine::error::EngineError;
+use crate::engine::Engine;
+use crate::locale::NumberFormat;
+
+/// Number of rolls simulated by `prob`
+pub const SIMULATIONS: usize = 10_000;
+
+impl Engine {
+    /// Handle `prob <expr> <op> <N>`, with `op` one of `=`, `<`, `<=`, `>` or `>=`
+    ///
+    pub fn prob(&self, args: &str) -> Result<String, EngineError> {
+        let usage = || EngineError::Usage("prob <expr> (=|<|<=|>|>=) <N>".to_string());
+
+        let defined = self.config.defined();
+        let Some((expr, op, value)) = split_condition(args, &defined) else {
+            // Say what is wrong with the expression before the comparison, if anything
+            //
+            Expr::parse_with(
+                args.split(['<', '>', '=']).next().unwrap_or_default(),
+                &defined,
+            )?;
+            return Err(usage());
+        };
+
+        let e = Expr::parse_with(expr, &defined)?;
+        let d = self
+            .config
+            .scope(|rng| simulate_rounded(&e, SIMULATIONS, rng, self.config.rules.rounding))?;
+        let nf = self.config.number_format();
+        Ok(format!(
+            "{} {op} {value}: {}% (mean {} over {} rolls)",
+            expr.trim(),
+            nf.float(100.0 * d.p(op, value), 1),
+            nf.float(d.mean(), 1),
+            nf.int(d.n as i64)
+        ))
+    }
+
+    /// Handle `anydice <expr> [--output (table|csv)]`
+    ///
+    pub fn anydice(&self, args: &str) -> Result<String, EngineError> {
+        let usage = || EngineError::Usage("anydice <expr> [--output (table|csv)]".to_string());
+
+        let (expr, output) = match args.split_once("--output") {
+            Some((expr, opt)) => {
+                let (_, output) = all_consuming(terminated(
+                    preceded(alt((tag("="), space1)), alphanumeric1),
+                    space0,
+                ))(opt)
+                .map_err(|_: nom::Err<nom::error::Error<&str>>| usage())?;
+                (expr.trim(), output)
+            }
+            None => (args.trim(), "table"),
+        };
+        let csv = match output.to_lowercase().as_str() {
+            "table" => false,
+            "csv" => true,
+            _ => return Err(EngineError::OutputFormat(output.to_string())),
+        };
+        if expr.is_empty() {
+            return Err(usage());
+        }
+        let nf = self.config.number_format();
+
+        let e = Expr::parse_with(expr, &self.config.defined())?;
+        let rounding = self.config.rules.rounding;
+        let (p, how) = self.config.scope(|rng| match exact_rounded(&e, rounding) {
+            Some(p) => Ok::<_, EngineError>((p, "exact".to_string())),
+            None => {
+                let d = simulate_rounded(&e, SIMULATIONS, rng, rounding)?;
+                Ok((
+                    Probabilities::from(&d),
+                    format!("{} rolls", nf.int(d.n as i64)),
+                ))
+            }
+        })?;
+
+        let rows = p.0.iter().map(|(v, pv)| (*v, *pv, p.at_least(*v)));
+        if csv {
+            // No grouping in a spreadsheet, and `;` between fields when `,` is the decimal
+            // separator
+            //
+            let nf = NumberFormat { group: None, ..nf };
+            let sep = if nf.decimal == ',' { ';' } else { ',' };
+            let lines = rows
+                .map(|(v, pv, al)| format!("{v}{sep}{}{sep}{}", nf.float(pv, 6), nf.float(al, 6)));
+            return Ok(
+                std::iter::once(format!("value{sep}probability{sep}at_least"))
+                    .chain(lines)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+        let percent = |p: f64| format!("{}%", nf.float(100.0 * p, 2));
+        let lines = rows.map(|(v, pv, al)| {
+            format!(
+                "{:>5}  {:>11}  {:>8}",
+                nf.int(v as i64),
+                percent(pv),
+                percent(al)
+            )
+        });
+        Ok(
+            std::iter::once(format!("{expr}: {how}, mean {}", nf.float(p.mean(), 2)))
+                .chain(std::iter::once("value  probability  at least".to_string()))
+                .chain(lines)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::dice::error::DiceError;
+    use crate::dice::rules::Rounding;
+    use crate::engine::Output;
+
+    use super::*;
+
+    fn engine(locale: &str) -> Engine {
+        let mut e = Engine::new();
+        e.config.locale = Some(locale.to_string());
+        e
+    }
+
+    #[test]
+    fn test_prob() {
+        let mut e = engine("C");
+
+        let s = e.prob(" d6 + 1 <= 7").unwrap();
+        assert!(s.starts_with("d6 + 1 <= 7: 100.0% (mean 4."), "{s}");
+        assert!(s.ends_with(" over 10000 rolls)"), "{s}");
+        let Ok(Output::Listing(s)) = e.eval("prob 2D6>12") else {
+            panic!("no prob");
+        };
+        assert!(s.starts_with("2D6 > 12: 0.0% (mean "), "{s}");
+
+        e.set("str 3").unwrap();
+        let Ok(Output::Listing(s)) = e.eval("prob d20 +$str >= 15") else {
+            panic!("no prob");
+        };
+        assert!(s.starts_with("d20 +3 >= 15: "), "{s}");
+    }
+
+    #[test]
+    fn test_prob_reroll() {
+        let s = engine("C").prob("2d6r<3 +10 >= 7").unwrap();
+
+        assert!(s.starts_with("2d6r<3 +10 >= 7: 100.0% (mean "), "{s}");
+    }
+
+    #[test]
+    fn test_prob_locale() {
+        let s = engine("fr").prob("d6 + 1 <= 7").unwrap();
+
+        assert!(s.starts_with("d6 + 1 <= 7: 100,0% (mean 4,"), "{s}");
+        assert!(s.ends_with(" over 10 000 rolls)"), "{s}");
+    }
+
+    #[rstest]
+    #[case("d20 +5")]
+    #[case("d20 +5 >= ")]
+    #[case("d20 +5 >= 15 adv")]
+    #[case("d20 >= x")]
+    fn test_prob_usage(#[case] args: &str) {
+        let e = Engine::new();
+
+        assert_eq!(
+            Err(EngineError::Usage(
+                "prob <expr> (=|<|<=|>|>=) <N>".to_string()
+            )),
+            e.prob(args)
+        );
+    }
+
+    #[test]
+    fn test_anydice() {
+        let e = engine("C");
+
+        let s = e.anydice(" 2d4").unwrap();
+        let lines = s.lines().collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "2d4: exact, mean 5.00",
+                "value  probability  at least",
+                "    2        6.25%   100.00%",
+                "    3       12.50%    93.75%",
+            ],
+            lines[..4]
+        );
+        assert_eq!("    8        6.25%     6.25%", lines[lines.len() - 1]);
+    }
+
+    #[test]
+    fn test_anydice_csv() {
+        let mut e = engine("C");
+        e.set("b 1").unwrap();
+
+        let Ok(Output::Listing(s)) = e.eval("anydice d2 + $b --output csv") else {
+            panic!("no anydice");
+        };
+        assert_eq!(
+            "value,probability,at_least\n2,0.500000,1.000000\n3,0.500000,0.500000",
+            s
+        );
+        assert_eq!(Ok(s), e.anydice("d2 + 1 --output=CSV "));
+    }
+
+    #[test]
+    fn test_anydice_locale() {
+        let e = engine("fr");
+
+        let s = e.anydice("2d4 --output table").unwrap();
+        assert!(s.starts_with("2d4: exact, mean 5,00\n"), "{s}");
+        assert!(s.ends_with("    8        6,25%     6,25%"), "{s}");
+        assert_eq!(
+            "value;probability;at_least\n2;0,500000;1,000000\n3;0,500000;0,500000",
+            e.anydice("d2 + 1 --output csv").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_anydice_rounding() {
+        let mut e = engine("C");
+        e.config.rules.rounding = Rounding::Up;
+
+        let s = e.anydice("d2 / 2 --output csv").unwrap();
+        assert_eq!("value,probability,at_least\n1,1.000000,1.000000", s);
+        let s = e.prob("d2 / 2 >= 1").unwrap();
+        assert!(s.starts_with("d2 / 2 >= 1: 100.0%"), "{s}");
+    }
+
+    #[test]
+    fn test_anydice_overflow() {
+        assert_eq!(
+            Err(EngineError::Dice(DiceError::Overflow)),
+            Engine::new().anydice("4294967295*4294967295*4294967295")
+        );
+    }
+
+    #[test]
+    fn test_anydice_simulated() {
+        let s = engine("en").anydice("4d6dl1").unwrap();
+
+        assert!(s.starts_with("4d6dl1: 10,000 rolls, mean 12."), "{s}");
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("--output csv")]
+    #[case("3d6 --output")]
+    #[case("3d6 --outputcsv")]
+    #[case("3d6 --output csv table")]
+    fn test_anydice_usage(#[case] args: &str) {
+        assert_eq!(
+            Err(EngineError::Usage(
+                "anydice <expr> [--output (table|csv)]".to_string()
+            )),
+            Engine::new().anydice(args)
+        );
+    }
+
+    #[test]
+    fn test_anydice_format() {
+        assert_eq!(
+            Err(EngineError::OutputFormat("json".to_string())),
+            Engine::new().anydice("3d6 --output json")
+        );
+    }
+
+    #[test]
+    fn test_prob_invalid_expr() {
+        assert!(matches!(
+            Engine::new().prob("3d >= 4"),
+            Err(EngineError::Dice(_))
+        ));
+    }
+}
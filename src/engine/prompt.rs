@@ -0,0 +1,109 @@
+//! Module to render the REPL prompt.
+//!
+//! The prompt is a template rendered before reading each line, the following variables are
+//! replaced by their current value:
+//!
+//! - `{last_roll}`  total of the last roll (`-` if none yet)
+//! - `{count}`      number of rolls done this session
+//! - `{time}`       current local time as `HH:MM:SS`
+//!
+//! Example:
+//! ```
+//! use dices_rs::config::Config;
+//! use dices_rs::engine::Engine;
+//!
+//! let cfg = Config {
+//!     prompt: "[{count}] Dices> ".to_string(),
+//!     ..Default::default()
+//! };
+//! let e = Engine::new().with_config(cfg);
+//!
+//! assert_eq!("[0] Dices> ", e.prompt());
+//! ```
+
+use chrono::Local;
+use log::trace;
+
+use crate::engine::Engine;
+
+/// Prompt of the lines continuing the previous one
+pub const CONTINUED: &str = "... ";
+
+/// Template variables
+const LAST_ROLL: &str = "{last_roll}";
+const COUNT: &str = "{count}";
+const TIME: &str = "{time}";
+
+impl Engine {
+    /// Render the prompt template from the configuration
+    ///
+    pub fn prompt(&self) -> String {
+        trace!("prompt");
+
+        let mut ps1 = self.config.prompt.clone();
+
+        if ps1.contains(LAST_ROLL) {
+            let last = match self.last {
+                Some(ref res) => res.total().to_string(),
+                None => "-".to_string(),
+            };
+            ps1 = ps1.replace(LAST_ROLL, &last);
+        }
+        if ps1.contains(COUNT) {
+            ps1 = ps1.replace(COUNT, &self.count.to_string());
+        }
+        if ps1.contains(TIME) {
+            ps1 = ps1.replace(TIME, &Local::now().format("%H:%M:%S").to_string());
+        }
+        ps1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::config::Config;
+    use crate::dice::result::Res;
+
+    use super::*;
+
+    fn engine_with(prompt: &str) -> Engine {
+        let cfg = Config {
+            prompt: prompt.to_string(),
+            ..Default::default()
+        };
+        Engine::new().with_config(cfg)
+    }
+
+    #[rstest]
+    #[case("Dices> ", "Dices> ")]
+    #[case("{last_roll}> ", "-> ")]
+    #[case("{count}> ", "0> ")]
+    #[case("{nothing}> ", "{nothing}> ")]
+    fn test_prompt_new(#[case] prompt: &str, #[case] want: &str) {
+        let e = engine_with(prompt);
+        assert_eq!(want, e.prompt());
+    }
+
+    #[test]
+    fn test_prompt_after_roll() {
+        let mut e = engine_with("({last_roll}/{count}) ");
+
+        let mut res = Res::new();
+        res.append(12);
+        e.last = Some(res);
+        e.count = 3;
+
+        assert_eq!("(12/3) ", e.prompt());
+    }
+
+    #[test]
+    fn test_prompt_time() {
+        let e = engine_with("{time}> ");
+
+        let p = e.prompt();
+        assert_eq!(10, p.len());
+        assert_eq!(Some(':'), p.chars().nth(2));
+    }
+}
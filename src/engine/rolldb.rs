@@ -0,0 +1,582 @@
+//! Roll database, only available with the `sqlite` feature.
+//!
+//! Every roll is recorded in a SQLite database with the time, who rolled (`actor` in the
+//! configuration, `$USER` by default), the command and the result.  The `query` command
+//! searches it with a small language:
+//!
+//! ```text
+//! rolls [where <cond> [and <cond>]...] [since <when>] [limit N]
+//!
+//! cond:   die=N | actor=NAME | cmd=NAME | total(=|<|>|<=|>=)N
+//! when:   today | yesterday | Nd (N days ago) | YYYY-MM-DD
+//! ```
+//!
+//! ```no_run
+//! use dices_rs::engine::rolldb::RollDb;
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Engine::new()
+//!     .with_roll_db(RollDb::open("/some/location/rolls.db").unwrap());
+//!
+//! println!("{}", e.query("rolls where die=20 and actor=Bob since yesterday").unwrap());
+//! ```
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{Days, Local, NaiveDate, TimeZone};
+use itertools::Itertools;
+use log::trace;
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while1},
+    character::complete::{i64, space0, space1, u32},
+    combinator::{all_consuming, map, map_opt, opt, value},
+    multi::separated_list1,
+    sequence::{delimited, preceded, separated_pair, terminated, tuple},
+    IResult,
+};
+use rusqlite::{params, params_from_iter, types::Value, Connection};
+
+use crate::dice::expr::parse_expr_with;
+use crate::dice::parse::{parse_open100, parse_open_bonus, Defined};
+use crate::dice::pool;
+use crate::dice::result::Res;
+use crate::dice::simulate::{parse_op, Op};
+use crate::engine::core::Cmd;
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine};
+use crate::messages::Msg;
+
+/// Syntax of `query`
+const USAGE: &str = "query rolls [where <cond> [and <cond>]...] [since <when>] [limit N]";
+
+/// One recorded roll
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Roll {
+    /// Unix timestamp
+    pub time: i64,
+    /// Who rolled
+    pub actor: String,
+    /// Name of the builtin command
+    pub cmd: String,
+    /// Its arguments
+    pub input: String,
+    /// Sizes of all the dices rolled
+    pub dices: Vec<usize>,
+    /// Total
+    pub total: isize,
+    /// Special result, as in `Res`
+    pub flag: String,
+}
+
+/// One condition of `where`
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Filter {
+    /// At least one dice of that size
+    Die(usize),
+    /// Rolled by
+    Actor(String),
+    /// With that builtin command
+    Cmd(String),
+    /// Total compared to a value
+    Total(Op, i64),
+}
+
+/// Start of the period
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Since {
+    Today,
+    Yesterday,
+    DaysAgo(u32),
+    Date(NaiveDate),
+}
+
+impl Since {
+    /// Timestamp of the local midnight starting the period, too many days ago being a usage
+    /// error
+    ///
+    pub fn start(&self) -> Result<i64, EngineError> {
+        let today = Local::now().date_naive();
+        let day = match *self {
+            Since::Today => Some(today),
+            Since::Yesterday => today.checked_sub_days(Days::new(1)),
+            Since::DaysAgo(n) => today.checked_sub_days(Days::new(n as u64)),
+            Since::Date(d) => Some(d),
+        }
+        .ok_or_else(|| EngineError::Usage(USAGE.to_string()))?;
+        let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+        match Local.from_local_datetime(&midnight).earliest() {
+            Some(t) => Ok(t.timestamp()),
+            None => Ok(midnight.timestamp()),
+        }
+    }
+}
+
+/// A parsed `query`
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Query {
+    pub filters: Vec<Filter>,
+    pub since: Option<Since>,
+    pub limit: Option<usize>,
+}
+
+impl Query {
+    /// Parse the query language described at the top
+    ///
+    pub fn parse(input: &str) -> Result<Self, EngineError> {
+        match all_consuming(parse_query)(input.trim()) {
+            Ok((_, q)) => Ok(q),
+            Err(_) => Err(EngineError::Usage(USAGE.to_string())),
+        }
+    }
+}
+
+/// Rolls kept in SQLite
+///
+#[derive(Debug)]
+pub struct RollDb {
+    conn: Mutex<Connection>,
+}
+
+impl RollDb {
+    /// Open or create the database in `path`
+    ///
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, EngineError> {
+        trace!("rolldb::open({:?})", path.as_ref());
+        Self::init(Connection::open(path).map_err(db_error)?)
+    }
+
+    /// Database only living in memory, mostly for tests
+    ///
+    pub fn open_in_memory() -> Result<Self, EngineError> {
+        Self::init(Connection::open_in_memory().map_err(db_error)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, EngineError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS rolls (
+                id INTEGER PRIMARY KEY,
+                time INTEGER NOT NULL,
+                actor TEXT NOT NULL,
+                cmd TEXT NOT NULL,
+                input TEXT NOT NULL,
+                dices TEXT NOT NULL,
+                total INTEGER NOT NULL,
+                flag TEXT NOT NULL
+            )",
+            [],
+        )
+        .map_err(db_error)?;
+        Ok(RollDb {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Add one roll
+    ///
+    pub fn record(&self, roll: &Roll) -> Result<(), EngineError> {
+        // Sizes are stored as ",6,20," so that `die=N` is a simple `LIKE '%,N,%'`
+        //
+        let dices = format!(",{},", roll.dices.iter().join(","));
+        self.conn()?
+            .execute(
+                "INSERT INTO rolls (time, actor, cmd, input, dices, total, flag)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    roll.time,
+                    roll.actor,
+                    roll.cmd,
+                    roll.input,
+                    dices,
+                    roll.total as i64,
+                    roll.flag
+                ],
+            )
+            .map_err(db_error)?;
+        Ok(())
+    }
+
+    /// All rolls matching `q`, oldest first
+    ///
+    pub fn query(&self, q: &Query) -> Result<Vec<Roll>, EngineError> {
+        let mut sql = String::from(
+            "SELECT time, actor, cmd, input, dices, total, flag FROM rolls WHERE 1 = 1",
+        );
+        let mut args = vec![];
+        for f in &q.filters {
+            match f {
+                Filter::Die(n) => {
+                    sql.push_str(" AND dices LIKE ?");
+                    args.push(Value::Text(format!("%,{n},%")));
+                }
+                Filter::Actor(a) => {
+                    sql.push_str(" AND actor = ?");
+                    args.push(Value::Text(a.clone()));
+                }
+                Filter::Cmd(c) => {
+                    sql.push_str(" AND cmd = ?");
+                    args.push(Value::Text(c.clone()));
+                }
+                Filter::Total(op, n) => {
+                    sql.push_str(&format!(" AND total {} ?", op));
+                    args.push(Value::Integer(*n));
+                }
+            }
+        }
+        if let Some(since) = q.since {
+            sql.push_str(" AND time >= ?");
+            args.push(Value::Integer(since.start()?));
+        }
+        // With a limit we want the latest ones
+        //
+        sql.push_str(" ORDER BY time DESC, id DESC");
+        if let Some(limit) = q.limit {
+            sql.push_str(" LIMIT ?");
+            args.push(Value::Integer(limit as i64));
+        }
+        trace!("{sql} {args:?}");
+
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&sql).map_err(db_error)?;
+        let rows = stmt
+            .query_map(params_from_iter(args), |row| {
+                let dices: String = row.get(4)?;
+                Ok(Roll {
+                    time: row.get(0)?,
+                    actor: row.get(1)?,
+                    cmd: row.get(2)?,
+                    input: row.get(3)?,
+                    dices: dices.split(',').filter_map(|s| s.parse().ok()).collect(),
+                    total: row.get::<_, i64>(5)? as isize,
+                    flag: row.get(6)?,
+                })
+            })
+            .map_err(db_error)?;
+        let mut rolls = rows.collect::<Result<Vec<_>, _>>().map_err(db_error)?;
+        rolls.reverse();
+        Ok(rolls)
+    }
+
+    fn conn(&self) -> Result<std::sync::MutexGuard<'_, Connection>, EngineError> {
+        self.conn
+            .lock()
+            .map_err(|e| EngineError::RollDb(e.to_string()))
+    }
+}
+
+impl Engine {
+    /// Record every roll in `db`
+    ///
+    pub fn with_roll_db(mut self, db: RollDb) -> Self {
+        self.rolldb = Some(db);
+        self
+    }
+
+    /// Run `query` against the roll database, one line per roll and a summary
+    ///
+    pub fn query(&self, query: &str) -> Result<String, EngineError> {
+        let db = self
+            .rolldb
+            .as_ref()
+            .ok_or_else(|| EngineError::RollDb("no database configured".to_string()))?;
+        let rolls = db.query(&Query::parse(query)?)?;
+        let m = self.config.messages();
+        if rolls.is_empty() {
+            return Ok(m.get(Msg::NoRolls).to_string());
+        }
+
+        let nf = self.config.number_format();
+        let totals = rolls.iter().map(|r| r.total).collect::<Vec<_>>();
+        let (min, max) = totals.iter().minmax().into_option().unwrap_or((&0, &0));
+        let avg = totals.iter().sum::<isize>() as f64 / totals.len() as f64;
+        let lines = rolls
+            .iter()
+            .map(|r| {
+                let time = match Local.timestamp_opt(r.time, 0).single() {
+                    Some(t) => t.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    None => r.time.to_string(),
+                };
+                format!(
+                    "{time}\t{}\t{} {}\t{} ({})",
+                    r.actor,
+                    r.cmd,
+                    r.input.trim(),
+                    nf.int(r.total as i64),
+                    r.flag
+                )
+            })
+            .join("\n");
+        let stats = m.fill(
+            Msg::RollStats,
+            &[
+                &nf.int(totals.len() as i64),
+                &nf.int(*min as i64),
+                &nf.float(avg, 1),
+                &nf.int(*max as i64),
+            ],
+        );
+        Ok(format!("{lines}\n{stats}"))
+    }
+
+    /// Add one roll to the database if there is one
+    ///
+    pub(crate) fn record(&self, cmd: &Command, input: &str, res: &Res) -> Result<(), EngineError> {
+        let (name, dices) = match cmd {
+            Command::Builtin { name, cmd } => (name, sizes(cmd, input, &self.config.defined())),
+            Command::Mechanic { name, mechanic } => (name, mechanic.sizes(input)),
+            _ => return Ok(()),
+        };
+        let Some(db) = self.rolldb.as_ref() else {
+            return Ok(());
+        };
+        // Rolls in a branch are only experiments
+        //
+        if self.depth() > 0 {
+            return Ok(());
+        }
+        db.record(&Roll {
+            time: Local::now().timestamp(),
+            actor: self.actor(),
+            cmd: name.clone(),
+            input: input.trim().to_string(),
+            dices,
+            total: res.total(),
+            flag: format!("{:?}", res.flag),
+        })
+    }
+}
+
+/// Sizes of the dices of a roll, parsed again from its arguments
+///
+fn sizes(cmd: &Cmd, input: &str, defined: &Defined) -> Vec<usize> {
+    let input = input.trim_start();
+    let ds = match cmd {
+        Cmd::Dice => {
+            return parse_expr_with(input, defined)
+                .map(|(_, e)| e.sizes())
+                .unwrap_or_default()
+        }
+        Cmd::Open => parse_open_bonus(input),
+        Cmd::Open100 => parse_open100(input),
+        Cmd::RollUnder => return vec![6; 3],
+        Cmd::Pool => return vec![pool::SIDES],
+        Cmd::Action => return vec![6, 10, 10],
+        Cmd::Forged => return vec![6],
+        Cmd::Flip | Cmd::Choose | Cmd::Invalid => return vec![],
+    };
+    match ds {
+        Ok((_, ds)) => ds.sizes(),
+        Err(_) => vec![],
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> EngineError {
+    EngineError::RollDb(e.to_string())
+}
+
+fn parse_name(input: &str) -> IResult<&str, String> {
+    map(
+        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+        String::from,
+    )(input)
+}
+
+fn parse_filter(input: &str) -> IResult<&str, Filter> {
+    let eq = |t| terminated(tag(t), delimited(space0, tag("="), space0));
+    alt((
+        map(preceded(eq("die"), u32), |n| Filter::Die(n as usize)),
+        map(preceded(eq("actor"), parse_name), Filter::Actor),
+        map(preceded(eq("cmd"), parse_name), Filter::Cmd),
+        map(
+            preceded(
+                tag("total"),
+                separated_pair(delimited(space0, parse_op, space0), space0, i64),
+            ),
+            |(op, n)| Filter::Total(op, n),
+        ),
+    ))(input)
+}
+
+fn parse_since(input: &str) -> IResult<&str, Since> {
+    let date = map_opt(
+        tuple((u32, tag("-"), u32, tag("-"), u32)),
+        |(y, _, m, _, d)| {
+            let y = i32::try_from(y).ok()?;
+            NaiveDate::from_ymd_opt(y, m, d).map(Since::Date)
+        },
+    );
+    alt((
+        value(Since::Today, tag("today")),
+        value(Since::Yesterday, tag("yesterday")),
+        date,
+        map(terminated(u32, tag("d")), Since::DaysAgo),
+    ))(input)
+}
+
+fn parse_query(input: &str) -> IResult<&str, Query> {
+    let filters = preceded(
+        tuple((space1, tag("where"), space1)),
+        separated_list1(tuple((space1, tag("and"), space1)), parse_filter),
+    );
+    let since = preceded(tuple((space1, tag("since"), space1)), parse_since);
+    let limit = preceded(tuple((space1, tag("limit"), space1)), u32);
+    map(
+        preceded(tag("rolls"), tuple((opt(filters), opt(since), opt(limit)))),
+        |(filters, since, limit)| Query {
+            filters: filters.unwrap_or_default(),
+            since,
+            limit: limit.map(|n| n as usize),
+        },
+    )(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn roll(actor: &str, dices: Vec<usize>, total: isize) -> Roll {
+        Roll {
+            time: Local::now().timestamp(),
+            actor: actor.to_string(),
+            cmd: "dice".to_string(),
+            input: "whatever".to_string(),
+            dices,
+            total,
+            flag: "None".to_string(),
+        }
+    }
+
+    #[rstest]
+    #[case("rolls", Query::default())]
+    #[case("rolls where die=20", Query { filters: vec![Filter::Die(20)], ..Default::default() })]
+    #[case("rolls where die = 20 and actor=Bob since yesterday", Query {
+        filters: vec![Filter::Die(20), Filter::Actor("Bob".to_string())],
+        since: Some(Since::Yesterday),
+        ..Default::default()
+    })]
+    #[case("rolls where total>=15 and cmd=open limit 5", Query {
+        filters: vec![Filter::Total(Op::Ge, 15), Filter::Cmd("open".to_string())],
+        limit: Some(5),
+        ..Default::default()
+    })]
+    #[case("rolls since 7d", Query { since: Some(Since::DaysAgo(7)), ..Default::default() })]
+    #[case("rolls since 2023-02-01", Query {
+        since: NaiveDate::from_ymd_opt(2023, 2, 1).map(Since::Date),
+        ..Default::default()
+    })]
+    fn test_query_parse(#[case] input: &str, #[case] want: Query) {
+        assert_eq!(Ok(want), Query::parse(input));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("dices")]
+    #[case("rolls where")]
+    #[case("rolls where die=20 or actor=Bob")]
+    #[case("rolls since 2023-02-30")]
+    #[case("rolls since 4294967295-01-01")]
+    #[case("rolls since 300000-01-01")]
+    fn test_query_parse_error(#[case] input: &str) {
+        assert_eq!(
+            Err(EngineError::Usage(USAGE.to_string())),
+            Query::parse(input)
+        );
+    }
+
+    #[test]
+    fn test_since_start() {
+        let now = Local::now().timestamp();
+
+        assert!(Since::Today.start().unwrap() <= now);
+        assert!(Since::Yesterday.start().unwrap() < Since::Today.start().unwrap());
+        assert_eq!(Since::Today.start(), Since::DaysAgo(0).start());
+        assert_eq!(
+            Err(EngineError::Usage(USAGE.to_string())),
+            Since::DaysAgo(100000000).start()
+        );
+    }
+
+    #[test]
+    fn test_rolldb_query() {
+        let db = RollDb::open_in_memory().unwrap();
+        db.record(&roll("Bob", vec![20], 17)).unwrap();
+        db.record(&roll("Alice", vec![20, 4], 12)).unwrap();
+        db.record(&roll("Bob", vec![6, 6], 7)).unwrap();
+        db.record(&roll("Bob", vec![200], 150)).unwrap();
+
+        let q = |s| db.query(&Query::parse(s).unwrap()).unwrap();
+
+        assert_eq!(4, q("rolls").len());
+        assert_eq!(2, q("rolls where die=20").len());
+        assert_eq!(
+            vec![roll("Bob", vec![20], 17)],
+            q("rolls where die=20 and actor=Bob")
+        );
+        assert_eq!(1, q("rolls where total<10 since today").len());
+        assert_eq!(vec![20, 4], q("rolls where actor=Alice")[0].dices);
+        let last = q("rolls limit 2");
+        assert_eq!(
+            vec![7, 150],
+            last.iter().map(|r| r.total).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_engine_query() {
+        let e = Engine::new()
+            .with_config(crate::config::Config {
+                actor: Some("Bob".to_string()),
+                ..Default::default()
+            })
+            .with_roll_db(RollDb::open_in_memory().unwrap());
+
+        assert_eq!(Ok("no rolls".to_string()), e.query("rolls"));
+
+        let cmd = e.cmds["dice"].clone();
+        let res = cmd.execute("2d6 + d20").unwrap();
+        e.record(&cmd, " 2d6 + d20", &res).unwrap();
+
+        let out = e.query("rolls where die=20 and actor=Bob").unwrap();
+        assert!(out.contains("\tBob\tdice 2d6 + d20\t"));
+        assert!(out.ends_with(&format!("1 rolls, min {0} avg {0}.0 max {0}", res.sum)));
+        assert_eq!(
+            Ok("no rolls".to_string()),
+            e.query("rolls where die=6 and cmd=open")
+        );
+    }
+
+    #[test]
+    fn test_engine_query_french() {
+        let e = Engine::new()
+            .with_config(crate::config::Config {
+                actor: Some("Bob".to_string()),
+                locale: Some("fr_FR.UTF-8".to_string()),
+                ..Default::default()
+            })
+            .with_roll_db(RollDb::open_in_memory().unwrap());
+
+        assert_eq!(Ok("aucun jet".to_string()), e.query("rolls"));
+
+        let cmd = e.cmds["dice"].clone();
+        let res = cmd.execute("d6 + 10").unwrap();
+        e.record(&cmd, " d6 + 10", &res).unwrap();
+
+        let out = e.query("rolls").unwrap();
+        assert!(out.ends_with(&format!("1 jets, min {0} moy. {0},0 max {0}", res.sum)));
+    }
+
+    #[test]
+    fn test_engine_query_no_db() {
+        assert!(matches!(
+            Engine::new().query("rolls"),
+            Err(EngineError::RollDb(_))
+        ));
+    }
+}
@@ -0,0 +1,216 @@
+//! Session log, an audit trail of every roll.
+//!
+//! Each roll is appended to the file as one JSON object per line (JSONL) with the time, who
+//! rolled (`actor` in the configuration, `$USER` by default), the line as typed, what it
+//! expanded to and the result.  Rolls made in a branch are logged too, with the depth of the
+//! branch, so nothing can be hidden from the GM.
+//!
+//! ```no_run
+//! use dices_rs::engine::rolllog::RollLog;
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Engine::new()
+//!     .with_roll_log(RollLog::open("/some/location/rolls.log").unwrap());
+//! ```
+//!
+//! ```text
+//! {"time":"2023-02-11T21:04:12.527+01:00","actor":"Bob","line":"mouv","expanded":"dice 3D6 -9 +7","branch":0,"res":{...}}
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, SecondsFormat};
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::dice::result::Res;
+use crate::engine::error::EngineError;
+use crate::engine::Engine;
+
+/// One line of the log
+///
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Entry {
+    /// Local time in RFC 3339
+    pub time: String,
+    /// Who rolled
+    pub actor: String,
+    /// Command as typed
+    pub line: String,
+    /// Command actually executed once aliases and macros are expanded
+    pub expanded: String,
+    /// Depth of the branch, 0 being the real session
+    pub branch: usize,
+    /// Result of the roll
+    pub res: Res,
+}
+
+/// Rolls appended to a file
+///
+#[derive(Debug)]
+pub struct RollLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl RollLog {
+    /// Open `path` for appending, creating it if needed
+    ///
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, EngineError> {
+        trace!("rolllog::open({:?})", path.as_ref());
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| log_error(&path, e))?;
+        Ok(RollLog {
+            path: path.as_ref().to_path_buf(),
+            file,
+        })
+    }
+
+    /// Where rolls are logged
+    ///
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one entry, written at once so that concurrent sessions do not mix lines
+    ///
+    pub fn record(&self, entry: &Entry) -> Result<(), EngineError> {
+        let mut line = serde_json::to_string(entry).map_err(|e| log_error(&self.path, e))?;
+        line.push('\n');
+        (&self.file)
+            .write_all(line.as_bytes())
+            .map_err(|e| log_error(&self.path, e))
+    }
+}
+
+impl Engine {
+    /// Log every roll to `log`
+    ///
+    pub fn with_roll_log(mut self, log: RollLog) -> Self {
+        self.roll_log = Some(log);
+        self
+    }
+
+    /// Append a roll to the log if there is one
+    ///
+    pub(crate) fn log_roll(&self, line: &str, res: &Res) -> Result<(), EngineError> {
+        let Some(log) = self.roll_log.as_ref() else {
+            return Ok(());
+        };
+        let expanded = self
+            .last_trace
+            .as_ref()
+            .and_then(|t| t.0.last())
+            .map_or(line, |l| l.as_str());
+        log.record(&Entry {
+            time: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            actor: self.actor(),
+            line: line.trim().to_string(),
+            expanded: expanded.trim().to_string(),
+            branch: self.depth(),
+            res: res.clone(),
+        })
+    }
+}
+
+fn log_error<P: AsRef<Path>, E: ToString>(path: P, e: E) -> EngineError {
+    EngineError::Io(format!("{:?}: {}", path.as_ref(), e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn read(path: &Path) -> Vec<Entry> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_roll_log() {
+        let path = std::env::temp_dir().join(format!("dices-rolls-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut e = Engine::new()
+            .with(Some(PathBuf::from("testdata/aliases")))
+            .with_roll_log(RollLog::open(&path).unwrap());
+        e.eval("mouv +2").unwrap();
+        e.eval("list").unwrap();
+        e.eval("branch").unwrap();
+        e.eval("dice 2D6 # goblin damage").unwrap();
+
+        let all = read(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, all.len());
+        assert_eq!("mouv +2", all[0].line);
+        assert_eq!("dice 3D6 -9 +7 +2", all[0].expanded);
+        assert_eq!(0, all[0].branch);
+        assert_eq!(e.actor(), all[0].actor);
+        assert_eq!("dice 2D6", all[1].expanded);
+        assert_eq!(1, all[1].branch);
+        assert_eq!(2, all[1].res.list.len());
+        assert_eq!(None, all[0].res.note);
+        assert_eq!(Some("goblin damage"), all[1].res.note.as_deref());
+    }
+
+    #[test]
+    fn test_roll_log_append() {
+        let path = std::env::temp_dir().join(format!("dices-append-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        for _ in 0..2 {
+            let mut e = Engine::new().with_roll_log(RollLog::open(&path).unwrap());
+            e.eval("dice 3D6").unwrap();
+        }
+        let all = read(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(2, all.len());
+    }
+
+    #[test]
+    fn test_roll_log_player() {
+        let path = std::env::temp_dir().join(format!("dices-player-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut e = Engine::new().with_roll_log(RollLog::open(&path).unwrap());
+        e.eval("as Alice dice d20 +3").unwrap();
+
+        let all = read(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!("Alice", all[0].actor);
+        assert_eq!("dice d20 +3", all[0].line);
+        assert_eq!(Some("Alice"), all[0].res.player.as_deref());
+    }
+
+    #[test]
+    fn test_roll_log_hidden() {
+        let path = std::env::temp_dir().join(format!("dices-hidden-{}.log", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut e = Engine::new().with_roll_log(RollLog::open(&path).unwrap());
+        e.eval("gm on").unwrap();
+        e.eval("dice 3D6").unwrap();
+
+        let all = read(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(1, all.len());
+        assert_eq!("dice 3D6", all[0].line);
+    }
+
+    #[test]
+    fn test_roll_log_error() {
+        assert!(RollLog::open("testdata/nowhere/rolls.log").is_err());
+    }
+}
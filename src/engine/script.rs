@@ -0,0 +1,223 @@
+//! Small scripts in Rhai with the `rhai` command, needs the `script` feature.
+//!
+//! A script can roll, read and change variables, loop and compute bonuses, which makes macros
+//! like this one possible in the aliases file (strings in backquotes, the script in double
+//! quotes to keep its `;` and the macro in single quotes):
+//!
+//! ```text
+//! fireball = 'rhai "let dmg = roll(`8d6`); if dmg.sum > 30 { set(`burnt`, 1) }; dmg"'
+//! ```
+//!
+//! Functions given to scripts:
+//!
+//! - `roll(expr)`  roll like `dice`, `$name` being replaced by the variable, and recorded like
+//!   any other roll.  The roll has `sum`, `bonus`, `list`, `flag` and `note`.
+//! - `variable(name)`  value of a variable, an error if there is none
+//! - `set(name, value)`  change a variable, kept after the script
+//!
+//! What the script returns is shown: a roll or an array of rolls as rolls, anything else as
+//! text, and all the rolls made if it returns nothing.  In GM screen mode everything is hidden.
+//!
+//! ```
+//! use dices_rs::engine::{Engine, Output};
+//!
+//! let mut e = Engine::new();
+//! let out = e.eval("rhai let r = roll(`3d6`); r.sum >= 3").unwrap();
+//!
+//! assert_eq!(Output::Listing("true".to_string()), out);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use log::trace;
+use rhai::{Array, Dynamic, EvalAltResult};
+
+use crate::compiler::substitute;
+use crate::dice::result::Res;
+use crate::engine::core::Cmd;
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine, Output};
+
+/// Most operations of a script, against endless loops
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Most rolls made by a script
+pub const MAX_ROLLS: usize = 1_000;
+
+type Fallible<T> = Result<T, Box<EvalAltResult>>;
+
+impl Engine {
+    /// Handle `rhai <script>`: run the script then record its rolls and keep its variables
+    ///
+    pub(crate) fn script(&mut self, line: &str, input: &str) -> Result<Output, EngineError> {
+        let input = input.trim();
+        let source = match input.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            Some(inner) => inner,
+            None => input,
+        };
+        trace!("script({source})");
+
+        let vars = Arc::new(Mutex::new(self.variables()));
+        let rolls = Arc::new(Mutex::new(Vec::<(String, Res)>::new()));
+
+        let mut rhai = rhai::Engine::new();
+        rhai.set_max_operations(MAX_OPERATIONS);
+        rhai.register_type_with_name::<Res>("Roll")
+            .register_get("sum", |r: &mut Res| r.sum as i64)
+            .register_get("bonus", |r: &mut Res| r.bonus as i64)
+            .register_get("list", |r: &mut Res| {
+                r.list
+                    .iter()
+                    .map(|d| Dynamic::from(*d as i64))
+                    .collect::<Array>()
+            })
+            .register_get("flag", |r: &mut Res| format!("{:?}", r.flag))
+            .register_get("note", |r: &mut Res| r.note.clone().unwrap_or_default())
+            .register_fn("to_string", |r: &mut Res| r.to_string());
+
+        let (cfg, all, made) = (self.config.clone(), vars.clone(), rolls.clone());
+        rhai.register_fn("roll", move |expr: &str| -> Fallible<Res> {
+            let mut made = made.lock().unwrap();
+            if made.len() >= MAX_ROLLS {
+                return Err(format!("more than {MAX_ROLLS} rolls").into());
+            }
+            let expr = substitute(expr, &all.lock().unwrap()).map_err(|e| e.to_string())?;
+            let res = Cmd::Dice
+                .execute_with(&expr, &cfg)
+                .map_err(|e| e.to_string())?;
+            made.push((expr, res.clone()));
+            Ok(res)
+        });
+        let all = vars.clone();
+        rhai.register_fn("variable", move |name: &str| -> Fallible<i64> {
+            all.lock()
+                .unwrap()
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unknown variable {name}").into())
+        });
+        let all = vars.clone();
+        rhai.register_fn("set", move |name: &str, value: i64| {
+            all.lock().unwrap().insert(name.to_string(), value);
+        });
+
+        let value = rhai.eval::<Dynamic>(source);
+
+        // Rolls made before a failure still happened
+        //
+        let cmd = Command::Builtin {
+            name: "rhai".to_string(),
+            cmd: Cmd::Dice,
+        };
+        let made = std::mem::take(&mut *rolls.lock().unwrap());
+        let made = made
+            .into_iter()
+            .map(|(expr, res)| self.keep(line, &cmd, &expr, res, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        let value = value.map_err(|e| EngineError::Script(e.to_string()))?;
+        self.keep_variables(&vars.lock().unwrap())?;
+
+        if self.gm || value.is_unit() {
+            return Ok(self.shown(made));
+        }
+        if value.is::<Res>() {
+            return Ok(Output::Roll(value.cast::<Res>()));
+        }
+        match value.clone().try_cast::<Array>() {
+            Some(list) if !list.is_empty() && list.iter().all(|v| v.is::<Res>()) => Ok(
+                Output::Rolls(list.into_iter().map(|v| v.cast::<Res>()).collect()),
+            ),
+            _ => Ok(Output::Listing(value.to_string())),
+        }
+    }
+
+    /// Set the variables changed by a script
+    ///
+    fn keep_variables(&mut self, vars: &HashMap<String, i64>) -> Result<(), EngineError> {
+        let before = self.variables();
+        vars.iter()
+            .filter(|(name, value)| before.get(*name) != Some(value))
+            .try_for_each(|(name, value)| self.set(&format!("{name} {value}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[test]
+    fn test_script_roll() {
+        let mut e = Engine::new();
+
+        match e.eval("rhai roll(`3d6 +1`)").unwrap() {
+            Output::Roll(r) => {
+                assert_eq!(3, r.list.len());
+                assert_eq!(1, r.bonus);
+            }
+            o => panic!("{o:?}"),
+        }
+        assert!(e.last_roll("").is_ok());
+    }
+
+    #[test]
+    fn test_script_rolls() {
+        let mut e = Engine::new();
+
+        let out = e.eval("rhai for i in 0..3 { roll(`d6`) }").unwrap();
+        assert!(matches!(out, Output::Rolls(r) if r.len() == 3));
+        let out = e.eval("rhai [roll(`d6`), roll(`d8`)]").unwrap();
+        assert!(matches!(out, Output::Rolls(r) if r.len() == 2));
+    }
+
+    #[test]
+    fn test_script_variables() {
+        let mut e = Engine::new();
+        e.eval("set str 3").unwrap();
+
+        let out = e
+            .eval("rhai \"let r = roll(`d6 +$str`); set(`hp`, r.bonus * 2); r.bonus\"")
+            .unwrap();
+        assert_eq!(Output::Listing("3".to_string()), out);
+        assert_eq!(Some(&6), e.variables().get("hp"));
+        assert_eq!(
+            Output::Listing("9".to_string()),
+            e.eval("rhai variable(`hp`) + variable(`str`)").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_script_macro() {
+        let mut e = Engine::new().merge(vec![Command::Macro {
+            name: "fireball".to_string(),
+            cmd: "rhai \"let d = roll(`8d6`); if d.sum > 8 { set(`burnt`, 1) }; d\"".to_string(),
+        }]);
+
+        assert!(matches!(e.eval("fireball"), Ok(Output::Roll(r)) if r.list.len() == 8));
+        assert_eq!(Some(&1), e.variables().get("burnt"));
+    }
+
+    #[rstest]
+    #[case("rhai roll(`3d`)")]
+    #[case("rhai variable(`nope`)")]
+    #[case("rhai let x = ")]
+    #[case("rhai loop { }")]
+    #[case("rhai loop { roll(`d6`) }")]
+    fn test_script_errors(#[case] line: &str) {
+        let mut e = Engine::new();
+        assert!(matches!(e.eval(line), Err(EngineError::Script(_))));
+    }
+
+    #[test]
+    fn test_script_gm() {
+        let mut e = Engine::new();
+        e.eval("gm on").unwrap();
+
+        assert_eq!(
+            Output::Listing("hidden rolls #1 to #2".to_string()),
+            e.eval("rhai roll(`d6`); roll(`d6`).sum").unwrap()
+        );
+    }
+}
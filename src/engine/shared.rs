@@ -0,0 +1,130 @@
+//! Using one `Engine` from several threads, like a bot answering rolls concurrently or
+//! `dices serve`.
+//!
+//! `Engine` is `Send + Sync`, this is checked when compiling.  Generators are never kept in the
+//! engine: every roll creates one of the configured kind, so threads never share one.  The
+//! weighted and symbolic dices of the configuration are given to the parser along with the
+//! line (see `dice::parse::Defined`), nothing is kept per thread.
+//!
+//! Everything changing the session (`eval`, `run`, variables, labels, history...) takes
+//! `&mut self` and needs a `Mutex` around the engine.  `roll_line` only reads it and can be
+//! called through an `Arc`:
+//!
+//! ```
+//! use std::sync::Arc;
+//! use std::thread;
+//!
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Arc::new(Engine::new());
+//! let all = (0..4)
+//!     .map(|_| {
+//!         let e = Arc::clone(&e);
+//!         thread::spawn(move || e.roll_line("dice 3D6").unwrap().sum)
+//!     })
+//!     .collect::<Vec<_>>();
+//!
+//! for t in all {
+//!     assert!((3..=18).contains(&t.join().unwrap()));
+//! }
+//! ```
+
+use crate::compiler::{Action, Compiler};
+use crate::dice::result::Res;
+use crate::engine::error::EngineError;
+use crate::engine::observer::Event;
+use crate::engine::Engine;
+
+// Fails to compile if anything in `Engine` can not be shared between threads
+//
+const _: fn() = || {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<Engine>();
+};
+
+impl Engine {
+    /// Roll `line` with the current commands and variables without changing anything: no
+    /// label, no history, `last` and the count stay as they were.  Observers still hear
+    /// about the roll.  Only lines rolling dices are accepted.
+    ///
+    pub fn roll_line(&self, line: &str) -> Result<Res, EngineError> {
+        let action = Compiler::new(&self.cmds)
+            .with_defined(self.config.defined())
+            .compile_with(line, &self.variables())?;
+        match action {
+            Action::Execute(cmd, input, _) => {
+                let res = cmd.execute_with(&input, &self.config)?;
+                self.notify(Event::Rolled {
+                    line,
+                    res: &res,
+                    hidden: false,
+                });
+                Ok(res)
+            }
+            _ => Err(EngineError::NotBuiltin),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("dice 3D6 +2", 5..=20)]
+    #[case("3d6", 3..=18)]
+    #[case("flip", 0..=1)]
+    fn test_roll_line(#[case] line: &str, #[case] want: std::ops::RangeInclusive<isize>) {
+        let e = Engine::new();
+
+        let res = e.roll_line(line).unwrap();
+        assert!(want.contains(&res.sum), "{res:?}");
+        assert!(e.last.is_none());
+        assert_eq!(0, e.count);
+        assert!(e.history().is_empty());
+    }
+
+    #[rstest]
+    #[case("set str 2")]
+    #[case("exit")]
+    #[case("foo")]
+    #[case("dice 3d1")]
+    fn test_roll_line_error(#[case] line: &str) {
+        assert!(Engine::new().roll_line(line).is_err());
+    }
+
+    #[test]
+    fn test_roll_line_threads() {
+        let mut e = Engine::new();
+        e.eval("set str 2").unwrap();
+        let e = Arc::new(e);
+
+        let all = (0..8)
+            .map(|_| {
+                let e = Arc::clone(&e);
+                thread::spawn(move || e.roll_line("dice d6 +$str").unwrap().sum)
+            })
+            .collect::<Vec<_>>();
+        all.into_iter()
+            .for_each(|t| assert!((3..=8).contains(&t.join().unwrap())));
+    }
+
+    #[test]
+    fn test_engine_mutex() {
+        let e = Arc::new(Mutex::new(Engine::new()));
+
+        let all = (0..4)
+            .map(|_| {
+                let e = Arc::clone(&e);
+                thread::spawn(move || e.lock().unwrap().eval("dice 2D6").is_ok())
+            })
+            .collect::<Vec<_>>();
+        assert!(all.into_iter().all(|t| t.join().unwrap()));
+        assert_eq!(4, e.lock().unwrap().count);
+    }
+}
@@ -0,0 +1,157 @@
+//! Module to speak results through the OS text-to-speech.
+//!
+//! The text is given on the standard input of a command, by default `say` on macOS and
+//! `espeak --stdin` elsewhere.  Any command reading text on stdin can be configured with
+//! `speak_command` in `config.toml`.
+//!
+//! This needs the `speech` feature.  It is disabled by default and toggled in the REPL with
+//! `speak on` / `speak off`, the first `speak on` registering a `Speaker` as an observer of
+//! the engine.  Totals are spoken one after the other by a thread of their own so the engine
+//! does not wait for them.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use log::{error, trace};
+
+use crate::engine::observer::{EngineObserver, Event};
+
+/// Default text-to-speech command
+#[cfg(target_os = "macos")]
+pub const SPEAK_COMMAND: &str = "say";
+#[cfg(not(target_os = "macos"))]
+pub const SPEAK_COMMAND: &str = "espeak --stdin";
+
+/// Give `text` to `command` and wait for it to be spoken.
+///
+pub fn speak(command: &str, text: &str) -> Result<()> {
+    trace!("speak({command}, {text})");
+
+    let mut args = command.split_whitespace();
+    let prog = args.next().ok_or_else(|| anyhow!("empty speak command"))?;
+
+    let mut child = Command::new(prog)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow!("can not run {}: {}", prog, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(anyhow!("{} failed: {}", prog, status)),
+    }
+}
+
+/// Speak the total of every roll shown while `on` is set
+///
+pub struct Speaker {
+    /// Speak or stay silent, shared with the engine
+    on: Arc<AtomicBool>,
+    /// Texts waiting to be spoken
+    queue: Sender<String>,
+}
+
+impl Speaker {
+    /// Speak with `command`, the thread doing it stops with the `Speaker`
+    ///
+    pub fn new(command: &str, on: Arc<AtomicBool>) -> Self {
+        let (queue, texts) = channel::<String>();
+        let command = command.to_string();
+        thread::spawn(move || {
+            texts.iter().for_each(|text| {
+                if let Err(e) = speak(&command, &text) {
+                    error!("{}", e);
+                }
+            })
+        });
+        Speaker { on, queue }
+    }
+}
+
+impl EngineObserver for Speaker {
+    fn notify(&self, event: &Event<'_>) {
+        if let Event::Rolled {
+            res, hidden: false, ..
+        } = event
+        {
+            if self.on.load(Ordering::Relaxed) {
+                let _ = self.queue.send(res.total().to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use crate::dice::result::Res;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_speak() {
+        assert!(speak("cat", "12").is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_speak_failed() {
+        assert!(speak("false", "12").is_err());
+    }
+
+    #[test]
+    fn test_speak_empty() {
+        assert!(speak("  ", "12").is_err());
+    }
+
+    #[test]
+    fn test_speak_unknown() {
+        assert!(speak("/nonexistent/tts", "12").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_speaker() {
+        let fname = std::env::temp_dir().join(format!("dices-spoken-{}", std::process::id()));
+        let _ = fs::remove_file(&fname);
+        let on = Arc::new(AtomicBool::new(false));
+        let speaker = Speaker::new(&format!("tee -a {}", fname.display()), on.clone());
+
+        let rolled = |sum, hidden| {
+            let res = Res { sum, ..Res::new() };
+            speaker.notify(&Event::Rolled {
+                line: "dice d20",
+                res: &res,
+                hidden,
+            });
+        };
+        rolled(1, false);
+        on.store(true, Ordering::Relaxed);
+        rolled(2, true);
+        rolled(3, false);
+
+        // Spoken in the background
+        //
+        for _ in 0..50 {
+            if fs::read_to_string(&fname).is_ok_and(|s| !s.is_empty()) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!("3", fs::read_to_string(&fname).unwrap());
+        let _ = fs::remove_file(&fname);
+    }
+}
@@ -0,0 +1,139 @@
+//! Where the session state (variables, labelled rolls and usage dice) is kept.
+//!
+//! The engine uses `MemoryStorage` by default, nothing touches the disk.  `FileStorage` keeps
+//! the state in a YAML file so that it survives restarts.  Anything else (a database, one
+//! entry per chat room, ...) only needs to implement `Storage`.
+//!
+//! ```no_run
+//! use dices_rs::engine::storage::FileStorage;
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Engine::new()
+//!     .with_storage(Box::new(FileStorage::new("/some/location/session.yaml")))
+//!     .unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::dice::result::Res;
+use crate::engine::error::EngineError;
+
+/// State of a session
+///
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Session {
+    /// Variables, used as `$name`
+    pub vars: HashMap<String, i64>,
+    /// Labelled rolls (`dice d20 as attack`), also usable as `$name`
+    pub labels: HashMap<String, Res>,
+    /// Current size of named usage dice, 0 once depleted
+    pub usage: HashMap<String, usize>,
+}
+
+/// A place to load and save the session from
+///
+pub trait Storage: Send + Sync {
+    /// Get the saved session, an empty one if there is none yet
+    ///
+    fn load(&self) -> Result<Session, EngineError>;
+
+    /// Save the whole session
+    ///
+    fn save(&mut self, session: &Session) -> Result<(), EngineError>;
+}
+
+/// Keep everything in memory
+///
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage(Session);
+
+impl Storage for MemoryStorage {
+    fn load(&self) -> Result<Session, EngineError> {
+        Ok(self.0.clone())
+    }
+
+    fn save(&mut self, session: &Session) -> Result<(), EngineError> {
+        self.0 = session.clone();
+        Ok(())
+    }
+}
+
+/// Keep the session in a YAML file
+///
+#[derive(Clone, Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+}
+
+impl FileStorage {
+    /// Use `path`, created on first save
+    ///
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileStorage { path: path.into() }
+    }
+}
+
+impl Storage for FileStorage {
+    fn load(&self) -> Result<Session, EngineError> {
+        trace!("storage::load({:?})", self.path);
+        if !self.path.exists() {
+            return Ok(Session::default());
+        }
+        let content = fs::read_to_string(&self.path).map_err(|e| EngineError::Io(e.to_string()))?;
+        serde_yaml::from_str(&content).map_err(|e| EngineError::Storage(e.to_string()))
+    }
+
+    fn save(&mut self, session: &Session) -> Result<(), EngineError> {
+        trace!("storage::save({:?})", self.path);
+        let content =
+            serde_yaml::to_string(session).map_err(|e| EngineError::Storage(e.to_string()))?;
+        fs::write(&self.path, content).map_err(|e| EngineError::Io(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let mut res = Res::new();
+        res.append(12);
+        Session {
+            vars: HashMap::from([("str".to_string(), 4)]),
+            labels: HashMap::from([("attack".to_string(), res)]),
+            usage: HashMap::from([("torch".to_string(), 8)]),
+        }
+    }
+
+    #[test]
+    fn test_memory_storage() {
+        let mut s = MemoryStorage::default();
+        assert_eq!(Session::default(), s.load().unwrap());
+
+        s.save(&session()).unwrap();
+        assert_eq!(session(), s.load().unwrap());
+    }
+
+    #[test]
+    fn test_file_storage() {
+        let path = std::env::temp_dir().join(format!("dices-session-{}.yaml", std::process::id()));
+        let mut s = FileStorage::new(&path);
+        assert_eq!(Session::default(), s.load().unwrap());
+
+        s.save(&session()).unwrap();
+        assert_eq!(session(), FileStorage::new(&path).load().unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_file_storage_invalid() {
+        let s = FileStorage::new("testdata/config.toml");
+        assert!(matches!(s.load(), Err(EngineError::Storage(_))));
+    }
+}
@@ -0,0 +1,406 @@
+//! Random tables: roll a dice and look up the matching entry.
+//!
+//! A table is a YAML file (`.yaml` or `.yml`) or a CSV file (`.csv`) named after the table,
+//! all of them being loaded from the `tables` directory next to the configuration (or
+//! `table_dir` in `config.toml`).  Each entry is a range of totals (or a single total) with
+//! its text, `dice` is what is rolled, the default being `D<highest total>`.
+//!
+//! ```text
+//! # encounter.yaml
+//! dice: D100
+//! 1-50: nothing
+//! 51-90: wandering monster
+//! 91-100: "treasure: [[treasure]]"
+//! ```
+//!
+//! ```text
+//! # treasure.csv
+//! dice,2D6
+//! 2-6,copper coins
+//! 7-11,silver coins
+//! 12,a gem
+//! ```
+//!
+//! `[[name]]` in a text is replaced by a roll on the table `name`, up to `MAX_DEPTH` levels
+//! deep.  `table encounter` rolls on a table, `table` alone lists them.
+//!
+//! ```no_run
+//! use dices_rs::engine::table::Table;
+//! use dices_rs::engine::Engine;
+//!
+//! let e = Table::load_dir("/some/location/tables")
+//!     .unwrap()
+//!     .into_iter()
+//!     .fold(Engine::new(), |e, t| e.with_table(t));
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use itertools::Itertools;
+use log::trace;
+use serde_yaml::Value;
+
+use crate::engine::core::Cmd;
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine};
+
+/// How many tables can refer to each other in a single roll
+pub const MAX_DEPTH: usize = 5;
+
+/// One entry, rolled with a total between `low` and `high`
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry {
+    pub low: usize,
+    pub high: usize,
+    pub text: String,
+}
+
+/// One random table
+///
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Table {
+    /// Used by `table <name>`, the name of the file
+    pub name: String,
+    /// Rolled to find the entry
+    pub dice: String,
+    /// Entries sorted by range
+    pub entries: Vec<Entry>,
+}
+
+impl Table {
+    /// Build a table from `(range, text)` rows, `dice` defaulting to `D<highest total>`
+    ///
+    pub fn new<S: Into<String>>(
+        name: &str,
+        dice: Option<String>,
+        rows: Vec<(S, String)>,
+    ) -> Result<Self, EngineError> {
+        let error = |e: String| EngineError::Table(format!("{name}: {e}"));
+
+        let mut entries = rows
+            .into_iter()
+            .map(|(range, text)| {
+                let range: String = range.into();
+                let (low, high) =
+                    parse_range(&range).ok_or_else(|| error(format!("invalid range {range:?}")))?;
+                Ok(Entry { low, high, text })
+            })
+            .collect::<Result<Vec<_>, EngineError>>()?;
+        entries.sort_by_key(|e| e.low);
+
+        if entries.is_empty() {
+            return Err(error("no entries".to_string()));
+        }
+        if let Some((a, b)) = entries
+            .iter()
+            .tuple_windows()
+            .find(|(a, b)| a.high >= b.low)
+        {
+            return Err(error(format!(
+                "{}-{} overlaps {}-{}",
+                a.low, a.high, b.low, b.high
+            )));
+        }
+        let highest = entries.last().map(|e| e.high).unwrap_or_default();
+        Ok(Table {
+            name: name.to_lowercase(),
+            dice: dice.unwrap_or_else(|| format!("D{highest}")),
+            entries,
+        })
+    }
+
+    /// Read a table from a YAML or CSV file, named after the file
+    ///
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, EngineError> {
+        let path = path.as_ref();
+        trace!("table::load({path:?})");
+
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let error = |e: String| EngineError::Table(format!("{path:?}: {e}"));
+        let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+
+        let mut dice = None;
+        let mut rows = vec![];
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => {
+                for line in content.lines().map(str::trim) {
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let (key, text) = line
+                        .split_once(',')
+                        .ok_or_else(|| error(format!("no text in {line:?}")))?;
+                    let text = text.trim().trim_matches('"').to_string();
+                    match key.trim() {
+                        "dice" => dice = Some(text),
+                        key => rows.push((key.to_string(), text)),
+                    }
+                }
+            }
+            _ => {
+                let map: serde_yaml::Mapping =
+                    serde_yaml::from_str(&content).map_err(|e| error(e.to_string()))?;
+                for (key, text) in map {
+                    let key = match key {
+                        Value::String(s) => s,
+                        Value::Number(n) => n.to_string(),
+                        k => return Err(error(format!("invalid range {k:?}"))),
+                    };
+                    let text = match text {
+                        Value::String(s) => s,
+                        Value::Number(n) => n.to_string(),
+                        t => return Err(error(format!("invalid text {t:?}"))),
+                    };
+                    match key.as_str() {
+                        "dice" => dice = Some(text),
+                        _ => rows.push((key, text)),
+                    }
+                }
+            }
+        }
+        Table::new(&name, dice, rows)
+    }
+
+    /// Load every `.yaml`, `.yml` and `.csv` file of `dir`
+    ///
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>, EngineError> {
+        let dir = dir.as_ref();
+        trace!("table::load_dir({dir:?})");
+
+        let files = fs::read_dir(dir).map_err(|e| EngineError::Table(format!("{dir:?}: {e}")))?;
+        files
+            .filter_map(|f| f.ok().map(|f| f.path()))
+            .filter(|p| {
+                matches!(
+                    p.extension().and_then(|e| e.to_str()),
+                    Some("yaml" | "yml" | "csv")
+                )
+            })
+            .sorted()
+            .map(Table::load)
+            .collect()
+    }
+
+    /// Entry for `total`, if any
+    ///
+    pub fn lookup(&self, total: isize) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|e| (e.low as isize..=e.high as isize).contains(&total))
+    }
+}
+
+/// `12` or `1-50`
+///
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (low, high) = match range.split_once('-') {
+        Some((low, high)) => (low.trim().parse().ok()?, high.trim().parse().ok()?),
+        None => {
+            let n = range.trim().parse().ok()?;
+            (n, n)
+        }
+    };
+    (low <= high).then_some((low, high))
+}
+
+impl Engine {
+    /// Add a table, replacing any other with the same name
+    ///
+    pub fn with_table(mut self, t: Table) -> Self {
+        self.tables.insert(t.name.clone(), t);
+        self
+    }
+
+    /// Handle `table [name]`, listing all tables without argument.  `line` is the original
+    /// line, recorded with the rolls.
+    ///
+    pub fn table(&mut self, line: &str, args: &str) -> Result<String, EngineError> {
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => Ok(self
+                .tables
+                .values()
+                .map(|t| format!("table \t{} = {}", t.name, t.dice))
+                .join("\n")),
+            [name] => {
+                let (total, text) = self.roll_table(line, name, 0)?;
+                Ok(format!("{} {total}: {text}", name.to_lowercase()))
+            }
+            _ => Err(EngineError::Usage("table [name]".to_string())),
+        }
+    }
+
+    /// Roll on the table `name`, replacing references to other tables in the text
+    ///
+    fn roll_table(
+        &mut self,
+        line: &str,
+        name: &str,
+        depth: usize,
+    ) -> Result<(isize, String), EngineError> {
+        trace!("roll_table({name}, {depth})");
+        if depth >= MAX_DEPTH {
+            return Err(EngineError::Table(format!(
+                "{name}: more than {MAX_DEPTH} nested tables"
+            )));
+        }
+        let t = self
+            .tables
+            .get(&name.to_lowercase())
+            .cloned()
+            .ok_or_else(|| EngineError::Table(format!("unknown {name}")))?;
+
+        let cmd = Command::Builtin {
+            name: "table".to_string(),
+            cmd: Cmd::Dice,
+        };
+        let total = self.roll(line, &cmd, &t.dice, None)?.total();
+        let text = t
+            .lookup(total)
+            .map(|e| e.text.clone())
+            .ok_or_else(|| EngineError::Table(format!("{name}: no entry for {total}")))?;
+
+        // Expand `[[other]]` in place
+        //
+        let mut rest = text.as_str();
+        let mut expanded = String::new();
+        while let Some((before, after)) = rest.split_once("[[") {
+            let Some((other, after)) = after.split_once("]]") else {
+                break;
+            };
+            let (_, sub) = self.roll_table(line, other.trim(), depth + 1)?;
+            expanded.push_str(before);
+            expanded.push_str(&sub);
+            rest = after;
+        }
+        expanded.push_str(rest);
+        Ok((total, expanded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::engine::Output;
+
+    use super::*;
+
+    fn tables() -> Engine {
+        Table::load_dir("testdata/tables")
+            .unwrap()
+            .into_iter()
+            .fold(Engine::new(), |e, t| e.with_table(t))
+    }
+
+    #[rstest]
+    #[case("12", Some((12, 12)))]
+    #[case("1-50", Some((1, 50)))]
+    #[case(" 2 - 6 ", Some((2, 6)))]
+    #[case("6-2", None)]
+    #[case("many", None)]
+    #[case("1-", None)]
+    fn test_parse_range(#[case] range: &str, #[case] r: Option<(usize, usize)>) {
+        assert_eq!(r, parse_range(range));
+    }
+
+    #[rstest]
+    #[case("testdata/tables/encounter.yaml", "D100", 3)]
+    #[case("testdata/tables/treasure.csv", "2D6", 3)]
+    #[case("testdata/tables/weather.yml", "D6", 4)]
+    fn test_table_load(#[case] path: &str, #[case] dice: &str, #[case] n: usize) {
+        let t = Table::load(path).unwrap();
+
+        assert_eq!(dice, t.dice);
+        assert_eq!(n, t.entries.len());
+    }
+
+    #[test]
+    fn test_table_load_error() {
+        assert!(Table::load("testdata/tables/none.yaml").is_err());
+        assert!(Table::load("testdata/aliases").is_err());
+        assert!(Table::load_dir("testdata/none").is_err());
+    }
+
+    #[test]
+    fn test_table_new_invalid() {
+        let rows = |r: &[&str]| {
+            r.iter()
+                .map(|r| (r.to_string(), "x".to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(Table::new("t", None, rows(&["1-3", "3-6"])).is_err());
+        assert!(Table::new("t", None, rows(&["1-3", "4-x"])).is_err());
+        assert!(Table::new("t", None, rows(&[])).is_err());
+        assert_eq!(
+            "D8",
+            Table::new("t", None, rows(&["5-8", "1-4"])).unwrap().dice
+        );
+    }
+
+    #[test]
+    fn test_table_lookup() {
+        let t = Table::load("testdata/tables/treasure.csv").unwrap();
+
+        assert_eq!("copper coins", t.lookup(2).unwrap().text);
+        assert_eq!("a gem", t.lookup(12).unwrap().text);
+        assert!(t.lookup(1).is_none());
+    }
+
+    #[test]
+    fn test_table_roll() {
+        let mut e = tables();
+
+        assert_eq!(
+            Ok(Output::Listing(
+                "table \tencounter = D100\ntable \ttreasure = 2D6\ntable \tweather = D6"
+                    .to_string()
+            )),
+            e.eval("table")
+        );
+        for _ in 0..50 {
+            let Ok(Output::Listing(s)) = e.eval("table Encounter") else {
+                panic!("no table");
+            };
+            assert!(s.starts_with("encounter "), "{s}");
+            assert!(!s.contains("[["), "{s}");
+        }
+        assert!(e.last_roll("").is_ok());
+    }
+
+    #[test]
+    fn test_table_nested_loop() {
+        let lp = |name: &str, other: &str| {
+            Table::new(name, None, vec![("1-2", format!("see [[{other}]]"))]).unwrap()
+        };
+        let mut e = Engine::new()
+            .with_table(lp("a", "b"))
+            .with_table(lp("b", "a"));
+
+        assert!(matches!(e.table("", "a"), Err(EngineError::Table(_))));
+
+        let mut e = Engine::new().with_table(lp("a", "c"));
+        assert_eq!(
+            Err(EngineError::Table("unknown c".to_string())),
+            e.table("", "a")
+        );
+    }
+
+    #[rstest]
+    #[case("nowhere", "unknown nowhere")]
+    #[case("encounter treasure", "")]
+    fn test_table_errors(#[case] args: &str, #[case] msg: &str) {
+        let mut e = tables();
+
+        let err = e.table("", args).unwrap_err();
+        match msg {
+            "" => assert_eq!(EngineError::Usage("table [name]".to_string()), err),
+            msg => assert_eq!(EngineError::Table(msg.to_string()), err),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Usage dice, a countdown mechanic common in OSR games.
+//!
+//! A usage die stands for a resource (torches, rations, arrows, ...).  Each time it is used
+//! the die is rolled and on 1 or 2 it gets one size smaller, down the chain of `SIZES`.  A d4
+//! rolling 1 or 2 is depleted.
+//!
+//! Named dice are kept in the session so they survive restarts like variables:
+//!
+//! ```text
+//! Dices> usage create torch d8
+//! torch is d8
+//! Dices> usage torch
+//! torch d8: 2, down to d6
+//! Dices> usage d6
+//! d6: 5, stays d6
+//! ```
+
+use itertools::Itertools;
+use log::trace;
+
+use crate::engine::core::Cmd;
+use crate::engine::error::EngineError;
+use crate::engine::{Command, Engine};
+
+/// Sizes of a usage die, from the largest one
+pub const SIZES: [usize; 6] = [20, 12, 10, 8, 6, 4];
+
+/// Highest roll making the die smaller
+pub const DEPLETE: usize = 2;
+
+/// Size after rolling `r` on a die of size `size`, 0 when depleted
+///
+pub fn next_size(size: usize, r: usize) -> usize {
+    match r > DEPLETE {
+        true => size,
+        false => SIZES
+            .iter()
+            .skip_while(|&&s| s != size)
+            .nth(1)
+            .copied()
+            .unwrap_or(0),
+    }
+}
+
+/// Size of `d<N>` if N is one of `SIZES`
+///
+fn parse_size(s: &str) -> Option<usize> {
+    s.strip_prefix(['d', 'D'])
+        .and_then(|n| n.parse::<usize>().ok())
+        .filter(|n| SIZES.contains(n))
+}
+
+/// `d6` or `depleted`
+///
+fn show_size(size: usize) -> String {
+    match size {
+        0 => "depleted".to_string(),
+        s => format!("d{s}"),
+    }
+}
+
+impl Engine {
+    /// Handle `usage [<name>|d<N>|create <name> d<N>]`, listing the named dice without
+    /// argument.  `line` is the original line, recorded with the roll.
+    ///
+    pub fn usage(&mut self, line: &str, args: &str) -> Result<String, EngineError> {
+        trace!("usage({args})");
+        let usage = || EngineError::Usage("usage [<name>|d<N>|create <name> d<N>]".to_string());
+
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => Ok(self
+                .session
+                .usage
+                .iter()
+                .sorted()
+                .map(|(n, s)| format!("usage \t{n} = {}", show_size(*s)))
+                .join("\n")),
+            ["create", name, size] => {
+                let size = parse_size(size).ok_or_else(usage)?;
+                if name == "create"
+                    || parse_size(name).is_some()
+                    || !name.chars().all(|c| c.is_alphanumeric() || c == '_')
+                {
+                    return Err(usage());
+                }
+                self.session.usage.insert(name.to_string(), size);
+                self.save_session()?;
+                Ok(format!("{name} is d{size}"))
+            }
+            [die] => match parse_size(die) {
+                Some(size) => {
+                    let (r, next) = self.roll_usage(line, size)?;
+                    Ok(format!("d{size}: {r}, {}", change(size, next)))
+                }
+                None => {
+                    let size =
+                        *self.session.usage.get(die).ok_or_else(|| {
+                            EngineError::Usage(format!("unknown usage die {die}"))
+                        })?;
+                    if size == 0 {
+                        return Err(EngineError::Usage(format!("{die} is depleted")));
+                    }
+                    let (r, next) = self.roll_usage(line, size)?;
+                    self.session.usage.insert(die.to_string(), next);
+                    self.save_session()?;
+                    Ok(format!("{die} d{size}: {r}, {}", change(size, next)))
+                }
+            },
+            _ => Err(usage()),
+        }
+    }
+
+    /// Roll a usage die of size `size`, returning the roll and the next size
+    ///
+    fn roll_usage(&mut self, line: &str, size: usize) -> Result<(usize, usize), EngineError> {
+        let cmd = Command::Builtin {
+            name: "usage".to_string(),
+            cmd: Cmd::Dice,
+        };
+        let res = self.roll(line, &cmd, &format!("d{size}"), None)?;
+        let r = res.list.first().copied().unwrap_or_default();
+        Ok((r, next_size(size, r)))
+    }
+}
+
+/// What happened to the die
+///
+fn change(size: usize, next: usize) -> String {
+    match next {
+        0 => "depleted".to_string(),
+        n if n == size => format!("stays d{n}"),
+        n => format!("down to d{n}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::engine::Output;
+
+    use super::*;
+
+    #[rstest]
+    #[case(20, 1, 12)]
+    #[case(8, 2, 6)]
+    #[case(8, 3, 8)]
+    #[case(6, 6, 6)]
+    #[case(4, 2, 0)]
+    #[case(4, 4, 4)]
+    fn test_next_size(#[case] size: usize, #[case] r: usize, #[case] next: usize) {
+        assert_eq!(next, next_size(size, r));
+    }
+
+    #[rstest]
+    #[case("d6", Some(6))]
+    #[case("D20", Some(20))]
+    #[case("d7", None)]
+    #[case("torch", None)]
+    fn test_parse_size(#[case] s: &str, #[case] size: Option<usize>) {
+        assert_eq!(size, parse_size(s));
+    }
+
+    #[test]
+    fn test_usage_named() {
+        let mut e = Engine::new();
+
+        assert_eq!(
+            Ok("torch is d8".to_string()),
+            e.usage("", "create torch d8")
+        );
+        assert_eq!(
+            Ok(Output::Listing("usage \ttorch = d8".to_string())),
+            e.eval("usage")
+        );
+
+        // Sooner or later the torch is gone
+        //
+        let mut size = 8;
+        while size > 0 {
+            let out = e.usage("", "torch").unwrap();
+            assert!(out.starts_with(&format!("torch d{size}: ")), "{out}");
+            size = e.session.usage["torch"];
+        }
+        assert_eq!("usage \ttorch = depleted", e.usage("", "").unwrap());
+        assert_eq!(
+            Err(EngineError::Usage("torch is depleted".to_string())),
+            e.usage("", "torch")
+        );
+    }
+
+    #[test]
+    fn test_usage_anonymous() {
+        let mut e = Engine::new();
+
+        let out = e.usage("usage d4", "d4").unwrap();
+        assert!(out.starts_with("d4: "));
+        assert!(
+            out.ends_with("stays d4") || out.ends_with("depleted"),
+            "{out}"
+        );
+        assert!(e.session.usage.is_empty());
+        assert!(e.last_roll("").is_ok());
+    }
+
+    #[rstest]
+    #[case("create torch")]
+    #[case("create torch d7")]
+    #[case("create d6 d8")]
+    #[case("create create d8")]
+    #[case("torch d6")]
+    fn test_usage_invalid(#[case] args: &str) {
+        let mut e = Engine::new();
+
+        assert_eq!(
+            Err(EngineError::Usage(
+                "usage [<name>|d<N>|create <name> d<N>]".to_string()
+            )),
+            e.usage("", args)
+        );
+        assert_eq!(
+            Err(EngineError::Usage("unknown usage die lamp".to_string())),
+            e.usage("", "lamp")
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! `version` command: everything worth putting in a bug report, and `banner`.
+//!
+//! ```text
+//! dices-rs/0.9.6 (git 1a2b3c4, built 2024-03-02)
+//! features: none
+//! rng: fast (thread_rng)
+//! config: /home/user/.config/dices/config.toml
+//! aliases: /home/user/.config/dices/aliases (profile dnd)
+//! ```
+//!
+//! The build part is available to programs as a `BuildInfo`:
+//!
+//! ```
+//! use dices_rs::engine::version::build_info;
+//!
+//! let b = build_info();
+//! assert_eq!("dices-rs", b.name);
+//! assert!(b.to_string().starts_with(&format!("dices-rs/{} (git ", b.version)));
+//! ```
+
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+
+use crate::engine::Engine;
+
+/// Crate name
+pub const NAME: &str = env!("CARGO_PKG_NAME");
+/// Crate version
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short git hash of the build, `unknown` if built outside of git
+pub const GIT_HASH: &str = env!("GIT_HASH");
+/// Day of the build, as `YYYY-MM-DD`
+pub const BUILD_DATE: &str = env!("BUILD_DATE");
+
+/// What was built and how
+///
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct BuildInfo {
+    /// Crate name
+    pub name: &'static str,
+    /// Crate version
+    pub version: &'static str,
+    /// Short git hash
+    pub git_hash: &'static str,
+    /// Day of the build
+    pub build_date: &'static str,
+    /// Enabled cargo features
+    pub features: Vec<&'static str>,
+}
+
+/// Build information of the library
+///
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        name: NAME,
+        version: VERSION,
+        git_hash: GIT_HASH,
+        build_date: BUILD_DATE,
+        features: features(),
+    }
+}
+
+impl BuildInfo {
+    /// Startup banner of a program called `name`, with the authors and description
+    ///
+    pub fn banner(&self, name: &str) -> String {
+        format!(
+            "{name}/{} (git {}) by {}\n{}",
+            self.version,
+            self.git_hash,
+            env!("CARGO_PKG_AUTHORS").replace(':', ", "),
+            env!("CARGO_PKG_DESCRIPTION")
+        )
+    }
+}
+
+impl Display for BuildInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} (git {}, built {})",
+            self.name, self.version, self.git_hash, self.build_date
+        )
+    }
+}
+
+/// Enabled cargo features
+///
+pub fn features() -> Vec<&'static str> {
+    // Add new cargo features here as `cfg!(feature = "...")` checks
+    //
+    [
+        ("cli", cfg!(feature = "cli")),
+        ("script", cfg!(feature = "script")),
+        ("server", cfg!(feature = "server")),
+        ("speech", cfg!(feature = "speech")),
+        ("sqlite", cfg!(feature = "sqlite")),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| on.then_some(name))
+    .collect()
+}
+
+impl Engine {
+    /// Version, build and runtime information
+    ///
+    pub fn version(&self) -> String {
+        let info = build_info();
+        let features = match &info.features {
+            f if f.is_empty() => "none".to_string(),
+            f => f.join(", "),
+        };
+        let path = |p: &Option<std::path::PathBuf>| match p {
+            Some(p) => p.display().to_string(),
+            None => "none".to_string(),
+        };
+        let profile = match self.profile() {
+            Some(p) => format!(" (profile {p})"),
+            None => String::new(),
+        };
+        format!(
+            "{info}\nfeatures: {features}\nrng: {}\nconfig: {}\naliases: {}{profile}",
+            self.config.roller,
+            path(&self.config.path),
+            path(&self.alias_file),
+        )
+    }
+
+    /// Name, version, authors and description
+    ///
+    pub fn banner(&self) -> String {
+        build_info().banner("dices")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_engine_version() {
+        let e = Engine::new().with(Some(PathBuf::from("testdata/aliases")));
+        let v = e.version();
+
+        assert!(v.starts_with(&format!("dices-rs/{VERSION} (git ")));
+        assert!(v.contains(&match features() {
+            f if f.is_empty() => "features: none".to_string(),
+            f => format!("features: {}", f.join(", ")),
+        }));
+        assert!(v.contains("rng: fast (thread_rng)"));
+        assert!(v.contains("config: none"));
+        assert!(v.contains("aliases: testdata/aliases"));
+        assert!(v.contains(&format!(", built {BUILD_DATE})\n")));
+    }
+
+    #[test]
+    fn test_build_info() {
+        let b = build_info();
+
+        assert_eq!(VERSION, b.version);
+        assert_eq!(features(), b.features);
+        assert_eq!(10, b.build_date.len());
+        assert_eq!(
+            format!("dices-rs/{VERSION} (git {GIT_HASH}, built {BUILD_DATE})"),
+            b.to_string()
+        );
+    }
+
+    #[test]
+    fn test_banner() {
+        let b = Engine::new().banner();
+
+        assert!(b.starts_with(&format!(
+            "dices/{VERSION} (git {GIT_HASH}) by Ollivier Robert"
+        )));
+        assert!(b.ends_with("\nSmall CLI utility to roll dices."));
+    }
+}
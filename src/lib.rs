@@ -1,7 +1,30 @@
+//! Library and CLI to roll dices.
+//!
+//! The easiest way in is the one-shot API:
+//! ```
+//! let r = dices_rs::roll_seeded("3D6 +2", 42).unwrap();
+//! assert_eq!(3, r.list.len());
+//! ```
+//!
+//! More complete examples are in the `examples/` directory:
+//!
+//! - `roll`    rolling dices from the library
+//! - `engine`  embedding the engine with its commands and aliases
+//! - `custom`  writing your own `Rollable`
+//! - `bot`     skeleton for a chat bot
+//!
+
 // Stitch our modules together
-mod compiler;
+pub mod compiler;
+pub mod config;
 pub mod dice;
 pub mod engine;
+pub mod locale;
+pub mod messages;
+#[cfg(feature = "cli")]
+pub mod paths;
+
+pub use dice::{roll, roll_seeded};
 
 /// Simple macro to generate PathBuf from a series of entries
 ///
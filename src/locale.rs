@@ -0,0 +1,176 @@
+//! Locale handling.
+//!
+//! This is about how numbers are displayed, large numbers are grouped and the decimal
+//! separator depends on the locale (`10,000.5` vs `10 000,5`).  The language of the messages
+//! is in `messages`.
+//!
+//! The locale is either given explicitly (see `Config`) or taken from the usual environment
+//! variables (`LC_ALL`, `LC_NUMERIC` then `LANG`).
+//!
+//! Example:
+//! ```
+//! use dices_rs::locale::NumberFormat;
+//!
+//! let nf = NumberFormat::new("fr_FR.UTF-8");
+//! assert_eq!("10 000", nf.int(10000));
+//! assert_eq!("12,50", nf.float(12.5, 2));
+//! ```
+
+use std::env;
+
+use log::trace;
+
+/// Environment variables checked in order to find the locale
+const LOCALE_VARS: [&str; 3] = ["LC_ALL", "LC_NUMERIC", "LANG"];
+
+/// How to display numbers
+///
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NumberFormat {
+    /// Separator between groups of thousands if any
+    pub group: Option<char>,
+    /// Decimal separator
+    pub decimal: char,
+}
+
+/// The "C" locale, no grouping and a dot.
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            group: None,
+            decimal: '.',
+        }
+    }
+}
+
+impl NumberFormat {
+    /// Get the number format for a given locale name like `fr_FR.UTF-8` or `en`.
+    ///
+    pub fn new(locale: &str) -> Self {
+        trace!("numberformat::new({locale})");
+
+        let lang = language(locale);
+        let (group, decimal) = match lang.as_str() {
+            "en" | "ja" | "ko" | "zh" => (Some(','), '.'),
+            "de" | "es" | "it" | "nl" | "pt" | "da" | "id" | "tr" => (Some('.'), ','),
+            "fr" | "cs" | "fi" | "nb" | "pl" | "ru" | "sv" | "uk" => (Some(' '), ','),
+            _ => (None, '.'),
+        };
+        NumberFormat { group, decimal }
+    }
+
+    /// Get the number format from the environment, defaults to the "C" locale.
+    ///
+    pub fn from_env() -> Self {
+        from_env(&LOCALE_VARS)
+            .map(|v| NumberFormat::new(&v))
+            .unwrap_or_default()
+    }
+
+    /// Format an integer
+    ///
+    pub fn int(&self, n: i64) -> String {
+        let digits = n.unsigned_abs().to_string();
+        let digits = self.grouped(&digits);
+        if n < 0 {
+            format!("-{digits}")
+        } else {
+            digits
+        }
+    }
+
+    /// Format a floating point number with `prec` decimals
+    ///
+    pub fn float(&self, f: f64, prec: usize) -> String {
+        let s = format!("{:.*}", prec, f.abs());
+        let (int, frac) = match s.split_once('.') {
+            Some((int, frac)) => (int, Some(frac)),
+            None => (s.as_str(), None),
+        };
+        let sign = if f.is_sign_negative() && s.chars().any(|c| c != '0' && c != '.') {
+            "-"
+        } else {
+            ""
+        };
+        match frac {
+            Some(frac) => format!("{sign}{}{}{frac}", self.grouped(int), self.decimal),
+            None => format!("{sign}{}", self.grouped(int)),
+        }
+    }
+
+    /// Insert the group separator every three digits
+    ///
+    fn grouped(&self, digits: &str) -> String {
+        match self.group {
+            Some(sep) => {
+                let len = digits.len();
+                digits
+                    .chars()
+                    .enumerate()
+                    .fold(String::new(), |mut acc, (i, c)| {
+                        if i > 0 && (len - i) % 3 == 0 {
+                            acc.push(sep);
+                        }
+                        acc.push(c);
+                        acc
+                    })
+            }
+            None => digits.to_string(),
+        }
+    }
+}
+
+/// Language part of a locale name, `fr` for `fr_FR.UTF-8`
+///
+pub(crate) fn language(locale: &str) -> String {
+    locale
+        .split(['_', '.', '@', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// First locale set in the environment variables `vars`
+///
+pub(crate) fn from_env(vars: &[&str]) -> Option<String> {
+    vars.iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("C", 10000, "10000")]
+    #[case("en_US.UTF-8", 10000, "10,000")]
+    #[case("en_GB", 999, "999")]
+    #[case("fr_FR.UTF-8", 1234567, "1 234 567")]
+    #[case("de_DE", -10000, "-10.000")]
+    #[case("fr", -12, "-12")]
+    fn test_int(#[case] locale: &str, #[case] n: i64, #[case] want: &str) {
+        assert_eq!(want, NumberFormat::new(locale).int(n));
+    }
+
+    #[rstest]
+    #[case("C", 1234.5, 2, "1234.50")]
+    #[case("en_US", 1234.5, 1, "1,234.5")]
+    #[case("fr_FR", 1234.5, 2, "1 234,50")]
+    #[case("de", -0.25, 2, "-0,25")]
+    #[case("fr", 12.0, 0, "12")]
+    #[case("en", -0.001, 1, "0.0")]
+    fn test_float(#[case] locale: &str, #[case] f: f64, #[case] prec: usize, #[case] want: &str) {
+        assert_eq!(want, NumberFormat::new(locale).float(f, prec));
+    }
+
+    #[test]
+    fn test_default() {
+        let nf = NumberFormat::default();
+
+        assert_eq!(None, nf.group);
+        assert_eq!('.', nf.decimal);
+    }
+}
@@ -0,0 +1,416 @@
+//! Messages of the REPL and phrasing of the results, in English or French.
+//!
+//! The language comes from the locale, either given explicitly (see `Config`) or taken from
+//! the usual environment variables (`LC_ALL`, `LC_MESSAGES` then `LANG`).  Anything else than
+//! French is English.
+//!
+//! Every text of the catalog can be replaced, by embedders with `with` or `set` and by users
+//! in the `[messages]` table of `config.toml`.  `{0}`, `{1}`... stand for the arguments.
+//!
+//! The notes of the builtin games (`wod`, `gurps`, `ironsworn`, `bitd`) and the summary of
+//! `query` are in the catalog.  Texts read from files like the `compare` notes of packs stay
+//! as written and errors other than unknown commands are in English.
+//!
+//! Example:
+//! ```
+//! use dices_rs::messages::{Messages, Msg};
+//!
+//! let m = Messages::new("fr_FR.UTF-8");
+//! assert_eq!("commande inconnue dcie", m.fill(Msg::UnknownCommand, &["dcie"]));
+//!
+//! let m = m.with(Msg::Total, "somme");
+//! assert_eq!("somme", m.get(Msg::Total));
+//! ```
+
+use std::collections::BTreeMap;
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::error::CompilerError;
+use crate::dice::result::Special;
+use crate::engine::error::EngineError;
+use crate::locale;
+
+/// Environment variables checked in order to find the language
+const LOCALE_VARS: [&str; 3] = ["LC_ALL", "LC_MESSAGES", "LANG"];
+
+/// Languages of the catalog
+///
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Lang {
+    #[default]
+    En,
+    Fr,
+}
+
+impl Lang {
+    /// Language of a locale name like `fr_FR.UTF-8`, English if unknown
+    ///
+    pub fn new(locale: &str) -> Self {
+        match locale::language(locale).as_str() {
+            "fr" => Lang::Fr,
+            _ => Lang::En,
+        }
+    }
+}
+
+/// Every text of the catalog
+///
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Msg {
+    /// Before the list of commands when the shell starts
+    AvailableCommands,
+    /// `{0}` is the name typed
+    UnknownCommand,
+    /// After `UnknownCommand`, `{0}` is the closest command
+    DidYouMean,
+    /// Result phrasing
+    Total,
+    InclBonus,
+    Rerolled,
+    Dropped,
+    Symbols,
+    /// An open-ended dice stopped exploding, see `dice::explode`
+    Capped,
+    /// Name of the `Special` flag of a result
+    Natural,
+    Fumble,
+    OpenHigh,
+    OpenLow,
+    Botch,
+    NoFlag,
+    /// `branch`, `merge` and `discard`, `{0}` is the depth or the name of a branch
+    Branch,
+    Session,
+    Merged,
+    Discarded,
+    /// State of the `speak`, `trace` and `gm` switches
+    SpeechOn,
+    SpeechOff,
+    TraceOn,
+    TraceOff,
+    GmOn,
+    GmOff,
+    /// Rolls behind the GM screen, `{0}` and `{1}` are their numbers
+    HiddenRoll,
+    HiddenRolls,
+    NoHiddenRoll,
+    /// `scores`, `{0}` is the player, `{1}` the number of rolls and `{2}` their average
+    Score,
+    NoRollYet,
+    /// Outcome of the first roll of `dice d20 >= 15 then 2d6`
+    Hit,
+    Miss,
+    /// Note of a `wod` pool, `{0}` is the number of successes
+    PoolBotch,
+    PoolFailure,
+    OneSuccess,
+    Successes,
+    /// Note of `ironsworn`, `{0}` is the outcome and `{1}`, `{2}` the challenge dice
+    StrongHit,
+    WeakHit,
+    ActionMiss,
+    WithMatch,
+    Versus,
+    /// Note of `bitd`, `{0}` is the outcome of a zero pool
+    Critical,
+    FullSuccess,
+    PartialSuccess,
+    ForgedFailure,
+    ZeroPool,
+    /// Note of `gurps`, `{0}` is the outcome and `{1}` the margin
+    CriticalSuccess,
+    CriticalFailure,
+    Success,
+    Failure,
+    Margin,
+    /// Summary of `query`, `{0}` is the number of rolls and `{1}` to `{3}` their totals
+    NoRolls,
+    RollStats,
+}
+
+impl Msg {
+    /// Text of the catalog for `lang`
+    ///
+    pub fn text(self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::En => match self {
+                Msg::AvailableCommands => "Available commands",
+                Msg::UnknownCommand => "unknown command {0}",
+                Msg::DidYouMean => ", did you mean {0}?",
+                Msg::Total => "total",
+                Msg::InclBonus => "incl. bonus",
+                Msg::Rerolled => "rerolled",
+                Msg::Dropped => "dropped",
+                Msg::Symbols => "symbols",
+                Msg::Capped => "explosions capped",
+                Msg::Natural => "Natural",
+                Msg::Fumble => "Fumble",
+                Msg::OpenHigh => "OpenHigh",
+                Msg::OpenLow => "OpenLow",
+                Msg::Botch => "Botch",
+                Msg::NoFlag => "None",
+                Msg::Branch => "branch {0}",
+                Msg::Session => "session",
+                Msg::Merged => "merged, back to {0}",
+                Msg::Discarded => "discarded, back to {0}",
+                Msg::SpeechOn => "speech is on",
+                Msg::SpeechOff => "speech is off",
+                Msg::TraceOn => "trace is on",
+                Msg::TraceOff => "trace is off",
+                Msg::GmOn => "gm screen is on",
+                Msg::GmOff => "gm screen is off",
+                Msg::HiddenRoll => "hidden roll #{0}",
+                Msg::HiddenRolls => "hidden rolls #{0} to #{1}",
+                Msg::NoHiddenRoll => "no hidden roll",
+                Msg::Score => "{0}\t{1} roll(s), average {2}",
+                Msg::NoRollYet => "no roll yet",
+                Msg::Hit => "hit",
+                Msg::Miss => "miss",
+                Msg::PoolBotch => "botch",
+                Msg::PoolFailure => "failure",
+                Msg::OneSuccess => "1 success",
+                Msg::Successes => "{0} successes",
+                Msg::StrongHit => "strong hit",
+                Msg::WeakHit => "weak hit",
+                Msg::ActionMiss => "miss",
+                Msg::WithMatch => "{0} with a match",
+                Msg::Versus => "{0} vs {1} and {2}",
+                Msg::Critical => "critical",
+                Msg::FullSuccess => "full success",
+                Msg::PartialSuccess => "partial success",
+                Msg::ForgedFailure => "failure",
+                Msg::ZeroPool => "{0}, zero pool",
+                Msg::CriticalSuccess => "critical success",
+                Msg::CriticalFailure => "critical failure",
+                Msg::Success => "success",
+                Msg::Failure => "failure",
+                Msg::Margin => "{0} by {1}",
+                Msg::NoRolls => "no rolls",
+                Msg::RollStats => "{0} rolls, min {1} avg {2} max {3}",
+            },
+            Lang::Fr => match self {
+                Msg::AvailableCommands => "Commandes disponibles",
+                Msg::UnknownCommand => "commande inconnue {0}",
+                Msg::DidYouMean => ", vouliez-vous dire {0} ?",
+                Msg::Total => "total",
+                Msg::InclBonus => "dont bonus",
+                Msg::Rerolled => "relancés",
+                Msg::Dropped => "écartés",
+                Msg::Symbols => "symboles",
+                Msg::Capped => "explosions plafonnées",
+                Msg::Natural => "Naturel",
+                Msg::Fumble => "Échec critique",
+                Msg::OpenHigh => "Ouvert haut",
+                Msg::OpenLow => "Ouvert bas",
+                Msg::Botch => "Échec total",
+                Msg::NoFlag => "Aucun",
+                Msg::Branch => "branche {0}",
+                Msg::Session => "session",
+                Msg::Merged => "fusionnée, retour à {0}",
+                Msg::Discarded => "abandonnée, retour à {0}",
+                Msg::SpeechOn => "synthèse vocale activée",
+                Msg::SpeechOff => "synthèse vocale désactivée",
+                Msg::TraceOn => "trace activée",
+                Msg::TraceOff => "trace désactivée",
+                Msg::GmOn => "écran du MJ activé",
+                Msg::GmOff => "écran du MJ désactivé",
+                Msg::HiddenRoll => "jet caché n°{0}",
+                Msg::HiddenRolls => "jets cachés n°{0} à n°{1}",
+                Msg::NoHiddenRoll => "aucun jet caché",
+                Msg::Score => "{0}\t{1} jet(s), moyenne {2}",
+                Msg::NoRollYet => "aucun jet pour l'instant",
+                Msg::Hit => "réussi",
+                Msg::Miss => "raté",
+                Msg::PoolBotch => "échec total",
+                Msg::PoolFailure => "échec",
+                Msg::OneSuccess => "1 succès",
+                Msg::Successes => "{0} succès",
+                Msg::StrongHit => "réussite forte",
+                Msg::WeakHit => "réussite faible",
+                Msg::ActionMiss => "échec",
+                Msg::WithMatch => "{0} avec une égalité",
+                Msg::Versus => "{0} contre {1} et {2}",
+                Msg::Critical => "critique",
+                Msg::FullSuccess => "réussite totale",
+                Msg::PartialSuccess => "réussite partielle",
+                Msg::ForgedFailure => "échec",
+                Msg::ZeroPool => "{0}, réserve vide",
+                Msg::CriticalSuccess => "réussite critique",
+                Msg::CriticalFailure => "échec critique",
+                Msg::Success => "réussite",
+                Msg::Failure => "échec",
+                Msg::Margin => "{0} de {1}",
+                Msg::NoRolls => "aucun jet",
+                Msg::RollStats => "{0} jets, min {1} moy. {2} max {3}",
+            },
+        }
+    }
+}
+
+/// Catalog for one language, with the texts replaced by the user or the embedder
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Messages {
+    /// Language of the texts not replaced
+    pub lang: Lang,
+    /// Replaced texts
+    overrides: BTreeMap<Msg, String>,
+}
+
+impl Messages {
+    /// Catalog for a locale name like `fr_FR.UTF-8` or `en`
+    ///
+    pub fn new(locale: &str) -> Self {
+        trace!("messages::new({locale})");
+        Messages {
+            lang: Lang::new(locale),
+            overrides: BTreeMap::new(),
+        }
+    }
+
+    /// Catalog for the language of the environment, defaults to English
+    ///
+    pub fn from_env() -> Self {
+        locale::from_env(&LOCALE_VARS)
+            .map(|l| Messages::new(&l))
+            .unwrap_or_default()
+    }
+
+    /// Replace the text of `msg`
+    ///
+    pub fn with(mut self, msg: Msg, text: &str) -> Self {
+        self.set(msg, text);
+        self
+    }
+
+    /// Same as `with` in place
+    ///
+    pub fn set(&mut self, msg: Msg, text: &str) -> &mut Self {
+        self.overrides.insert(msg, text.to_string());
+        self
+    }
+
+    /// Text of `msg`
+    ///
+    pub fn get(&self, msg: Msg) -> &str {
+        self.overrides
+            .get(&msg)
+            .map(String::as_str)
+            .unwrap_or_else(|| msg.text(self.lang))
+    }
+
+    /// Text of `msg` with `{0}`, `{1}`... replaced by `args`
+    ///
+    pub fn fill(&self, msg: Msg, args: &[&str]) -> String {
+        args.iter()
+            .enumerate()
+            .fold(self.get(msg).to_string(), |s, (i, a)| {
+                s.replace(&format!("{{{i}}}"), a)
+            })
+    }
+
+    /// Name of the flag of a result
+    ///
+    pub fn flag(&self, flag: Special) -> &str {
+        self.get(match flag {
+            Special::Natural => Msg::Natural,
+            Special::Fumble => Msg::Fumble,
+            Special::OpenHigh => Msg::OpenHigh,
+            Special::OpenLow => Msg::OpenLow,
+            Special::Botch => Msg::Botch,
+            Special::None => Msg::NoFlag,
+        })
+    }
+
+    /// One of two texts, depending on `on`
+    ///
+    pub fn switch(&self, on: bool, when_on: Msg, when_off: Msg) -> &str {
+        self.get(if on { when_on } else { when_off })
+    }
+
+    /// Error for the user, errors not in the catalog are in English
+    ///
+    pub fn error(&self, e: &EngineError) -> String {
+        match e {
+            EngineError::Compiler(CompilerError::UnknownCommand(name, near)) => {
+                let near = near
+                    .as_ref()
+                    .map(|n| self.fill(Msg::DidYouMean, &[n]))
+                    .unwrap_or_default();
+                format!("{}{near}", self.fill(Msg::UnknownCommand, &[name]))
+            }
+            _ => e.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("C", Lang::En)]
+    #[case("en_US.UTF-8", Lang::En)]
+    #[case("fr_FR.UTF-8", Lang::Fr)]
+    #[case("fr", Lang::Fr)]
+    #[case("de_DE", Lang::En)]
+    fn test_lang(#[case] locale: &str, #[case] want: Lang) {
+        assert_eq!(want, Messages::new(locale).lang);
+    }
+
+    #[rstest]
+    #[case("en", "unknown command dcie, did you mean dice?")]
+    #[case("fr", "commande inconnue dcie, vouliez-vous dire dice ?")]
+    fn test_error(#[case] locale: &str, #[case] want: &str) {
+        let e = CompilerError::UnknownCommand("dcie".to_string(), Some("dice".to_string()));
+
+        assert_eq!(want, Messages::new(locale).error(&e.into()));
+    }
+
+    #[test]
+    fn test_error_english() {
+        let e = CompilerError::UnknownCommand("dance".to_string(), None);
+
+        assert_eq!(e.to_string(), Messages::default().error(&e.clone().into()));
+        assert_eq!(
+            "invalid command",
+            Messages::new("fr").error(&CompilerError::InvalidCommand.into())
+        );
+    }
+
+    #[rstest]
+    #[case("en", "merged, back to branch 2")]
+    #[case("fr", "fusionnée, retour à branche 2")]
+    fn test_fill_nested(#[case] locale: &str, #[case] want: &str) {
+        let m = Messages::new(locale);
+        let branch = m.fill(Msg::Branch, &["2"]);
+
+        assert_eq!(want, m.fill(Msg::Merged, &[&branch]));
+    }
+
+    #[test]
+    fn test_switch() {
+        let m = Messages::new("fr");
+
+        assert_eq!("trace activée", m.switch(true, Msg::TraceOn, Msg::TraceOff));
+        assert_eq!(
+            "écran du MJ désactivé",
+            m.switch(false, Msg::GmOn, Msg::GmOff)
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let mut m = Messages::new("fr").with(Msg::UnknownCommand, "{0} ? connais pas");
+        m.set(Msg::Natural, "20 naturel");
+
+        assert_eq!("dcie ? connais pas", m.fill(Msg::UnknownCommand, &["dcie"]));
+        assert_eq!("20 naturel", m.flag(Special::Natural));
+        assert_eq!("Échec critique", m.flag(Special::Fumble));
+    }
+}
@@ -0,0 +1,307 @@
+//! Where the `dices` shell keeps its files.
+//!
+//! Everything lives in the configuration directory of the platform (or a temporary directory
+//! without home): `$XDG_CONFIG_HOME/dices` (usually `~/.config/dices`) on Linux and other
+//! Unix systems, `~/Library/Application Support/net.keltia.dices` on macOS and
+//! `%APPDATA%\keltia\dices\config` on Windows.  There we have the configuration in
+//! `config.toml`, the aliases in `aliases`, the history in `history` and the random tables in
+//! `tables/`.  The configuration can move the history elsewhere or turn it off entirely.
+//!
+//! Older versions used `~/.config/dices` everywhere, `migrate_from` moves it to its new place.
+//!
+//! On the first run the directory is created with a commented `config.toml` and `aliases`
+//! (see `init`), `dices init` writes them again.
+//!
+//! ```
+//! use std::path::PathBuf;
+//! use dices_rs::config::Config;
+//! use dices_rs::paths::Paths;
+//!
+//! let p = Paths::with_base("/home/me/.config/dices");
+//! assert_eq!(Some(PathBuf::from("/home/me/.config/dices/aliases")), p.aliases());
+//!
+//! let cfg = Config { history: false, ..Config::default() };
+//! assert_eq!(None, p.history(&cfg));
+//! assert_eq!(None, Paths::none().config());
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use directories::{BaseDirs, ProjectDirs};
+use log::{trace, warn};
+
+use crate::config::Config;
+use crate::makepath;
+
+/// Our old directory, under the home directory
+pub const LEGACY_DIR: &str = ".config";
+/// Aliases file in our directory
+pub const ALIASES_FILE: &str = "aliases";
+/// Configuration file in our directory
+pub const CONFIG_FILE: &str = "config.toml";
+/// History of the shell in our directory
+pub const HISTORY_FILE: &str = "history";
+/// Random tables in our directory
+pub const TABLES_DIR: &str = "tables";
+
+/// Commented configuration written by `init`
+pub const SAMPLE_CONFIG: &str = include_str!("sample/config.toml");
+/// Commented aliases written by `init`
+pub const SAMPLE_ALIASES: &str = include_str!("sample/aliases");
+
+/// Location of our files, if any
+///
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Paths {
+    base: Option<PathBuf>,
+}
+
+impl Paths {
+    /// Our directory in the configuration directory of the platform, or a temporary one if
+    /// there is no home directory
+    ///
+    pub fn new() -> Self {
+        let base = match ProjectDirs::from("net", "keltia", "dices") {
+            Some(dirs) => dirs.config_dir().to_path_buf(),
+            None => {
+                let tmp = std::env::temp_dir().join("dices");
+                warn!("no home directory, using {:?}", tmp);
+                tmp
+            }
+        };
+        Paths { base: Some(base) }
+    }
+
+    /// Where older versions kept our files, `$HOME/.config/dices` on every platform
+    ///
+    pub fn legacy() -> Option<PathBuf> {
+        BaseDirs::new().map(|d| makepath!(d.home_dir(), LEGACY_DIR, "dices"))
+    }
+
+    /// No files at all, like `--no-config`
+    ///
+    pub fn none() -> Self {
+        Paths { base: None }
+    }
+
+    /// Our files in `dir`
+    ///
+    pub fn with_base<P: AsRef<Path>>(dir: P) -> Self {
+        Paths {
+            base: Some(dir.as_ref().to_path_buf()),
+        }
+    }
+
+    /// Our directory
+    ///
+    pub fn base(&self) -> Option<&Path> {
+        self.base.as_deref()
+    }
+
+    /// Default configuration file
+    ///
+    pub fn config(&self) -> Option<PathBuf> {
+        self.file(CONFIG_FILE)
+    }
+
+    /// Default aliases file
+    ///
+    pub fn aliases(&self) -> Option<PathBuf> {
+        self.file(ALIASES_FILE)
+    }
+
+    /// Default directory of the random tables
+    ///
+    pub fn tables(&self) -> Option<PathBuf> {
+        self.file(TABLES_DIR)
+    }
+
+    /// History file from `cfg` or the default one, `None` when the history is off
+    ///
+    pub fn history(&self, cfg: &Config) -> Option<PathBuf> {
+        if !cfg.history {
+            return None;
+        }
+        cfg.history_file.clone().or_else(|| self.file(HISTORY_FILE))
+    }
+
+    /// Move `old` to our directory if it does not exist yet.  Returns whether it was moved.
+    ///
+    pub fn migrate_from(&self, old: &Path) -> Result<bool> {
+        let base = match self.base() {
+            Some(base) if base != old && old.is_dir() && !base.exists() => base,
+            _ => return Ok(false),
+        };
+        trace!("migrate_from({old:?}) to {base:?}");
+        if let Some(parent) = base.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(old, base)
+            .map_err(|e| anyhow!("can not move {} to {}: {e}", old.display(), base.display()))?;
+        Ok(true)
+    }
+
+    /// Create our directory with a sample configuration and aliases, if it does not exist yet.
+    /// Returns the files written.
+    ///
+    pub fn first_run(&self) -> Result<Vec<PathBuf>> {
+        match self.base() {
+            Some(base) if !base.exists() => self.init(false),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Write the sample configuration and aliases, creating our directory if needed.  Existing
+    /// files are kept unless `force` is given, then they are saved with a `.bak` extension
+    /// first.  Returns the files written.
+    ///
+    pub fn init(&self, force: bool) -> Result<Vec<PathBuf>> {
+        let base = self
+            .base()
+            .ok_or_else(|| anyhow!("no directory for our files"))?;
+        trace!("init({base:?}, {force})");
+        fs::create_dir_all(base)?;
+
+        let mut written = vec![];
+        for (name, content) in [(CONFIG_FILE, SAMPLE_CONFIG), (ALIASES_FILE, SAMPLE_ALIASES)] {
+            let fname = base.join(name);
+            if fname.exists() {
+                if !force {
+                    continue;
+                }
+                fs::rename(&fname, fname.with_extension("bak"))?;
+            }
+            fs::write(&fname, content)?;
+            written.push(fname);
+        }
+        Ok(written)
+    }
+
+    fn file(&self, name: &str) -> Option<PathBuf> {
+        self.base.as_ref().map(|b| b.join(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::aliases::AliasSet;
+
+    use super::*;
+
+    /// Empty directory for a test
+    fn tmp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dices-paths-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_paths() {
+        let p = Paths::with_base("base");
+
+        assert_eq!(Some(Path::new("base")), p.base());
+        assert_eq!(Some(makepath!("base", "config.toml")), p.config());
+        assert_eq!(Some(makepath!("base", "tables")), p.tables());
+        assert_eq!(
+            Some(makepath!("base", "history")),
+            p.history(&Config::default())
+        );
+        assert!(Paths::new().base().is_some());
+        assert!(Paths::legacy().unwrap().ends_with(".config/dices"));
+    }
+
+    #[test]
+    fn test_migrate_from() {
+        let dir = tmp("migrate");
+        let old = dir.join("old");
+        let p = Paths::with_base(dir.join("new").join("dices"));
+
+        assert!(!p.migrate_from(&old).unwrap());
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join(ALIASES_FILE), "stats = \"dice 4D6dl1\"").unwrap();
+
+        assert!(p.migrate_from(&old).unwrap());
+        assert!(!old.exists());
+        assert!(p.aliases().unwrap().exists());
+
+        fs::create_dir_all(&old).unwrap();
+        assert!(!p.migrate_from(&old).unwrap());
+        assert!(!Paths::with_base(&old).migrate_from(&old).unwrap());
+        assert!(!Paths::none().migrate_from(&old).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_paths_history() {
+        let cfg = Config {
+            history_file: Some(PathBuf::from("/tmp/hist")),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            Some(PathBuf::from("/tmp/hist")),
+            Paths::none().history(&cfg)
+        );
+        assert_eq!(
+            Some(PathBuf::from("/tmp/hist")),
+            Paths::with_base("base").history(&cfg)
+        );
+        assert_eq!(None, Paths::none().history(&Config::default()));
+
+        let cfg = Config {
+            history: false,
+            ..cfg
+        };
+        assert_eq!(None, Paths::with_base("base").history(&cfg));
+    }
+
+    #[test]
+    fn test_samples() {
+        assert_eq!(Config::default(), Config::parse(SAMPLE_CONFIG).unwrap());
+
+        let dir = tmp("samples");
+        let p = Paths::with_base(&dir);
+        p.init(false).unwrap();
+        let set = AliasSet::load(&p.aliases().unwrap());
+        assert_eq!(1, set.common.len());
+        assert!(set.profiles.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_first_run() {
+        let dir = tmp("first");
+        let p = Paths::with_base(&dir);
+
+        assert_eq!(
+            vec![p.config().unwrap(), p.aliases().unwrap()],
+            p.first_run().unwrap()
+        );
+        assert!(p.first_run().unwrap().is_empty());
+        assert!(Paths::none().first_run().unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_init() {
+        let dir = tmp("init");
+        let p = Paths::with_base(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(p.config().unwrap(), "prompt = \"> \"").unwrap();
+
+        assert_eq!(vec![p.aliases().unwrap()], p.init(false).unwrap());
+        assert_eq!(2, p.init(true).unwrap().len());
+        assert_eq!(
+            "prompt = \"> \"",
+            fs::read_to_string(dir.join("config.bak")).unwrap()
+        );
+        assert_eq!(
+            SAMPLE_CONFIG,
+            fs::read_to_string(p.config().unwrap()).unwrap()
+        );
+        assert!(Paths::none().init(true).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
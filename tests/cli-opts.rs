@@ -1,3 +1,5 @@
+#![cfg(feature = "cli")]
+
 use assert_cmd::Command;
 
 const BIN: &str = "dices";
@@ -14,3 +16,222 @@ fn test_version() {
 
     cmd.arg("-V").assert().success();
 }
+
+#[test]
+fn test_batch_file() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args([
+        "-c",
+        "testdata/nonexistent.toml",
+        "-f",
+        "testdata/session.dice",
+    ])
+    .assert()
+    .success();
+}
+
+#[test]
+fn test_batch_stdin_errors() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args(["-c", "testdata/nonexistent.toml"])
+        .write_stdin("dice d6\nfoo\n")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_no_config() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.arg("--no-config")
+        .env_remove("HOME")
+        .write_stdin("dice 3D6\n")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_no_color() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd
+        .args(["--no-config", "--no-color"])
+        .write_stdin("dice 3D6\n")
+        .assert()
+        .success();
+    assert!(!String::from_utf8_lossy(&out.get_output().stderr).contains('\x1b'));
+}
+
+#[test]
+fn test_porcelain() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd
+        .args(["--no-config", "--porcelain"])
+        .write_stdin("dice 3D6 +2\nroll 2D6\n")
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&out.get_output().stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+
+    assert_eq!(2, lines.len(), "{stdout}");
+    let fields = lines[0].split(':').collect::<Vec<_>>();
+    assert_eq!(3, fields.len());
+    assert_eq!(3, fields[1].split(',').count());
+    assert_eq!("2", fields[2]);
+}
+
+#[test]
+fn test_no_debug_output() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd
+        .args(["--no-config", "--porcelain"])
+        .env_remove("RUST_LOG")
+        .write_stdin("dice 3D6 +2\nopen D6 -1\nroll 2D6 +1 +1\n")
+        .assert()
+        .success();
+    let (stdout, stderr) = (&out.get_output().stdout, &out.get_output().stderr);
+
+    assert_eq!(3, String::from_utf8_lossy(stdout).lines().count());
+    assert!(stderr.is_empty(), "{}", String::from_utf8_lossy(stderr));
+}
+
+#[test]
+fn test_log_file() {
+    let log = std::env::temp_dir().join(format!("dices-cli-{}.log", std::process::id()));
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args(["--no-config", "--log-file"])
+        .arg(&log)
+        .write_stdin(
+            "dice 3D6
+list
+roll 2D6
+",
+        )
+        .assert()
+        .success();
+    let content = std::fs::read_to_string(&log).unwrap();
+    std::fs::remove_file(&log).unwrap();
+
+    assert_eq!(2, content.lines().count());
+    assert!(content.contains(r#""line":"roll 2D6","expanded":"dice 2D6""#));
+}
+
+#[test]
+fn test_character() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd
+        .args(["--no-config", "--porcelain", "-C", "testdata/bob.toml"])
+        .args(["--character", "testdata/alice.yaml"])
+        .write_stdin(
+            "attack
+char use alice
+attack
+init
+",
+        )
+        .assert()
+        .failure();
+    let stdout = String::from_utf8_lossy(&out.get_output().stdout);
+    let bonus = stdout
+        .lines()
+        .filter_map(|l| l.rsplit(':').next())
+        .collect::<Vec<_>>();
+
+    assert_eq!(vec!["7", "playing alice", "5"], bonus, "{stdout}");
+}
+
+#[test]
+fn test_profile() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args(["--no-config", "-A", "testdata/aliases.profiles"])
+        .args(["--profile", "dnd"])
+        .write_stdin("save\nstats\nprofile rolemaster\nmoving\n")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+    cmd.args(["--no-config", "-A", "testdata/aliases.profiles"])
+        .args(["--profile", "gurps"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_table() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd
+        .args(["--porcelain", "-c", "testdata/tables.toml"])
+        .write_stdin("table weather\ntable treasure\n")
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&out.get_output().stdout);
+    let tables = stdout
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .collect::<Vec<_>>();
+
+    assert_eq!(vec!["weather", "treasure"], tables, "{stdout}");
+}
+
+#[cfg(not(feature = "server"))]
+#[test]
+fn test_serve_without_feature() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd.args(["--no-config", "serve"]).assert().failure();
+    assert!(String::from_utf8_lossy(&out.get_output().stderr).contains("server feature"));
+}
+
+#[test]
+fn test_no_config_conflict() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args(["--no-config", "-c", "testdata/config.toml"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_history_conflict() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args(["--no-history", "--history-file", "/tmp/dices-history"])
+        .assert()
+        .failure();
+}
+
+// Our directory is elsewhere on macOS and Windows
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn test_init() {
+    let home = std::env::temp_dir().join(format!("dices-cli-home-{}", std::process::id()));
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    let out = cmd
+        .env("HOME", &home)
+        .env_remove("XDG_CONFIG_HOME")
+        .arg("init")
+        .assert()
+        .success();
+    let stdout = String::from_utf8_lossy(&out.get_output().stdout).to_string();
+    let base = home.join(".config").join("dices");
+    std::fs::remove_dir_all(&home).unwrap();
+
+    assert!(stdout.contains(&format!("wrote {}", base.join("config.toml").display())));
+    assert!(stdout.contains(&format!("wrote {}", base.join("aliases").display())));
+}
+
+#[test]
+fn test_init_no_config() {
+    let mut cmd = Command::cargo_bin(BIN).unwrap();
+
+    cmd.args(["--no-config", "init"]).assert().failure();
+}
@@ -0,0 +1,132 @@
+//! Statistical checks of the rolls: every roller has to be uniform and sums have to follow the
+//! exact distribution of the expression.  They roll a lot and are skipped by default, run them
+//! with `cargo test --test fairness -- --ignored`.
+//!
+//! Each check is a chi-square test at the 0.1% level, with seeded generators for `Fast` and
+//! `Legacy` so a failure can be reproduced; `Crypto` can not be seeded.
+//!
+
+use std::collections::BTreeMap;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use dices_rs::dice::exact::exact;
+use dices_rs::dice::expr::Expr;
+use dices_rs::dice::internal::{internal_roll_with, legacy_roll_with, Roller, RollerKind};
+
+/// Usual sizes of dices
+const SIZES: [usize; 7] = [4, 6, 8, 10, 12, 20, 100];
+
+/// Rolls expected on each face
+const PER_FACE: usize = 1_000;
+
+/// Quantile of the normal distribution for a 0.1% risk
+const Z: f64 = 3.09;
+
+/// Critical value of a chi-square with `k` degrees of freedom, Wilson-Hilferty approximation
+///
+fn critical(k: usize) -> f64 {
+    let k = k as f64;
+    let h = 2.0 / (9.0 * k);
+    k * (1.0 - h + Z * h.sqrt()).powi(3)
+}
+
+/// Chi-square statistic of `seen` against `expected` counts
+///
+fn chi_square(seen: &[usize], expected: &[f64]) -> f64 {
+    seen.iter()
+        .zip(expected)
+        .map(|(o, e)| (*o as f64 - e).powi(2) / e)
+        .sum()
+}
+
+/// Roll a dice of `sides` faces with `roll` and check every face comes out as often
+///
+fn check_uniform(name: &str, sides: usize, mut roll: impl FnMut(usize) -> usize) {
+    let mut seen = vec![0; sides];
+    for _ in 0..sides * PER_FACE {
+        let r = roll(sides);
+        assert!((1..=sides).contains(&r), "{name}: {r} out of D{sides}");
+        seen[r - 1] += 1;
+    }
+    let chi2 = chi_square(&seen, &vec![PER_FACE as f64; sides]);
+    assert!(
+        chi2 < critical(sides - 1),
+        "{name} D{sides}: chi2 = {chi2:.1} >= {:.1}, {seen:?}",
+        critical(sides - 1)
+    );
+}
+
+#[test]
+#[ignore = "statistical, run with --ignored"]
+fn test_fast_uniform() {
+    let mut rng = StdRng::seed_from_u64(0xd1ce);
+    for sides in SIZES {
+        check_uniform("fast", sides, |s| internal_roll_with(&mut rng, s));
+    }
+}
+
+#[test]
+#[ignore = "statistical, run with --ignored"]
+fn test_legacy_uniform() {
+    let mut rng = StdRng::seed_from_u64(0xd1ce);
+    for sides in SIZES {
+        check_uniform("legacy", sides, |s| legacy_roll_with(&mut rng, s));
+    }
+}
+
+#[test]
+#[ignore = "statistical, run with --ignored"]
+fn test_crypto_uniform() {
+    RollerKind::Crypto.scope(|rng| {
+        for sides in SIZES {
+            check_uniform("crypto", sides, |s| rng.roll(s));
+        }
+    });
+}
+
+/// Sums of `input` rolled `n` times against its exact distribution, with every roller
+///
+fn check_sums(input: &str, n: usize) {
+    let expr = Expr::parse(input).unwrap();
+    let p = exact(&expr).unwrap();
+
+    for kind in [RollerKind::Fast, RollerKind::Legacy, RollerKind::Crypto] {
+        let mut rng = match kind {
+            RollerKind::Crypto => Roller::from(kind),
+            _ => Roller::new(StdRng::seed_from_u64(0xd1ce)).with_kind(kind),
+        };
+        let mut seen = BTreeMap::new();
+        for _ in 0..n {
+            *seen.entry(expr.eval(&mut rng).unwrap().sum).or_insert(0) += 1;
+        }
+        assert!(seen.keys().all(|v| p.0.contains_key(v)), "{kind}: {seen:?}");
+
+        let counts = p.0.keys().map(|v| seen.get(v).copied().unwrap_or(0));
+        let expected = p.0.values().map(|q| q * n as f64).collect::<Vec<_>>();
+        let chi2 = chi_square(&counts.collect::<Vec<_>>(), &expected);
+        assert!(
+            chi2 < critical(p.0.len() - 1),
+            "{kind} {input}: chi2 = {chi2:.1} >= {:.1}",
+            critical(p.0.len() - 1)
+        );
+    }
+}
+
+#[test]
+#[ignore = "statistical, run with --ignored"]
+fn test_sums() {
+    check_sums("3D6", 50_000);
+    check_sums("2D6 + D4", 50_000);
+    check_sums("D20 + 5", 20_000);
+    check_sums("2D10 - D6", 50_000);
+}
+
+#[test]
+fn test_critical() {
+    // Values from chi-square tables at 0.1%
+    //
+    [(5, 20.52), (19, 43.82), (99, 148.23)]
+        .iter()
+        .for_each(|(k, want)| assert!((critical(*k) - want).abs() < 0.3, "{k}"));
+}
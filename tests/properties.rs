@@ -0,0 +1,131 @@
+//! Properties of the dice parser: whatever the spacing and the case of `d`, an expression is
+//! displayed in one canonical way, which parses back to the same expression, and its rolls
+//! always stay within its possible totals.
+//!
+
+use proptest::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use dices_rs::dice::exact::exact;
+use dices_rs::dice::expr::{parse_expr, Expr};
+use dices_rs::dice::internal::Roller;
+
+/// Up to `max` dices like `3d6` or `D20`, as typed and as displayed
+///
+fn dices(max: usize) -> impl Strategy<Value = (String, String)> {
+    (
+        1..=max,
+        prop::sample::select(vec![4usize, 6, 8, 10, 12, 20, 100]),
+        any::<bool>(),
+        any::<bool>(),
+    )
+        .prop_map(|(n, s, upper, implicit)| {
+            let d = if upper { 'D' } else { 'd' };
+            let typed = match (n, implicit) {
+                (1, true) => format!("{d}{s}"),
+                _ => format!("{n}{d}{s}"),
+            };
+            let shown = match n {
+                1 => format!("D{s}"),
+                _ => format!("{n}D{s}"),
+            };
+            (typed, shown)
+        })
+}
+
+/// Up to `max` dices or a number
+///
+fn operand(max: usize) -> impl Strategy<Value = (String, String)> {
+    prop_oneof![
+        dices(max),
+        (0usize..1000).prop_map(|n| (n.to_string(), n.to_string())),
+    ]
+}
+
+/// Operands and operators without parentheses, with random spaces around operators
+///
+fn flat() -> impl Strategy<Value = (String, String)> {
+    (
+        operand(12),
+        prop::collection::vec(
+            (
+                prop::sample::select(vec!['+', '-', '*', '/']),
+                " {0,2}",
+                " {0,2}",
+                operand(12),
+            ),
+            0..5,
+        ),
+    )
+        .prop_map(|((typed, shown), rest)| {
+            rest.into_iter().fold(
+                (typed, shown),
+                |(typed, shown), (op, before, after, (t, s))| {
+                    (
+                        format!("{typed}{before}{op}{after}{t}"),
+                        format!("{shown} {op} {s}"),
+                    )
+                },
+            )
+        })
+}
+
+/// Any expression, with parentheses and negations
+///
+fn nested() -> impl Strategy<Value = String> {
+    operand(12)
+        .prop_map(|(t, _)| t)
+        .prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|e| format!("({e})")),
+                inner.clone().prop_map(|e| format!("-{e}")),
+                (
+                    inner.clone(),
+                    prop::sample::select(vec!['+', '-', '*', '/']),
+                    inner
+                )
+                    .prop_map(|(a, op, b)| format!("{a} {op} {b}")),
+            ]
+        })
+}
+
+/// Only additions and subtractions of a few dices, there is an exact distribution
+///
+fn sum() -> impl Strategy<Value = String> {
+    (
+        operand(4),
+        prop::collection::vec((prop::sample::select(vec!['+', '-']), operand(4)), 0..3),
+    )
+        .prop_map(|((first, _), rest)| {
+            rest.into_iter()
+                .fold(first, |all, (op, (t, _))| format!("{all} {op} {t}"))
+        })
+}
+
+proptest! {
+    #[test]
+    fn test_display_normalized((typed, shown) in flat()) {
+        let (rest, expr) = parse_expr(&typed).unwrap();
+
+        prop_assert_eq!("", rest);
+        prop_assert_eq!(shown, expr.to_string());
+    }
+
+    #[test]
+    fn test_display_parse(input in nested()) {
+        let expr = Expr::parse(&input).unwrap();
+        let shown = expr.to_string();
+
+        prop_assert_eq!(&expr, &Expr::parse(&shown).unwrap());
+        prop_assert_eq!(&shown, &Expr::parse(&shown).unwrap().to_string());
+    }
+
+    #[test]
+    fn test_roll_possible(input in sum(), seed in any::<u64>()) {
+        let expr = Expr::parse(&input).unwrap();
+        let p = exact(&expr).unwrap();
+        let res = expr.eval(&mut Roller::new(StdRng::seed_from_u64(seed))).unwrap();
+
+        prop_assert!(p.0.contains_key(&res.sum), "{} = {}", input, res.sum);
+    }
+}